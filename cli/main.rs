@@ -1,30 +1,265 @@
+use clap::{Args, Parser, Subcommand};
+use cnut::doctor;
+use cnut::network::{NetworkManifest, RunningNetwork};
 use cnut::prelude::*;
 use cnut::tokio;
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+
+/// Runs local Casper networks.
+#[derive(Parser)]
+#[command(name = "cnut", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs a network, and serves a web app to monitor it.
+    Run(RunArgs),
+    /// Checks that the tools needed to build and run a network are installed.
+    Doctor {
+        /// Prints the result as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Handles the artifacts imported in the system cache.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Shows the status of the nodes of a network run by another process,
+    /// from the `network.json` manifest in its directory.
+    Attach {
+        /// The path of the manifest.
+        manifest: PathBuf,
+        /// Prints the result as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Lists the cached artifacts.
+    List {
+        /// Prints the result as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Removes the cached artifacts.
+    Clear,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// The amount of validator nodes.
+    #[arg(long, default_value_t = 5)]
+    validators: usize,
+    /// The amount of non-validator nodes.
+    #[arg(long, alias = "keep-up", default_value_t = 0)]
+    keepups: usize,
+    /// The local casper-node repository to build the nodes from.
+    #[arg(long, default_value = "../casper-node", conflicts_with_all = ["tag", "branch"])]
+    local_path: PathBuf,
+    /// The casper-node tag to build the nodes from, instead of a local
+    /// repository.
+    #[arg(long)]
+    tag: Option<String>,
+    /// The casper-node branch to build the nodes from, instead of a local
+    /// repository. Its head is fetched and compiled at every run.
+    #[arg(long, conflicts_with = "tag")]
+    branch: Option<String>,
+    /// The port the web app listens on.
+    #[arg(long, default_value_t = 6532)]
+    web_port: u16,
+    /// Uses the binaries already built instead of compiling them.
+    #[arg(long)]
+    no_compile: bool,
+    /// Shuts the network down after this duration, for example `60s`, instead
+    /// of waiting for CTRL+C.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    duration: Option<Duration>,
+    /// Leaves the network files on the disk after the run.
+    #[arg(long)]
+    keep_files: bool,
+}
 
 #[tokio::main]
-async fn main() -> cnut::error::Result<()> {
-    start_logger();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
 
-    let artifacts = Artifacts::builder()
-        .local_path("../casper-node")
-        .build()
-        .await?;
+    let result = match cli.command {
+        Command::Run(args) => {
+            start_logger();
+            run(args).await
+        }
+        Command::Doctor { json } => doctor(json).await,
+        Command::Cache {
+            command: CacheCommand::List { json },
+        } => cache_list(json).await,
+        Command::Cache {
+            command: CacheCommand::Clear,
+        } => cache_clear().await,
+        Command::Attach { manifest, json } => attach(&manifest, json).await,
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
 
-    let _network = NetworkBuilder::new()
-        .with(5 * Node::validator(artifacts.clone()).name("Alice"))
-        .with(Node::keep_up(artifacts.clone()).name("Bob"))
-        .with(Node::validator(artifacts.clone()))
+async fn run(args: RunArgs) -> cnut::error::Result<ExitCode> {
+    let artifacts = match (args.tag, args.branch) {
+        (Some(tag), _) => Artifacts::builder().tag(tag),
+        (None, Some(branch)) => Artifacts::builder().branch(branch),
+        (None, None) => Artifacts::builder().local_path(args.local_path),
+    }
+    .compile(!args.no_compile)
+    .build()
+    .await?;
+
+    let network = NetworkBuilder::new()
+        .default_artifacts(artifacts.clone())
+        .with(args.validators * Node::default_validator())
+        .with(args.keepups * Node::default_keep_up())
         .with(Chainspec::from(artifacts))
-        //.with(5 * Node::validator(artifacts.clone()).config("../config.toml"))
-        //.with(15 * Node::keep_up(artifacts.clone()))
+        .web_port(args.web_port)
+        .keep_files(args.keep_files)
         .prepare()
         .await?
         .start_all()
-        .await?
-        .serve_web_app_and_wait()
         .await?;
 
-    Ok(())
+    let manifest = network.write_manifest().await?;
+    println!("Attach with: cnut attach {}", manifest.display());
+
+    network.serve_web_app().await?;
+    match args.duration {
+        Some(duration) => network.run_for(duration).await?,
+        None => network.wait().await?,
+    }
+    print_summary(&network, args.keep_files);
+
+    Ok(if network.any_crashed().await {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Fails if a required tool is missing.
+async fn doctor(json: bool) -> cnut::error::Result<ExitCode> {
+    let diagnostics = doctor::diagnose().await;
+
+    if json {
+        print_json(&diagnostics);
+    } else {
+        for diagnostic in &diagnostics {
+            let mark = match (diagnostic.ok, diagnostic.optional) {
+                (true, _) => "ok",
+                (false, true) => "warning",
+                (false, false) => "missing",
+            };
+            println!("[{mark}] {}: {}", diagnostic.name, diagnostic.details);
+        }
+    }
+
+    let ready = diagnostics
+        .iter()
+        .all(|diagnostic| diagnostic.ok || diagnostic.optional);
+    Ok(if ready {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+async fn cache_list(json: bool) -> cnut::error::Result<ExitCode> {
+    let bundles = Artifacts::cached_bundles().await?;
+    let paths: Vec<_> = bundles.iter().map(Artifacts::path).collect();
+
+    if json {
+        print_json(&paths);
+    } else if paths.is_empty() {
+        println!("No cached artifacts");
+    } else {
+        paths.iter().for_each(|path| println!("{}", path.display()));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn cache_clear() -> cnut::error::Result<ExitCode> {
+    Artifacts::clear_cached_bundles().await?;
+    println!("Cached artifacts removed");
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Fails if a node does not answer.
+async fn attach(manifest: &Path, json: bool) -> cnut::error::Result<ExitCode> {
+    let manifest = NetworkManifest::read(manifest).await?;
+    let reports = manifest.status().await;
+
+    if json {
+        print_json(&reports);
+    } else {
+        println!(
+            "Chain {} in {}",
+            manifest.chain_name,
+            manifest.run_directory.display()
+        );
+        for report in &reports {
+            match (report.era, report.height) {
+                (Some(era), Some(height)) => {
+                    println!("  {}: era {era}, height {height}", report.name)
+                }
+                _ => println!("  {}: unreachable", report.name),
+            }
+        }
+    }
+
+    Ok(if reports.iter().all(|report| report.reachable) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn print_json(value: &impl serde::Serialize) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("JSON serialization failed")
+    );
+}
+
+/// Prints the nodes with their ports, and where the files are.
+fn print_summary(network: &RunningNetwork, keep_files: bool) {
+    println!("{} nodes ran:", network.nodes_count());
+    for index in 0..network.nodes_count() {
+        let node = network.node_by_index(index).expect("index in bounds");
+        println!(
+            "  {}: RPC {}, REST {}, bind {}",
+            node.name(),
+            node.rpc_port(),
+            node.rest_port(),
+            node.bind_port(),
+        );
+    }
+    if keep_files {
+        println!("Files kept in {}", network.temp_directory().display());
+    } else {
+        println!("Files removed from {}", network.temp_directory().display());
+    }
 }
 
 pub fn start_logger() {
@@ -57,3 +292,42 @@ pub fn start_logger() {
         .start()
         .expect("Failed to start the logger");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::error::ErrorKind;
+
+    #[test]
+    fn a_tag_conflicts_with_a_local_path() {
+        let result = Cli::try_parse_from(["cnut", "run", "--tag", "v1.5.0", "--local-path", "."]);
+
+        assert!(matches!(result, Err(e) if e.kind() == ErrorKind::ArgumentConflict));
+    }
+
+    #[test]
+    fn a_tag_replaces_the_default_local_path() {
+        let cli = Cli::try_parse_from(["cnut", "run", "--tag", "v1.5.0"]).unwrap();
+
+        assert!(matches!(cli.command, Command::Run(args) if args.tag.as_deref() == Some("v1.5.0")));
+    }
+
+    #[test]
+    fn the_cached_artifacts_are_listed_as_json_on_demand() {
+        let json = Cli::try_parse_from(["cnut", "cache", "list", "--json"]).unwrap();
+        let text = Cli::try_parse_from(["cnut", "cache", "list"]).unwrap();
+
+        assert!(matches!(
+            json.command,
+            Command::Cache {
+                command: CacheCommand::List { json: true }
+            }
+        ));
+        assert!(matches!(
+            text.command,
+            Command::Cache {
+                command: CacheCommand::List { json: false }
+            }
+        ));
+    }
+}
@@ -0,0 +1,113 @@
+// A stand-in for htmx 1.9.10, the version the page was written for, until
+// `vendor.sh` replaces this file with the real build. It implements the
+// subset of htmx used by the web app: `hx-get`, `hx-post`, `hx-target`
+// (`this` or a CSS selector), `hx-trigger` (`load`, `click` and `every <n>s`,
+// comma-separated), `hx-vals` (a JSON object, sent as a form body, or in the
+// query of a `hx-get`) and `hx-disabled-elt` (`this`). A successful response
+// replaces the content of the target. While the request is in flight, the
+// element has the `htmx-request` class, which shows its `.htmx-indicator`
+// children.
+(function () {
+    "use strict";
+
+    function target(element) {
+        const selector = element.getAttribute("hx-target");
+        if (!selector || selector === "this") {
+            return element;
+        }
+        return document.querySelector(selector);
+    }
+
+    async function request(element, method, url) {
+        const disable = element.getAttribute("hx-disabled-elt") === "this";
+        if (element.classList.contains("htmx-request")) {
+            return;
+        }
+        element.classList.add("htmx-request");
+        if (disable) {
+            element.disabled = true;
+        }
+        try {
+            const headers = { "HX-Request": "true" };
+            const vals = element.getAttribute("hx-vals");
+            let body;
+            if (vals) {
+                const params = new URLSearchParams(JSON.parse(vals)).toString();
+                if (method === "GET") {
+                    url += (url.includes("?") ? "&" : "?") + params;
+                } else {
+                    headers["Content-Type"] = "application/x-www-form-urlencoded";
+                    body = params;
+                }
+            }
+            const response = await fetch(url, {
+                method: method,
+                headers: headers,
+                body: body,
+            });
+            const text = await response.text();
+            if (!response.ok) {
+                console.warn("Request to " + url + " failed: " + text);
+                return;
+            }
+            const swapped = target(element);
+            if (swapped) {
+                swapped.innerHTML = text;
+                process(swapped);
+            }
+        } catch (error) {
+            console.warn("Request to " + url + " failed", error);
+        } finally {
+            element.classList.remove("htmx-request");
+            if (disable) {
+                element.disabled = false;
+            }
+        }
+    }
+
+    function setUp(element) {
+        if (element.hxProcessed) {
+            return;
+        }
+        element.hxProcessed = true;
+
+        const method = element.hasAttribute("hx-post") ? "POST" : "GET";
+        const url = element.getAttribute("hx-post") || element.getAttribute("hx-get");
+        const defaultTrigger = element.tagName === "BUTTON" || element.tagName === "A" ? "click" : "load";
+        const triggers = (element.getAttribute("hx-trigger") || defaultTrigger)
+            .split(",")
+            .map((trigger) => trigger.trim());
+        const send = () => request(element, method, url);
+
+        for (const trigger of triggers) {
+            const every = /^every\s+(\d+(?:\.\d+)?)(ms|s)$/.exec(trigger);
+
+            if (trigger === "load") {
+                send();
+            } else if (every) {
+                const delay = parseFloat(every[1]) * (every[2] === "s" ? 1000 : 1);
+                const interval = setInterval(() => {
+                    if (!document.body.contains(element)) {
+                        clearInterval(interval);
+                        return;
+                    }
+                    send();
+                }, delay);
+            } else {
+                element.addEventListener(trigger, (event) => {
+                    event.preventDefault();
+                    send();
+                });
+            }
+        }
+    }
+
+    function process(root) {
+        if (root.matches && root.matches("[hx-get], [hx-post]")) {
+            setUp(root);
+        }
+        root.querySelectorAll("[hx-get], [hx-post]").forEach(setUp);
+    }
+
+    document.addEventListener("DOMContentLoaded", () => process(document.body));
+})();
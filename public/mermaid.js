@@ -0,0 +1,3 @@
+// A stand-in for mermaid 10.9.1, until `vendor.sh` replaces this file with
+// the real build: it defines nothing, so the topology page shows the source of
+// the diagram instead of rendering it.
@@ -0,0 +1,14 @@
+#!/bin/sh
+# Fetches the pinned builds of the JavaScript libraries the web app serves, so
+# that it works without internet access. The files are written next to this
+# script, and embedded in the crate with `include_bytes!`.
+set -eu
+
+cd "$(dirname "$0")"
+
+fetch() {
+    curl --fail --silent --show-error --location --output "$2" "$1"
+}
+
+fetch https://unpkg.com/htmx.org@1.9.10/dist/htmx.min.js hx.js
+fetch https://unpkg.com/mermaid@10.9.1/dist/mermaid.min.js mermaid.js
@@ -2,24 +2,65 @@
 //! WASM contracts and configuration files needed for it to run. See
 //! [`Artifacts`] for more detail.
 
+mod bundle;
+
 use crate::{
     error::{Error, ProcessError, Result},
-    util::{spawn_process, ProcessOutputExt as _, Spinner},
+    util::{
+        self, spawn_network_process, spawn_process, FileLock, OutputMode, ProcessOutputExt as _,
+        Spinner,
+    },
 };
+use blake2::{digest::consts::U8, Blake2b, Digest as _};
+use hex_fmt::HexFmt;
 use std::{
+    collections::BTreeMap,
     ffi::{OsStr, OsString},
+    fmt,
+    hash::{Hash, Hasher},
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 use tokio::fs;
 
+/// The file name of the client binary in the artifacts.
+const CLIENT_BINARY: &str = "casper-client";
+/// The version of the client installed from crates.io.
+const CLIENT_VERSION: &str = "2.0.0";
+/// The file locked during a build, in the `target` directory of the project.
+const BUILD_LOCK: &str = "cnut.lock";
+/// The file written after a build, in the `target` directory of the project.
+/// Its modification time tells when the last build finished.
+const BUILD_STAMP: &str = "cnut.built";
+
 /// The following files are included in the `Artifacts` path:
 ///
 /// - The Casper node binary;
 /// - Client smart contracts;
 /// - Chainspec template `chainspec.toml`
 /// - Node config example `config.toml`.
+///
+/// The artifacts built from a local project are stored in its
+/// `target/cnut` directory (see [`Artifacts::cache_dir`]), so another
+/// process can use them without building them again:
+///
+/// ```no_run
+/// # use cnut::artifacts::Artifacts;
+/// let artifacts = Artifacts::from_path(Artifacts::cache_dir("../casper-node"));
+/// ```
+///
+/// Some files can be taken from elsewhere with [`Artifacts::with_override`].
+///
+/// Two artifacts are equal if they point to the same directory, and to the
+/// same overrides. The paths are canonicalized when they exist, but the
+/// content of the files is not compared.
 #[derive(Debug, Clone)]
-pub struct Artifacts(pub(crate) PathBuf);
+pub struct Artifacts {
+    /// The directory the files are in.
+    dir: PathBuf,
+    /// The files taken from elsewhere, by file name.
+    overrides: BTreeMap<String, PathBuf>,
+}
 
 /// Allows to build [`Artifacts`].
 #[derive(Debug)]
@@ -28,6 +69,41 @@ pub struct ArtifactsBuilder {
     location: Location,
     /// Tells if the binary will be (re)complied or not. The default depends on the location.
     pub compile: Option<bool>,
+    /// How the build progress is shown.
+    output_mode: OutputMode,
+    /// Weither the `casper-client` binary is added to the artifacts.
+    with_client: bool,
+    /// See [`ArtifactsBuilder::cargo_target_dir`].
+    cargo_target_dir: Option<PathBuf>,
+    /// See [`ArtifactsBuilder::offline`].
+    offline: bool,
+    /// See [`ArtifactsBuilder::assume_toolchains`].
+    assume_toolchains: bool,
+}
+
+/// How cargo is run to build a project.
+#[derive(Debug)]
+struct CargoSettings {
+    /// The directory the outputs are written to.
+    target_dir: PathBuf,
+    /// Weither cargo must not access the network.
+    offline: bool,
+    /// Weither the toolchains are expected to be installed already.
+    assume_toolchains: bool,
+}
+
+impl PartialEq for Artifacts {
+    fn eq(&self, other: &Self) -> bool {
+        self.identity() == other.identity()
+    }
+}
+
+impl Eq for Artifacts {}
+
+impl Hash for Artifacts {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.identity().hash(state);
+    }
 }
 
 #[derive(Debug)]
@@ -37,14 +113,78 @@ enum Location {
     /// We'll download the codebase.
     Remote {
         url: Option<String>,
-        reference: TagOrHash,
+        reference: GitReference,
     },
 }
 
 #[derive(Debug)]
-enum TagOrHash {
+enum GitReference {
     Tag(String),
     Hash(String),
+    /// The tip of the branch, which moves, so it is never cached.
+    Branch(String),
+}
+
+impl GitReference {
+    /// Returns the name of the directory the sources are checked out in,
+    /// unique for the repository at `url` and this reference.
+    fn dir_name(&self, url: &str) -> String {
+        let (kind, name) = match self {
+            Self::Tag(tag) => ("tag", tag),
+            Self::Hash(hash) => ("hash", hash),
+            Self::Branch(branch) => ("branch", branch),
+        };
+        let repository = HexFmt(Blake2b::<U8>::digest(url.as_bytes())).to_string();
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        format!("{repository}-{kind}-{name}")
+    }
+
+    /// Returns `true` if the reference can point to another commit later, so
+    /// that its artifacts are never reused.
+    fn moves(&self) -> bool {
+        matches!(self, Self::Branch(_))
+    }
+
+    /// Returns the refspec fetching the reference, or `None` to fetch the
+    /// default branches, which hold the commit.
+    fn refspec(&self) -> Option<String> {
+        match self {
+            Self::Tag(tag) => Some(format!("+refs/tags/{tag}:refs/tags/{tag}")),
+            Self::Hash(_) => None,
+            Self::Branch(branch) => {
+                Some(format!("+refs/heads/{branch}:refs/remotes/origin/{branch}"))
+            }
+        }
+    }
+
+    /// Returns the revision checked out once the reference is fetched.
+    fn revision(&self) -> String {
+        match self {
+            Self::Tag(tag) => format!("refs/tags/{tag}"),
+            Self::Hash(hash) => hash.clone(),
+            Self::Branch(branch) => format!("refs/remotes/origin/{branch}"),
+        }
+    }
+}
+
+impl fmt::Display for GitReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tag(tag) => write!(f, "the tag {tag}"),
+            Self::Hash(hash) => write!(f, "the commit {hash}"),
+            Self::Branch(branch) => write!(f, "the branch {branch}"),
+        }
+    }
 }
 
 impl Artifacts {
@@ -53,6 +193,11 @@ impl Artifacts {
         ArtifactsBuilder {
             location: Location::Local { project_dir: None },
             compile: None,
+            output_mode: OutputMode::default(),
+            with_client: false,
+            cargo_target_dir: None,
+            offline: false,
+            assume_toolchains: false,
         }
     }
 
@@ -61,17 +206,91 @@ impl Artifacts {
     /// - Node binary;
     /// - Client smart contracts.
     pub fn from_path<P: Into<PathBuf>>(path: P) -> Self {
-        Self(path.into())
+        Self {
+            dir: path.into(),
+            overrides: BTreeMap::new(),
+        }
+    }
+
+    /// Takes the file `file_name` from `path` instead of the artifacts
+    /// directory, for example to try a chainspec template without building the
+    /// node again:
+    ///
+    /// ```no_run
+    /// # use cnut::artifacts::Artifacts;
+    /// let artifacts = Artifacts::from_path(Artifacts::cache_dir("../casper-node"))
+    ///     .with_override("chainspec.toml", "my-chainspec.toml")
+    ///     .with_override("casper-node", "../other-node/target/release/casper-node");
+    /// ```
+    ///
+    /// Any file can be overridden, such as the node binary, the config or a
+    /// WASM contract, and a file not in the directory is added. When the
+    /// network is prepared, the files are gathered in a directory of the run
+    /// directory, so that the nodes see them as a single folder.
+    pub fn with_override(mut self, file_name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.overrides.insert(file_name.into(), path.into());
+        self
+    }
+
+    /// Returns the directory the artifacts are located in. The overridden
+    /// files are not in it, see [`Artifacts::file_path`].
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the path of the file `file_name`, which is its override if it
+    /// has one.
+    pub fn file_path(&self, file_name: &str) -> PathBuf {
+        match self.overrides.get(file_name) {
+            Some(path) => path.clone(),
+            None => self.dir.join(file_name),
+        }
+    }
+
+    /// Returns the files taken from elsewhere, by file name.
+    pub(crate) fn overrides(&self) -> &BTreeMap<String, PathBuf> {
+        &self.overrides
+    }
+
+    /// Returns the canonicalized paths the artifacts are compared with.
+    fn identity(&self) -> (PathBuf, BTreeMap<&str, PathBuf>) {
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_owned());
+
+        (
+            canonical(&self.dir),
+            self.overrides
+                .iter()
+                .map(|(file_name, path)| (file_name.as_str(), canonical(path)))
+                .collect(),
+        )
+    }
+
+    /// Returns the directory the artifacts built from the local project at
+    /// `project_dir` are copied to.
+    pub fn cache_dir(project_dir: impl AsRef<Path>) -> PathBuf {
+        project_dir.as_ref().join("target").join(crate::PROJECT_DIR)
+    }
+
+    /// Returns the path of the `casper-client` binary, or an error if it is not
+    /// in the artifacts. See [`ArtifactsBuilder::with_client`].
+    pub fn client_path(&self) -> Result<PathBuf> {
+        let path = self.file_path(CLIENT_BINARY);
+
+        if path.is_file() {
+            Ok(path)
+        } else {
+            Err(Error::ClientBinaryMissing(path))
+        }
     }
 
     /// Returns the chainspec path from these artifacts.
     pub fn chainspec_path(&self) -> PathBuf {
-        self.0.join("chainspec.toml")
+        self.file_path("chainspec.toml")
     }
 
     /// Returns the config path from these artifacts.
     pub fn config_path(&self) -> PathBuf {
-        self.0.join("config.toml")
+        self.file_path("config.toml")
     }
 }
 
@@ -84,35 +303,107 @@ impl ArtifactsBuilder {
     /// - Not cached (artifacts are stored in the project directly);
     ///
     /// Remote:
+    /// - Cloned in the default system cache location, then the reference is
+    ///   fetched and checked out with `git`;
     /// - Not compiled by default, in the sense that it tries and look in the cache first;
-    /// - Cached in the default system location;
+    /// - Cached in the default system location, except for a branch, whose head
+    ///   moves: it is fetched and compiled again at every build;
+    ///
+    /// The processes building the same project wait for each other: a build
+    /// finished while waiting is used instead of compiling again.
     pub async fn build(self) -> Result<Artifacts> {
-        let Self { location, compile } = self;
+        let Self {
+            location,
+            compile,
+            output_mode,
+            with_client,
+            cargo_target_dir,
+            offline,
+            assume_toolchains,
+        } = self;
 
         let artifacts = match location {
             Location::Local { project_dir } => {
-                let project_dir = project_dir
-                    .unwrap_or_else(|| PathBuf::from("../casper-node"))
-                    .canonicalize()
-                    .map_err(Error::FailedToCanonicalizePath)?;
-                let dest = project_dir.join("target/").join(crate::PROJECT_DIR);
-
-                if compile.unwrap_or(true) {
-                    run_compilation(&project_dir).await?;
-                    // Let's copy everything to a canonical place:
-                    copy_project_output_to(&project_dir, &dest).await?;
+                let project_dir = project_dir.unwrap_or_else(|| PathBuf::from("../casper-node"));
+                let project_dir = project_dir.canonicalize().map_err(|io_err| {
+                    Error::FailedToCanonicalizePath {
+                        path: project_dir,
+                        io_err,
+                    }
+                })?;
+                let dest = Artifacts::cache_dir(&project_dir);
+                let target_dir = project_dir.join("target");
+                let cargo = CargoSettings {
+                    target_dir: effective_target_dir(
+                        &project_dir,
+                        cargo_target_dir,
+                        std::env::var_os("CARGO_TARGET_DIR"),
+                    ),
+                    offline,
+                    assume_toolchains,
+                };
+
+                // Another process may be building the same project:
+                let (_lock, compiled) = compile_once(
+                    &target_dir.join(BUILD_LOCK),
+                    &target_dir.join(BUILD_STAMP),
+                    || compile.unwrap_or(true),
+                    async {
+                        run_compilation(&project_dir, &cargo, output_mode).await?;
+                        // Let's copy everything to a canonical place:
+                        copy_project_output_to(&project_dir, &cargo.target_dir, &dest, output_mode)
+                            .await
+                    },
+                )
+                .await?;
+                if with_client && (compiled || !dest.join(CLIENT_BINARY).is_file()) {
+                    install_client(&project_dir, &cargo, &dest, output_mode).await?;
                 }
 
-                Artifacts(dest)
+                Artifacts::from_path(dest)
             }
             Location::Remote { url, reference } => {
-                let _ = (url, reference);
-                //let url = url.as_deref().unwrap_or(NODE_GIT_URL);
-                //let repo = match git2::Repository::clone(url, "/path/to/a/repo") {
-                //    Ok(repo) => repo,
-                //    Err(e) => panic!("failed to clone: {}", e),
-                //};
-                todo!("No remote download for now")
+                let url = url.unwrap_or_else(|| crate::NODE_GIT_URL.to_owned());
+                let sources_dir = util::cache()?.join("sources");
+                let dir_name = reference.dir_name(&url);
+                let project_dir = sources_dir.join(&dir_name);
+                let dest = Artifacts::cache_dir(&project_dir);
+                let cargo = CargoSettings {
+                    target_dir: effective_target_dir(
+                        &project_dir,
+                        cargo_target_dir,
+                        std::env::var_os("CARGO_TARGET_DIR"),
+                    ),
+                    offline,
+                    assume_toolchains,
+                };
+
+                // Another process may be building the same sources, or the
+                // same head of a branch:
+                let (_lock, compiled) = compile_once(
+                    &sources_dir.join(format!("{dir_name}.lock")),
+                    &project_dir.join("target").join(BUILD_STAMP),
+                    || {
+                        let cached = !reference.moves() && dest.join("casper-node").is_file();
+                        compile.unwrap_or(!cached)
+                    },
+                    async {
+                        checkout(&url, &reference, &project_dir, offline, output_mode).await?;
+                        run_compilation(&project_dir, &cargo, output_mode).await?;
+                        copy_project_output_to(&project_dir, &cargo.target_dir, &dest, output_mode)
+                            .await
+                    },
+                )
+                .await?;
+
+                if !compiled {
+                    log::info!("Using the artifacts of {url} at {reference} cached in {dest:?}");
+                }
+                if with_client && (compiled || !dest.join(CLIENT_BINARY).is_file()) {
+                    install_client(&project_dir, &cargo, &dest, output_mode).await?;
+                }
+
+                Artifacts::from_path(dest)
             }
         };
 
@@ -127,6 +418,50 @@ impl ArtifactsBuilder {
         }
     }
 
+    /// Whether the `casper-client` binary is built and added to the artifacts,
+    /// `false` by default. Its version 2.0.0 is installed from crates.io, and
+    /// built again whenever the node is.
+    pub fn with_client(self, with_client: bool) -> Self {
+        Self {
+            with_client,
+            ..self
+        }
+    }
+
+    /// Sets the directory cargo writes the build outputs to, relative to the
+    /// project if it is relative. By default, the `CARGO_TARGET_DIR`
+    /// environment variable is used if it is set, and the `target` directory
+    /// of the project otherwise.
+    pub fn cargo_target_dir(self, path: impl Into<PathBuf>) -> Self {
+        Self {
+            cargo_target_dir: Some(path.into()),
+            ..self
+        }
+    }
+
+    /// Weither cargo is run with `--offline`, for the environments where the
+    /// dependencies are vendored, `false` by default.
+    pub fn offline(self, offline: bool) -> Self {
+        Self { offline, ..self }
+    }
+
+    /// Weither the pinned toolchains and the WASM target are expected to be
+    /// installed already, so that `rustup` is not run, `false` by default.
+    pub fn assume_toolchains(self, assume_toolchains: bool) -> Self {
+        Self {
+            assume_toolchains,
+            ..self
+        }
+    }
+
+    /// Sets how the build progress is shown. Interactive by default.
+    pub fn output_mode(self, output_mode: OutputMode) -> Self {
+        Self {
+            output_mode,
+            ..self
+        }
+    }
+
     /// Specifies a local path to use the binary from.
     pub fn local_path(self, path: impl Into<PathBuf>) -> Self {
         Self {
@@ -142,7 +477,7 @@ impl ArtifactsBuilder {
         Self {
             location: Location::Remote {
                 url: None,
-                reference: TagOrHash::Hash(hash.to_owned()),
+                reference: GitReference::Hash(hash.to_owned()),
             },
             ..self
         }
@@ -153,7 +488,7 @@ impl ArtifactsBuilder {
         Self {
             location: Location::Remote {
                 url: None,
-                reference: TagOrHash::Tag(tag.to_owned()),
+                reference: GitReference::Tag(tag.to_owned()),
             },
             ..self
         }
@@ -168,7 +503,7 @@ impl ArtifactsBuilder {
         Self {
             location: Location::Remote {
                 url: Some(repo_url.to_owned()),
-                reference: TagOrHash::Hash(hash.to_owned()),
+                reference: GitReference::Hash(hash.to_owned()),
             },
             ..self
         }
@@ -183,17 +518,212 @@ impl ArtifactsBuilder {
         Self {
             location: Location::Remote {
                 url: Some(repo_url.to_owned()),
-                reference: TagOrHash::Tag(tag.to_owned()),
+                reference: GitReference::Tag(tag.to_owned()),
+            },
+            ..self
+        }
+    }
+
+    /// The binary will be downloaded from the official repository at the head
+    /// of the given branch, such as `dev`. Since the head moves, it is not
+    /// cached: the branch is fetched and compiled again at every build.
+    pub fn branch(self, branch: impl ToOwned<Owned = String>) -> Self {
+        Self {
+            location: Location::Remote {
+                url: None,
+                reference: GitReference::Branch(branch.to_owned()),
+            },
+            ..self
+        }
+    }
+
+    /// The binary will be downloaded from the given repository at the head of
+    /// the given branch. Like with [`ArtifactsBuilder::branch`], it is not
+    /// cached.
+    pub fn repo_branch(
+        self,
+        repo_url: impl ToOwned<Owned = String>,
+        branch: impl ToOwned<Owned = String>,
+    ) -> Self {
+        Self {
+            location: Location::Remote {
+                url: Some(repo_url.to_owned()),
+                reference: GitReference::Branch(branch.to_owned()),
             },
             ..self
         }
     }
 }
 
+/// Returns the directory cargo writes the outputs of the project to: the
+/// `explicit` one, or the one from the `CARGO_TARGET_DIR` environment variable,
+/// or the `target` directory of the project. A relative path is relative to
+/// the project.
+fn effective_target_dir(
+    project_dir: &Path,
+    explicit: Option<PathBuf>,
+    from_env: Option<OsString>,
+) -> PathBuf {
+    let target_dir = explicit
+        .or_else(|| from_env.filter(|dir| !dir.is_empty()).map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("target"));
+
+    project_dir.join(target_dir)
+}
+
+impl CargoSettings {
+    /// Returns the `cargo fetch` command with the `toolchain`.
+    fn fetch(&self, toolchain: &str) -> Vec<OsString> {
+        let mut command = vec![
+            OsString::from("cargo"),
+            OsString::from(format!("+{toolchain}")),
+            OsString::from("fetch"),
+        ];
+        if self.offline {
+            command.push(OsString::from("--offline"));
+        }
+
+        command
+    }
+
+    /// Returns the release `cargo build` command with the `toolchain`,
+    /// writing to the target directory.
+    fn build(&self, toolchain: &str) -> Vec<OsString> {
+        let mut command = vec![
+            OsString::from("cargo"),
+            OsString::from(format!("+{toolchain}")),
+            OsString::from("build"),
+            OsString::from("--release"),
+            OsString::from("--target-dir"),
+            OsString::from(&self.target_dir),
+        ];
+        if self.offline {
+            command.push(OsString::from("--offline"));
+        }
+
+        command
+    }
+}
+
+/// Clones the repository at `url` into `dir` unless it is already there,
+/// fetches the `reference`, and checks it out. Nothing is fetched when
+/// `offline`, so that a reference already fetched can be built again.
+async fn checkout(
+    url: &str,
+    reference: &GitReference,
+    dir: &Path,
+    offline: bool,
+    output_mode: OutputMode,
+) -> Result<()> {
+    let spinner = Spinner::create("Fetching the node sources", output_mode);
+
+    if !dir.join(".git").is_dir() {
+        let parent = dir
+            .parent()
+            .expect("the sources to be in the cache directory");
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("creating the sources directory {parent:?}"),
+                io_err,
+            })?;
+        log::info!("Cloning {url} into {dir:?}");
+        spawn_network_process(
+            parent,
+            [
+                OsStr::new("git"),
+                OsStr::new("clone"),
+                OsStr::new("--no-checkout"),
+                OsStr::new(url),
+                dir.as_os_str(),
+            ],
+        )
+        .await?
+        .status_ok_or(ProcessError::FailedToFetchSources)?;
+    }
+    if offline {
+        log::debug!("Not fetching {reference} from {url}, offline");
+    } else {
+        let mut fetch = vec![
+            "git".to_owned(),
+            "fetch".to_owned(),
+            "--force".to_owned(),
+            "origin".to_owned(),
+        ];
+        fetch.extend(reference.refspec());
+        spawn_network_process(dir, fetch)
+            .await?
+            .status_ok_or(ProcessError::FailedToFetchSources)?;
+    }
+    spawn_process(
+        dir,
+        [
+            "git".to_owned(),
+            "checkout".to_owned(),
+            "--force".to_owned(),
+            "--detach".to_owned(),
+            reference.revision(),
+        ],
+    )
+    .await?
+    .status_ok_or(ProcessError::FailedToFetchSources)?;
+
+    spinner.success();
+
+    Ok(())
+}
+
+/// Runs `build` while holding the lock at `lock_path`, if `should_compile`
+/// tells so once the lock is held. A build finished by another process while
+/// waiting for the lock, as told by the stamp at `stamp_path`, is used instead,
+/// so that the processes asking for the same artifacts compile them once.
+///
+/// Returns the lock, to be held until the artifacts are complete, and weither
+/// `build` was run.
+async fn compile_once(
+    lock_path: &Path,
+    stamp_path: &Path,
+    should_compile: impl FnOnce() -> bool,
+    build: impl std::future::Future<Output = Result<()>>,
+) -> Result<(FileLock, bool)> {
+    let requested_at = SystemTime::now();
+    let lock = FileLock::acquire(lock_path).await?;
+    let built_meanwhile = fs::metadata(stamp_path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|built_at| built_at >= requested_at);
+    if built_meanwhile {
+        log::info!("The artifacts were built by another process");
+    }
+    let compile = !built_meanwhile && should_compile();
+
+    if compile {
+        build.await?;
+        write_build_stamp(stamp_path).await?;
+    }
+
+    Ok((lock, compile))
+}
+
+/// Writes the file telling when the last build finished.
+async fn write_build_stamp(path: &Path) -> Result<()> {
+    let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+
+    fs::write(path, now)
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("writing the build stamp {path:?}"),
+            io_err,
+        })
+}
+
 /// Compiles the given project.
-async fn run_compilation(path: &Path) -> Result<()> {
-    //TODO use a logging crate
-    println!("Path is {:?}", path);
+async fn run_compilation(
+    path: &Path,
+    cargo: &CargoSettings,
+    output_mode: OutputMode,
+) -> Result<()> {
+    log::debug!("Compiling the project at {path:?}");
 
     // Read the pinned versions. We'll use them later:
     let pinned_nightly = {
@@ -236,70 +766,69 @@ async fn run_compilation(path: &Path) -> Result<()> {
     log::debug!("Pinned Stable: {pinned_stable}");
 
     // First, install the pinned toolchains, and the wasm target:
-    let spinner = Spinner::create("Installing Rust components");
+    if cargo.assume_toolchains {
+        log::debug!("Assuming the toolchains {pinned_stable} and {pinned_nightly} are installed");
+    } else {
+        let spinner = Spinner::create("Installing Rust components", output_mode);
 
-    spawn_process(
-        path,
-        [
-            "rustup",
-            "toolchain",
-            "install",
-            &pinned_stable,
-            &pinned_nightly,
-        ],
-    )
-    .await?
-    .status_ok_or(ProcessError::FailedToSetupRust)?;
-    spawn_process(
-        path,
-        [
-            "rustup",
-            "target",
-            "add",
-            "--toolchain",
-            &pinned_stable,
-            "wasm32-unknown-unknown",
-        ],
-    )
-    .await?
-    .status_ok_or(ProcessError::FailedToSetupRust)?;
-    spawn_process(
-        path,
-        [
-            "rustup",
-            "target",
-            "add",
-            "--toolchain",
-            &pinned_nightly,
-            "wasm32-unknown-unknown",
-        ],
-    )
-    .await?
-    .status_ok_or(ProcessError::FailedToSetupRust)?;
+        spawn_network_process(
+            path,
+            [
+                "rustup",
+                "toolchain",
+                "install",
+                &pinned_stable,
+                &pinned_nightly,
+            ],
+        )
+        .await?
+        .status_ok_or(ProcessError::FailedToSetupRust)?;
+        spawn_network_process(
+            path,
+            [
+                "rustup",
+                "target",
+                "add",
+                "--toolchain",
+                &pinned_stable,
+                "wasm32-unknown-unknown",
+            ],
+        )
+        .await?
+        .status_ok_or(ProcessError::FailedToSetupRust)?;
+        spawn_network_process(
+            path,
+            [
+                "rustup",
+                "target",
+                "add",
+                "--toolchain",
+                &pinned_nightly,
+                "wasm32-unknown-unknown",
+            ],
+        )
+        .await?
+        .status_ok_or(ProcessError::FailedToSetupRust)?;
 
-    spinner.success();
+        spinner.success();
+    }
 
-    // Then, build the node binary:
-    let spinner = Spinner::create("Building the node");
+    // Then, build the node binary, after fetching its dependencies:
+    let spinner = Spinner::create("Building the node", output_mode);
 
-    spawn_process(
-        path,
-        [
-            "cargo",
-            &format!("+{pinned_stable}"),
-            "build",
-            "--release",
-            "-p",
-            "casper-node",
-        ],
-    )
-    .await?
-    .status_ok_or(ProcessError::FailedToBuildNode)?;
+    spawn_network_process(path, cargo.fetch(&pinned_stable))
+        .await?
+        .status_ok_or(ProcessError::FailedToBuildNode)?;
+    let mut build_node = cargo.build(&pinned_stable);
+    build_node.extend([OsString::from("-p"), OsString::from("casper-node")]);
+    spawn_process(path, build_node)
+        .await?
+        .status_ok_or(ProcessError::FailedToBuildNode)?;
 
     spinner.success();
 
     // Then, build the client smart contracts:
-    let spinner = Spinner::create("Building the smart contracts");
+    let spinner = Spinner::create("Building the smart contracts", output_mode);
     let smart_contracts_path = path.join("smart_contracts/contracts/client");
     let params = {
         let mut dirs_reader = fs::read_dir(&smart_contracts_path)
@@ -310,12 +839,7 @@ async fn run_compilation(path: &Path) -> Result<()> {
                 ),
                 io_err,
             })?;
-        let mut results = vec![
-            OsString::from("cargo"),
-            OsString::from(&format!("+{pinned_nightly}")),
-            OsString::from("build"),
-            OsString::from("--release"),
-        ];
+        let mut results = cargo.build(&pinned_nightly);
 
         while let Some(file_name) = dirs_reader
             .next_entry()
@@ -333,6 +857,12 @@ async fn run_compilation(path: &Path) -> Result<()> {
         results
     };
 
+    spawn_network_process(
+        path.join("smart_contracts/contracts"),
+        cargo.fetch(&pinned_nightly),
+    )
+    .await?
+    .status_ok_or(ProcessError::FailedToBuildSmartContracts)?;
     spawn_process(path.join("smart_contracts/contracts"), params)
         .await?
         .status_ok_or(ProcessError::FailedToBuildSmartContracts)?;
@@ -342,13 +872,60 @@ async fn run_compilation(path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Installs the `casper-client` binary from crates.io, and copies it to `dest`.
+async fn install_client(
+    project_path: &Path,
+    cargo: &CargoSettings,
+    dest: &Path,
+    output_mode: OutputMode,
+) -> Result<()> {
+    let spinner = Spinner::create("Building the client", output_mode);
+    let root = project_path.join("target").join("cnut-client");
+    let mut command = vec![
+        OsStr::new("cargo"),
+        OsStr::new("install"),
+        OsStr::new("--locked"),
+        OsStr::new("--version"),
+        OsStr::new(CLIENT_VERSION),
+        OsStr::new("--root"),
+        root.as_os_str(),
+        OsStr::new(CLIENT_BINARY),
+    ];
+    if cargo.offline {
+        command.push(OsStr::new("--offline"));
+    }
+
+    spawn_network_process(project_path, command)
+        .await?
+        .status_ok_or(ProcessError::FailedToBuildClient)?;
+
+    let client_path = root.join("bin").join(CLIENT_BINARY);
+    fs::create_dir_all(dest)
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("creating the destination directory {dest:?}"),
+            io_err,
+        })?;
+    fs::copy(&client_path, dest.join(CLIENT_BINARY))
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("copying the client {client_path:?} to {dest:?}"),
+            io_err,
+        })?;
+    spinner.success();
+
+    Ok(())
+}
+
+/// Copies the files of the artifacts to `dest`, the compiled ones being taken
+/// from the `target_dir`.
 async fn copy_project_output_to(
-    project_path: impl AsRef<Path>,
-    dest: impl AsRef<Path>,
+    project_path: &Path,
+    target_dir: &Path,
+    dest: &Path,
+    output_mode: OutputMode,
 ) -> Result<()> {
-    let project_path = project_path.as_ref();
-    let dest = dest.as_ref();
-    let spinner = Spinner::create("Copying the files");
+    let spinner = Spinner::create("Copying the files", output_mode);
 
     // Create the destination:
 
@@ -361,7 +938,7 @@ async fn copy_project_output_to(
 
     // Copy the wasm contracts:
 
-    let contracts_source = project_path.join("target/wasm32-unknown-unknown/release/");
+    let contracts_source = target_dir.join("wasm32-unknown-unknown/release/");
     let mut dir_reader =
         fs::read_dir(&contracts_source)
             .await
@@ -391,7 +968,7 @@ async fn copy_project_output_to(
 
     // Copy the node binary:
 
-    let node_path = project_path.join("target/release/casper-node");
+    let node_path = target_dir.join("release/casper-node");
 
     fs::copy(&node_path, &dest.join(node_path.file_name().unwrap()))
         .await
@@ -424,3 +1001,299 @@ async fn copy_project_output_to(
 
     Ok(spinner.success())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        process::Command,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    /// Runs `git` with the `args` in `dir`, and panics if it fails.
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=cnut", "-c", "user.email=cnut@localhost"])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .expect("git to run");
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    /// Creates a repository whose `version` file is `1` at the tag `v1`, then
+    /// `2` at the head of `main`.
+    fn repository() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet", "--initial-branch", "main"]);
+        for version in ["1", "2"] {
+            std::fs::write(dir.path().join("version"), version).unwrap();
+            git(dir.path(), &["add", "version"]);
+            git(dir.path(), &["commit", "--quiet", "-m", version]);
+            if version == "1" {
+                git(dir.path(), &["tag", "v1"]);
+            }
+        }
+
+        dir
+    }
+
+    #[test]
+    fn dir_name_is_unique_and_sanitized() {
+        let tag = GitReference::Tag("v1.5/rc 1".to_owned());
+        let name = tag.dir_name("https://example.com/node.git");
+
+        assert!(name.ends_with("-tag-v1.5_rc_1"), "{name}");
+        assert_ne!(name, tag.dir_name("https://example.com/fork.git"));
+        assert_ne!(
+            name,
+            GitReference::Branch("v1.5/rc 1".to_owned()).dir_name("https://example.com/node.git")
+        );
+    }
+
+    #[tokio::test]
+    async fn checkout_tag_and_hash() {
+        let repository = repository();
+        let url = repository.path().to_str().unwrap();
+        let sources = tempfile::tempdir().unwrap();
+        let dir = sources.path().join("node");
+
+        let tag = GitReference::Tag("v1".to_owned());
+        checkout(url, &tag, &dir, false, OutputMode::Silent)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("version")).unwrap(), "1");
+
+        let head = Command::new("git")
+            .args(["rev-parse", "main"])
+            .current_dir(repository.path())
+            .output()
+            .unwrap();
+        let hash = GitReference::Hash(String::from_utf8(head.stdout).unwrap().trim().to_owned());
+        checkout(url, &hash, &dir, false, OutputMode::Silent)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("version")).unwrap(), "2");
+    }
+
+    #[tokio::test]
+    async fn checkout_branch_follows_its_head() {
+        let repository = repository();
+        let url = repository.path().to_str().unwrap();
+        let sources = tempfile::tempdir().unwrap();
+        let dir = sources.path().join("node");
+        let branch = GitReference::Branch("main".to_owned());
+        assert!(branch.moves());
+
+        checkout(url, &branch, &dir, false, OutputMode::Silent)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("version")).unwrap(), "2");
+
+        std::fs::write(repository.path().join("version"), "3").unwrap();
+        git(repository.path(), &["commit", "--quiet", "-am", "3"]);
+        checkout(url, &branch, &dir, false, OutputMode::Silent)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("version")).unwrap(), "3");
+    }
+
+    /// Builds the fake project in `dir` with [`compile_once`], counting the
+    /// compilations, each of which takes a while.
+    async fn fake_build(dir: &Path, compilations: &AtomicUsize) -> bool {
+        let target_dir = dir.join("target");
+        let (_lock, compiled) = compile_once(
+            &target_dir.join(BUILD_LOCK),
+            &target_dir.join(BUILD_STAMP),
+            || true,
+            async {
+                compilations.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok(())
+            },
+        )
+        .await
+        .unwrap();
+
+        compiled
+    }
+
+    #[tokio::test]
+    async fn concurrent_builds_compile_once() {
+        let project = tempfile::tempdir().unwrap();
+        let compilations = AtomicUsize::new(0);
+
+        let (first, second) = tokio::join!(
+            fake_build(project.path(), &compilations),
+            fake_build(project.path(), &compilations),
+        );
+
+        assert_eq!(compilations.load(Ordering::SeqCst), 1);
+        assert!(first ^ second);
+    }
+
+    #[tokio::test]
+    async fn a_later_build_compiles_again() {
+        let project = tempfile::tempdir().unwrap();
+        let compilations = AtomicUsize::new(0);
+
+        assert!(fake_build(project.path(), &compilations).await);
+        assert!(fake_build(project.path(), &compilations).await);
+        assert_eq!(compilations.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn checkout_unknown_tag_fails() {
+        let repository = repository();
+        let url = repository.path().to_str().unwrap();
+        let sources = tempfile::tempdir().unwrap();
+
+        let tag = GitReference::Tag("v9".to_owned());
+        let result = checkout(
+            url,
+            &tag,
+            &sources.path().join("node"),
+            false,
+            OutputMode::Silent,
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::ChildProcess(ProcessError::FailedToFetchSources(_)))
+        ));
+    }
+
+    /// Returns the `args` as a command.
+    fn command(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn the_cargo_commands_follow_the_settings() {
+        let cargo = CargoSettings {
+            target_dir: PathBuf::from("/cache/target"),
+            offline: false,
+            assume_toolchains: false,
+        };
+        assert_eq!(cargo.fetch("1.77"), command(&["cargo", "+1.77", "fetch"]));
+        assert_eq!(
+            cargo.build("1.77"),
+            command(&[
+                "cargo",
+                "+1.77",
+                "build",
+                "--release",
+                "--target-dir",
+                "/cache/target"
+            ])
+        );
+
+        let cargo = CargoSettings {
+            offline: true,
+            ..cargo
+        };
+        assert_eq!(
+            cargo.fetch("nightly"),
+            command(&["cargo", "+nightly", "fetch", "--offline"])
+        );
+        assert_eq!(
+            cargo.build("nightly"),
+            command(&[
+                "cargo",
+                "+nightly",
+                "build",
+                "--release",
+                "--target-dir",
+                "/cache/target",
+                "--offline"
+            ])
+        );
+    }
+
+    #[test]
+    fn the_target_dir_is_the_explicit_one_then_the_one_from_the_env() {
+        let project = Path::new("/sources/node");
+        let explicit = || Some(PathBuf::from("/explicit"));
+        let from_env = || Some(OsString::from("/from-env"));
+
+        assert_eq!(
+            effective_target_dir(project, explicit(), from_env()),
+            Path::new("/explicit")
+        );
+        assert_eq!(
+            effective_target_dir(project, None, from_env()),
+            Path::new("/from-env")
+        );
+        assert_eq!(
+            effective_target_dir(project, None, Some(OsString::new())),
+            Path::new("/sources/node/target")
+        );
+        assert_eq!(
+            effective_target_dir(project, None, None),
+            Path::new("/sources/node/target")
+        );
+        assert_eq!(
+            effective_target_dir(project, Some(PathBuf::from("out")), None),
+            Path::new("/sources/node/out")
+        );
+        assert_eq!(
+            effective_target_dir(project, None, Some(OsString::from("../shared"))),
+            Path::new("/sources/node/../shared")
+        );
+    }
+
+    #[tokio::test]
+    async fn the_outputs_are_copied_from_the_target_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("node");
+        let target_dir = dir.path().join("shared-target");
+        let dest = dir.path().join("artifacts");
+        let write = |path: PathBuf, content: &str| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write(target_dir.join("release/casper-node"), "node");
+        write(
+            target_dir.join("wasm32-unknown-unknown/release/transfer.wasm"),
+            "wasm",
+        );
+        write(
+            target_dir.join("wasm32-unknown-unknown/release/transfer.d"),
+            "deps",
+        );
+        write(project.join("target/release/casper-node"), "stale node");
+        write(project.join("resources/local/config.toml"), "config");
+        write(
+            project.join("resources/local/chainspec.toml.in"),
+            "chainspec",
+        );
+
+        copy_project_output_to(&project, &target_dir, &dest, OutputMode::Silent)
+            .await
+            .unwrap();
+
+        let mut files: Vec<_> = std::fs::read_dir(&dest)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let content = std::fs::read_to_string(&path).unwrap();
+                (
+                    path.file_name().unwrap().to_str().unwrap().to_owned(),
+                    content,
+                )
+            })
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                ("casper-node".to_owned(), "node".to_owned()),
+                ("chainspec.toml".to_owned(), "chainspec".to_owned()),
+                ("config.toml".to_owned(), "config".to_owned()),
+                ("transfer.wasm".to_owned(), "wasm".to_owned()),
+            ]
+        );
+    }
+}
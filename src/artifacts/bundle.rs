@@ -0,0 +1,484 @@
+//! Packs the artifacts into a single archive, so that they can be shared
+//! between machines instead of being built on each of them.
+//!
+//! A bundle is a `tar.gz` archive of the artifact files under
+//! [`ARTIFACTS_PREFIX`], with the [`BUNDLE_METADATA`] file under
+//! [`METADATA_PREFIX`], so that no artifact can be mistaken for it. The archive
+//! is handled by the `tar` command, so that the binaries are streamed rather
+//! than loaded in memory.
+
+use super::Artifacts;
+use crate::{
+    error::{Error, ProcessError, Result},
+    util::{self, spawn_process, ProcessOutputExt as _},
+};
+use blake2::{digest::consts::U32, Blake2b, Digest as _};
+use hex_fmt::HexFmt;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::{fs, io::AsyncReadExt as _};
+
+/// The directory of the archive holding the artifact files.
+const ARTIFACTS_PREFIX: &str = "artifacts";
+/// The directory of the archive holding the [`BUNDLE_METADATA`].
+const METADATA_PREFIX: &str = "cnut-bundle";
+/// The file describing the bundle, in [`METADATA_PREFIX`].
+const BUNDLE_METADATA: &str = "cnut-bundle.toml";
+
+/// What is known about the bundled artifacts.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleMetadata {
+    /// The checksum of the artifact files, see [`checksum`].
+    checksum: String,
+    /// The commit of the project the artifacts were built from, if known.
+    source_commit: Option<String>,
+    /// When the node binary was built.
+    build_date: String,
+    /// The output of `casper-node --version`, if it could be run.
+    node_version: Option<String>,
+}
+
+impl Artifacts {
+    /// Packs the artifacts into a bundle at `path`, for example
+    /// `artifacts.tar.gz`, with the commit they were built from, their build
+    /// date and the node version. See [`Artifacts::import_bundle`].
+    pub async fn export_bundle(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        // The archive content is found by changing directories:
+        let artifacts = fs::canonicalize(self.path()).await.map_err(|io_err| {
+            Error::FailedToCanonicalizePath {
+                path: self.path().to_owned(),
+                io_err,
+            }
+        })?;
+        let metadata = BundleMetadata {
+            checksum: checksum(&artifacts).await?,
+            source_commit: source_commit(&artifacts).await,
+            build_date: build_date(&artifacts).await,
+            node_version: node_version(&artifacts).await,
+        };
+        let staging = temp_dir()?;
+        let metadata_dir = staging.path().join(METADATA_PREFIX);
+
+        fs::create_dir(&metadata_dir)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("creating the directory {metadata_dir:?}"),
+                io_err,
+            })?;
+        fs::write(
+            metadata_dir.join(BUNDLE_METADATA),
+            toml::to_string_pretty(&metadata).expect("TOML serialization failed"),
+        )
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("writing the bundle metadata in {metadata_dir:?}"),
+            io_err,
+        })?;
+        link_dir(&artifacts, &staging.path().join(ARTIFACTS_PREFIX)).await?;
+
+        spawn_process(
+            ".",
+            [
+                "tar".as_ref(),
+                "-czf".as_ref(),
+                path.as_os_str(),
+                "-C".as_ref(),
+                staging.path().as_os_str(),
+                METADATA_PREFIX.as_ref(),
+                // Through the link, but keeping the links in the artifacts:
+                format!("{ARTIFACTS_PREFIX}/.").as_ref(),
+            ],
+        )
+        .await?
+        .status_ok_or(ProcessError::FailedToCreateBundle)?;
+        log::info!("Artifacts {artifacts:?} bundled into {path:?}");
+
+        Ok(())
+    }
+
+    /// Returns the artifacts imported with [`Artifacts::import_bundle`], in
+    /// the system cache directory.
+    pub async fn cached_bundles() -> Result<Vec<Artifacts>> {
+        let dir = bundles_dir()?;
+        let mut bundles = Vec::new();
+
+        if !dir.is_dir() {
+            return Ok(bundles);
+        }
+        let mut entries = fs::read_dir(&dir)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("reading the directory {dir:?}"),
+                io_err,
+            })?;
+        while let Some(entry) =
+            entries
+                .next_entry()
+                .await
+                .map_err(|io_err| Error::FileOperation {
+                    description: format!("reading the directory entry in {dir:?}"),
+                    io_err,
+                })?
+        {
+            // Skips the bundles being imported:
+            if !entry.file_name().to_string_lossy().starts_with('.') {
+                bundles.push(Artifacts::from_path(entry.path()));
+            }
+        }
+        bundles.sort_by(|a, b| a.path().cmp(b.path()));
+
+        Ok(bundles)
+    }
+
+    /// Removes the artifacts imported with [`Artifacts::import_bundle`] from
+    /// the system cache directory.
+    pub async fn clear_cached_bundles() -> Result<()> {
+        let dir = bundles_dir()?;
+
+        match fs::remove_dir_all(&dir).await {
+            Ok(()) => Ok(()),
+            Err(io_err) if io_err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(io_err) => Err(Error::FileOperation {
+                description: format!("removing the cached bundles {dir:?}"),
+                io_err,
+            }),
+        }
+    }
+
+    /// Unpacks the bundle at `path`, created with [`Artifacts::export_bundle`],
+    /// into the cache directory, and returns its artifacts. The bundle is
+    /// unpacked and checked aside first, so that nothing is written to the
+    /// cache if it is corrupted or truncated.
+    pub async fn import_bundle(path: impl AsRef<Path>) -> Result<Artifacts> {
+        import_bundle_into(path.as_ref(), &bundles_dir()?).await
+    }
+}
+
+/// See [`Artifacts::import_bundle`]. The bundle is unpacked into
+/// `bundles_dir`.
+async fn import_bundle_into(path: &Path, bundles_dir: &Path) -> Result<Artifacts> {
+    let invalid = |reason: String| Error::InvalidBundle {
+        path: path.to_owned(),
+        reason,
+    };
+    let staging = temp_dir()?;
+
+    let output = spawn_process(
+        ".",
+        [
+            "tar".as_ref(),
+            "-xzf".as_ref(),
+            path.as_os_str(),
+            "-C".as_ref(),
+            staging.path().as_os_str(),
+        ],
+    )
+    .await?;
+    if !output.status.success() {
+        return Err(invalid(output.stderr_string().trim().to_owned()));
+    }
+
+    let metadata = fs::read_to_string(staging.path().join(METADATA_PREFIX).join(BUNDLE_METADATA))
+        .await
+        .map_err(|_| invalid(format!("{BUNDLE_METADATA} is missing")))?;
+    let metadata: BundleMetadata = toml::from_str(&metadata)
+        .map_err(|e| invalid(format!("{BUNDLE_METADATA} is invalid: {e}")))?;
+    let unpacked = staging.path().join(ARTIFACTS_PREFIX);
+    if !unpacked.is_dir() {
+        return Err(invalid(format!("{ARTIFACTS_PREFIX} is missing")));
+    }
+
+    let checksum = checksum(&unpacked).await?;
+    if checksum != metadata.checksum {
+        return Err(invalid(format!(
+            "the checksum is {checksum}, {} was expected",
+            metadata.checksum
+        )));
+    }
+
+    let dest = bundles_dir.join(&checksum[..16]);
+    fs::create_dir_all(bundles_dir)
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("creating the directory {bundles_dir:?}"),
+            io_err,
+        })?;
+    // Another process may be importing the same bundle:
+    let _lock = util::FileLock::acquire(bundles_dir.with_extension("lock")).await?;
+    if !dest.is_dir() {
+        // Copied aside, then renamed, so that an interrupted copy is never
+        // taken for an imported bundle:
+        let partial = tempfile::Builder::new()
+            .prefix(".importing-")
+            .tempdir_in(bundles_dir)
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("creating a temporary directory in {bundles_dir:?}"),
+                io_err,
+            })?;
+        util::copy_dir(&unpacked, partial.path()).await?;
+        fs::rename(partial.path(), &dest)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("renaming {:?} to {dest:?}", partial.path()),
+                io_err,
+            })?;
+    }
+    log::info!(
+        "Bundle {path:?} imported into {dest:?} (commit {}, built {}, {})",
+        metadata.source_commit.as_deref().unwrap_or("unknown"),
+        metadata.build_date,
+        metadata
+            .node_version
+            .as_deref()
+            .unwrap_or("unknown version"),
+    );
+
+    Ok(Artifacts::from_path(dest))
+}
+
+/// Returns the directory the imported bundles are unpacked in.
+fn bundles_dir() -> Result<PathBuf> {
+    Ok(util::cache()?.join("bundles"))
+}
+
+fn temp_dir() -> Result<tempfile::TempDir> {
+    tempfile::tempdir().map_err(|io_err| Error::FileOperation {
+        description: "creating the temporary directory".to_owned(),
+        io_err,
+    })
+}
+
+/// Makes the directory `original` reachable at `link`, to archive it under
+/// another name.
+#[cfg(unix)]
+async fn link_dir(original: &Path, link: &Path) -> Result<()> {
+    fs::symlink(original, link)
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("linking {original:?} to {link:?}"),
+            io_err,
+        })
+}
+
+/// Makes the directory `original` reachable at `link`, to archive it under
+/// another name. Copied, since the links cannot be relied on on this
+/// platform.
+#[cfg(not(unix))]
+async fn link_dir(original: &Path, link: &Path) -> Result<()> {
+    util::copy_dir(original, link).await
+}
+
+/// Returns the hash of the files in `dir`, with their relative paths, in hex.
+/// The files are read by chunks.
+async fn checksum(dir: &Path) -> Result<String> {
+    let mut hasher = Blake2b::<U32>::new();
+    let mut files = Vec::new();
+    let mut to_read = vec![dir.to_owned()];
+
+    while let Some(current) = to_read.pop() {
+        let mut entries = fs::read_dir(&current)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("reading the directory {current:?}"),
+                io_err,
+            })?;
+
+        while let Some(entry) =
+            entries
+                .next_entry()
+                .await
+                .map_err(|io_err| Error::FileOperation {
+                    description: format!("reading the directory entry in {current:?}"),
+                    io_err,
+                })?
+        {
+            let path = entry.path();
+            if fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+                to_read.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+
+    let mut buffer = vec![0; 1 << 16];
+    for file in files {
+        let relative = file.strip_prefix(dir).expect("file in dir");
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+
+        let mut reader = fs::File::open(&file)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("opening the file {file:?}"),
+                io_err,
+            })?;
+        loop {
+            let read = reader
+                .read(&mut buffer)
+                .await
+                .map_err(|io_err| Error::FileOperation {
+                    description: format!("reading the file {file:?}"),
+                    io_err,
+                })?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+    }
+
+    Ok(HexFmt(hasher.finalize()).to_string())
+}
+
+/// Returns the commit of the git repository holding the artifacts, which is
+/// the project they were built from for the local builds.
+async fn source_commit(artifacts: &Path) -> Option<String> {
+    let output = spawn_process(artifacts, ["git", "rev-parse", "HEAD"])
+        .await
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| output.stdout_string().trim().to_owned())
+}
+
+/// Returns the modification date of the node binary.
+async fn build_date(artifacts: &Path) -> String {
+    let modified = fs::metadata(artifacts.join("casper-node"))
+        .await
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or_else(|_| SystemTime::now());
+
+    humantime::format_rfc3339_seconds(modified).to_string()
+}
+
+async fn node_version(artifacts: &Path) -> Option<String> {
+    let node = artifacts.join("casper-node");
+    let output = spawn_process(artifacts, [node.as_os_str(), "--version".as_ref()])
+        .await
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| output.stdout_string().trim().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::process::Command;
+
+    /// Runs `tar` with `args`, as the tests cannot tamper with an archive
+    /// otherwise.
+    fn tar(args: &[&std::ffi::OsStr]) {
+        let status = Command::new("tar").args(args).status().unwrap();
+        assert!(status.success());
+    }
+
+    /// Returns the content of `dir`, or nothing if it does not exist.
+    fn entries(dir: &Path) -> Vec<PathBuf> {
+        match std::fs::read_dir(dir) {
+            Ok(entries) => entries.map(|entry| entry.unwrap().path()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_bundle_is_imported_as_it_was_exported() {
+        let (_dir, artifacts) = testing::artifacts();
+        let work = tempfile::tempdir().unwrap();
+        let (bundle, cache) = (work.path().join("a.tar.gz"), work.path().join("bundles"));
+
+        artifacts.export_bundle(&bundle).await.unwrap();
+        let imported = import_bundle_into(&bundle, &cache).await.unwrap();
+
+        let expected = checksum(artifacts.path()).await.unwrap();
+        assert_eq!(checksum(imported.path()).await.unwrap(), expected);
+        assert_eq!(imported.path(), cache.join(&expected[..16]));
+        assert!(!imported.path().join(BUNDLE_METADATA).exists());
+        assert_eq!(entries(&cache), [imported.path().to_owned()]);
+
+        // The second import finds the first one:
+        let again = import_bundle_into(&bundle, &cache).await.unwrap();
+        assert_eq!(again.path(), imported.path());
+        assert_eq!(entries(&cache).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn the_metadata_is_apart_from_the_artifacts() {
+        let (dir, artifacts) = testing::artifacts();
+        // An artifact with the name of the metadata is kept as it is:
+        std::fs::write(dir.path().join(BUNDLE_METADATA), "not = \"metadata\"").unwrap();
+        let work = tempfile::tempdir().unwrap();
+        let (bundle, cache) = (work.path().join("a.tar.gz"), work.path().join("bundles"));
+
+        artifacts.export_bundle(&bundle).await.unwrap();
+        let imported = import_bundle_into(&bundle, &cache).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(imported.path().join(BUNDLE_METADATA)).unwrap(),
+            "not = \"metadata\""
+        );
+        assert_eq!(
+            checksum(imported.path()).await.unwrap(),
+            checksum(artifacts.path()).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn a_truncated_bundle_is_rejected() {
+        let (_dir, artifacts) = testing::artifacts();
+        let work = tempfile::tempdir().unwrap();
+        let (bundle, cache) = (work.path().join("a.tar.gz"), work.path().join("bundles"));
+        artifacts.export_bundle(&bundle).await.unwrap();
+
+        let content = std::fs::read(&bundle).unwrap();
+        std::fs::write(&bundle, &content[..content.len() / 2]).unwrap();
+        let result = import_bundle_into(&bundle, &cache).await;
+
+        assert!(matches!(result, Err(Error::InvalidBundle { .. })));
+        assert!(entries(&cache).is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_tampered_bundle_is_rejected() {
+        let (_dir, artifacts) = testing::artifacts();
+        let work = tempfile::tempdir().unwrap();
+        let (bundle, cache) = (work.path().join("a.tar.gz"), work.path().join("bundles"));
+        let unpacked = work.path().join("unpacked");
+        artifacts.export_bundle(&bundle).await.unwrap();
+
+        std::fs::create_dir(&unpacked).unwrap();
+        tar(&[
+            "-xzf".as_ref(),
+            bundle.as_os_str(),
+            "-C".as_ref(),
+            unpacked.as_os_str(),
+        ]);
+        std::fs::write(unpacked.join(ARTIFACTS_PREFIX).join("config.toml"), "").unwrap();
+        tar(&[
+            "-czf".as_ref(),
+            bundle.as_os_str(),
+            "-C".as_ref(),
+            unpacked.as_os_str(),
+            METADATA_PREFIX.as_ref(),
+            ARTIFACTS_PREFIX.as_ref(),
+        ]);
+        let result = import_bundle_into(&bundle, &cache).await;
+
+        assert!(matches!(
+            result,
+            Err(Error::InvalidBundle { reason, .. }) if reason.starts_with("the checksum is")
+        ));
+        assert!(entries(&cache).is_empty());
+    }
+}
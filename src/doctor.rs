@@ -0,0 +1,65 @@
+//! Checks that the tools needed to build and run a network are installed,
+//! to tell what is missing before a long build fails.
+
+use crate::util::{self, spawn_process, ProcessOutputExt as _};
+use serde::Serialize;
+
+/// The tools looked for: the command, and weither the network can be built
+/// and run without it.
+const TOOLS: [(&str, bool); 5] = [
+    ("cargo", false),
+    ("rustup", false),
+    ("git", false),
+    ("tar", true),
+    ("docker", true),
+];
+
+/// The result of a check. See [`diagnose`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    /// What is checked, for example `cargo`.
+    pub name: String,
+    /// Weither the check passed.
+    pub ok: bool,
+    /// Weither a failed check only disables some features, such as the
+    /// Docker backend.
+    pub optional: bool,
+    /// The version found, or why the check failed.
+    pub details: String,
+}
+
+/// Checks that the needed tools are installed, and that the cache directory
+/// can be found.
+pub async fn diagnose() -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (tool, optional) in TOOLS {
+        let (ok, details) = match spawn_process(".", [tool, "--version"]).await {
+            Ok(output) if output.status.success() => {
+                let version = output.stdout_string();
+                (true, version.lines().next().unwrap_or_default().to_owned())
+            }
+            Ok(output) => (false, output.stderr_string().trim().to_owned()),
+            Err(e) => (false, e.to_string()),
+        };
+        diagnostics.push(Diagnostic {
+            name: tool.to_owned(),
+            ok,
+            optional,
+            details,
+        });
+    }
+
+    let (ok, details) = match util::cache() {
+        Ok(path) => (true, path.to_string_lossy().into_owned()),
+        Err(e) => (false, e.to_string()),
+    };
+    diagnostics.push(Diagnostic {
+        name: "cache directory".to_owned(),
+        ok,
+        optional: false,
+        details,
+    });
+
+    diagnostics
+}
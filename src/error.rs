@@ -1,6 +1,9 @@
 //! Holds all the error-related code.
 
-use std::{fmt, io::Error as IoError, process::Output as ProcessOutput};
+use crate::util::ProcessOutputExt as _;
+use std::{
+    fmt, io::Error as IoError, net::SocketAddr, path::PathBuf, process::Output as ProcessOutput,
+};
 use thiserror::Error;
 
 /// Main result type for this library.
@@ -16,8 +19,14 @@ pub enum Error {
 
     /// The path `canonicalize`` function failed, meaning that a provided
     /// path was somehow invalid.
-    #[error("failed canonicalize the path: {0}")]
-    FailedToCanonicalizePath(IoError),
+    #[error("failed to canonicalize the path {path:?} because {io_err}")]
+    FailedToCanonicalizePath {
+        /// The invalid path.
+        path: PathBuf,
+        /// The underlying IO error.
+        #[source]
+        io_err: IoError,
+    },
 
     /// A child process could not be spawned.
     #[error("failed to spawn the process `{full_command}` because {io_err}")]
@@ -44,10 +53,25 @@ pub enum Error {
         io_err: IoError,
     },
 
-    /// A read TOML file was invalid.
+    /// A TOML content given directly, not read from a file, was invalid.
     #[error(transparent)]
     TomlParsing(#[from] toml::de::Error),
 
+    /// A read TOML file was invalid.
+    #[error("invalid TOML file {path:?}: {source}")]
+    TomlParse {
+        /// The path of the file.
+        path: PathBuf,
+        /// The parsing error.
+        #[source]
+        source: toml::de::Error,
+    },
+
+    /// Some of the config and chainspec files given to the network are not valid
+    /// TOML. Each of them is listed with its error.
+    #[error("invalid TOML files:{}", format_toml_errors(.0))]
+    InvalidTomlFiles(Vec<(PathBuf, toml::de::Error)>),
+
     /// Encoding the DER info failed.
     #[error(transparent)]
     DerEncoding(#[from] derp::Error),
@@ -57,8 +81,14 @@ pub enum Error {
     Ed25519(ed25519_dalek::pkcs8::spki::der::pem::Error),
 
     /// There were an error while starting the web server.
-    #[error("Failed to start the web server: {:?}", .0)]
-    StartingServerWeb(IoError),
+    #[error("failed to start the web server on {address} because {io_err}")]
+    StartingServerWeb {
+        /// The address the server binds to.
+        address: SocketAddr,
+        /// The underlying IO error.
+        #[source]
+        io_err: IoError,
+    },
 
     /// There is no node with this name.
     #[error("Node does not exist: {}", .0)]
@@ -67,6 +97,392 @@ pub enum Error {
     /// There is no node with this index.
     #[error("Node does not exist: {}", .0)]
     NodeIndexOutOfBounds(usize),
+
+    /// The same port is pinned by two nodes, or pinned by a node and already
+    /// given to another one. See [`Node::rpc_port`](crate::network::Node::rpc_port).
+    #[error("port {port} is assigned to both {first} and {second}")]
+    DuplicatePortAssignment {
+        /// The port.
+        port: u16,
+        /// The node the port was given to first.
+        first: String,
+        /// The node the port was pinned for then.
+        second: String,
+    },
+
+    /// The ports of a category go past the last port. See
+    /// [`PortScheme`](crate::network::PortScheme).
+    #[error("the {category} ports of {amount_nodes} nodes go past the last port")]
+    PortRangeOverflow {
+        /// The category of the ports.
+        category: &'static str,
+        /// The amount of nodes.
+        amount_nodes: usize,
+    },
+
+    /// The ports of the nodes are all the same. See
+    /// [`PortScheme::stride`](crate::network::PortScheme::stride).
+    #[error("the port stride must not be zero")]
+    ZeroPortStride,
+
+    /// The ports of two categories overlap. See
+    /// [`PortScheme`](crate::network::PortScheme).
+    #[error("the {first} and {second} ports of {amount_nodes} nodes overlap")]
+    PortRangesOverlap {
+        /// The category with the lowest ports.
+        first: &'static str,
+        /// The category with the ports overlapping the first one.
+        second: &'static str,
+        /// The amount of nodes.
+        amount_nodes: usize,
+    },
+
+    /// A pinned port cannot be bound, for example because it is used by
+    /// another program.
+    #[error("port {port} pinned by the node {node} is not available: {io_err}")]
+    PortUnavailable {
+        /// The port.
+        port: u16,
+        /// The node pinning the port.
+        node: String,
+        /// The error binding the port.
+        #[source]
+        io_err: IoError,
+    },
+
+    /// The operation needs the node to be stopped.
+    #[error("the node {0} is running")]
+    NodeRunning(String),
+
+    /// The chainspec of the node is a hard link to the one of the other nodes,
+    /// so it cannot be changed for this node only. See
+    /// [`NetworkBuilder::shared_genesis`](crate::network::NetworkBuilder::shared_genesis).
+    #[error("the chainspec of {0} is shared with the other nodes, build the network with `shared_genesis(false)` to patch it alone")]
+    SharedChainspec(String),
+
+    /// Fewer nodes than requested match the filter. See
+    /// [`RunningNetwork::random_nodes`](crate::network::RunningNetwork::random_nodes).
+    #[error("{requested} nodes requested, but only {matching} match the filter")]
+    NotEnoughMatchingNodes {
+        /// The amount of nodes requested.
+        requested: usize,
+        /// The amount of nodes matching the filter.
+        matching: usize,
+    },
+
+    /// A signal could not be sent to a node. See
+    /// [`RunningNode::send_signal`](crate::network::RunningNode::send_signal).
+    #[error("could not send {signal} to the node {name}: {reason}")]
+    SignalNotSent {
+        /// The name of the node.
+        name: String,
+        /// The signal.
+        signal: crate::network::Signal,
+        /// Why it was not sent.
+        reason: String,
+    },
+
+    /// No running node answered. See
+    /// [`RunningNetwork::any_healthy_node`](crate::network::RunningNetwork::any_healthy_node).
+    #[error("no node of the network is healthy")]
+    NoHealthyNode,
+
+    /// The node is already isolated. See
+    /// [`RunningNetwork::isolate_node`](crate::network::RunningNetwork::isolate_node).
+    #[error("The node {0} is already isolated")]
+    NodeAlreadyIsolated(String),
+
+    /// The node is not isolated. See
+    /// [`RunningNetwork::rejoin_node`](crate::network::RunningNetwork::rejoin_node).
+    #[error("The node {0} is not isolated")]
+    NodeNotIsolated(String),
+
+    /// The name of a node cannot be used as its directory name.
+    #[error("invalid node name {name:?}: {reason}")]
+    InvalidNodeName {
+        /// The name given to the node.
+        name: String,
+        /// Why it is invalid.
+        reason: &'static str,
+    },
+
+    /// The network is already shutting down, so the shutdown callback cannot
+    /// be registered. See
+    /// [`RunningNetwork::on_shutdown`](crate::network::RunningNetwork::on_shutdown).
+    #[error("The network is already shutting down")]
+    ShuttingDown,
+
+    /// There is already a node with this name in the network.
+    #[error("A node is already named {}", .0)]
+    DuplicateNodeName(String),
+
+    /// There is not enough free space for the network on the filesystem
+    /// hosting the run directory. See
+    /// [`NetworkBuilder::min_free_space`](crate::network::NetworkBuilder::min_free_space).
+    #[error("only {available} bytes are available in {path:?}, {required} are required")]
+    InsufficientDiskSpace {
+        /// The free space, in bytes.
+        available: u64,
+        /// The space required, in bytes.
+        required: u64,
+        /// The directory checked.
+        path: PathBuf,
+    },
+
+    /// The [`StakeDistribution::Custom`](crate::network::StakeDistribution::Custom)
+    /// stakes are not one per validator.
+    #[error("{stakes} stakes are given for {validators} validators")]
+    StakeDistributionMismatch {
+        /// The amount of stakes.
+        stakes: usize,
+        /// The amount of validators.
+        validators: usize,
+    },
+
+    /// The config of a node was rejected by one of its checks. See
+    /// [`Node::validate_config`](crate::network::Node::validate_config).
+    #[error("invalid config {path:?}: {reason}")]
+    InvalidNodeConfig {
+        /// The path the config was about to be written to.
+        path: PathBuf,
+        /// Why it was rejected.
+        reason: String,
+    },
+
+    /// Some validators were started after the genesis. See
+    /// [`NetworkBuilder::strict_genesis`](crate::network::NetworkBuilder::strict_genesis).
+    #[error("the validators {} were started after the genesis", .nodes.join(", "))]
+    MissedGenesis {
+        /// The names of the validators.
+        nodes: Vec<String>,
+    },
+
+    /// A validator has no stake at genesis.
+    #[error("the validator {0} has a zero bonded amount")]
+    ZeroBondedAmount(String),
+
+    /// The stake of a validator is above its balance.
+    #[error(
+        "the bonded amount {bonded_amount} of the validator {name} exceeds its balance {balance}"
+    )]
+    BondedAmountExceedsBalance {
+        /// The name of the node.
+        name: String,
+        /// The bonded amount, in motes.
+        bonded_amount: u128,
+        /// The balance, in motes.
+        balance: u128,
+    },
+
+    /// There are more validators than validator slots in the chainspec.
+    #[error("{validators} validators do not fit in the {slots} validator slots of the chainspec")]
+    TooManyValidators {
+        /// The amount of validators.
+        validators: usize,
+        /// The `core.validator_slots` of the chainspec.
+        slots: i64,
+    },
+
+    /// The sum of the stakes of the validators does not fit in 128 bits.
+    #[error("the total stake of the validators overflows 128 bits")]
+    TotalStakeOverflow,
+
+    /// A node was created without artifacts, and the network has no default
+    /// ones. See
+    /// [`NetworkBuilder::default_artifacts`](crate::network::NetworkBuilder::default_artifacts).
+    #[error("no artifacts for the node {name}, and no default ones")]
+    NoArtifactsForNode {
+        /// The name of the node, or of its group.
+        name: String,
+    },
+
+    /// The network has no chainspec, and no artifacts to take it from: it
+    /// neither has default artifacts nor a first node with artifacts.
+    #[error("no chainspec for the network, and no artifacts to take it from")]
+    NoChainspec,
+
+    /// An artifacts bundle could not be imported. See
+    /// [`Artifacts::import_bundle`](crate::artifacts::Artifacts::import_bundle).
+    #[error("invalid artifacts bundle {path:?}: {reason}")]
+    InvalidBundle {
+        /// The path of the bundle.
+        path: PathBuf,
+        /// What is wrong with it.
+        reason: String,
+    },
+
+    /// A value could not be set at a dotted TOML path, for example in
+    /// [`Node::config_from_env`](crate::network::Node::config_from_env).
+    #[error("cannot set `{path}` at the key `{key}`: {reason}")]
+    InvalidTomlPath {
+        /// The full path.
+        path: String,
+        /// The offending key of the path.
+        key: String,
+        /// Why the key cannot be followed.
+        reason: String,
+    },
+
+    /// A network definition file is invalid. See
+    /// [`NetworkBuilder::from_toml`](crate::network::NetworkBuilder::from_toml).
+    #[error("invalid network definition {path:?} at `{key}`: {message}")]
+    InvalidNetworkDefinition {
+        /// The path of the definition.
+        path: PathBuf,
+        /// The path of the offending key in the file, such as `nodes[0].count`.
+        key: String,
+        /// What is wrong with it.
+        message: String,
+    },
+
+    /// A network manifest could not be parsed. See
+    /// [`NetworkManifest::read`](crate::network::NetworkManifest::read).
+    #[error("invalid network manifest {path:?}: {json_err}")]
+    InvalidManifest {
+        /// The path of the manifest.
+        path: PathBuf,
+        /// The parsing error.
+        #[source]
+        json_err: serde_json::Error,
+    },
+
+    /// The node binary is absent from the artifacts, or is not executable.
+    #[error("the node binary {0:?} is missing or is not executable")]
+    NodeBinaryMissing(PathBuf),
+
+    /// The `casper-client` binary is absent from the artifacts. See
+    /// [`ArtifactsBuilder::with_client`](crate::artifacts::ArtifactsBuilder::with_client).
+    #[error("the client binary {0:?} is missing, the artifacts must be built with the client")]
+    ClientBinaryMissing(PathBuf),
+
+    /// A JSON-RPC request could not be sent to a node, or its response could
+    /// not be read.
+    #[error("failed to call the RPC method `{method}` because {source}")]
+    RpcRequest {
+        /// The called method.
+        method: String,
+        /// The underlying HTTP error.
+        #[source]
+        source: reqwest::Error,
+    },
+
+    /// An error with the operation it happened during, such as the node being
+    /// started. See [`Error::context`].
+    #[error("{context}: {source}")]
+    Context {
+        /// The operation that failed.
+        context: String,
+        /// The error.
+        #[source]
+        source: Box<Error>,
+    },
+
+    /// A node answered a JSON-RPC request with an error.
+    #[error("the RPC method `{method}` failed with the code {code}: {message}")]
+    RpcResponse {
+        /// The called method.
+        method: String,
+        /// The JSON-RPC error code.
+        code: i64,
+        /// The error message sent by the node.
+        message: String,
+    },
+
+    /// The node has no block yet, so its global state cannot be queried. See
+    /// [`RunningNode::query_state`](crate::network::RunningNode::query_state).
+    #[error("the node {0} has no state root hash yet")]
+    NoStateRootHash(String),
+
+    /// The chain does not know the validators of this era yet. See
+    /// [`RunningNetwork::era_validators`](crate::network::RunningNetwork::era_validators).
+    #[error("the validators of the era {era} are not known yet{}", latest.map(|latest| format!(", the latest known era is {latest}")).unwrap_or_default())]
+    EraValidatorsNotYetAvailable {
+        /// The requested era.
+        era: u64,
+        /// The latest era whose validators are known, if any.
+        latest: Option<u64>,
+    },
+
+    /// The chain does not tell the validators of this past era anymore. See
+    /// [`RunningNetwork::era_validators`](crate::network::RunningNetwork::era_validators).
+    #[error("the validators of the era {era} are not available anymore, the oldest known era is {oldest}")]
+    EraValidatorsUnavailable {
+        /// The requested era.
+        era: u64,
+        /// The oldest era whose validators are known.
+        oldest: u64,
+    },
+
+    /// The interval of the health reports is too short. See
+    /// [`RunningNetwork::enable_health_reports`](crate::network::RunningNetwork::enable_health_reports).
+    #[error("the health report interval {} is shorter than one second", humantime::format_duration(*.0))]
+    HealthReportIntervalTooShort(std::time::Duration),
+
+    /// The rate of a workload is not a positive amount of deploys per second.
+    /// See [`WorkloadConfig::rate`](crate::workload::WorkloadConfig::rate).
+    #[error("the workload rate {0} is not a positive amount of deploys per second")]
+    InvalidWorkloadRate(f64),
+
+    /// The transfers of a workload need another node than the sender. See
+    /// [`DeployTemplate::Transfer`](crate::workload::DeployTemplate::Transfer).
+    #[error("the transfers of a workload need at least 2 nodes")]
+    NotEnoughTransferTargets,
+
+    /// The nodes did not agree on their height in time. See
+    /// [`RunningNetwork::wait_for_all_synced`](crate::network::RunningNetwork::wait_for_all_synced).
+    #[error("the nodes are not synced after {}:{}", humantime::format_duration(*timeout), format_heights(heights))]
+    NodesNotSynced {
+        /// How long the nodes were waited for.
+        timeout: std::time::Duration,
+        /// The name of each node, with its height if it answered.
+        heights: Vec<(String, Option<u64>)>,
+    },
+
+    /// Some running nodes did not become ready in time. See
+    /// [`RunningNetwork::wait_until_ready`](crate::network::RunningNetwork::wait_until_ready).
+    #[error(
+        "nodes not ready after {}: {}",
+        humantime::format_duration(*.timeout),
+        .nodes.join(", ")
+    )]
+    NodesNotReady {
+        /// How long the nodes were waited for.
+        timeout: std::time::Duration,
+        /// The names of the nodes which are not ready.
+        nodes: Vec<String>,
+    },
+
+    /// The nodes of a phase did not meet the condition to start the next one
+    /// in time. See
+    /// [`RunningNetwork::start_phased`](crate::network::RunningNetwork::start_phased).
+    #[error(
+        "the phase {next_phase} is not started, nodes still waited for after {}: {}",
+        humantime::format_duration(*.timeout),
+        .nodes.join(", ")
+    )]
+    PhaseNotReached {
+        /// The phase which was to be started.
+        next_phase: u8,
+        /// How long the nodes were waited for.
+        timeout: std::time::Duration,
+        /// The names of the nodes which did not meet the condition.
+        nodes: Vec<String>,
+    },
+
+    /// The validators of an era are not the expected ones. See
+    /// [`RunningNetwork::assert_validators`](crate::network::RunningNetwork::assert_validators).
+    #[error(
+        "unexpected validators in the era {era}:{}",
+        format_validators_diff(missing, unexpected)
+    )]
+    UnexpectedValidators {
+        /// The era checked.
+        era: u64,
+        /// The expected nodes which are not validators.
+        missing: Vec<String>,
+        /// The validators which were not expected.
+        unexpected: Vec<String>,
+    },
 }
 
 /// Error used to show the error a child process returned.
@@ -82,6 +498,50 @@ pub enum ProcessError {
     /// The Casper client smart contracts failed to compile.
     #[error("failed to build the client smart contracts, exited with code {}", .0.status)]
     FailedToBuildSmartContracts(ProcessOutput),
+    /// The Casper client failed to install.
+    #[error("failed to build the Casper client, exited with code {}", .0.status)]
+    FailedToBuildClient(ProcessOutput),
+    /// The artifacts bundle could not be written.
+    #[error("failed to create the artifacts bundle, exited with code {}", .0.status)]
+    FailedToCreateBundle(ProcessOutput),
+    /// The node sources could not be cloned, fetched or checked out.
+    #[error("failed to fetch the node sources, exited with code {}", .0.status)]
+    FailedToFetchSources(ProcessOutput),
+}
+
+fn format_toml_errors(errors: &[(PathBuf, toml::de::Error)]) -> String {
+    errors
+        .iter()
+        .map(|(path, e)| format!("\n{path:?}: {e}"))
+        .collect()
+}
+
+fn format_heights(heights: &[(String, Option<u64>)]) -> String {
+    heights
+        .iter()
+        .map(|(name, height)| match height {
+            Some(height) => format!("\n{name}: {height}"),
+            None => format!("\n{name}: unreachable"),
+        })
+        .collect()
+}
+
+fn format_validators_diff(missing: &[String], unexpected: &[String]) -> String {
+    let missing = missing.iter().map(|name| format!("\n- {name}"));
+    let unexpected = unexpected.iter().map(|name| format!("\n+ {name}"));
+
+    missing.chain(unexpected).collect()
+}
+
+impl Error {
+    /// Wraps the error with the operation it happened during, for example
+    /// `starting the node Alice-1`.
+    pub fn context(self, context: impl Into<String>) -> Self {
+        Self::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
 }
 
 impl From<ed25519_dalek::pkcs8::spki::der::pem::Error> for Error {
@@ -94,9 +554,10 @@ impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::FailedToFindBaseDirectory => write!(f, "FailedToFindBaseDirectory"),
-            Self::FailedToCanonicalizePath(arg0) => f
-                .debug_tuple("FailedToCanonicalizePath")
-                .field(arg0)
+            Self::FailedToCanonicalizePath { path, io_err } => f
+                .debug_struct("FailedToCanonicalizePath")
+                .field("path", path)
+                .field("io_err", io_err)
                 .finish(),
             Self::FailedToSpawnProcess {
                 full_command,
@@ -116,47 +577,339 @@ impl fmt::Debug for Error {
                 .field("io_err", io_err)
                 .finish(),
             Self::TomlParsing(e) => write!(f, "TomlParsing({e:?})"),
+            Self::TomlParse { path, source } => f
+                .debug_struct("TomlParse")
+                .field("path", path)
+                .field("source", source)
+                .finish(),
+            Self::InvalidTomlFiles(errors) => write!(f, "InvalidTomlFiles({errors:?})"),
             Self::DerEncoding(e) => write!(f, "DerEncoding({e:?})"),
             Self::Ed25519(e) => write!(f, "Ed25519({e:?})"),
-            Self::StartingServerWeb(e) => write!(f, "StartingServerWeb({e:?})"),
+            Self::StartingServerWeb { address, io_err } => f
+                .debug_struct("StartingServerWeb")
+                .field("address", address)
+                .field("io_err", io_err)
+                .finish(),
             Self::NodeNameNotFound(name) => write!(f, "NodeNameNotFound({name})"),
             Self::NodeIndexOutOfBounds(index) => write!(f, "NodeIndexOutOfBounds({index})"),
+            Self::NodeRunning(name) => write!(f, "NodeRunning({name})"),
+            Self::SharedChainspec(name) => write!(f, "SharedChainspec({name})"),
+            Self::NotEnoughMatchingNodes {
+                requested,
+                matching,
+            } => f
+                .debug_struct("NotEnoughMatchingNodes")
+                .field("requested", requested)
+                .field("matching", matching)
+                .finish(),
+            Self::SignalNotSent {
+                name,
+                signal,
+                reason,
+            } => f
+                .debug_struct("SignalNotSent")
+                .field("name", name)
+                .field("signal", signal)
+                .field("reason", reason)
+                .finish(),
+            Self::NoHealthyNode => write!(f, "NoHealthyNode"),
+            Self::NodeAlreadyIsolated(name) => write!(f, "NodeAlreadyIsolated({name})"),
+            Self::NodeNotIsolated(name) => write!(f, "NodeNotIsolated({name})"),
+            Self::InvalidNodeName { name, reason } => f
+                .debug_struct("InvalidNodeName")
+                .field("name", name)
+                .field("reason", reason)
+                .finish(),
+            Self::ShuttingDown => write!(f, "ShuttingDown"),
+            Self::DuplicateNodeName(name) => write!(f, "DuplicateNodeName({name})"),
+            Self::DuplicatePortAssignment {
+                port,
+                first,
+                second,
+            } => f
+                .debug_struct("DuplicatePortAssignment")
+                .field("port", port)
+                .field("first", first)
+                .field("second", second)
+                .finish(),
+            Self::PortRangeOverflow {
+                category,
+                amount_nodes,
+            } => f
+                .debug_struct("PortRangeOverflow")
+                .field("category", category)
+                .field("amount_nodes", amount_nodes)
+                .finish(),
+            Self::ZeroPortStride => write!(f, "ZeroPortStride"),
+            Self::PortRangesOverlap {
+                first,
+                second,
+                amount_nodes,
+            } => f
+                .debug_struct("PortRangesOverlap")
+                .field("first", first)
+                .field("second", second)
+                .field("amount_nodes", amount_nodes)
+                .finish(),
+            Self::PortUnavailable { port, node, io_err } => f
+                .debug_struct("PortUnavailable")
+                .field("port", port)
+                .field("node", node)
+                .field("io_err", io_err)
+                .finish(),
+            Self::InsufficientDiskSpace {
+                available,
+                required,
+                path,
+            } => f
+                .debug_struct("InsufficientDiskSpace")
+                .field("available", available)
+                .field("required", required)
+                .field("path", path)
+                .finish(),
+            Self::InvalidNodeConfig { path, reason } => f
+                .debug_struct("InvalidNodeConfig")
+                .field("path", path)
+                .field("reason", reason)
+                .finish(),
+            Self::MissedGenesis { nodes } => f
+                .debug_struct("MissedGenesis")
+                .field("nodes", nodes)
+                .finish(),
+            Self::ZeroBondedAmount(name) => write!(f, "ZeroBondedAmount({name})"),
+            Self::BondedAmountExceedsBalance {
+                name,
+                bonded_amount,
+                balance,
+            } => f
+                .debug_struct("BondedAmountExceedsBalance")
+                .field("name", name)
+                .field("bonded_amount", bonded_amount)
+                .field("balance", balance)
+                .finish(),
+            Self::TooManyValidators { validators, slots } => f
+                .debug_struct("TooManyValidators")
+                .field("validators", validators)
+                .field("slots", slots)
+                .finish(),
+            Self::TotalStakeOverflow => write!(f, "TotalStakeOverflow"),
+            Self::StakeDistributionMismatch { stakes, validators } => f
+                .debug_struct("StakeDistributionMismatch")
+                .field("stakes", stakes)
+                .field("validators", validators)
+                .finish(),
+            Self::NoArtifactsForNode { name } => f
+                .debug_struct("NoArtifactsForNode")
+                .field("name", name)
+                .finish(),
+            Self::NoChainspec => write!(f, "NoChainspec"),
+            Self::InvalidBundle { path, reason } => f
+                .debug_struct("InvalidBundle")
+                .field("path", path)
+                .field("reason", reason)
+                .finish(),
+            Self::InvalidTomlPath { path, key, reason } => f
+                .debug_struct("InvalidTomlPath")
+                .field("path", path)
+                .field("key", key)
+                .field("reason", reason)
+                .finish(),
+            Self::InvalidNetworkDefinition { path, key, message } => f
+                .debug_struct("InvalidNetworkDefinition")
+                .field("path", path)
+                .field("key", key)
+                .field("message", message)
+                .finish(),
+            Self::InvalidManifest { path, json_err } => f
+                .debug_struct("InvalidManifest")
+                .field("path", path)
+                .field("json_err", json_err)
+                .finish(),
+            Self::NodeBinaryMissing(path) => write!(f, "NodeBinaryMissing({path:?})"),
+            Self::ClientBinaryMissing(path) => write!(f, "ClientBinaryMissing({path:?})"),
+            Self::RpcRequest { method, source } => f
+                .debug_struct("RpcRequest")
+                .field("method", method)
+                .field("source", source)
+                .finish(),
+            Self::Context { context, source } => f
+                .debug_struct("Context")
+                .field("context", context)
+                .field("source", source)
+                .finish(),
+            Self::RpcResponse {
+                method,
+                code,
+                message,
+            } => f
+                .debug_struct("RpcResponse")
+                .field("method", method)
+                .field("code", code)
+                .field("message", message)
+                .finish(),
+            Self::NoStateRootHash(name) => write!(f, "NoStateRootHash({name})"),
+            Self::EraValidatorsNotYetAvailable { era, latest } => f
+                .debug_struct("EraValidatorsNotYetAvailable")
+                .field("era", era)
+                .field("latest", latest)
+                .finish(),
+            Self::EraValidatorsUnavailable { era, oldest } => f
+                .debug_struct("EraValidatorsUnavailable")
+                .field("era", era)
+                .field("oldest", oldest)
+                .finish(),
+            Self::HealthReportIntervalTooShort(interval) => {
+                write!(f, "HealthReportIntervalTooShort({interval:?})")
+            }
+            Self::InvalidWorkloadRate(rate) => write!(f, "InvalidWorkloadRate({rate})"),
+            Self::NotEnoughTransferTargets => write!(f, "NotEnoughTransferTargets"),
+            Self::NodesNotSynced { timeout, heights } => f
+                .debug_struct("NodesNotSynced")
+                .field("timeout", timeout)
+                .field("heights", heights)
+                .finish(),
+            Self::NodesNotReady { timeout, nodes } => f
+                .debug_struct("NodesNotReady")
+                .field("timeout", timeout)
+                .field("nodes", nodes)
+                .finish(),
+            Self::PhaseNotReached {
+                next_phase,
+                timeout,
+                nodes,
+            } => f
+                .debug_struct("PhaseNotReached")
+                .field("next_phase", next_phase)
+                .field("timeout", timeout)
+                .field("nodes", nodes)
+                .finish(),
+            Self::UnexpectedValidators {
+                era,
+                missing,
+                unexpected,
+            } => f
+                .debug_struct("UnexpectedValidators")
+                .field("era", era)
+                .field("missing", missing)
+                .field("unexpected", unexpected)
+                .finish(),
         }
     }
 }
 
 impl fmt::Debug for ProcessError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::FailedToSetupRust(ProcessOutput {
-                status,
-                stdout: _,
-                stderr,
-            }) => write!(
-                f,
-                "FailedToSetupRust:\n\tStatus: {status:?}\n\tOutput:\n{}",
-                String::from_utf8_lossy(stderr)
-            ),
-
-            Self::FailedToBuildNode(ProcessOutput {
-                status,
-                stdout: _,
-                stderr,
-            }) => write!(
-                f,
-                "FailedToBuildNode:\n\tStatus: {status:?}\n\tOutput:\n{}",
-                String::from_utf8_lossy(stderr)
-            ),
-
-            Self::FailedToBuildSmartContracts(ProcessOutput {
-                status,
-                stdout: _,
-                stderr,
-            }) => write!(
-                f,
-                "FailedToBuildSmartContracts:\n\tStatus: {status:?}\n\tOutput:\n{}",
-                String::from_utf8_lossy(stderr)
-            ),
+        let (name, output) = match self {
+            Self::FailedToSetupRust(output) => ("FailedToSetupRust", output),
+            Self::FailedToBuildNode(output) => ("FailedToBuildNode", output),
+            Self::FailedToBuildSmartContracts(output) => ("FailedToBuildSmartContracts", output),
+            Self::FailedToBuildClient(output) => ("FailedToBuildClient", output),
+            Self::FailedToCreateBundle(output) => ("FailedToCreateBundle", output),
+            Self::FailedToFetchSources(output) => ("FailedToFetchSources", output),
+        };
+
+        write!(
+            f,
+            "{name}:\n\tStatus: {:?}\n\tOutput:\n{}",
+            output.status,
+            output.stderr_string()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{error::Error as _, io::ErrorKind};
+
+    /// Returns the IO error of a missing file.
+    fn io_err() -> IoError {
+        IoError::new(ErrorKind::NotFound, "no such file or directory")
+    }
+
+    #[test]
+    fn file_operation() {
+        let error = Error::FileOperation {
+            description: "reading the config \"/tmp/cnut/Alice/config.toml\"".to_owned(),
+            io_err: io_err(),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "failed doing the file operation: `reading the config \"/tmp/cnut/Alice/config.toml\"` \
+             because no such file or directory"
+        );
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn failed_to_canonicalize_path() {
+        let error = Error::FailedToCanonicalizePath {
+            path: PathBuf::from("../casper-node"),
+            io_err: io_err(),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "failed to canonicalize the path \"../casper-node\" because no such file or directory"
+        );
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn starting_server_web() {
+        let error = Error::StartingServerWeb {
+            address: ([127, 0, 0, 1], 8080).into(),
+            io_err: IoError::new(ErrorKind::AddrInUse, "address already in use"),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "failed to start the web server on 127.0.0.1:8080 because address already in use"
+        );
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn toml_parse() {
+        let source = "name = 1\nname = 2\n".parse::<toml::Table>().unwrap_err();
+        let error = Error::TomlParse {
+            path: PathBuf::from("chainspec.toml"),
+            source,
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "invalid TOML file \"chainspec.toml\": TOML parse error at line 2, column 1\n  \
+             |\n\
+             2 | name = 2\n  \
+             | ^\n\
+             duplicate key `name` in document root\n"
+        );
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn context_chain() {
+        let error = Error::FileOperation {
+            description: "copying the node binary".to_owned(),
+            io_err: io_err(),
+        }
+        .context("preparing the node Alice")
+        .context("preparing the network");
+
+        assert_eq!(
+            error.to_string(),
+            "preparing the network: preparing the node Alice: failed doing the file operation: \
+             `copying the node binary` because no such file or directory"
+        );
+        let mut chain = Vec::new();
+        let mut current: Option<&dyn std::error::Error> = Some(&error);
+        while let Some(error) = current {
+            chain.push(error.to_string());
+            current = error.source();
         }
+        assert_eq!(chain.len(), 4);
+        assert_eq!(chain[3], "no such file or directory");
     }
 }
@@ -8,17 +8,28 @@
 pub extern crate tokio;
 
 pub mod artifacts;
+pub mod doctor;
 pub mod error;
 pub mod network;
+pub mod rpc;
 pub(crate) mod web_app;
+pub mod workload;
 
+#[cfg(test)]
+mod testing;
 pub(crate) mod util;
 
+pub use util::{
+    crypto::{PublicKey, Signature},
+    OutputMode, ProcessOutputExt,
+};
+
 /// Allows to have what is needed to run a network with a single import.
 pub mod prelude {
     pub use crate::{
         artifacts::Artifacts,
-        network::{Chainspec, NetworkBuilder, Node},
+        network::{Chainspec, NetworkBuilder, Node, Preset},
+        OutputMode,
     };
     pub use toml::Value as TomlValue;
 }
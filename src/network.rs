@@ -10,43 +10,155 @@
 //! At this step, the type used is [`PreparedNetwork`].
 //! - Finally, the node can be run. A [`RunningNetwork`] is then returned.
 
+mod accounts;
+mod backend;
+mod config_diff;
+mod config_patch;
+mod consensus;
+mod definition;
 mod describe;
+mod era_hooks;
+mod fork;
+mod isolation;
+mod lifecycle;
+mod logs;
+mod manifest;
+mod monitor;
+mod ports;
 mod prepare;
+mod presets;
+mod propagation;
+mod reports;
 mod run;
+mod select;
+mod shutdown_hooks;
+mod signal;
+mod stakes;
+mod topology;
 
-pub use describe::{Chainspec, NetworkBuilder, Node};
+pub use accounts::{GenesisAccount, GenesisAccounts, GenesisDelegator, GenesisValidator};
+pub use backend::Backend;
+pub use config_diff::{ConfigDiff, ConfigDiffEntry};
+pub use config_patch::{NetworkSection, NodeConfigPatch, ServerSection, StorageSection};
+pub use consensus::EraSupervisorStatus;
+pub use describe::{Chainspec, ChainspecWithPreset, NetworkBuilder, Node, NodeConfig};
+pub use fork::{BlockAgreement, ForkCheck};
+pub use lifecycle::NetworkState;
+pub use logs::LogAlert;
+pub use manifest::{ManifestNode, NetworkManifest, NodeReport};
+pub use monitor::{HistoryEntry, NodeHealth};
+pub use ports::PortScheme;
+pub use presets::Preset;
+pub use propagation::DeployTrace;
+pub use run::{PhaseWait, RestartMode};
+pub use select::NodeFilter;
+pub use signal::Signal;
+pub use stakes::StakeDistribution;
+pub use topology::TopologyFormat;
 
-pub(crate) use describe::NodeConfig;
 pub(crate) use prepare::prepare_network;
 
-use crate::util::{
-    crypto::{PublicKey, SecretKey},
-    ShutdownState,
+use crate::{
+    util::{
+        crypto::{PublicKey, SecretKey, Signature},
+        OutputMode, RunDirectory, ShutdownState,
+    },
+    web_app::WebAuth,
 };
 use std::{
+    collections::{BTreeMap, VecDeque},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicU32, AtomicU64},
+        atomic::{AtomicBool, AtomicU32},
         Arc,
     },
+    time::{Duration, SystemTime},
 };
 use tokio::{
     process::Child,
-    sync::{Mutex, Notify},
+    sync::{broadcast, watch, Mutex, Notify},
+};
+use tokio_util::{
+    sync::{CancellationToken, DropGuard},
+    task::task_tracker::TaskTracker,
 };
-use tokio_util::task::task_tracker::TaskTracker;
 
 type ProcessExitStatus = std::result::Result<std::process::ExitStatus, std::io::Error>;
 
+/// A network whose file tree is created, but whose nodes have not been started
+/// yet. The files can be inspected or modified before starting it.
+#[derive(Debug)]
+pub struct PreparedNetwork {
+    nodes: Vec<RunningNode>,
+    temp_directory: Arc<RunDirectory>,
+    /// The preset and user values applied on top of the chainspec template.
+    chainspec_overrides: toml::Table,
+    monitor_settings: monitor::MonitorSettings,
+    output_mode: OutputMode,
+    task_tracker: TaskTracker,
+    events: broadcast::Sender<NetworkEvent>,
+    node_factory: prepare::NodeFactory,
+    chain_name: String,
+    shutdown_timeout: Duration,
+    web_port: u16,
+    web_auth: WebAuth,
+    era_hooks: Arc<std::sync::Mutex<era_hooks::EraHooks>>,
+    genesis_stakes: Vec<(String, u128)>,
+    genesis_accounts: GenesisAccounts,
+    activation_point: Option<SystemTime>,
+    strict_genesis: bool,
+}
+
 /// A network representation in CNUT. When this type is obtained, the file tree
 /// is created, and it is ready to start, or already started.
 #[derive(Clone, Debug)]
 pub struct RunningNetwork {
     pub(crate) nodes: Vec<RunningNode>,
-    temp_directory: Arc<tempfile::TempDir>,
+    temp_directory: Arc<RunDirectory>,
     shutdown_state: ShutdownState,
     exit_notification: Arc<Notify>,
+    pub(crate) output_mode: OutputMode,
     task_tracker: TaskTracker,
+    events: broadcast::Sender<NetworkEvent>,
+    /// Stops the tasks running for the whole network life, such as the node
+    /// monitoring.
+    background_tasks: CancellationToken,
+    /// Stops the background tasks when the last clone is dropped.
+    _background_tasks_guard: Arc<DropGuard>,
+    /// Creates the nodes added with [`RunningNetwork::add_node`].
+    node_factory: Arc<std::sync::Mutex<prepare::NodeFactory>>,
+    /// See [`NetworkBuilder::chain_name`].
+    chain_name: String,
+    /// See [`NetworkBuilder::shutdown_timeout`].
+    shutdown_timeout: Duration,
+    /// See [`NetworkBuilder::web_port`].
+    pub(crate) web_port: u16,
+    /// See [`NetworkBuilder::web_credentials`].
+    pub(crate) web_auth: WebAuth,
+    /// See [`RunningNetwork::at_era`].
+    era_hooks: Arc<std::sync::Mutex<era_hooks::EraHooks>>,
+    /// See [`RunningNetwork::genesis_stakes`].
+    genesis_stakes: Vec<(String, u128)>,
+    /// See [`RunningNetwork::genesis_accounts`].
+    genesis_accounts: GenesisAccounts,
+    /// Picks the nodes, see [`RunningNetwork::random_node`].
+    selection_rng: Arc<std::sync::Mutex<rand::rngs::StdRng>>,
+    /// See [`RunningNetwork::any_healthy_node`].
+    health_cache: Arc<std::sync::Mutex<select::HealthCache>>,
+    /// See [`RunningNetwork::health`].
+    monitor_settings: monitor::MonitorSettings,
+    /// The known addresses of the isolated nodes, by name, to restore them.
+    /// See [`RunningNetwork::isolate_node`].
+    isolated: Arc<std::sync::Mutex<BTreeMap<String, Vec<SocketAddr>>>>,
+    /// See [`RunningNetwork::on_shutdown`].
+    shutdown_hooks: Arc<std::sync::Mutex<shutdown_hooks::ShutdownHooks>>,
+    /// See [`RunningNetwork::state`].
+    state: Arc<watch::Sender<NetworkState>>,
+    /// The genesis time, unless the activation point is not a timestamp.
+    activation_point: Option<SystemTime>,
+    /// See [`NetworkBuilder::strict_genesis`].
+    strict_genesis: bool,
 }
 
 /// A running node. It can be started, stopped or crashed.
@@ -57,24 +169,89 @@ pub struct RunningNetwork {
 pub struct RunningNode {
     /// Path where the node will run, with the config.
     data_dir: PathBuf,
-    /// Path of the directory with binaries (node and wasm).
-    artifact_dir: PathBuf,
+    /// Path of the directory with binaries (node and wasm). Shared, so that
+    /// the clones see the artifacts of [`RunningNetwork::restart_all_onto`].
+    artifact_dir: Arc<std::sync::RwLock<PathBuf>>,
     /// Used during the node preparation phase.
-    default_config_path: PathBuf,
+    default_config: NodeConfig,
 
     name: String,
-    public_key: PublicKey,
-    secret_key: SecretKey,
+    /// The name shared by the nodes added together.
+    group: String,
+    /// Shared, so that the clones see the rotated keys.
+    keys: Arc<std::sync::RwLock<(PublicKey, SecretKey)>>,
     validator: bool,
 
     rpc_port: u16,
     rest_port: u16,
     speculative_execution_port: u16,
+    bind_port: u16,
+    event_stream_port: u16,
+    /// See [`NetworkBuilder::bind_host`].
+    bind_host: IpAddr,
+    /// See [`RunningNode::config_patches`].
+    config_patches: Vec<&'static str>,
+    /// The dotted config keys cnut sets for this node. See
+    /// [`RunningNetwork::compare_configs`].
+    per_node_keys: Vec<String>,
+    /// The ports set by the user, see [`Node::rpc_port`].
+    pinned_ports: describe::PinnedPorts,
+
+    /// Environment variables set for the node process.
+    env: BTreeMap<String, String>,
+    /// See [`Node::config_from_env`].
+    config_from_env: BTreeMap<String, String>,
+    /// See [`Node::config_overrides`].
+    config_overrides: toml::Table,
+    /// See [`Node::validate_config`].
+    config_validators: describe::ConfigValidators,
+    /// See [`Node::chainspec`].
+    chainspec: Option<Chainspec>,
+    /// See [`NetworkBuilder::shared_genesis`].
+    shared_genesis: bool,
+    /// The client of [`RunningNode::rpc`], shared by all the nodes. See
+    /// [`NetworkBuilder::rpc_timeout`].
+    pub(crate) rpc_client: reqwest::Client,
+    /// See [`NetworkBuilder::shutdown_timeout`].
+    shutdown_timeout: Duration,
+    /// See [`Node::balance`].
+    balance: Option<u128>,
+    /// See [`Node::bonded_amount`].
+    bonded_amount: Option<u128>,
+    /// The node binary subcommand.
+    launch_mode: String,
+    /// Arguments passed to the node binary after the config path.
+    extra_args: Vec<String>,
+    /// See [`Node::launcher`].
+    launcher: Vec<String>,
+    /// See [`Node::start_phase`].
+    start_phase: u8,
+    /// The name of the chain the node is part of.
+    pub(crate) chain_name: Arc<str>,
+    /// Patterns looked for in the node output, or `None` if the output is not
+    /// captured.
+    log_alert_patterns: Option<Arc<[String]>>,
+    /// Weither the process is only logged instead of being spawned.
+    dry_run: bool,
+    /// How the process is run.
+    backend: Backend,
 
     process_id: Arc<AtomicU32>,
     task_tracker: TaskTracker,
     status: Arc<Mutex<NodeStatus>>,
+    alerts: Arc<Mutex<Vec<LogAlert>>>,
+    history: Arc<Mutex<VecDeque<HistoryEntry>>>,
+    events: broadcast::Sender<NetworkEvent>,
     pub(crate) kill_notifier: Arc<Notify>,
+    /// The exit status of the last process, or `None` while it runs.
+    exit_status: Arc<watch::Sender<Option<ProcessExitStatus>>>,
+    /// Held while the node is started or stopped, so that these operations
+    /// do not interleave.
+    operation_lock: Arc<Mutex<()>>,
+    /// Weither the process is paused by [`RunningNode::freeze`].
+    frozen: Arc<AtomicBool>,
+    /// See [`RunningNode::crash_count`].
+    crashes: Arc<AtomicU32>,
 }
 
 /// The status of the node.
@@ -86,6 +263,53 @@ pub enum NodeStatus {
     Stopped(ProcessExitStatus),
     /// The node has crashed.
     Crashed(ProcessExitStatus),
+    /// The network is a dry run: the node would be running with this command.
+    WouldRun(String),
+}
+
+/// An event happening in the network. See [`RunningNetwork::subscribe`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum NetworkEvent {
+    /// A line of a node output matched one of the alert patterns.
+    LogAlert(LogAlert),
+    /// The free space on the filesystem hosting the run directory dropped
+    /// below the threshold set with
+    /// [`NetworkBuilder::low_disk_space_threshold`].
+    LowDiskSpace {
+        /// The free space, in bytes.
+        available: u64,
+        /// The threshold crossed, in bytes.
+        threshold: u64,
+    },
+    /// The nodes have different blocks at the same height. See
+    /// [`RunningNetwork::fork_watch`].
+    Fork {
+        /// The height of the blocks.
+        height: u64,
+        /// The name of each node, with the hash of its block.
+        hashes: Vec<(String, String)>,
+    },
+    /// A callback registered with [`RunningNetwork::at_era`] or
+    /// [`RunningNetwork::every_era`] panicked.
+    EraHookPanicked {
+        /// The era the callback was run for.
+        era: u64,
+        /// The panic message.
+        message: String,
+    },
+    /// The height of a node has not increased for longer than the threshold
+    /// set with [`NetworkBuilder::stall_threshold`]. It is raised once, until
+    /// the node adds a block again. Listen to it to treat a stalled node as a
+    /// failure of the test.
+    NodeStalled {
+        /// The name of the node.
+        name: String,
+        /// When the node reached its current height.
+        since: std::time::SystemTime,
+    },
+    /// The state of the network changed. See [`RunningNetwork::state`].
+    StateChanged(NetworkState),
 }
 
 impl Default for NodeStatus {
@@ -105,6 +329,66 @@ impl RunningNetwork {
         self.temp_directory.path()
     }
 
+    /// Leaves the network files on the disk once the network is dropped, and
+    /// returns their directory. The files stay where they are, so that the
+    /// running nodes and the accessors such as [`RunningNode::data_dir`] keep
+    /// working. Call it before dropping the network to inspect the files
+    /// afterwards. See also [`NetworkBuilder::keep_files`].
+    pub fn persist(&self) -> PathBuf {
+        self.temp_directory.keep();
+        log::info!("The network files are kept in {:?}", self.temp_directory());
+
+        self.temp_directory().to_owned()
+    }
+
+    /// Returns the name of the chain, needed to sign the deploys. See
+    /// [`NetworkBuilder::chain_name`].
+    pub fn chain_name(&self) -> &str {
+        &self.chain_name
+    }
+
+    /// Returns the name and the stake of the validators written in the
+    /// genesis accounts, in the order they were added. See
+    /// [`NetworkBuilder::stake_distribution`].
+    pub fn genesis_stakes(&self) -> &[(String, u128)] {
+        &self.genesis_stakes
+    }
+
+    /// Returns the accounts written in `accounts.toml`, which can be looked
+    /// up by node name:
+    ///
+    /// ```no_run
+    /// # async fn f(network: cnut::network::RunningNetwork) {
+    /// let alice = network.genesis_accounts().by_name("alice").unwrap();
+    /// assert!(alice.balance > 0);
+    /// # }
+    /// ```
+    pub fn genesis_accounts(&self) -> &GenesisAccounts {
+        &self.genesis_accounts
+    }
+
+    /// Returns `true` if all the nodes are running.
+    pub async fn all_running(&self) -> bool {
+        for node in &self.nodes {
+            if !node.running().await {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Returns `true` if at least one node has crashed.
+    pub async fn any_crashed(&self) -> bool {
+        for node in &self.nodes {
+            if node.status.lock().await.crashed() {
+                return true;
+            }
+        }
+
+        false
+    }
+
     /// Orders the network to shutdown. This causes the wait functions to return.
     pub fn shutdown(&self) {
         self.exit_notification.notify_one();
@@ -117,6 +401,43 @@ impl RunningNode {
         &self.name
     }
 
+    /// Returns the directory of the node, relative to the run directory, with
+    /// `/` separators: the name of its group, followed by its index in the
+    /// group if it was multiplied, such as `Alice/0`. The names given by the
+    /// user are checked to be valid directory names, so it is the node name.
+    pub fn dir_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the name of the group the node was added with: for nodes added
+    /// with `n * Node`, the name shared by the `n` nodes. A node added alone
+    /// is its own group.
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    /// Returns the phase the node is started in. See [`Node::start_phase`].
+    pub fn start_phase(&self) -> u8 {
+        self.start_phase
+    }
+
+    /// Returns the public key of the node.
+    pub fn public_key(&self) -> PublicKey {
+        self.keys.read().expect("poisoned lock").0.clone()
+    }
+
+    /// Signs the `bytes` with the secret key of the node, such as to build a
+    /// finality signature or a custom deploy. The signature can be checked
+    /// with [`PublicKey::verify`].
+    pub fn sign(&self, bytes: &[u8]) -> Signature {
+        self.keys.read().expect("poisoned lock").1.sign(bytes)
+    }
+
+    /// Returns the secret key of the node.
+    pub(crate) fn secret_key(&self) -> SecretKey {
+        self.keys.read().expect("poisoned lock").1.clone()
+    }
+
     /// Returns weither the node is a validator or not.
     pub fn validator(&self) -> bool {
         self.validator
@@ -127,6 +448,40 @@ impl RunningNode {
         self.status.lock().await.running()
     }
 
+    /// Returns how many times the node process exited by itself, since the
+    /// network was created.
+    pub fn crash_count(&self) -> u32 {
+        self.crashes.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Returns the ID of the node process, or `None` if the node is not
+    /// running.
+    pub fn pid(&self) -> Option<u32> {
+        match self.process_id.load(std::sync::atomic::Ordering::Relaxed) {
+            0 => None,
+            pid => Some(pid),
+        }
+    }
+
+    /// Returns the host the node is reached at: the one it listens on, or the
+    /// loopback address if it listens on all the interfaces. See
+    /// [`NetworkBuilder::bind_host`].
+    pub fn host(&self) -> IpAddr {
+        match self.bind_host {
+            IpAddr::V4(host) if host.is_unspecified() => Ipv4Addr::LOCALHOST.into(),
+            IpAddr::V6(host) if host.is_unspecified() => Ipv6Addr::LOCALHOST.into(),
+            host => host,
+        }
+    }
+
+    /// Returns the config sections patched by cnut when the node was
+    /// prepared, such as `rpc_server`. The optional sections absent from the
+    /// config template are not patched, since older nodes reject the unknown
+    /// sections.
+    pub fn config_patches(&self) -> &[&'static str] {
+        &self.config_patches
+    }
+
     /// Returns the RPC port for this node.
     pub fn rpc_port(&self) -> u16 {
         self.rpc_port
@@ -137,19 +492,53 @@ impl RunningNode {
         self.rest_port
     }
 
+    /// Returns the address the RPC server of the node is reached at.
+    pub fn rpc_address(&self) -> SocketAddr {
+        SocketAddr::new(self.host(), self.rpc_port)
+    }
+
+    /// Returns the address the REST server of the node is reached at, for
+    /// example to probe its `/status`.
+    pub fn rest_address(&self) -> SocketAddr {
+        SocketAddr::new(self.host(), self.rest_port)
+    }
+
     /// Returns the speculative execution port for this node.
     pub fn speculative_execution_port(&self) -> u16 {
         self.speculative_execution_port
     }
 
+    /// Returns the port the node binds to for the other nodes.
+    pub fn bind_port(&self) -> u16 {
+        self.bind_port
+    }
+
+    /// Returns the event stream port for this node.
+    pub fn event_stream_port(&self) -> u16 {
+        self.event_stream_port
+    }
+
+    /// Returns the names of the ports pinned by the user: `rpc`, `rest` or
+    /// `bind`.
+    pub(crate) fn pinned_port_names(&self) -> Vec<&'static str> {
+        let describe::PinnedPorts { rpc, rest, bind } = self.pinned_ports;
+
+        [("rpc", rpc), ("rest", rest), ("bind", bind)]
+            .into_iter()
+            .filter_map(|(name, port)| port.map(|_| name))
+            .collect()
+    }
+
     /// Path where the node will run, with the config, secret key, chainspec, etc.
     pub fn data_dir(&self) -> &Path {
         &self.data_dir
     }
 
-    /// Path of the directory with binaries (node and wasm).
-    pub fn artifact_dir(&self) -> &Path {
-        &self.artifact_dir
+    /// Path of the directory with binaries (node and wasm). It is owned, as it
+    /// changes when the network is restarted onto new artifacts, see
+    /// [`RunningNetwork::restart_all_onto`].
+    pub fn artifact_dir(&self) -> PathBuf {
+        self.artifact_dir.read().expect("poisoned lock").clone()
     }
 
     /// Chainspec path.
@@ -171,10 +560,43 @@ impl RunningNode {
     pub fn public_key_path(&self) -> PathBuf {
         self.data_dir().join("public_key.pem")
     }
+
+    /// Path of the public key in hexadecimal, the `public_key_hex` file read
+    /// by the Casper tools.
+    pub fn public_key_hex_path(&self) -> PathBuf {
+        self.data_dir().join("public_key_hex")
+    }
+
+    /// Path of the node storage, with the database and the blocks.
+    pub fn storage_path(&self) -> PathBuf {
+        self.data_dir().join("node-storage")
+    }
+
+    /// Path of the file holding the process ID while the node runs. The node
+    /// process also has its name in the `CNUT_NODE_NAME` environment variable.
+    pub fn pid_path(&self) -> PathBuf {
+        self.data_dir().join("node.pid")
+    }
+
+    /// Path of the captured standard output. It only exists if the logs are
+    /// captured, see [`NetworkBuilder::capture_logs`].
+    pub fn stdout_path(&self) -> PathBuf {
+        self.data_dir().join("stdout.log")
+    }
+
+    /// Path of the captured standard error. It only exists if the logs are
+    /// captured, see [`NetworkBuilder::capture_logs`].
+    pub fn stderr_path(&self) -> PathBuf {
+        self.data_dir().join("stderr.log")
+    }
 }
 
 impl NodeStatus {
     fn running(&self) -> bool {
         matches!(self, Self::Running)
     }
+
+    fn crashed(&self) -> bool {
+        matches!(self, Self::Crashed(_))
+    }
 }
@@ -0,0 +1,369 @@
+//! A typed view of the `accounts.toml` file, to check the genesis balances and
+//! stakes without reading the TOML by hand.
+
+use crate::{
+    error::{Error, Result},
+    util::read_toml,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// The genesis accounts of a network, as written in `accounts.toml`. See
+/// [`RunningNetwork::genesis_accounts`](super::RunningNetwork::genesis_accounts).
+///
+/// The amounts are in motes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisAccounts {
+    /// The accounts, one per node for the generated files.
+    #[serde(default)]
+    pub accounts: Vec<GenesisAccount>,
+    /// The delegations made at genesis.
+    #[serde(default)]
+    pub delegators: Vec<GenesisDelegator>,
+    /// The public key of each node, by name. Empty for a file read with
+    /// [`GenesisAccounts::from_file`].
+    #[serde(skip)]
+    names: BTreeMap<String, String>,
+}
+
+/// An account of the [`GenesisAccounts`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisAccount {
+    /// The public key, in hexadecimal.
+    pub public_key: String,
+    /// The balance.
+    #[serde(with = "motes")]
+    pub balance: u128,
+    /// The stake, if the account is a validator.
+    pub validator: Option<GenesisValidator>,
+}
+
+/// The stake of a validator [`GenesisAccount`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisValidator {
+    /// The bonded amount.
+    #[serde(with = "motes")]
+    pub bonded_amount: u128,
+    /// The percentage of the rewards kept from the delegators.
+    #[serde(default)]
+    pub delegation_rate: u8,
+}
+
+/// A delegation of the [`GenesisAccounts`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisDelegator {
+    /// The public key of the validator delegated to, in hexadecimal.
+    pub validator_public_key: String,
+    /// The public key of the delegator, in hexadecimal.
+    pub delegator_public_key: String,
+    /// The balance of the delegator.
+    #[serde(with = "motes")]
+    pub balance: u128,
+    /// The delegated amount.
+    #[serde(with = "motes")]
+    pub delegated_amount: u128,
+}
+
+impl GenesisAccounts {
+    /// Reads an accounts file, for example one not generated by cnut. The
+    /// accounts cannot be looked up by node name.
+    pub async fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        read_toml(path, "the accounts")
+            .await?
+            .try_into()
+            .map_err(|source| Error::TomlParse {
+                path: path.to_owned(),
+                source,
+            })
+    }
+
+    /// Parses the generated accounts, and names them after their node.
+    pub(crate) fn generated(
+        accounts: toml::Value,
+        names: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        let accounts: Self = accounts
+            .try_into()
+            .expect("the generated accounts to be valid");
+
+        Self {
+            names: names.into_iter().collect(),
+            ..accounts
+        }
+    }
+
+    /// Returns the account with the given public key, in hexadecimal.
+    pub fn by_public_key(&self, public_key: &str) -> Option<&GenesisAccount> {
+        self.accounts
+            .iter()
+            .find(|account| account.public_key.eq_ignore_ascii_case(public_key))
+    }
+
+    /// Returns the account of the node with the given name.
+    pub fn by_name(&self, name: &str) -> Option<&GenesisAccount> {
+        self.by_public_key(self.names.get(name)?)
+    }
+
+    /// Returns the delegations made to the validator with the given public
+    /// key, in hexadecimal.
+    pub fn delegators_of<'a>(
+        &'a self,
+        validator_public_key: &'a str,
+    ) -> impl Iterator<Item = &'a GenesisDelegator> {
+        self.delegators.iter().filter(move |delegator| {
+            delegator
+                .validator_public_key
+                .eq_ignore_ascii_case(validator_public_key)
+        })
+    }
+
+    /// Returns the sum of the bonded amounts of the validators.
+    pub fn total_stake(&self) -> u128 {
+        self.accounts
+            .iter()
+            .filter_map(|account| account.validator.as_ref())
+            .map(|validator| validator.bonded_amount)
+            .sum()
+    }
+}
+
+impl GenesisAccount {
+    /// Returns the bonded amount, zero if the account is not a validator.
+    pub fn bonded_amount(&self) -> u128 {
+        self.validator
+            .as_ref()
+            .map_or(0, |validator| validator.bonded_amount)
+    }
+}
+
+/// The amounts are written as strings, since they exceed the TOML integers.
+mod motes {
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(motes: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(motes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        let motes = String::deserialize(deserializer)?;
+
+        motes
+            .parse()
+            .map_err(|_| D::Error::custom(format!("invalid amount of motes {motes:?}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::Node, testing};
+
+    const ALICE: &str = "01aa00000000000000000000000000000000000000000000000000000000000000";
+    const BOB: &str = "01bb00000000000000000000000000000000000000000000000000000000000000";
+    const CAROL: &str = "01cc00000000000000000000000000000000000000000000000000000000000000";
+
+    /// An accounts file with two validators, a plain account and two
+    /// delegations, with amounts above `u64::MAX`.
+    fn accounts_file() -> String {
+        format!(
+            r#"
+            [[accounts]]
+            public_key = "{ALICE}"
+            balance = "100000000000000000000000"
+
+            [accounts.validator]
+            bonded_amount = "50000000000000000000000"
+            delegation_rate = 10
+
+            [[accounts]]
+            public_key = "{BOB}"
+            balance = "2000"
+
+            [accounts.validator]
+            bonded_amount = "1000"
+
+            [[accounts]]
+            public_key = "{CAROL}"
+            balance = "300"
+
+            [[delegators]]
+            validator_public_key = "{ALICE}"
+            delegator_public_key = "{CAROL}"
+            balance = "300"
+            delegated_amount = "100"
+
+            [[delegators]]
+            validator_public_key = "{BOB}"
+            delegator_public_key = "{CAROL}"
+            balance = "300"
+            delegated_amount = "200"
+            "#
+        )
+    }
+
+    /// Parses the [`accounts_file`] through [`GenesisAccounts::from_file`].
+    async fn read_accounts() -> GenesisAccounts {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("accounts.toml");
+        std::fs::write(&path, accounts_file()).unwrap();
+
+        GenesisAccounts::from_file(&path).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn an_accounts_file_is_read() {
+        let accounts = read_accounts().await;
+
+        assert_eq!(accounts.accounts.len(), 3);
+        let alice = &accounts.accounts[0];
+        assert_eq!(alice.public_key, ALICE);
+        assert_eq!(alice.balance, 100_000_000_000_000_000_000_000);
+        assert_eq!(
+            alice.validator,
+            Some(GenesisValidator {
+                bonded_amount: 50_000_000_000_000_000_000_000,
+                delegation_rate: 10,
+            })
+        );
+        assert_eq!(
+            accounts.accounts[1]
+                .validator
+                .as_ref()
+                .unwrap()
+                .delegation_rate,
+            0
+        );
+        assert_eq!(accounts.accounts[2].bonded_amount(), 0);
+        assert_eq!(
+            accounts.delegators[1],
+            GenesisDelegator {
+                validator_public_key: BOB.to_owned(),
+                delegator_public_key: CAROL.to_owned(),
+                balance: 300,
+                delegated_amount: 200,
+            }
+        );
+        assert_eq!(accounts.by_name("Alice"), None);
+    }
+
+    #[test]
+    fn the_motes_are_written_as_strings() {
+        let account = GenesisAccount {
+            public_key: ALICE.to_owned(),
+            balance: u128::MAX,
+            validator: Some(GenesisValidator {
+                bonded_amount: u64::MAX as u128 + 1,
+                delegation_rate: 0,
+            }),
+        };
+
+        let table = toml::Table::try_from(&account).unwrap();
+        assert_eq!(
+            table["balance"].as_str(),
+            Some(u128::MAX.to_string().as_str())
+        );
+        assert_eq!(
+            table["validator"]["bonded_amount"].as_str(),
+            Some("18446744073709551616")
+        );
+        let read: GenesisAccount = toml::Value::Table(table).try_into().unwrap();
+        assert_eq!(read, account);
+    }
+
+    #[test]
+    fn an_invalid_amount_is_rejected() {
+        let result: std::result::Result<GenesisAccount, _> = toml::from_str(&format!(
+            r#"
+            public_key = "{ALICE}"
+            balance = "12 motes"
+            "#
+        ));
+
+        let message = result.unwrap_err().to_string();
+        assert!(
+            message.contains(r#"invalid amount of motes "12 motes""#),
+            "{message}"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_accounts_are_looked_up_by_public_key_or_name() {
+        let accounts = read_accounts().await;
+        let accounts = GenesisAccounts {
+            names: BTreeMap::from([("Bob".to_owned(), BOB.to_owned())]),
+            ..accounts
+        };
+
+        assert_eq!(accounts.by_public_key(ALICE).unwrap().public_key, ALICE);
+        assert_eq!(
+            accounts
+                .by_public_key(&ALICE.to_uppercase())
+                .unwrap()
+                .public_key,
+            ALICE
+        );
+        assert_eq!(accounts.by_public_key("01dd"), None);
+        assert_eq!(accounts.by_name("Bob").unwrap().public_key, BOB);
+        assert_eq!(accounts.by_name("Carol"), None);
+    }
+
+    #[tokio::test]
+    async fn the_delegations_and_stakes_are_summed_up() {
+        let accounts = read_accounts().await;
+
+        let delegated: Vec<_> = accounts
+            .delegators_of(&BOB.to_uppercase())
+            .map(|delegator| delegator.delegated_amount)
+            .collect();
+        assert_eq!(delegated, [200]);
+        assert_eq!(accounts.delegators_of(CAROL).count(), 0);
+        assert_eq!(
+            accounts.total_stake(),
+            50_000_000_000_000_000_000_000 + 1000
+        );
+    }
+
+    #[tokio::test]
+    async fn the_prepared_network_exposes_the_generated_accounts() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::builder()
+            .with(
+                Node::validator(artifacts.clone())
+                    .balance(u64::MAX as u128 * 3)
+                    .bonded_amount(1_000),
+            )
+            .with(Node::keep_up(artifacts.clone()).balance(42))
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+
+        let accounts = network.genesis_accounts();
+        let validator = accounts.by_name("Node_A").unwrap();
+        assert_eq!(validator.balance, u64::MAX as u128 * 3);
+        assert_eq!(validator.bonded_amount(), 1_000);
+        let keep_up = accounts.by_name("Node_B").unwrap();
+        assert_eq!(keep_up.balance, 42);
+        assert_eq!(keep_up.validator, None);
+        assert_eq!(accounts.total_stake(), 1_000);
+        assert_eq!(
+            accounts.by_public_key(
+                &network
+                    .node_by_name("Node_A")
+                    .unwrap()
+                    .public_key()
+                    .to_string()
+            ),
+            Some(validator)
+        );
+        assert_eq!(
+            GenesisAccounts::from_file(network.temp_directory().join("accounts.toml"))
+                .await
+                .unwrap()
+                .accounts,
+            accounts.accounts
+        );
+    }
+}
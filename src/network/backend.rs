@@ -0,0 +1,221 @@
+//! How the node processes are run: directly, or inside containers.
+
+use super::RunningNode;
+use std::net::SocketAddr;
+use tokio::process::Command;
+
+/// Runs the nodes. See [`NetworkBuilder::backend`](super::NetworkBuilder::backend).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// The nodes are child processes of the program.
+    #[default]
+    Process,
+    /// Each node runs in a Docker container, started with the `docker` CLI.
+    ///
+    /// The node and artifacts directories are mounted at the same paths in the
+    /// container, and the ports cnut assigned to the node are published on the
+    /// [bind host](super::NetworkBuilder::bind_host), so that the node is
+    /// reached as if it ran on the host. Since the nodes reach each other
+    /// through the host, their known addresses must be reachable from the
+    /// containers. The image must be able to run the node binary from the
+    /// artifacts.
+    ///
+    /// Only an existing image is supported: an image described by a
+    /// Dockerfile must be built first, with `docker build --tag <image>`.
+    Docker {
+        /// The image the containers are created from.
+        image: String,
+    },
+}
+
+impl Backend {
+    /// Creates a Docker backend with the given image.
+    pub fn docker(image: impl Into<String>) -> Self {
+        Self::Docker {
+            image: image.into(),
+        }
+    }
+}
+
+impl RunningNode {
+    /// Returns the name of the node container if the node runs in Docker,
+    /// whether it is running or not. See [`Backend::Docker`].
+    pub fn container_id(&self) -> Option<String> {
+        match self.backend {
+            Backend::Process => None,
+            Backend::Docker { .. } => {
+                let name: String = self
+                    .name
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                    .collect();
+
+                Some(format!("cnut-{}-{name}", std::process::id()))
+            }
+        }
+    }
+
+    /// Returns the command running the node with its backend.
+    pub(crate) fn command(&self) -> Command {
+        let artifact_dir = self.artifact_dir();
+        let node_path = artifact_dir.join("casper-node");
+        let config_path = self.config_path();
+
+        match &self.backend {
+            Backend::Process => {
+                let mut command = match self.launcher.split_first() {
+                    Some((launcher, launcher_args)) => {
+                        let mut command = Command::new(launcher);
+                        command.args(launcher_args).arg(&node_path);
+                        command
+                    }
+                    None => Command::new(&node_path),
+                };
+                command
+                    .arg(&self.launch_mode)
+                    .arg(&config_path)
+                    .args(&self.extra_args)
+                    .env("CNUT_NODE_NAME", &self.name)
+                    .envs(&self.env)
+                    .current_dir(&self.data_dir);
+                command
+            }
+            Backend::Docker { image } => {
+                let (data_dir, artifact_dir) = (
+                    self.data_dir.to_string_lossy(),
+                    artifact_dir.to_string_lossy(),
+                );
+                let mut command = Command::new("docker");
+                command.args(["run", "--rm", "--name"]).arg(
+                    self.container_id()
+                        .expect("a Docker node to have a container"),
+                );
+                for port in [
+                    self.rpc_port,
+                    self.rest_port,
+                    self.speculative_execution_port,
+                    self.event_stream_port,
+                    self.bind_port,
+                ] {
+                    let host = SocketAddr::new(self.bind_host, port);
+                    command.args(["-p", &format!("{host}:{port}")]);
+                }
+                command
+                    .args(["-v", &format!("{data_dir}:{data_dir}")])
+                    .args(["-v", &format!("{artifact_dir}:{artifact_dir}:ro")])
+                    .args(["-w", &data_dir])
+                    .args(["-e", &format!("CNUT_NODE_NAME={}", self.name)]);
+                for (key, value) in &self.env {
+                    command.args(["-e", &format!("{key}={value}")]);
+                }
+                command
+                    .arg(image)
+                    .args(&self.launcher)
+                    .arg(&node_path)
+                    .arg(&self.launch_mode)
+                    .arg(&config_path)
+                    .args(&self.extra_args)
+                    .current_dir(&self.data_dir);
+                command
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::Node, testing};
+
+    /// Returns the program and the arguments of the `command`.
+    fn args(command: &Command) -> Vec<String> {
+        let command = command.as_std();
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn a_docker_node_is_run_with_its_ports_and_directories() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::builder()
+            .with(
+                Node::validator(artifacts.clone())
+                    .env("RUST_LOG", "info")
+                    .launcher(vec!["valgrind".to_owned()])
+                    .extra_args(vec!["--verbose".to_owned()]),
+            )
+            .backend(Backend::docker("casper-node:test"))
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+        let node = network.node_by_name("Node_A").unwrap();
+        let (data_dir, artifact_dir) = (
+            node.data_dir().to_string_lossy().into_owned(),
+            node.artifact_dir().to_string_lossy().into_owned(),
+        );
+        let publish = |port: u16| ["-p".to_owned(), format!("127.0.0.1:{port}:{port}")];
+
+        let mut expected = vec![
+            "docker".to_owned(),
+            "run".to_owned(),
+            "--rm".to_owned(),
+            "--name".to_owned(),
+            node.container_id().unwrap(),
+        ];
+        for port in [
+            node.rpc_port(),
+            node.rest_port(),
+            node.speculative_execution_port(),
+            node.event_stream_port(),
+            node.bind_port(),
+        ] {
+            expected.extend(publish(port));
+        }
+        expected.extend([
+            "-v".to_owned(),
+            format!("{data_dir}:{data_dir}"),
+            "-v".to_owned(),
+            format!("{artifact_dir}:{artifact_dir}:ro"),
+            "-w".to_owned(),
+            data_dir.clone(),
+            "-e".to_owned(),
+            "CNUT_NODE_NAME=Node_A".to_owned(),
+            "-e".to_owned(),
+            "RUST_LOG=info".to_owned(),
+            "casper-node:test".to_owned(),
+            "valgrind".to_owned(),
+            format!("{artifact_dir}/casper-node"),
+            "validator".to_owned(),
+            node.config_path().to_string_lossy().into_owned(),
+            "--verbose".to_owned(),
+        ]);
+        assert_eq!(args(&node.command()), expected);
+    }
+
+    #[tokio::test]
+    async fn a_process_node_is_run_directly() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 1)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+        let node = network.node_by_name("Node_A").unwrap();
+
+        assert_eq!(node.container_id(), None);
+        assert_eq!(
+            args(&node.command()),
+            [
+                node.artifact_dir()
+                    .join("casper-node")
+                    .to_string_lossy()
+                    .into_owned(),
+                "validator".to_owned(),
+                node.config_path().to_string_lossy().into_owned(),
+            ]
+        );
+    }
+}
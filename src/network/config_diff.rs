@@ -0,0 +1,330 @@
+//! Compares the configs of two nodes key by key, to find out why a node
+//! behaves differently from the other ones.
+
+use crate::{
+    error::Result,
+    network::{RunningNetwork, RunningNode},
+    util::read_toml,
+};
+use std::collections::BTreeMap;
+
+/// A key of the configs of two nodes, with its value in each of them. See
+/// [`RunningNetwork::compare_configs`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigDiffEntry {
+    /// The dotted path of the key, such as `network.bind_address`. The items of
+    /// the arrays of tables are indexed, such as `servers[0].address`.
+    pub key: String,
+    /// The value in the config of the left node, `None` if it is absent.
+    pub left: Option<toml::Value>,
+    /// The value in the config of the right node, `None` if it is absent.
+    pub right: Option<toml::Value>,
+    /// Weither cnut sets this key for each node, such as the addresses, so
+    /// that it is expected to differ.
+    pub per_node: bool,
+}
+
+impl ConfigDiffEntry {
+    /// Returns `true` if the values of both nodes differ.
+    pub fn differs(&self) -> bool {
+        self.left != self.right
+    }
+}
+
+/// The configs of two nodes compared key by key. See
+/// [`RunningNetwork::compare_configs`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigDiff {
+    /// The name of the left node.
+    pub left: String,
+    /// The name of the right node.
+    pub right: String,
+    /// All the keys of both configs, sorted, whether they differ or not.
+    pub entries: Vec<ConfigDiffEntry>,
+}
+
+impl ConfigDiff {
+    /// Returns the keys with a different value in both configs, except the
+    /// ones cnut sets for each node.
+    pub fn differences(&self) -> impl Iterator<Item = &ConfigDiffEntry> {
+        self.all_differences().filter(|entry| !entry.per_node)
+    }
+
+    /// Returns the keys with a different value in both configs, including
+    /// the ones cnut sets for each node.
+    pub fn all_differences(&self) -> impl Iterator<Item = &ConfigDiffEntry> {
+        self.entries.iter().filter(|entry| entry.differs())
+    }
+}
+
+impl RunningNetwork {
+    /// Compares the configs of the nodes named `left` and `right`, as they
+    /// are on disk, key by key. The keys cnut sets for each node, such as the
+    /// ports or the known addresses, are flagged as
+    /// [`per_node`](ConfigDiffEntry::per_node), so that
+    /// [`ConfigDiff::differences`] ignores them.
+    pub async fn compare_configs(&self, left: &str, right: &str) -> Result<ConfigDiff> {
+        let (left, right) = (self.node_by_name(left)?, self.node_by_name(right)?);
+        let (left_config, right_config) = (read_config(left).await?, read_config(right).await?);
+        let per_node = |key: &str| {
+            [left, right]
+                .into_iter()
+                .flat_map(|node| &node.per_node_keys)
+                .any(|per_node_key| is_under(key, per_node_key))
+        };
+
+        let entries = diff_values(&left_config, &right_config)
+            .into_iter()
+            .map(|(key, left, right)| ConfigDiffEntry {
+                per_node: per_node(&key),
+                key,
+                left,
+                right,
+            })
+            .collect();
+
+        Ok(ConfigDiff {
+            left: left.name.clone(),
+            right: right.name.clone(),
+            entries,
+        })
+    }
+
+    /// Returns the keys whose value differs in the configs of the nodes named
+    /// `left` and `right`, with the value of each node, sorted by key. The keys
+    /// cnut sets for each node are left out, so that an accidental per-node
+    /// override stands out, as well as the keys absent from one of the configs:
+    /// see [`RunningNetwork::compare_configs`] for the full comparison.
+    pub async fn diff_configs(
+        &self,
+        left: &str,
+        right: &str,
+    ) -> Result<Vec<(String, toml::Value, toml::Value)>> {
+        let diff = self.compare_configs(left, right).await?;
+
+        Ok(diff
+            .differences()
+            .filter_map(|entry| {
+                let (left, right) = (entry.left.clone()?, entry.right.clone()?);
+                Some((entry.key.clone(), left, right))
+            })
+            .collect())
+    }
+}
+
+async fn read_config(node: &RunningNode) -> Result<toml::Value> {
+    read_toml(&node.config_path(), &format!("the config of {}", node.name)).await
+}
+
+/// Returns `true` if the `key` is the `parent` one, or one of its children.
+fn is_under(key: &str, parent: &str) -> bool {
+    key.strip_prefix(parent)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+}
+
+/// Returns all the keys of both values with their value on each side, sorted
+/// by key.
+fn diff_values(
+    left: &toml::Value,
+    right: &toml::Value,
+) -> Vec<(String, Option<toml::Value>, Option<toml::Value>)> {
+    let mut left = flatten(left);
+    let mut right = flatten(right);
+    let mut keys: Vec<String> = left.keys().chain(right.keys()).cloned().collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .map(|key| {
+            let (left, right) = (left.remove(&key), right.remove(&key));
+            (key, left, right)
+        })
+        .collect()
+}
+
+/// Returns the leaf values of the `value` by dotted key. The arrays holding
+/// tables are flattened with the index of their items, while the other arrays
+/// are compared as a whole.
+pub(super) fn flatten(value: &toml::Value) -> BTreeMap<String, toml::Value> {
+    fn visit(prefix: String, value: &toml::Value, leaves: &mut BTreeMap<String, toml::Value>) {
+        match value {
+            toml::Value::Table(table) if !table.is_empty() => {
+                for (key, value) in table {
+                    let key = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{prefix}.{key}")
+                    };
+                    visit(key, value, leaves);
+                }
+            }
+            toml::Value::Array(items) if items.iter().any(toml::Value::is_table) => {
+                for (index, item) in items.iter().enumerate() {
+                    visit(format!("{prefix}[{index}]"), item, leaves);
+                }
+            }
+            _ => {
+                leaves.insert(prefix, value.clone());
+            }
+        }
+    }
+
+    let mut leaves = BTreeMap::new();
+    visit(String::new(), value, &mut leaves);
+
+    leaves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::Node, testing};
+
+    fn diff(left: &str, right: &str) -> Vec<(String, Option<toml::Value>, Option<toml::Value>)> {
+        diff_values(
+            &left.parse::<toml::Table>().unwrap().into(),
+            &right.parse::<toml::Table>().unwrap().into(),
+        )
+    }
+
+    #[test]
+    fn nested_tables_are_diffed_by_dotted_key() {
+        let entries = diff(
+            "[a.b]\nc = 1\nd = 2\n[a.e]\nf = true",
+            "[a.b]\nc = 1\nd = 3\n[a.e]\nf = true",
+        );
+
+        assert_eq!(
+            entries,
+            [
+                ("a.b.c".to_owned(), Some(1.into()), Some(1.into())),
+                ("a.b.d".to_owned(), Some(2.into()), Some(3.into())),
+                ("a.e.f".to_owned(), Some(true.into()), Some(true.into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays_of_values_are_compared_as_a_whole() {
+        let entries = diff("known = [1, 2]", "known = [1]");
+
+        assert_eq!(
+            entries,
+            [(
+                "known".to_owned(),
+                Some(toml::Value::Array(vec![1.into(), 2.into()])),
+                Some(toml::Value::Array(vec![1.into()])),
+            )]
+        );
+    }
+
+    #[test]
+    fn arrays_of_tables_are_diffed_by_index() {
+        let entries = diff(
+            "[[servers]]\naddress = \"a\"\n[[servers]]\naddress = \"b\"",
+            "[[servers]]\naddress = \"a\"",
+        );
+
+        assert_eq!(
+            entries,
+            [
+                (
+                    "servers[0].address".to_owned(),
+                    Some("a".into()),
+                    Some("a".into())
+                ),
+                ("servers[1].address".to_owned(), Some("b".into()), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_on_one_side_only_are_absent_on_the_other() {
+        let entries = diff("[a]\nleft = 1", "[a]\nright = 2\n[b]");
+
+        assert_eq!(
+            entries,
+            [
+                ("a.left".to_owned(), Some(1.into()), None),
+                ("a.right".to_owned(), None, Some(2.into())),
+                ("b".to_owned(), None, Some(toml::Table::new().into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_key_is_under_itself_and_its_children_only() {
+        assert!(is_under(
+            "network.known_addresses",
+            "network.known_addresses"
+        ));
+        assert!(is_under("network.bind_address", "network"));
+        assert!(is_under("servers[0].address", "servers"));
+        assert!(!is_under("network_extra.port", "network"));
+        assert!(!is_under("network", "network.bind_address"));
+    }
+
+    #[tokio::test]
+    async fn the_keys_set_for_each_node_are_per_node() {
+        let (_dir, artifacts) = testing::artifacts();
+        let overrides = "[logging]\nformat = \"json\"".parse().unwrap();
+        let network = testing::network(&artifacts, 1)
+            .with(Node::validator(artifacts.clone()).config_overrides(overrides))
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+
+        let diff = network.compare_configs("Node_A", "Node_B").await.unwrap();
+        let differences: Vec<_> = diff.differences().map(|entry| &entry.key).collect();
+        assert_eq!(differences, ["logging.format"]);
+        let per_node: Vec<_> = diff
+            .all_differences()
+            .filter(|entry| entry.per_node)
+            .map(|entry| entry.key.as_str())
+            .collect();
+        assert_eq!(
+            per_node,
+            [
+                "event_stream_server.address",
+                "network.bind_address",
+                "rest_server.address",
+                "rpc_server.address",
+            ]
+        );
+        let storage = diff
+            .entries
+            .iter()
+            .find(|entry| entry.key == "storage.path");
+        assert!(storage.is_some_and(|entry| entry.per_node && !entry.differs()));
+    }
+
+    #[tokio::test]
+    async fn only_the_values_set_on_both_sides_are_diffed() {
+        let (_dir, artifacts) = testing::artifacts();
+        let left = "[network]\nmax_peers = 5".parse().unwrap();
+        let right = "[network]\nmax_peers = 6\n[logging]\nformat = \"json\""
+            .parse()
+            .unwrap();
+        let network = testing::builder()
+            .with(Node::validator(artifacts.clone()).config_overrides(left))
+            .with(Node::validator(artifacts.clone()).config_overrides(right))
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+
+        let diff = network.diff_configs("Node_A", "Node_B").await.unwrap();
+        assert_eq!(diff, [("network.max_peers".to_owned(), 5.into(), 6.into())]);
+        assert!(matches!(
+            network.diff_configs("Node_A", "Node_Z").await,
+            Err(crate::error::Error::NodeNameNotFound(name)) if name == "Node_Z"
+        ));
+    }
+}
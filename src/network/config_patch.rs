@@ -0,0 +1,245 @@
+//! A typed model of the node config sections cnut manages, so that the
+//! patches applied to the configs are checked at compile time.
+
+use serde::Serialize;
+use std::{net::SocketAddr, path::PathBuf};
+
+/// Values overriding some node config ones. The `None` fields are left as they
+/// are in the config. See
+/// [`PreparedNetwork::apply_config_overrides`](super::PreparedNetwork::apply_config_overrides).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NodeConfigPatch {
+    /// The `[network]` section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkSection>,
+    /// The `[rpc_server]` section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_server: Option<ServerSection>,
+    /// The `[speculative_exec_server]` section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speculative_exec_server: Option<ServerSection>,
+    /// The `[rest_server]` section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rest_server: Option<ServerSection>,
+    /// The `[event_stream_server]` section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_stream_server: Option<ServerSection>,
+    /// The `[storage]` section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<StorageSection>,
+}
+
+/// The `[network]` section of the node config.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NetworkSection {
+    /// The address the node listens to for the other nodes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bind_address: Option<SocketAddr>,
+    /// The addresses of the nodes to connect to first.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub known_addresses: Option<Vec<SocketAddr>>,
+}
+
+/// A server section of the node config, such as `[rpc_server]`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ServerSection {
+    /// The address the server listens to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<SocketAddr>,
+}
+
+/// The `[storage]` section of the node config.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StorageSection {
+    /// The directory of the node storage, relative to the config one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+impl NodeConfigPatch {
+    /// Returns the names of the sections patched.
+    pub fn sections(&self) -> Vec<&'static str> {
+        [
+            ("network", self.network.is_some()),
+            ("rpc_server", self.rpc_server.is_some()),
+            (
+                "speculative_exec_server",
+                self.speculative_exec_server.is_some(),
+            ),
+            ("rest_server", self.rest_server.is_some()),
+            ("event_stream_server", self.event_stream_server.is_some()),
+            ("storage", self.storage.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, patched)| patched.then_some(name))
+        .collect()
+    }
+
+    /// Removes the patches of the sections absent from the `config`, among
+    /// the ones older nodes do not know about, since a node rejects a config
+    /// with an unknown section. Returns the names of the removed sections.
+    pub(crate) fn retain_known_sections(&mut self, config: &toml::Value) -> Vec<&'static str> {
+        let mut skipped = Vec::new();
+        let mut retain = |name, section: &mut Option<ServerSection>| {
+            if section.is_some() && config.get(name).is_none() {
+                *section = None;
+                skipped.push(name);
+            }
+        };
+
+        retain("speculative_exec_server", &mut self.speculative_exec_server);
+        retain("event_stream_server", &mut self.event_stream_server);
+
+        skipped
+    }
+}
+
+impl From<NodeConfigPatch> for toml::Table {
+    fn from(patch: NodeConfigPatch) -> Self {
+        toml::Table::try_from(patch).expect("TOML serialization failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        artifacts::Artifacts,
+        network::{PreparedNetwork, RunningNode},
+        testing,
+    };
+    use std::collections::BTreeSet;
+
+    /// Returns the prepared network of one node with the `artifacts`.
+    async fn prepare(artifacts: &Artifacts) -> PreparedNetwork {
+        testing::network(artifacts, 1)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+    }
+
+    /// Returns the config at `path`, parsed.
+    fn read_config(path: &std::path::Path) -> toml::Table {
+        std::fs::read_to_string(path).unwrap().parse().unwrap()
+    }
+
+    /// Returns the sections of the config of the `node` and the ones of its
+    /// template.
+    fn sections(node: &RunningNode, artifacts: &Artifacts) -> (BTreeSet<String>, BTreeSet<String>) {
+        let written = read_config(&node.config_path())
+            .into_iter()
+            .map(|(name, _)| name);
+        let template = read_config(&artifacts.config_path())
+            .into_iter()
+            .map(|(name, _)| name);
+
+        (written.collect(), template.collect())
+    }
+
+    #[tokio::test]
+    async fn a_template_without_the_speculative_section_is_not_given_one() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = prepare(&artifacts).await;
+        let node = network.node_by_name("Node_A").unwrap();
+
+        assert_eq!(
+            node.config_patches(),
+            [
+                "network",
+                "rpc_server",
+                "rest_server",
+                "event_stream_server",
+                "storage"
+            ]
+        );
+        let (written, template) = sections(node, &artifacts);
+        assert_eq!(written, template);
+        assert!(!written.contains("speculative_exec_server"));
+    }
+
+    #[tokio::test]
+    async fn a_template_with_the_speculative_section_is_patched() {
+        let (dir, artifacts) = testing::artifacts();
+        let template = dir.path().join("config-with-speculative.toml");
+        let mut config = read_config(&artifacts.config_path());
+        config.insert(
+            "speculative_exec_server".to_owned(),
+            toml::toml! { address = "0.0.0.0:7778" }.into(),
+        );
+        std::fs::write(&template, toml::to_string(&config).unwrap()).unwrap();
+        let artifacts = artifacts.with_override("config.toml", &template);
+        let network = prepare(&artifacts).await;
+        let node = network.node_by_name("Node_A").unwrap();
+
+        assert_eq!(
+            node.config_patches(),
+            [
+                "network",
+                "rpc_server",
+                "speculative_exec_server",
+                "rest_server",
+                "event_stream_server",
+                "storage"
+            ]
+        );
+        let (written, template) = sections(node, &artifacts);
+        assert_eq!(written, template);
+        let config = read_config(&node.config_path());
+        assert_eq!(
+            config["speculative_exec_server"]["address"].as_str(),
+            Some(format!("127.0.0.1:{}", node.speculative_execution_port()).as_str())
+        );
+    }
+
+    #[test]
+    fn the_patch_is_serialized_as_the_config_sections() {
+        let patch = NodeConfigPatch {
+            network: Some(NetworkSection {
+                bind_address: Some(([0, 0, 0, 0], 22101).into()),
+                known_addresses: Some(vec![([127, 0, 0, 1], 22100).into()]),
+            }),
+            rpc_server: Some(ServerSection {
+                address: Some(([127, 0, 0, 1], 11101).into()),
+            }),
+            storage: Some(StorageSection {
+                path: Some(PathBuf::from("./node-storage")),
+            }),
+            ..NodeConfigPatch::default()
+        };
+
+        let expected = toml::toml! {
+            [network]
+            bind_address = "0.0.0.0:22101"
+            known_addresses = ["127.0.0.1:22100"]
+
+            [rpc_server]
+            address = "127.0.0.1:11101"
+
+            [storage]
+            path = "./node-storage"
+        };
+        assert_eq!(toml::Table::from(patch), expected);
+    }
+
+    #[test]
+    fn the_empty_fields_are_omitted() {
+        let patch = NodeConfigPatch {
+            network: Some(NetworkSection {
+                bind_address: None,
+                known_addresses: Some(Vec::new()),
+            }),
+            rest_server: Some(ServerSection::default()),
+            ..NodeConfigPatch::default()
+        };
+
+        let expected = toml::toml! {
+            [network]
+            known_addresses = []
+
+            [rest_server]
+        };
+        assert_eq!(toml::Table::from(patch), expected);
+        assert!(toml::Table::from(NodeConfigPatch::default()).is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+//! Gathers the consensus state of the nodes, to debug the liveness failures,
+//! and checks the validators of the eras.
+
+use crate::{
+    error::{Error, Result},
+    network::{RunningNetwork, RunningNode},
+};
+use futures::future::join_all;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The consensus state of a node. See [`RunningNetwork::era_supervisor_status`].
+///
+/// The values are `None` when the node could not tell them, for example
+/// because it is not running or not reachable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EraSupervisorStatus {
+    /// The name of the node.
+    pub node: String,
+    /// The era of the last block added by the node.
+    pub era_id: Option<u64>,
+    /// The height of the last block added by the node.
+    pub height: Option<u64>,
+    /// Weither the node is a validator of its current era.
+    pub current_era_validator: Option<bool>,
+}
+
+impl RunningNetwork {
+    /// Asks every node for its current era, its last block height, and weither
+    /// it validates the current era, in the order of the nodes.
+    pub async fn era_supervisor_status(&self) -> Vec<EraSupervisorStatus> {
+        join_all(self.nodes.iter().map(era_supervisor_status)).await
+    }
+
+    /// Returns the validators of the `era` according to the chain, as the
+    /// weight of each public key, in hexadecimal.
+    ///
+    /// The chain only knows the validators of its current era and of the few
+    /// next ones, depending on the auction delay: a later era fails with
+    /// [`Error::EraValidatorsNotYetAvailable`], and an earlier one with
+    /// [`Error::EraValidatorsUnavailable`].
+    pub async fn era_validators(&self, era: u64) -> Result<BTreeMap<String, u128>> {
+        let auction_info = self
+            .any_healthy_node()
+            .await?
+            .rpc()
+            .get_auction_info()
+            .await?;
+        let eras = auction_info["auction_state"]["era_validators"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let era_ids = eras.iter().filter_map(|era| era["era_id"].as_u64());
+
+        match (era_ids.clone().min(), era_ids.max()) {
+            (Some(oldest), _) if era < oldest => {
+                return Err(Error::EraValidatorsUnavailable { era, oldest })
+            }
+            (_, latest) if latest.is_none_or(|latest| era > latest) => {
+                return Err(Error::EraValidatorsNotYetAvailable { era, latest })
+            }
+            _ => (),
+        }
+
+        Ok(eras
+            .iter()
+            .filter(|validators| validators["era_id"].as_u64() == Some(era))
+            .flat_map(|validators| validators["validator_weights"].as_array())
+            .flatten()
+            .filter_map(|weight| {
+                let public_key = weight["public_key"].as_str()?.to_lowercase();
+                let weight = match &weight["weight"] {
+                    Value::String(weight) => weight.parse().ok()?,
+                    weight => weight.as_u64()?.into(),
+                };
+
+                Some((public_key, weight))
+            })
+            .collect())
+    }
+
+    /// Checks that the validators of the `era` are the nodes with the given
+    /// names, and fails with [`Error::UnexpectedValidators`] otherwise. The
+    /// validators not created by cnut are reported as `external`. See
+    /// [`RunningNetwork::era_validators`].
+    pub async fn assert_validators<S: AsRef<str>>(
+        &self,
+        era: u64,
+        expected_names: impl IntoIterator<Item = S>,
+    ) -> Result<()> {
+        let expected = expected_names
+            .into_iter()
+            .map(|name| {
+                let node = self.node_by_name(name.as_ref())?;
+                Ok((node.public_key().to_string().to_lowercase(), node.name()))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        let actual = self.era_validators(era).await?;
+        let name_of = |public_key: &String| match self.nodes.iter().find(|node| {
+            node.public_key()
+                .to_string()
+                .eq_ignore_ascii_case(public_key)
+        }) {
+            Some(node) => node.name().to_owned(),
+            None => format!("external ({public_key})"),
+        };
+
+        let missing: BTreeSet<String> = expected
+            .iter()
+            .filter(|(public_key, _)| !actual.contains_key(*public_key))
+            .map(|(_, name)| (*name).to_owned())
+            .collect();
+        let unexpected: BTreeSet<String> = actual
+            .keys()
+            .filter(|public_key| !expected.contains_key(*public_key))
+            .map(name_of)
+            .collect();
+
+        if missing.is_empty() && unexpected.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::UnexpectedValidators {
+                era,
+                missing: missing.into_iter().collect(),
+                unexpected: unexpected.into_iter().collect(),
+            })
+        }
+    }
+}
+
+async fn era_supervisor_status(node: &RunningNode) -> EraSupervisorStatus {
+    let mut result = EraSupervisorStatus {
+        node: node.name().to_owned(),
+        era_id: None,
+        height: None,
+        current_era_validator: None,
+    };
+    if !node.running().await {
+        return result;
+    }
+    let rpc = node.rpc();
+
+    match rpc.get_status().await {
+        Ok(status) => {
+            let info = &status["last_added_block_info"];
+            result.era_id = info["era_id"].as_u64();
+            result.height = info["height"].as_u64();
+        }
+        Err(e) => {
+            log::debug!("Could not get the status of {}: {e}", node.name());
+            return result;
+        }
+    }
+    if let Some(era_id) = result.era_id {
+        match rpc.get_auction_info().await {
+            Ok(auction_info) => {
+                result.current_era_validator = is_era_validator(&auction_info, era_id, node);
+            }
+            Err(e) => log::debug!("Could not get the auction info of {}: {e}", node.name()),
+        }
+    }
+
+    result
+}
+
+/// Returns weither the node is among the validators of the era in the result
+/// of `state_get_auction_info`, or `None` if the era is not in it.
+fn is_era_validator(auction_info: &Value, era_id: u64, node: &RunningNode) -> Option<bool> {
+    let public_key = node.public_key().to_string();
+    let era = auction_info["auction_state"]["era_validators"]
+        .as_array()?
+        .iter()
+        .find(|era| era["era_id"].as_u64() == Some(era_id))?;
+
+    Some(
+        era["validator_weights"]
+            .as_array()?
+            .iter()
+            .any(|weight| weight["public_key"].as_str() == Some(&public_key)),
+    )
+}
@@ -0,0 +1,362 @@
+//! Describes a network in a TOML file instead of code, for the simple
+//! topologies. See [`NetworkBuilder::from_toml`].
+
+use crate::{
+    artifacts::Artifacts,
+    error::{Error, Result},
+    network::{Chainspec, NetworkBuilder, Node, NodeConfig, Preset},
+};
+use serde::{Deserialize, Deserializer};
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::fs;
+
+/// The content of the definition file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct NetworkDefinition {
+    /// The default artifacts of the nodes.
+    artifacts: Option<PathBuf>,
+    chainspec: Option<PathBuf>,
+    preset: Option<Preset>,
+    #[serde(default)]
+    chainspec_overrides: toml::Table,
+    chain_name: Option<String>,
+    seed: Option<u64>,
+    run_dir_prefix: Option<String>,
+    #[serde(default, deserialize_with = "duration")]
+    shutdown_timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "duration")]
+    readiness_timeout: Option<Duration>,
+    web_port: Option<u16>,
+    bind_host: Option<IpAddr>,
+    keep_files: Option<bool>,
+    shared_genesis: Option<bool>,
+    capture_logs: Option<bool>,
+    #[serde(default)]
+    nodes: Vec<NodeGroup>,
+}
+
+/// Nodes added together, like `count * Node`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct NodeGroup {
+    #[serde(default = "one")]
+    count: usize,
+    role: Role,
+    name: Option<String>,
+    artifacts: Option<PathBuf>,
+    #[serde(default, deserialize_with = "motes")]
+    balance: Option<u128>,
+    #[serde(default, deserialize_with = "motes")]
+    bonded_amount: Option<u128>,
+    config: Option<PathBuf>,
+    chainspec: Option<PathBuf>,
+    #[serde(default)]
+    config_overrides: toml::Table,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    start_phase: Option<u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Role {
+    Validator,
+    KeepUp,
+}
+
+impl NetworkBuilder {
+    /// Creates the network described in the TOML file at `path`. The relative
+    /// paths in the file are relative to its directory:
+    ///
+    /// ```toml
+    /// artifacts = "../casper-node/target/cnut"
+    /// preset = "fast-local"
+    /// chain-name = "my-test"
+    /// seed = 42
+    /// shutdown-timeout = "10s"
+    ///
+    /// [chainspec-overrides.core]
+    /// validator_slots = 10
+    ///
+    /// [[nodes]]
+    /// count = 3
+    /// role = "validator"
+    /// bonded-amount = "1000000000000000"
+    ///
+    /// [[nodes]]
+    /// role = "keep-up"
+    /// name = "observer"
+    /// balance = "5000000000000000000"
+    /// env = { RUST_LOG = "debug" }
+    ///
+    /// [nodes.config-overrides.network]
+    /// max_outgoing_byte_rate_non_validators = 0
+    /// ```
+    ///
+    /// The other network keys are `chainspec`, `run-dir-prefix`, `web-port`,
+    /// `bind-host`, `keep-files`, `shared-genesis` and `capture-logs`, and the other node keys
+    /// are `artifacts`, `config`, `chainspec` and `start-phase`. They match the builder
+    /// methods of the same name. The amounts in motes are strings or
+    /// integers.
+    ///
+    /// The builder can be completed afterwards, for example with the default
+    /// artifacts. An unknown key fails with its path in the file. See
+    /// `examples/network.toml` for a commented definition.
+    pub async fn from_toml(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("reading the network definition {path:?}"),
+                io_err,
+            })?;
+        let definition: NetworkDefinition =
+            serde_path_to_error::deserialize(toml::Deserializer::new(&content)).map_err(|e| {
+                Error::InvalidNetworkDefinition {
+                    path: path.to_owned(),
+                    key: e.path().to_string(),
+                    message: e.inner().message().to_owned(),
+                }
+            })?;
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+
+        Ok(definition.into_builder(base_dir))
+    }
+}
+
+impl NetworkDefinition {
+    fn into_builder(self, base_dir: &Path) -> NetworkBuilder {
+        let mut builder = NetworkBuilder::new().chainspec_overrides(self.chainspec_overrides);
+
+        if let Some(artifacts) = self.artifacts {
+            builder = builder.default_artifacts(Artifacts::from_path(base_dir.join(artifacts)));
+        }
+        if let Some(chainspec) = self.chainspec {
+            builder = builder.with(Chainspec::Path(base_dir.join(chainspec)));
+        }
+        if let Some(preset) = self.preset {
+            builder = builder.with(preset);
+        }
+        if let Some(chain_name) = self.chain_name {
+            builder = builder.chain_name(&chain_name);
+        }
+        if let Some(seed) = self.seed {
+            builder = builder.seed(seed);
+        }
+        if let Some(prefix) = self.run_dir_prefix {
+            builder = builder.run_dir_prefix(&prefix);
+        }
+        if let Some(shutdown_timeout) = self.shutdown_timeout {
+            builder = builder.shutdown_timeout(shutdown_timeout);
+        }
+        if let Some(readiness_timeout) = self.readiness_timeout {
+            builder = builder.readiness_timeout(readiness_timeout);
+        }
+        if let Some(web_port) = self.web_port {
+            builder = builder.web_port(web_port);
+        }
+        if let Some(bind_host) = self.bind_host {
+            builder = builder.bind_host(bind_host);
+        }
+        if let Some(keep_files) = self.keep_files {
+            builder = builder.keep_files(keep_files);
+        }
+        if let Some(shared_genesis) = self.shared_genesis {
+            builder = builder.shared_genesis(shared_genesis);
+        }
+        if let Some(capture_logs) = self.capture_logs {
+            builder = builder.capture_logs(capture_logs);
+        }
+
+        builder.with_iter(
+            self.nodes
+                .into_iter()
+                .map(|group| group.into_node(base_dir)),
+        )
+    }
+}
+
+impl NodeGroup {
+    fn into_node(self, base_dir: &Path) -> Node {
+        let artifacts = self
+            .artifacts
+            .map(|artifacts| Artifacts::from_path(base_dir.join(artifacts)));
+        let mut node = match (self.role, artifacts) {
+            (Role::Validator, Some(artifacts)) => Node::validator(artifacts),
+            (Role::Validator, None) => Node::default_validator(),
+            (Role::KeepUp, Some(artifacts)) => Node::keep_up(artifacts),
+            (Role::KeepUp, None) => Node::default_keep_up(),
+        };
+
+        if let Some(name) = self.name {
+            node = node.name(name);
+        }
+        if let Some(balance) = self.balance {
+            node = node.balance(balance);
+        }
+        if let Some(bonded_amount) = self.bonded_amount {
+            node = node.bonded_amount(bonded_amount);
+        }
+        if let Some(config) = self.config {
+            node = node.config(NodeConfig::Path(base_dir.join(config)));
+        }
+        if let Some(chainspec) = self.chainspec {
+            node = node.chainspec(Chainspec::Path(base_dir.join(chainspec)));
+        }
+        if let Some(start_phase) = self.start_phase {
+            node = node.start_phase(start_phase);
+        }
+        for (key, value) in self.env {
+            node = node.env(key, value);
+        }
+
+        self.count * node.config_overrides(self.config_overrides)
+    }
+}
+
+fn one() -> usize {
+    1
+}
+
+/// Reads an amount of motes written as a string, like in the accounts file,
+/// or as an integer.
+fn motes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<u128>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Motes {
+        Integer(u64),
+        String(String),
+    }
+
+    match Motes::deserialize(deserializer)? {
+        Motes::Integer(motes) => Ok(Some(motes.into())),
+        Motes::String(motes) => motes
+            .parse()
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid amount of motes {motes:?}"))),
+    }
+}
+
+/// Reads a duration written like `10s` or `2min`.
+fn duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Duration>, D::Error> {
+    let duration = String::deserialize(deserializer)?;
+
+    humantime::parse_duration(&duration)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        network::PreparedNetwork,
+        testing,
+        util::{toml_map, OutputMode},
+    };
+
+    /// Returns the content of the files in `dir`, by relative path.
+    fn tree(dir: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+        let mut tree = BTreeMap::new();
+        let mut to_read = vec![dir.to_owned()];
+
+        while let Some(current) = to_read.pop() {
+            for entry in std::fs::read_dir(&current).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    to_read.push(path);
+                } else {
+                    let content = std::fs::read(&path).unwrap();
+                    tree.insert(path.strip_prefix(dir).unwrap().to_owned(), content);
+                }
+            }
+        }
+
+        tree
+    }
+
+    /// Prepares the `network` without starting it, with the `artifacts` and
+    /// the ports of the `scheme`, at a fixed activation point.
+    async fn prepare(
+        network: NetworkBuilder,
+        artifacts: &Artifacts,
+        scheme: crate::network::PortScheme,
+    ) -> PreparedNetwork {
+        network
+            .default_artifacts(artifacts.clone())
+            .port_scheme(scheme)
+            .output_mode(OutputMode::Silent)
+            .min_free_space(0)
+            .chainspec_overrides(toml_map! {
+                "protocol", "activation_point" => "2024-01-01T00:00:00Z",
+            })
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_example_is_the_same_as_its_builder() {
+        let (_dir, artifacts) = testing::artifacts();
+        let scheme = testing::port_scheme();
+        let example = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/network.toml");
+        let from_toml = NetworkBuilder::from_toml(example).await.unwrap();
+        let from_code = NetworkBuilder::new()
+            .with(Preset::FastLocal)
+            .chain_name("example")
+            .seed(42)
+            .shutdown_timeout(Duration::from_secs(10))
+            .chainspec_overrides(toml_map! {
+                "core", "validator_slots" => 10,
+            })
+            .with(3 * Node::default_validator().bonded_amount(1_000_000_000_000_000))
+            .with(
+                Node::default_keep_up()
+                    .name("observer")
+                    .balance(5_000_000_000_000_000_000)
+                    .env("RUST_LOG", "debug")
+                    .config_overrides(toml_map! {
+                        "network", "max_outgoing_byte_rate_non_validators" => 0,
+                    }),
+            );
+
+        let from_toml = prepare(from_toml, &artifacts, scheme).await;
+        let from_code = prepare(from_code, &artifacts, scheme).await;
+
+        let tree_from_toml = tree(from_toml.temp_directory());
+        assert!(tree_from_toml.contains_key(Path::new("observer/config.toml")));
+        assert_eq!(tree_from_toml, tree(from_code.temp_directory()));
+    }
+
+    #[tokio::test]
+    async fn an_unknown_key_is_reported_with_its_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("network.toml");
+        std::fs::write(
+            &path,
+            "seed = 1\n\n[[nodes]]\nrole = \"validator\"\n\n[[nodes]]\nrole = \"keep-up\"\nbonded-amout = 1\n",
+        )
+        .unwrap();
+
+        let result = NetworkBuilder::from_toml(&path).await;
+
+        assert!(matches!(
+            result,
+            Err(Error::InvalidNetworkDefinition { path: error_path, key, message })
+                if error_path == path
+                    && key == "nodes[1].bonded-amout"
+                    && message.starts_with("unknown field `bonded-amout`")
+        ));
+    }
+}
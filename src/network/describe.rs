@@ -1,8 +1,38 @@
-use crate::{artifacts::Artifacts, error::Result};
+use crate::{
+    artifacts::Artifacts,
+    error::{Error, Result},
+    util::{merge_tables, read_toml, OutputMode},
+    web_app::{WebAuth, WebCredentials},
+};
 use sealed::NetworkItem;
-use std::{ops, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    net::{IpAddr, Ipv4Addr},
+    ops,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
-use super::RunningNetwork;
+use super::{
+    monitor::MonitorSettings, Backend, PortScheme, PreparedNetwork, Preset, StakeDistribution,
+};
+
+/// The subcommand the node binary is run with by default.
+const DEFAULT_LAUNCH_MODE: &str = "validator";
+/// How long the nodes have to stop by default.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long after the preparation the genesis happens at least, by default.
+const DEFAULT_ACTIVATION_DELAY: Duration = Duration::from_secs(1);
+/// The time allowed to each node to start, by default.
+const DEFAULT_STARTUP_ALLOWANCE: Duration = Duration::from_millis(250);
+/// The port the web app listens on by default.
+const DEFAULT_WEB_PORT: u16 = 6532;
+/// The start of the run directory name by default.
+const DEFAULT_RUN_DIR_PREFIX: &str = "cnut-run-";
+/// How long the nodes have to answer a JSON-RPC request by default.
+const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// The notwork. Add the nodes, and run it.
 #[derive(Debug, Clone)]
@@ -11,6 +41,61 @@ pub struct NetworkBuilder {
     /// Chainspec for the nodes. If it is not specified, the one from the first
     /// available node with be taken.
     pub(super) chainspec: Option<Chainspec>,
+    /// Values applied on top of the chainspec template, before the user ones.
+    pub(super) chainspec_preset: Option<Preset>,
+    /// Values applied on top of the chainspec template, last.
+    pub(super) chainspec_overrides: toml::Table,
+    /// Weither the storage path written in the configs is absolute.
+    pub(super) absolute_storage_path: bool,
+    /// Weither the output of the nodes is written to files.
+    pub(super) capture_logs: bool,
+    /// Patterns looked for in the captured output of the nodes.
+    pub(super) log_alert_patterns: Vec<String>,
+    /// How the running nodes are polled.
+    pub(super) monitor_settings: MonitorSettings,
+    /// Weither the node processes are only logged instead of being spawned.
+    pub(super) dry_run: bool,
+    /// The start of the run directory name.
+    pub(super) run_dir_prefix: String,
+    /// How the progress is shown.
+    pub(super) output_mode: OutputMode,
+    /// Seeds the random generation (keys, balances, etc.), for reproducible
+    /// networks.
+    pub(super) seed: Option<u64>,
+    /// The range the account balances and validator stakes are drawn from.
+    pub(super) balance_range: Option<(u128, u128)>,
+    /// The free space required to prepare the network, in bytes.
+    pub(super) min_free_space: Option<u64>,
+    /// The artifacts of the nodes created without any.
+    pub(super) default_artifacts: Option<Artifacts>,
+    /// How the nodes are run.
+    pub(super) backend: Backend,
+    /// How the ports of the nodes are allocated.
+    pub(super) port_scheme: PortScheme,
+    /// The name of the chain, random if not set.
+    pub(super) chain_name: Option<String>,
+    /// How long the nodes have to stop before being killed.
+    pub(super) shutdown_timeout: Duration,
+    /// The minimum time between the preparation and the genesis.
+    pub(super) activation_delay: Duration,
+    /// The time allowed to each node to start before the genesis.
+    pub(super) startup_allowance: Duration,
+    /// Weither starting a validator after the genesis is an error.
+    pub(super) strict_genesis: bool,
+    /// The port the web app listens on.
+    pub(super) web_port: u16,
+    /// Who may use the web app.
+    pub(super) web_auth: WebAuth,
+    /// Weither the run directory is left on the disk.
+    pub(super) keep_files: bool,
+    /// Weither the nodes share the genesis files through hard links.
+    pub(super) shared_genesis: bool,
+    /// How the stakes are spread across the validators.
+    pub(super) stake_distribution: Option<StakeDistribution>,
+    /// The host the nodes listen on.
+    pub(super) bind_host: IpAddr,
+    /// How long the nodes have to answer a JSON-RPC request.
+    pub(super) rpc_timeout: Duration,
 }
 
 mod sealed {
@@ -25,6 +110,35 @@ impl NetworkBuilder {
         NetworkBuilder {
             nodes: Vec::new(),
             chainspec: None,
+            chainspec_preset: None,
+            chainspec_overrides: toml::Table::new(),
+            absolute_storage_path: false,
+            capture_logs: false,
+            log_alert_patterns: super::logs::DEFAULT_ALERT_PATTERNS
+                .map(ToOwned::to_owned)
+                .to_vec(),
+            monitor_settings: MonitorSettings::default(),
+            dry_run: false,
+            run_dir_prefix: DEFAULT_RUN_DIR_PREFIX.to_owned(),
+            output_mode: OutputMode::default(),
+            seed: None,
+            balance_range: None,
+            min_free_space: None,
+            default_artifacts: None,
+            backend: Backend::default(),
+            port_scheme: PortScheme::default(),
+            chain_name: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            activation_delay: DEFAULT_ACTIVATION_DELAY,
+            startup_allowance: DEFAULT_STARTUP_ALLOWANCE,
+            strict_genesis: false,
+            web_port: DEFAULT_WEB_PORT,
+            web_auth: WebAuth::default(),
+            keep_files: false,
+            shared_genesis: true,
+            stake_distribution: None,
+            bind_host: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            rpc_timeout: DEFAULT_RPC_TIMEOUT,
         }
     }
 
@@ -34,38 +148,539 @@ impl NetworkBuilder {
         self
     }
 
-    /// Returns the chainspec's full path.
+    /// Adds several items to the network, for example nodes built in a loop.
+    /// Each node keeps its own amount, so that `2 * Node::validator(a)` adds two
+    /// nodes.
+    pub fn with_iter<I: NetworkItem>(mut self, items: impl IntoIterator<Item = I>) -> Self {
+        for item in items {
+            item.add_to(&mut self);
+        }
+        self
+    }
+
+    /// Adds the nodes of `other` after the ones of this network, to compose
+    /// networks from reusable fragments:
     ///
-    /// If it is not explicitely specified, we use the first node template one.
-    pub(crate) fn chainspec_path(&self) -> PathBuf {
-        self.chainspec
-            .clone()
-            .unwrap_or_else(|| Chainspec::Artifacts(self.nodes.first().unwrap().artifacts.clone()))
-            .path()
+    /// ```no_run
+    /// # use cnut::prelude::*;
+    /// fn base() -> NetworkBuilder {
+    ///     NetworkBuilder::new().with(3 * Node::default_validator())
+    /// }
+    ///
+    /// let network = base().merge(NetworkBuilder::new().with(Node::default_keep_up().name("observer")));
+    /// ```
+    ///
+    /// On conflict, this network wins: its chainspec, preset and default
+    /// artifacts are kept if it has some, and its chainspec overrides take
+    /// precedence over the ones of `other`, which are merged in. The other
+    /// options, such as the seed or the ports, are the ones of this network.
+    ///
+    /// The node names are not changed, so the preparation fails with
+    /// [`Error::DuplicateNodeName`] if both networks have a node with the same
+    /// name. The nodes without a name are named after their position in the
+    /// merged network.
+    pub fn merge(self, other: NetworkBuilder) -> Self {
+        let mut nodes = self.nodes;
+        nodes.extend(other.nodes);
+
+        Self {
+            nodes,
+            chainspec: self.chainspec.or(other.chainspec),
+            chainspec_preset: self.chainspec_preset.or(other.chainspec_preset),
+            chainspec_overrides: merge_tables(other.chainspec_overrides, self.chainspec_overrides),
+            default_artifacts: self.default_artifacts.or(other.default_artifacts),
+            ..self
+        }
+    }
+
+    /// Sets the artifacts of the nodes created without any, such as
+    /// [`Node::default_validator`]. The nodes with their own artifacts keep
+    /// them.
+    pub fn default_artifacts(self, artifacts: Artifacts) -> Self {
+        Self {
+            default_artifacts: Some(artifacts),
+            ..self
+        }
+    }
+
+    /// Overrides some values of the chainspec. They take precedence over the
+    /// [`Preset`], if any. Calling it several times merges the overrides.
+    pub fn chainspec_overrides(self, overrides: toml::Table) -> Self {
+        Self {
+            chainspec_overrides: merge_tables(self.chainspec_overrides, overrides),
+            ..self
+        }
+    }
+
+    /// Writes the absolute path of the node storage in the configs, instead of
+    /// the relative `./node-storage`, so that a path copied from a config is
+    /// not ambiguous. Disabled by default.
+    pub fn absolute_storage_path(self, absolute_storage_path: bool) -> Self {
+        Self {
+            absolute_storage_path,
+            ..self
+        }
+    }
+
+    /// Writes the standard output and error of each node into `stdout.log` and
+    /// `stderr.log` in its directory. Disabled by default.
+    pub fn capture_logs(self, capture_logs: bool) -> Self {
+        Self {
+            capture_logs,
+            ..self
+        }
+    }
+
+    /// Sets the patterns looked for in the captured output of the nodes. A line
+    /// containing one of them raises a [`LogAlert`](super::LogAlert). The
+    /// patterns are plain substrings.
+    ///
+    /// By default, the patterns are `panicked at`, `ERROR` and `unwrap`. This
+    /// has no effect if the logs are not captured.
+    pub fn log_alert_patterns(self, log_alert_patterns: Vec<String>) -> Self {
+        Self {
+            log_alert_patterns,
+            ..self
+        }
+    }
+
+    /// Sets the start of the run directory name, followed by the creation date
+    /// and a random suffix. `cnut-run-` by default.
+    pub fn run_dir_prefix(self, prefix: &str) -> Self {
+        Self {
+            run_dir_prefix: prefix.to_owned(),
+            ..self
+        }
+    }
+
+    /// Seeds the random generation of the network, such as the node keys and
+    /// the randomized balances, so that it is the same from a run to another.
+    pub fn seed(self, seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    /// Draws the balance of every account and the stake of every validator
+    /// uniformly between `min` and `max` motes (included), instead of giving
+    /// them all the same amounts. Use [`NetworkBuilder::seed`] to make it
+    /// reproducible. The bounds are swapped if needed.
+    pub fn randomize_balances(self, min: u128, max: u128) -> Self {
+        Self {
+            balance_range: Some((min.min(max), min.max(max))),
+            ..self
+        }
+    }
+
+    /// Spreads the stakes of the validators following the `distribution`,
+    /// instead of giving them all the same. It is applied to the validators
+    /// in the order they are added. The effective stakes are given by
+    /// [`RunningNetwork::genesis_stakes`](super::RunningNetwork::genesis_stakes).
+    pub fn stake_distribution(self, distribution: StakeDistribution) -> Self {
+        Self {
+            stake_distribution: Some(distribution),
+            ..self
+        }
+    }
+
+    /// Sets how the preparation progress and the web app address are shown.
+    /// Interactive by default.
+    pub fn output_mode(self, output_mode: OutputMode) -> Self {
+        Self {
+            output_mode,
+            ..self
+        }
+    }
+
+    /// Sets the minimum time between the preparation of the network and its
+    /// genesis, one second by default. The genesis is delayed further if the
+    /// [startup allowance](NetworkBuilder::startup_allowance) of all the nodes
+    /// is longer. It has no effect if the `protocol.activation_point` of the
+    /// chainspec is overridden.
+    pub fn activation_delay(self, activation_delay: Duration) -> Self {
+        Self {
+            activation_delay,
+            ..self
+        }
+    }
+
+    /// Sets the time each node is expected to take to start, 250 ms by
+    /// default. The genesis happens once all the nodes had time to start one
+    /// after the other, so that the slow machines do not miss it. See
+    /// [`NetworkBuilder::activation_delay`].
+    pub fn startup_allowance(self, startup_allowance: Duration) -> Self {
+        Self {
+            startup_allowance,
+            ..self
+        }
+    }
+
+    /// Weither starting the nodes fails with
+    /// [`Error::MissedGenesis`](crate::error::Error::MissedGenesis) when some
+    /// validators are started after the genesis, `false` by default: it is
+    /// only logged as a warning.
+    pub fn strict_genesis(self, strict_genesis: bool) -> Self {
+        Self {
+            strict_genesis,
+            ..self
+        }
+    }
+
+    /// Sets how long the nodes have to stop once asked to with `SIGTERM`,
+    /// when the network shuts down or a node is stopped, 30 seconds by
+    /// default. The processes still running after that are killed.
+    pub fn shutdown_timeout(self, shutdown_timeout: Duration) -> Self {
+        Self {
+            shutdown_timeout,
+            ..self
+        }
+    }
+
+    /// Weither the run directory is left on the disk once the network is
+    /// dropped, `false` by default. Its path is given by
+    /// [`RunningNetwork::temp_directory`](super::RunningNetwork::temp_directory).
+    pub fn keep_files(self, keep_files: bool) -> Self {
+        Self { keep_files, ..self }
+    }
+
+    /// Weither the `chainspec.toml` and `accounts.toml` files of the nodes are
+    /// hard links to the ones of the run directory, `true` by default. When
+    /// `false`, each node gets its own copy, which can be changed with
+    /// [`RunningNode::patch_chainspec`](super::RunningNode::patch_chainspec),
+    /// for example to test a validator with a divergent chainspec.
+    pub fn shared_genesis(self, shared_genesis: bool) -> Self {
+        Self {
+            shared_genesis,
+            ..self
+        }
+    }
+
+    /// Sets the host the nodes listen on, `0.0.0.0` by default. The nodes are
+    /// reached at this host, or at the loopback address of the same family if
+    /// it is unspecified: `127.0.0.1` for `0.0.0.0`, `::1` for `::`. Use an
+    /// IPv6 host, such as `::`, on IPv6-only machines.
+    pub fn bind_host(self, bind_host: IpAddr) -> Self {
+        Self { bind_host, ..self }
+    }
+
+    /// Sets how long a node has to answer a JSON-RPC request, such as the ones
+    /// of [`RunningNode::rpc`](super::RunningNode::rpc), 10 seconds by
+    /// default. A node which does not answer in time fails the request, so
+    /// that a hung node cannot block the callers.
+    pub fn rpc_timeout(self, rpc_timeout: Duration) -> Self {
+        Self {
+            rpc_timeout,
+            ..self
+        }
+    }
+
+    /// Sets the port the web app listens on, 6532 by default. See
+    /// [`RunningNetwork::serve_web_app`](super::RunningNetwork::serve_web_app).
+    pub fn web_port(self, web_port: u16) -> Self {
+        Self { web_port, ..self }
+    }
+
+    /// Protects the web app with a basic authentication. Without it, the
+    /// credentials are read from the `CNUT_WEB_USERNAME` and
+    /// `CNUT_WEB_PASSWORD` environment variables when the web app is served,
+    /// and the web app is open if they are not set.
+    pub fn web_credentials(mut self, username: &str, password: &str) -> Self {
+        self.web_auth.credentials = Some(WebCredentials::new(username, password));
+        self
+    }
+
+    /// Sets weither the read-only pages of the web app, such as the node
+    /// status or the topology, are open when it is protected by credentials,
+    /// `false` by default. The actions, such as stopping a node, the node
+    /// files and the config diffs always require the credentials. See
+    /// [`NetworkBuilder::web_credentials`].
+    pub fn web_public_status(mut self, public_status: bool) -> Self {
+        self.web_auth.public_status = public_status;
+        self
+    }
+
+    /// Sets the name of the chain, written in the chainspec. By default, a
+    /// random name such as `cnut-1a2b3c4d` is given to every network, so that
+    /// two networks running at the same time cannot mix their nodes or
+    /// deploys.
+    pub fn chain_name(self, chain_name: &str) -> Self {
+        Self {
+            chain_name: Some(chain_name.to_owned()),
+            ..self
+        }
+    }
+
+    /// Sets how the ports of the nodes are allocated, for example to leave
+    /// more room between the categories of a large network.
+    pub fn port_scheme(self, port_scheme: PortScheme) -> Self {
+        Self {
+            port_scheme,
+            ..self
+        }
+    }
+
+    /// Sets how the nodes are run: as child processes by default, or in Docker
+    /// containers.
+    pub fn backend(self, backend: Backend) -> Self {
+        Self { backend, ..self }
+    }
+
+    /// Prepares the files as usual, but never spawns the node processes:
+    /// starting a node only logs the command it would run, and sets its status
+    /// to [`NodeStatus::WouldRun`](super::NodeStatus::WouldRun). Disabled by
+    /// default.
+    pub fn dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
+    }
+
+    /// Sets how often the running nodes are polled for their status. One
+    /// second by default.
+    pub fn status_interval(mut self, interval: Duration) -> Self {
+        self.monitor_settings.interval = interval;
+        self
+    }
+
+    /// Sets the free space required on the filesystem hosting the run
+    /// directory, in bytes. The preparation fails early with
+    /// [`Error::InsufficientDiskSpace`](crate::error::Error::InsufficientDiskSpace)
+    /// if there is less. 1 GiB per node by default.
+    pub fn min_free_space(self, bytes: u64) -> Self {
+        Self {
+            min_free_space: Some(bytes),
+            ..self
+        }
+    }
+
+    /// Sets the free space, in bytes, below which a
+    /// [`NetworkEvent::LowDiskSpace`](super::NetworkEvent::LowDiskSpace) is
+    /// raised while the network runs. The space is checked at the status
+    /// interval. 512 MiB by default, zero disables the check.
+    pub fn low_disk_space_threshold(mut self, bytes: u64) -> Self {
+        self.monitor_settings.disk_space_threshold = bytes;
+        self
+    }
+
+    /// Sets how many status entries are kept per node, see
+    /// [`RunningNode::history`](super::RunningNode::history). The oldest
+    /// entries are dropped first. 600 by default, zero disables the polling.
+    pub fn history_length(mut self, length: usize) -> Self {
+        self.monitor_settings.history_length = length;
+        self
+    }
+
+    /// Sets how long the height of a node can stay the same before it is
+    /// reported as stalled, see
+    /// [`RunningNetwork::health`](super::RunningNetwork::health). One minute
+    /// by default.
+    pub fn stall_threshold(mut self, threshold: Duration) -> Self {
+        self.monitor_settings.stall_threshold = threshold;
+        self
+    }
+
+    /// Sets how many blocks a node can be behind the highest node before it
+    /// is reported as syncing, see
+    /// [`RunningNetwork::health`](super::RunningNetwork::health). 10 by
+    /// default.
+    pub fn syncing_lag(mut self, blocks: u64) -> Self {
+        self.monitor_settings.syncing_lag = blocks;
+        self
+    }
+
+    /// Sets how long [`RunningNetwork::wait_until_ready`](super::RunningNetwork::wait_until_ready)
+    /// waits for the nodes before failing, and how long
+    /// [`RunningNetwork::start_phased`](super::RunningNetwork::start_phased)
+    /// waits for a phase. One minute by default.
+    pub fn readiness_timeout(mut self, timeout: Duration) -> Self {
+        self.monitor_settings.readiness_timeout = timeout;
+        self
+    }
+
+    /// Sets how often [`RunningNetwork::wait_until_ready`](super::RunningNetwork::wait_until_ready)
+    /// checks the nodes. One second by default.
+    pub fn readiness_interval(mut self, interval: Duration) -> Self {
+        self.monitor_settings.readiness_interval = interval;
+        self
+    }
+
+    /// Returns the chainspec source.
+    ///
+    /// If it is not explicitely specified, we use the first node template one,
+    /// if the node has artifacts.
+    pub(crate) fn chainspec(&self) -> Option<Chainspec> {
+        match &self.chainspec {
+            Some(chainspec) => Some(chainspec.clone()),
+            None => self
+                .nodes
+                .first()
+                .and_then(|node| node.artifacts.as_ref())
+                .or(self.default_artifacts.as_ref())
+                .cloned()
+                .map(Chainspec::Artifacts),
+        }
+    }
+
+    /// Returns the preset values merged with the user ones, which win.
+    pub(crate) fn effective_chainspec_overrides(&self) -> toml::Table {
+        let preset = self
+            .chainspec_preset
+            .map(Preset::overrides)
+            .unwrap_or_default();
+
+        merge_tables(preset, self.chainspec_overrides.clone())
     }
 
     /// Prepares the network so that it is ready to start. Concretely, that
     /// means copying the files to their right location while patching the
     /// config and chainspec TOMLs with the correct values.
-    pub async fn prepare(self) -> Result<RunningNetwork> {
+    pub async fn prepare(self) -> Result<PreparedNetwork> {
         super::prepare_network(self).await
     }
 
+    /// Returns the chain name set by the user, either directly or through the
+    /// chainspec overrides, or a random one.
+    pub(crate) fn effective_chain_name(&self) -> String {
+        let overridden = self
+            .chainspec_overrides
+            .get("network")
+            .and_then(|network| network.get("name"))
+            .and_then(toml::Value::as_str);
+
+        match (&self.chain_name, overridden) {
+            (Some(chain_name), _) => chain_name.clone(),
+            (None, Some(chain_name)) => chain_name.to_owned(),
+            (None, None) => format!("cnut-{:08x}", rand::random::<u32>()),
+        }
+    }
+
+    /// Reads every distinct config and chainspec file once, and fails with all
+    /// the ones which are not valid TOML.
+    pub(crate) async fn check_toml_sources(&self) -> Result<()> {
+        let chainspec_path = match self.chainspec() {
+            Some(Chainspec::Path(path)) => Some(path),
+            Some(Chainspec::Artifacts(artifacts)) => Some(artifacts.chainspec_path()),
+            Some(Chainspec::Inline(_)) | None => None,
+        };
+        let node_chainspec_paths = self.nodes.iter().filter_map(|node| match &node.chainspec {
+            Some(Chainspec::Path(path)) => Some(path.clone()),
+            Some(Chainspec::Artifacts(artifacts)) => Some(artifacts.chainspec_path()),
+            Some(Chainspec::Inline(_)) | None => None,
+        });
+        let config_paths = self.nodes.iter().filter_map(|node| match &node.config {
+            Some(NodeConfig::Path(path)) => Some(path.clone()),
+            Some(NodeConfig::Artifacts(artifacts)) => Some(artifacts.config_path()),
+            Some(NodeConfig::Inline(_)) => None,
+            None => node
+                .artifacts
+                .as_ref()
+                .or(self.default_artifacts.as_ref())
+                .map(Artifacts::config_path),
+        });
+        let paths: BTreeSet<PathBuf> = chainspec_path
+            .into_iter()
+            .chain(node_chainspec_paths)
+            .chain(config_paths)
+            .collect();
+        let mut errors = Vec::new();
+
+        for path in paths {
+            let content =
+                tokio::fs::read_to_string(&path)
+                    .await
+                    .map_err(|io_err| Error::FileOperation {
+                        description: format!("reading {path:?}"),
+                        io_err,
+                    })?;
+            if let Err(e) = content.parse::<toml::Value>() {
+                errors.push((path, e));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidTomlFiles(errors))
+        }
+    }
+
     /// Returns the amount of nodes in the network.
     pub(crate) fn amount_nodes(&self) -> usize {
         self.nodes.iter().map(|n| n.amount).sum()
     }
+
+    pub(crate) fn amount_validators(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|n| n.validator)
+            .map(|n| n.amount)
+            .sum()
+    }
 }
 
 /// Several nodes to be added, with the given artifacts.
 #[derive(Debug, Clone)]
 pub struct Node {
-    pub(crate) artifacts: Artifacts,
+    /// The network default artifacts are used if `None`.
+    pub(crate) artifacts: Option<Artifacts>,
     pub(crate) amount: usize,
     /// Overload the config from `Artifacts`.
     pub(crate) config: Option<NodeConfig>,
+    /// Replaces the network chainspec, see [`Node::chainspec`].
+    pub(crate) chainspec: Option<Chainspec>,
     pub(crate) name: Option<String>,
     pub(crate) validator: bool,
+    /// Environment variables set for the node process.
+    pub(crate) env: BTreeMap<String, String>,
+    /// The config keys read from environment variables, see
+    /// [`Node::config_from_env`].
+    pub(crate) config_from_env: BTreeMap<String, String>,
+    /// Values applied on top of the config, see [`Node::config_overrides`].
+    pub(crate) config_overrides: toml::Table,
+    /// The genesis balance, drawn like the other ones if `None`.
+    pub(crate) balance: Option<u128>,
+    /// The genesis stake, drawn like the other ones if `None`.
+    pub(crate) bonded_amount: Option<u128>,
+    /// The node binary subcommand, `validator` by default.
+    pub(crate) launch_mode: String,
+    /// Arguments passed to the node binary after the config path.
+    pub(crate) extra_args: Vec<String>,
+    /// The command wrapping the node binary, see [`Node::launcher`].
+    pub(crate) launcher: Vec<String>,
+    /// When the node is started by [`RunningNetwork::start_phased`](super::RunningNetwork::start_phased).
+    pub(crate) start_phase: u8,
+    pub(crate) pinned_ports: PinnedPorts,
+    /// See [`Node::validate_config`].
+    pub(crate) config_validators: ConfigValidators,
+}
+
+type ConfigCheck = Arc<dyn Fn(&toml::Value) -> std::result::Result<(), String> + Send + Sync>;
+
+/// The checks run on the final config of a node. See [`Node::validate_config`].
+#[derive(Clone, Default)]
+pub(crate) struct ConfigValidators(Vec<ConfigCheck>);
+
+impl fmt::Debug for ConfigValidators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ConfigValidators")
+            .field(&self.0.len())
+            .finish()
+    }
+}
+
+impl ConfigValidators {
+    /// Runs the checks in order, and returns the reason of the first failure.
+    pub fn check(&self, config: &toml::Value) -> std::result::Result<(), String> {
+        self.0.iter().try_for_each(|check| check(config))
+    }
+}
+
+/// The ports set by the user for a node, instead of the automatic ones. See
+/// [`Node::rpc_port`].
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct PinnedPorts {
+    pub rpc: Option<u16>,
+    pub rest: Option<u16>,
+    pub bind: Option<u16>,
 }
 
 /// Where to find the chainspec for the network.
@@ -75,6 +690,16 @@ pub enum Chainspec {
     Path(PathBuf),
     /// An [`Artifacts`] structure.
     Artifacts(Artifacts),
+    /// The chainspec content itself, patched like the files.
+    Inline(toml::Value),
+}
+
+/// A [`Chainspec`] with a [`Preset`] applied on top of it. See
+/// [`Chainspec::preset`].
+#[derive(Debug, Clone)]
+pub struct ChainspecWithPreset {
+    chainspec: Chainspec,
+    preset: Preset,
 }
 
 /// Where to find the node configuration.
@@ -84,6 +709,8 @@ pub enum NodeConfig {
     Path(PathBuf),
     /// An [`Artifacts`] structure.
     Artifacts(Artifacts),
+    /// The config content itself, patched like the files.
+    Inline(toml::Value),
 }
 
 // Node
@@ -97,23 +724,54 @@ impl NetworkItem for Node {
 impl Node {
     /// Creates a new validator [`Node`] from [`Artifacts`].
     pub fn validator(artifacts: Artifacts) -> Self {
-        Self {
-            artifacts,
-            amount: 1,
-            config: None,
-            name: None,
-            validator: true,
-        }
+        Self::new(Some(artifacts), true)
+    }
+
+    /// Creates a new validator [`Node`] from [`Artifacts`], with the `weight`
+    /// as both its genesis balance and stake, in motes. It is a shorthand for
+    /// [`Node::balance`] and [`Node::bonded_amount`].
+    pub fn validator_with_weight(artifacts: Artifacts, weight: u128) -> Self {
+        Self::validator(artifacts)
+            .balance(weight)
+            .bonded_amount(weight)
     }
 
     /// Creates a new non-validator [`Node`] from [`Artifacts`].
     pub fn keep_up(artifacts: Artifacts) -> Self {
+        Self::new(Some(artifacts), false)
+    }
+
+    /// Creates a new validator [`Node`] using the network default artifacts.
+    /// See [`NetworkBuilder::default_artifacts`].
+    pub fn default_validator() -> Self {
+        Self::new(None, true)
+    }
+
+    /// Creates a new non-validator [`Node`] using the network default
+    /// artifacts. See [`NetworkBuilder::default_artifacts`].
+    pub fn default_keep_up() -> Self {
+        Self::new(None, false)
+    }
+
+    fn new(artifacts: Option<Artifacts>, validator: bool) -> Self {
         Self {
             artifacts,
             amount: 1,
             config: None,
+            chainspec: None,
             name: None,
-            validator: false,
+            validator,
+            env: BTreeMap::new(),
+            config_from_env: BTreeMap::new(),
+            config_overrides: toml::Table::new(),
+            balance: None,
+            bonded_amount: None,
+            launch_mode: DEFAULT_LAUNCH_MODE.to_owned(),
+            extra_args: Vec::new(),
+            launcher: Vec::new(),
+            start_phase: 0,
+            pinned_ports: PinnedPorts::default(),
+            config_validators: ConfigValidators::default(),
         }
     }
 
@@ -125,6 +783,151 @@ impl Node {
         }
     }
 
+    /// Gives this node or these nodes their own chainspec, instead of the
+    /// network one, for example to test an upgrade or a rejection. It is
+    /// patched like the network chainspec, with the chain name, the
+    /// activation point and the [overrides](NetworkBuilder::chainspec_overrides).
+    ///
+    /// A node whose chainspec differs from its peers usually refuses to
+    /// connect to them, which is what some tests check.
+    pub fn chainspec(self, chainspec: impl Into<Chainspec>) -> Self {
+        Self {
+            chainspec: Some(chainspec.into()),
+            ..self
+        }
+    }
+
+    /// Sets an environment variable for the process of this node or these
+    /// nodes, for example `RUST_LOG`. The variable is kept across restarts.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sources the config `key`, a dotted path such as
+    /// `consensus.highway.minimum_round_exponent`, from the environment
+    /// variable `var` when the network is prepared. If the variable is not
+    /// set, the config value is left as is.
+    ///
+    /// The variable content is parsed as a TOML value: `12` is an integer,
+    /// `1.5` a float, `true` a boolean and `[1, 2]` an array. Anything that is
+    /// not a valid TOML value is taken as a string, and a string that looks
+    /// like another type must be quoted: `"12"`.
+    pub fn config_from_env(mut self, key: impl Into<String>, var: impl Into<String>) -> Self {
+        self.config_from_env.insert(key.into(), var.into());
+        self
+    }
+
+    /// Applies the `overrides` on top of the config of this node or these
+    /// nodes, after the values set by cnut such as the ports. Calling it
+    /// several times merges the overrides.
+    pub fn config_overrides(self, overrides: toml::Table) -> Self {
+        Self {
+            config_overrides: merge_tables(self.config_overrides, overrides),
+            ..self
+        }
+    }
+
+    /// Checks the config of this node or these nodes once all the overrides
+    /// are applied, each time cnut writes it. The `check` returns why the
+    /// config is invalid, which aborts the preparation with
+    /// [`Error::InvalidNodeConfig`]. For example, to make sure that the node
+    /// listens on the expected port:
+    ///
+    /// ```no_run
+    /// # use cnut::{artifacts::Artifacts, network::Node};
+    /// # let artifacts = Artifacts::from_path("artifacts");
+    /// Node::validator(artifacts).validate_config(|config| {
+    ///     match config["network"]["bind_address"].as_str() {
+    ///         Some(address) if address.ends_with(":34553") => Ok(()),
+    ///         address => Err(format!("unexpected bind address {address:?}")),
+    ///     }
+    /// });
+    /// ```
+    pub fn validate_config<F>(mut self, check: F) -> Self
+    where
+        F: Fn(&toml::Value) -> std::result::Result<(), String> + Send + Sync + 'static,
+    {
+        self.config_validators.0.push(Arc::new(check));
+        self
+    }
+
+    /// Sets the genesis balance of the account of this node or these nodes,
+    /// in motes, instead of the default or randomized one.
+    pub fn balance(self, balance: u128) -> Self {
+        Self {
+            balance: Some(balance),
+            ..self
+        }
+    }
+
+    /// Sets the genesis stake of this validator or these validators, in motes,
+    /// instead of the one given by the
+    /// [stake distribution](NetworkBuilder::stake_distribution). It has no
+    /// effect on a non-validator node.
+    pub fn bonded_amount(self, bonded_amount: u128) -> Self {
+        Self {
+            bonded_amount: Some(bonded_amount),
+            ..self
+        }
+    }
+
+    /// Sets the subcommand the node binary is run with, instead of `validator`.
+    /// The non-validator nodes are run with `validator` as well by default,
+    /// since it is how the node binary runs any kind of node.
+    pub fn launch_mode(self, launch_mode: impl Into<String>) -> Self {
+        Self {
+            launch_mode: launch_mode.into(),
+            ..self
+        }
+    }
+
+    /// Pins the RPC port of the node, instead of the automatic one. The pinned
+    /// ports must be unique in the network, so a multiplied node cannot have
+    /// one.
+    pub fn rpc_port(mut self, port: u16) -> Self {
+        self.pinned_ports.rpc = Some(port);
+        self
+    }
+
+    /// Pins the REST port of the node. See [`Node::rpc_port`].
+    pub fn rest_port(mut self, port: u16) -> Self {
+        self.pinned_ports.rest = Some(port);
+        self
+    }
+
+    /// Pins the port the node binds to for the other nodes. See
+    /// [`Node::rpc_port`].
+    pub fn bind_port(mut self, port: u16) -> Self {
+        self.pinned_ports.bind = Some(port);
+        self
+    }
+
+    /// Sets the phase this node or these nodes are started in, 0 by default.
+    /// See [`RunningNetwork::start_phased`](super::RunningNetwork::start_phased).
+    pub fn start_phase(self, start_phase: u8) -> Self {
+        Self {
+            start_phase,
+            ..self
+        }
+    }
+
+    /// Passes additional arguments to the node binary, after the config path.
+    pub fn extra_args(self, extra_args: Vec<String>) -> Self {
+        Self { extra_args, ..self }
+    }
+
+    /// Runs the node binary through a wrapper command, such as
+    /// `["valgrind", "--leak-check=full"]`: the node is then started with
+    /// `valgrind --leak-check=full casper-node validator config.toml`. The
+    /// wrapper output is captured with the node one.
+    ///
+    /// Note that the signals are sent to the wrapper process, which must
+    /// forward them to the node. There is no wrapper by default.
+    pub fn launcher(self, launcher: Vec<String>) -> Self {
+        Self { launcher, ..self }
+    }
+
     /// Overloads the config for this node or these nodes.
     pub fn name(self, name: impl Into<String>) -> Self {
         Self {
@@ -158,10 +961,42 @@ impl<P: Into<PathBuf>> From<P> for NodeConfig {
 }
 
 impl NodeConfig {
-    pub(crate) fn path(&self) -> PathBuf {
+    /// Creates a config from its content. A `From<toml::Table>` implementation
+    /// would conflict with the path one.
+    pub fn inline(table: toml::Table) -> Self {
+        NodeConfig::Inline(table.into())
+    }
+
+    /// Creates a config from its TOML content, which is parsed right away.
+    /// Like the config files, it is written in the node directory with the
+    /// network and port values set by cnut:
+    ///
+    /// ```no_run
+    /// # use cnut::network::{Node, NodeConfig};
+    /// # fn f() -> cnut::error::Result<()> {
+    /// let config = NodeConfig::from_toml_str(
+    ///     r#"
+    ///     [consensus]
+    ///     secret_key_path = "secret_key.pem"
+    ///
+    ///     [network]
+    ///     bind_address = "0.0.0.0:0"
+    ///     "#,
+    /// )?;
+    /// let node = Node::default_validator().config(config);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_toml_str(content: &str) -> Result<Self> {
+        Ok(NodeConfig::Inline(content.parse()?))
+    }
+
+    /// Returns the config content.
+    pub(crate) async fn read(&self) -> Result<toml::Value> {
         match self {
-            Self::Path(path) => path.to_owned(),
-            Self::Artifacts(artifacts) => artifacts.config_path(),
+            Self::Path(path) => read_toml(path, "the config").await,
+            Self::Artifacts(artifacts) => read_toml(&artifacts.config_path(), "the config").await,
+            Self::Inline(value) => Ok(value.clone()),
         }
     }
 }
@@ -169,10 +1004,35 @@ impl NodeConfig {
 // Chainspec
 
 impl Chainspec {
-    pub(crate) fn path(&self) -> PathBuf {
+    /// Creates a chainspec from its content. A `From<toml::Table>`
+    /// implementation would conflict with the path one.
+    pub fn inline(table: toml::Table) -> Self {
+        Chainspec::Inline(table.into())
+    }
+
+    /// Creates a chainspec from its TOML content, which is parsed right away.
+    pub fn from_toml_str(content: &str) -> Result<Self> {
+        Ok(Chainspec::Inline(content.parse()?))
+    }
+
+    /// Returns the chainspec content.
+    pub(crate) async fn read(&self) -> Result<toml::Value> {
         match self {
-            Self::Path(path) => path.clone(),
-            Self::Artifacts(artifacts) => artifacts.chainspec_path(),
+            Self::Path(path) => read_toml(path, "the chainspec").await,
+            Self::Artifacts(artifacts) => {
+                read_toml(&artifacts.chainspec_path(), "the chainspec").await
+            }
+            Self::Inline(value) => Ok(value.clone()),
+        }
+    }
+}
+
+impl Chainspec {
+    /// Applies the given [`Preset`] on top of this chainspec.
+    pub fn preset(self, preset: Preset) -> ChainspecWithPreset {
+        ChainspecWithPreset {
+            chainspec: self,
+            preset,
         }
     }
 }
@@ -183,6 +1043,20 @@ impl NetworkItem for Chainspec {
     }
 }
 
+impl NetworkItem for ChainspecWithPreset {
+    fn add_to(self, network: &mut NetworkBuilder) {
+        network.chainspec = Some(self.chainspec);
+        network.chainspec_preset = Some(self.preset);
+    }
+}
+
+/// Applies the preset on top of the default chainspec.
+impl NetworkItem for Preset {
+    fn add_to(self, network: &mut NetworkBuilder) {
+        network.chainspec_preset = Some(self);
+    }
+}
+
 impl From<Artifacts> for Chainspec {
     fn from(artifacts: Artifacts) -> Self {
         Chainspec::Artifacts(artifacts)
@@ -194,3 +1068,123 @@ impl<P: Into<PathBuf>> From<P> for Chainspec {
         Chainspec::Path(path.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Returns the TOML file at `path` with a `[custom]` section, to tell it
+    /// apart from the file.
+    fn customized(path: &std::path::Path) -> toml::Table {
+        let mut table: toml::Table = std::fs::read_to_string(path).unwrap().parse().unwrap();
+        table.insert("custom".to_owned(), toml::toml! { key = 1 }.into());
+
+        table
+    }
+
+    /// Returns the `[custom]` key of the TOML file at `path`.
+    fn custom_key(path: &std::path::Path) -> Option<i64> {
+        let table: toml::Table = std::fs::read_to_string(path).unwrap().parse().unwrap();
+
+        table.get("custom")?.get("key")?.as_integer()
+    }
+
+    #[tokio::test]
+    async fn an_inline_config_is_patched_like_a_file() {
+        let (_dir, artifacts) = testing::artifacts();
+        let config = NodeConfig::inline(customized(&artifacts.config_path()));
+        let network = testing::builder()
+            .with(Node::validator(artifacts.clone()).config(config))
+            .with(Node::validator(artifacts.clone()))
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+
+        let inline = network.node_by_name("Node_A").unwrap();
+        assert_eq!(custom_key(&inline.config_path()), Some(1));
+        let written: toml::Table = std::fs::read_to_string(inline.config_path())
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(
+            written["rpc_server"]["address"].as_str(),
+            Some(format!("127.0.0.1:{}", inline.rpc_port()).as_str())
+        );
+        let from_file = network.node_by_name("Node_B").unwrap();
+        assert_eq!(custom_key(&from_file.config_path()), None);
+    }
+
+    #[tokio::test]
+    async fn an_inline_chainspec_is_patched_like_a_file() {
+        let (_dir, artifacts) = testing::artifacts();
+        let chainspec = Chainspec::inline(customized(&artifacts.chainspec_path()));
+        let network = testing::network(&artifacts, 2)
+            .with(chainspec)
+            .chain_name("inline")
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+
+        let chainspec = network.chainspec().await.unwrap();
+        assert_eq!(chainspec["custom"]["key"].as_integer(), Some(1));
+        assert_eq!(chainspec["network"]["name"].as_str(), Some("inline"));
+        assert_eq!(chainspec["core"]["validator_slots"].as_integer(), Some(2));
+    }
+
+    #[test]
+    fn an_invalid_inline_content_is_rejected() {
+        assert!(matches!(
+            NodeConfig::from_toml_str("[network"),
+            Err(Error::TomlParsing(_))
+        ));
+        assert!(matches!(
+            Chainspec::from_toml_str("name = "),
+            Err(Error::TomlParsing(_))
+        ));
+    }
+
+    /// Returns the `[network] name` of the chainspec written for the nodes of
+    /// the `network`.
+    async fn written_chain_name(network: &PreparedNetwork) -> String {
+        network.chainspec().await.unwrap()["network"]["name"]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[tokio::test]
+    async fn the_chainspec_has_the_chain_name() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 1)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+
+        assert_eq!(written_chain_name(&network).await, network.chain_name());
+    }
+
+    #[tokio::test]
+    async fn the_chain_name_is_random_unless_set() {
+        let (_dir, artifacts) = testing::artifacts();
+        let builder = || testing::network(&artifacts, 1).dry_run(true);
+
+        assert_ne!(
+            builder().effective_chain_name(),
+            builder().effective_chain_name()
+        );
+        let named = builder().chain_name("x").prepare().await.unwrap();
+        assert_eq!(named.chain_name(), "x");
+        assert_eq!(written_chain_name(&named).await, "x");
+        let overridden = builder()
+            .chainspec_overrides(crate::util::toml_map! { "network", "name" => "y" })
+            .prepare()
+            .await
+            .unwrap();
+        assert_eq!(overridden.chain_name(), "y");
+        assert_eq!(written_chain_name(&overridden).await, "y");
+    }
+}
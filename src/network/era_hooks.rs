@@ -0,0 +1,337 @@
+//! Runs the callbacks registered for the eras, to script scenarios such as
+//! "at era 3, stop a node".
+
+use crate::network::{monitor, NetworkEvent, PreparedNetwork, RunningNetwork, RunningNode};
+use futures::{
+    future::{join_all, BoxFuture},
+    FutureExt as _,
+};
+use std::{
+    collections::BTreeMap, fmt, future::Future, panic::AssertUnwindSafe, sync::Arc, time::Duration,
+};
+use tokio::{select, time::MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+type AtEra = Box<dyn FnOnce(RunningNetwork) -> BoxFuture<'static, ()> + Send>;
+type EveryEra = Arc<dyn Fn(RunningNetwork, u64) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// The callbacks registered with [`RunningNetwork::at_era`] and
+/// [`RunningNetwork::every_era`], shared by the network clones.
+#[derive(Default)]
+pub(crate) struct EraHooks {
+    at: BTreeMap<u64, Vec<AtEra>>,
+    every: Vec<EveryEra>,
+    /// The last era the callbacks were run for.
+    last_era: Option<u64>,
+}
+
+impl fmt::Debug for EraHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EraHooks")
+            .field("at", &self.at.keys().collect::<Vec<_>>())
+            .field("every", &self.every.len())
+            .field("last_era", &self.last_era)
+            .finish()
+    }
+}
+
+impl EraHooks {
+    fn is_empty(&self) -> bool {
+        self.at.is_empty() && self.every.is_empty()
+    }
+
+    /// Takes the callbacks to run once the network has reached the `era`, in
+    /// the order they must be run.
+    fn take_due(&mut self, era: u64) -> Vec<AtEra> {
+        let later = self.at.split_off(&era.saturating_add(1));
+        let mut due: Vec<AtEra> = std::mem::replace(&mut self.at, later)
+            .into_values()
+            .flatten()
+            .collect();
+
+        if self.last_era < Some(era) {
+            self.last_era = Some(era);
+            due.extend(
+                self.every
+                    .iter()
+                    .cloned()
+                    .map(|callback| Box::new(move |network| callback(network, era)) as AtEra),
+            );
+        }
+
+        due
+    }
+
+    fn at_era<F, Fut>(&mut self, era: u64, callback: F)
+    where
+        F: FnOnce(RunningNetwork) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.at
+            .entry(era)
+            .or_default()
+            .push(Box::new(move |network| callback(network).boxed()));
+    }
+
+    fn every_era<F, Fut>(&mut self, callback: F)
+    where
+        F: Fn(RunningNetwork, u64) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.every
+            .push(Arc::new(move |network, era| callback(network, era).boxed()));
+    }
+}
+
+impl PreparedNetwork {
+    /// Same as [`RunningNetwork::at_era`], the callback being registered
+    /// before the network starts.
+    pub fn at_era<F, Fut>(&self, era: u64, callback: F)
+    where
+        F: FnOnce(RunningNetwork) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.era_hooks
+            .lock()
+            .expect("poisoned lock")
+            .at_era(era, callback);
+    }
+
+    /// Same as [`RunningNetwork::every_era`], the callback being registered
+    /// before the network starts.
+    pub fn every_era<F, Fut>(&self, callback: F)
+    where
+        F: Fn(RunningNetwork, u64) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.era_hooks
+            .lock()
+            .expect("poisoned lock")
+            .every_era(callback);
+    }
+}
+
+impl RunningNetwork {
+    /// Runs the `callback` once, when the network first reaches the `era`, or
+    /// at the next poll if it is already reached. The callback is given a
+    /// handle of the network, which does not keep it running once the
+    /// network is dropped.
+    ///
+    /// The callbacks of the same era are run one after the other, in the
+    /// order they were registered, before the ones registered with
+    /// [`RunningNetwork::every_era`]. A panicking callback is reported with
+    /// [`NetworkEvent::EraHookPanicked`].
+    pub fn at_era<F, Fut>(&self, era: u64, callback: F)
+    where
+        F: FnOnce(RunningNetwork) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.era_hooks
+            .lock()
+            .expect("poisoned lock")
+            .at_era(era, callback);
+    }
+
+    /// Runs the `callback` each time the network reaches a new era, with the
+    /// era. See [`RunningNetwork::at_era`].
+    pub fn every_era<F, Fut>(&self, callback: F)
+    where
+        F: Fn(RunningNetwork, u64) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.era_hooks
+            .lock()
+            .expect("poisoned lock")
+            .every_era(callback);
+    }
+
+    /// Returns a handle of the network which does not keep the background
+    /// tasks running, nor prevents the network from being killed once all
+    /// the other handles are dropped.
+    pub(crate) fn detached(&self) -> RunningNetwork {
+        let mut network = self.clone();
+        network.shutdown_state = Default::default();
+        network._background_tasks_guard = Arc::new(CancellationToken::new().drop_guard());
+
+        network
+    }
+
+    /// Polls the era of the network every `interval`, and runs the due
+    /// callbacks, until the network is shut down.
+    pub(crate) fn spawn_era_hooks(&self, interval: Duration) {
+        let network = self.detached();
+        let cancellation = self.background_tasks.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                select! {
+                    _ = cancellation.cancelled() => break,
+                    _ = interval.tick() => (),
+                }
+
+                if network.era_hooks.lock().expect("poisoned lock").is_empty() {
+                    continue;
+                }
+                let Some(era) = current_era(&network.nodes).await else {
+                    continue;
+                };
+                let due = network
+                    .era_hooks
+                    .lock()
+                    .expect("poisoned lock")
+                    .take_due(era);
+
+                for callback in due {
+                    let handle = network.clone();
+                    let future =
+                        AssertUnwindSafe(async move { callback(handle).await }).catch_unwind();
+                    let result = select! {
+                        _ = cancellation.cancelled() => break,
+                        result = network.task_tracker.spawn(future) => result,
+                    };
+
+                    if let Ok(Err(panic)) = result {
+                        let message = panic_message(panic);
+                        log::error!("An era {era} callback panicked: {message}");
+                        let _ = network
+                            .events
+                            .send(NetworkEvent::EraHookPanicked { era, message });
+                    }
+                }
+            }
+
+            log::debug!("Era hooks stopped");
+        });
+    }
+}
+
+/// Returns the highest era reached by the running nodes, asked with their
+/// client, so that a node not answering is given up after the RPC timeout.
+async fn current_era(nodes: &[RunningNode]) -> Option<u64> {
+    join_all(nodes.iter().map(|node| async move {
+        if node.running().await {
+            monitor::fetch_block_info(&node.rpc_client, node).await
+        } else {
+            None
+        }
+    }))
+    .await
+    .into_iter()
+    .flatten()
+    .map(|info| info.era_id)
+    .max()
+}
+
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic payload".to_owned(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::sync::Mutex;
+
+    type Calls = Arc<Mutex<Vec<String>>>;
+
+    /// Registers callbacks recording their call in `calls`: two at the era 2,
+    /// one at the era 1 registered after them, and one every era.
+    fn hooks(calls: &Calls) -> EraHooks {
+        let mut hooks = EraHooks::default();
+        for (era, name) in [(2, "first at 2"), (2, "second at 2"), (1, "at 1")] {
+            let calls = calls.clone();
+            hooks.at_era(era, move |_| async move {
+                calls.lock().unwrap().push(name.to_owned());
+            });
+        }
+        let every = calls.clone();
+        hooks.every_era(move |_, era| {
+            let calls = every.clone();
+            async move { calls.lock().unwrap().push(format!("every at {era}")) }
+        });
+
+        hooks
+    }
+
+    /// Runs the `due` callbacks in order, and returns the new calls.
+    async fn run(due: Vec<AtEra>, network: &RunningNetwork, calls: &Calls) -> Vec<String> {
+        for callback in due {
+            callback(network.clone()).await;
+        }
+
+        std::mem::take(&mut *calls.lock().unwrap())
+    }
+
+    #[tokio::test]
+    async fn the_due_callbacks_are_taken_in_order() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 1)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let calls = Calls::default();
+        let mut hooks = hooks(&calls);
+
+        assert_eq!(
+            run(hooks.take_due(0), &network, &calls).await,
+            ["every at 0"]
+        );
+        assert_eq!(
+            run(hooks.take_due(2), &network, &calls).await,
+            ["at 1", "first at 2", "second at 2", "every at 2"]
+        );
+        assert!(run(hooks.take_due(2), &network, &calls).await.is_empty());
+        assert!(run(hooks.take_due(1), &network, &calls).await.is_empty());
+        assert_eq!(
+            run(hooks.take_due(3), &network, &calls).await,
+            ["every at 3"]
+        );
+        assert!(hooks.at.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_panicking_callback_is_reported_and_the_next_ones_run() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 1).prepare().await.unwrap();
+        let (sender, mut reached) = tokio::sync::mpsc::unbounded_channel();
+        network.at_era(1, |_| async { panic!("scripted failure") });
+        network.at_era(2, move |_| async move {
+            let _ = sender.send(());
+        });
+        let network = network.start().await.unwrap();
+        let mut events = network.subscribe();
+
+        let panicked = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let NetworkEvent::EraHookPanicked { era, message } = events.recv().await.unwrap()
+                {
+                    break (era, message);
+                }
+            }
+        })
+        .await
+        .expect("the panic to be reported");
+        assert_eq!(panicked.1, "scripted failure");
+        assert!(panicked.0 >= 1);
+        tokio::time::timeout(Duration::from_secs(10), reached.recv())
+            .await
+            .expect("the callback of the era 2 to run")
+            .unwrap();
+
+        network.shutdown();
+        network.wait().await.unwrap();
+    }
+}
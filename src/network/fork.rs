@@ -0,0 +1,168 @@
+//! Compares the blocks of the nodes, to detect the ones finalizing different
+//! blocks at the same height.
+
+use crate::{
+    error::Error,
+    network::{NetworkEvent, RunningNetwork, RunningNode},
+};
+use futures::future::join_all;
+use std::time::Duration;
+use tokio::{select, time::MissedTickBehavior};
+
+/// The result of [`RunningNetwork::check_fork`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForkCheck {
+    /// Weither the nodes with a block at the height agree on it.
+    pub agreement: BlockAgreement,
+    /// The running nodes without any block at the height yet, for example
+    /// because they are syncing.
+    pub without_block: Vec<String>,
+    /// The running nodes which did not answer.
+    pub unreachable: Vec<String>,
+}
+
+/// Weither the nodes have the same block at a given height.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockAgreement {
+    /// All the nodes with a block at the height have the one with this hash.
+    Consistent(String),
+    /// The nodes have different blocks: the name of each node, with the hash
+    /// of its block.
+    Forked(Vec<(String, String)>),
+    /// No node has a block at the height.
+    NoBlock,
+}
+
+impl RunningNetwork {
+    /// Asks every running node for its block at the given `height`, and
+    /// compares their hashes.
+    pub async fn check_fork(&self, height: u64) -> ForkCheck {
+        check_fork(&self.nodes, height).await
+    }
+
+    /// Checks the nodes for a fork every `interval`, at the highest block
+    /// height reached by all the running nodes, until the network is shut
+    /// down. When a fork is first observed, it is logged as an error and a
+    /// [`NetworkEvent::Fork`] is raised, then the watch stops.
+    pub fn fork_watch(&self, interval: Duration) {
+        let nodes = self.nodes.clone();
+        let events = self.events.clone();
+        let cancellation = self.background_tasks.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut checked_height = None;
+
+            loop {
+                select! {
+                    _ = cancellation.cancelled() => break,
+                    _ = interval.tick() => (),
+                }
+
+                let Some(height) = common_height(&nodes).await else {
+                    continue;
+                };
+                if checked_height == Some(height) {
+                    continue;
+                }
+                checked_height = Some(height);
+
+                if let BlockAgreement::Forked(hashes) = check_fork(&nodes, height).await.agreement {
+                    log::error!("FORK DETECTED at height {height}: {hashes:?}");
+                    let _ = events.send(NetworkEvent::Fork { height, hashes });
+                    break;
+                }
+            }
+
+            log::debug!("Fork watch stopped");
+        });
+    }
+}
+
+async fn check_fork(nodes: &[RunningNode], height: u64) -> ForkCheck {
+    let mut result = ForkCheck {
+        agreement: BlockAgreement::NoBlock,
+        without_block: Vec::new(),
+        unreachable: Vec::new(),
+    };
+    let mut hashes: Vec<(String, String)> = Vec::new();
+    let answers = join_all(nodes.iter().map(|node| async move {
+        if node.running().await {
+            Some((node.name().to_owned(), node.rpc().block_hash(height).await))
+        } else {
+            None
+        }
+    }))
+    .await;
+
+    for (name, answer) in answers.into_iter().flatten() {
+        match answer {
+            Ok(Some(hash)) => hashes.push((name, hash)),
+            // The node does not know the block:
+            Ok(None) | Err(Error::RpcResponse { .. }) => result.without_block.push(name),
+            Err(e) => {
+                log::debug!("Could not get the block {height} from {name}: {e}");
+                result.unreachable.push(name);
+            }
+        }
+    }
+
+    result.agreement = match hashes.first() {
+        None => BlockAgreement::NoBlock,
+        Some((_, first)) if hashes.iter().all(|(_, hash)| hash == first) => {
+            BlockAgreement::Consistent(first.clone())
+        }
+        Some(_) => BlockAgreement::Forked(hashes),
+    };
+
+    result
+}
+
+/// Returns the highest block height reached by all the running nodes which
+/// answered.
+pub(super) async fn common_height(nodes: &[RunningNode]) -> Option<u64> {
+    join_all(nodes.iter().map(|node| async move {
+        if node.running().await {
+            node.rpc().latest_block_height().await.ok().flatten()
+        } else {
+            None
+        }
+    }))
+    .await
+    .into_iter()
+    .flatten()
+    .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::Node, testing};
+
+    #[tokio::test]
+    async fn a_hung_node_is_unreachable() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 1)
+            .with(Node::validator(artifacts.clone()).env("CNUT_FAKE_NODE_HANG", "1"))
+            .rpc_timeout(Duration::from_millis(500))
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        network.wait_until_ready().await.unwrap();
+
+        let check = tokio::time::timeout(Duration::from_secs(5), network.check_fork(0))
+            .await
+            .expect("the hung node to time out");
+        assert_eq!(
+            check.agreement,
+            BlockAgreement::Consistent(testing::BLOCK_HASH.to_owned())
+        );
+        assert_eq!(check.unreachable, ["Node_B"]);
+
+        network.stop_all().await.unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+//! Cuts a node from the network by emptying the addresses it knows, for the
+//! partition experiments, and restores them afterwards.
+
+use crate::{
+    error::{Error, Result},
+    network::{prepare, topology, RunningNetwork, RunningNode},
+    util::{read_toml, toml_map},
+};
+use std::net::SocketAddr;
+
+impl RunningNetwork {
+    /// Isolates the node with the given `name`: the known addresses of its
+    /// config are removed, and the node is restarted if it is running. They
+    /// are restored by [`RunningNetwork::rejoin_node`].
+    ///
+    /// Only the config of the node is changed: the peers knowing its address
+    /// may still connect to it. It waits for the start or stop operation in
+    /// progress on the node, if any.
+    pub async fn isolate_node(&self, name: &str) -> Result<()> {
+        let (index, node) = self.indexed_node(name)?;
+        let _operation = node.operation_lock.clone().lock_owned().await;
+
+        if self.is_isolated(name) {
+            return Err(Error::NodeAlreadyIsolated(name.to_owned()));
+        }
+        let known_addresses = topology::known_addresses(&node).await?;
+        rewrite_known_addresses(node, index, &[])
+            .await
+            .map_err(|e| e.context(format!("isolating the node {name}")))?;
+        self.isolated
+            .lock()
+            .expect("poisoned lock")
+            .insert(name.to_owned(), known_addresses);
+        log::info!("Node {name} isolated");
+
+        Ok(())
+    }
+
+    /// Restores the known addresses of a node isolated with
+    /// [`RunningNetwork::isolate_node`], and restarts it if it is running.
+    pub async fn rejoin_node(&self, name: &str) -> Result<()> {
+        let (index, node) = self.indexed_node(name)?;
+        let _operation = node.operation_lock.clone().lock_owned().await;
+
+        let known_addresses = self
+            .isolated
+            .lock()
+            .expect("poisoned lock")
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::NodeNotIsolated(name.to_owned()))?;
+        rewrite_known_addresses(node, index, &known_addresses)
+            .await
+            .map_err(|e| e.context(format!("rejoining the node {name}")))?;
+        self.isolated.lock().expect("poisoned lock").remove(name);
+        log::info!("Node {name} rejoined the network");
+
+        Ok(())
+    }
+
+    /// Returns weither the node with the given `name` is isolated. See
+    /// [`RunningNetwork::isolate_node`].
+    pub fn is_isolated(&self, name: &str) -> bool {
+        self.isolated
+            .lock()
+            .expect("poisoned lock")
+            .contains_key(name)
+    }
+
+    fn indexed_node(&self, name: &str) -> Result<(usize, RunningNode)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .find(|(_, node)| node.name == name)
+            .map(|(index, node)| (index, node.clone()))
+            .ok_or_else(|| Error::NodeNameNotFound(name.to_owned()))
+    }
+}
+
+/// Writes the known addresses in the config of the node, and restarts it if
+/// it is running. The caller must hold the operation lock.
+async fn rewrite_known_addresses(
+    mut node: RunningNode,
+    index: usize,
+    known_addresses: &[SocketAddr],
+) -> Result<()> {
+    let running = node.running().await;
+    if running {
+        node.stop_locked().await?;
+        let _ = node.wait_for_exit().await;
+    }
+
+    let config_path = node.config_path();
+    let known_addresses: Vec<String> = known_addresses.iter().map(ToString::to_string).collect();
+    prepare::write_config(
+        read_toml(&config_path, "the config").await?,
+        &config_path,
+        &prepare::config_header(&node, index),
+        toml_map! {
+            "network", "known_addresses" => known_addresses,
+        },
+        &node.config_validators,
+    )
+    .await?;
+
+    if running {
+        node.start_locked().await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{testing, util::read_toml};
+
+    /// Returns the known addresses written in the config of the node.
+    async fn known_addresses(network: &crate::network::RunningNetwork, name: &str) -> Vec<String> {
+        let config_path = network.node_by_name(name).unwrap().config_path();
+        let config = read_toml(&config_path, "the config").await.unwrap();
+
+        config["network"]["known_addresses"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|address| address.as_str().unwrap().to_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn isolation_rewrites_the_known_addresses() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 3)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let original = known_addresses(&network, "Node_A/1").await;
+        assert_eq!(original.len(), 3);
+
+        network.isolate_node("Node_A/1").await.unwrap();
+        assert!(known_addresses(&network, "Node_A/1").await.is_empty());
+        assert_eq!(known_addresses(&network, "Node_A/0").await.len(), 3);
+        assert!(network.node_by_name("Node_A/1").unwrap().running().await);
+        assert!(network.isolate_node("Node_A/1").await.is_err());
+
+        network.rejoin_node("Node_A/1").await.unwrap();
+        assert_eq!(known_addresses(&network, "Node_A/1").await, original);
+        assert!(!network.is_isolated("Node_A/1"));
+        assert!(network.rejoin_node("Node_A/1").await.is_err());
+
+        network.stop_all().await.unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+//! The coarse lifecycle state of the whole network.
+
+use crate::network::{NetworkEvent, RunningNetwork};
+use std::fmt;
+
+/// The state of the whole network. See [`RunningNetwork::state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkState {
+    /// The network is prepared, no node has been started yet.
+    Prepared,
+    /// The nodes are being started.
+    Starting,
+    /// All the nodes have been started.
+    Running,
+    /// The nodes are being stopped, and the shutdown callbacks run.
+    ShuttingDown,
+    /// All the nodes have been stopped, or the network has shut down.
+    Stopped,
+}
+
+impl fmt::Display for NetworkState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Prepared => "prepared",
+            Self::Starting => "starting",
+            Self::Running => "running",
+            Self::ShuttingDown => "shutting down",
+            Self::Stopped => "stopped",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl RunningNetwork {
+    /// Returns the state of the whole network. It is updated when the nodes
+    /// are started or stopped together, and when the network shuts down. Each
+    /// change is also sent as a [`NetworkEvent::StateChanged`].
+    pub fn state(&self) -> NetworkState {
+        *self.state.borrow()
+    }
+
+    /// Waits until the network is in the given `state`, and returns right
+    /// away if it already is.
+    pub async fn wait_for_state(&self, state: NetworkState) {
+        let mut receiver = self.state.subscribe();
+        let _ = receiver.wait_for(|current| *current == state).await;
+    }
+
+    /// Changes the state of the network, and notifies the subscribers.
+    pub(super) fn set_state(&self, state: NetworkState) {
+        let changed = self.state.send_if_modified(|current| {
+            let changed = *current != state;
+            *current = state;
+            changed
+        });
+
+        if changed {
+            log::debug!("The network is now {state}");
+            let _ = self.events.send(NetworkEvent::StateChanged(state));
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Handles the output of the nodes: it is written to files and watched for
+//! alerts while the nodes run, then the files can be gathered so that they
+//! survive the deletion of the temporary directory, for example to be uploaded
+//! as a CI failure artifact.
+
+use crate::{
+    error::{Error, Result},
+    network::{NetworkEvent, RunningNetwork, RunningNode},
+    util,
+};
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::{AsyncBufReadExt as _, AsyncRead, AsyncWriteExt as _, BufReader},
+};
+
+/// Files always present in a node directory.
+const NODE_FILES: [&str; 3] = ["config.toml", "chainspec.toml", "accounts.toml"];
+
+/// Patterns raising an alert when none is specified.
+pub(crate) const DEFAULT_ALERT_PATTERNS: [&str; 3] = ["panicked at", "ERROR", "unwrap"];
+
+/// A line of a node output which matched one of the alert patterns. See
+/// [`NetworkBuilder::log_alert_patterns`](crate::network::NetworkBuilder::log_alert_patterns).
+#[derive(Debug, Clone)]
+pub struct LogAlert {
+    /// The name of the node.
+    pub node: String,
+    /// The full line.
+    pub line: String,
+    /// When the line was read.
+    pub timestamp: SystemTime,
+}
+
+/// Content of the `manifest.json` file written at the root of the bundle.
+#[derive(Serialize)]
+struct Manifest {
+    run_directory: String,
+    chain_name: String,
+    nodes: Vec<ManifestNode>,
+}
+
+#[derive(Serialize)]
+struct ManifestNode {
+    name: String,
+    validator: bool,
+    public_key: String,
+    rpc_port: u16,
+    rest_port: u16,
+    speculative_execution_port: u16,
+    bind_port: u16,
+    event_stream_port: u16,
+    /// The ports set by the user rather than automatically: `rpc`, `rest`
+    /// or `bind`.
+    pinned_ports: Vec<&'static str>,
+    /// The files copied in the node directory of the bundle.
+    files: Vec<String>,
+}
+
+impl RunningNetwork {
+    /// Copies the logs, config and chainspec of every node into a directory
+    /// named after the node under `dest`, and writes a `manifest.json`
+    /// describing the bundle at its root.
+    ///
+    /// The nodes which never produced any log are skipped with a warning.
+    pub async fn collect_logs(&self, dest: impl AsRef<Path>) -> Result<()> {
+        let dest = dest.as_ref();
+        let mut manifest = Manifest {
+            run_directory: self.temp_directory().to_string_lossy().into_owned(),
+            chain_name: self.chain_name().to_owned(),
+            nodes: Vec::with_capacity(self.nodes.len()),
+        };
+
+        for node in &self.nodes {
+            let node_dest = dest.join(node.name());
+            let mut files = Vec::new();
+
+            fs::create_dir_all(&node_dest)
+                .await
+                .map_err(|io_err| Error::FileOperation {
+                    description: format!("creating the log directory {node_dest:?}"),
+                    io_err,
+                })?;
+
+            for file_name in NODE_FILES {
+                copy_file(&node.data_dir().join(file_name), &node_dest.join(file_name)).await?;
+                files.push(file_name.to_owned());
+            }
+
+            for src in [node.stdout_path(), node.stderr_path()] {
+                let file_name = src.file_name().expect("log file name").to_string_lossy();
+
+                if !fs::try_exists(&src).await.unwrap_or(false) {
+                    log::warn!("Node {} has no {file_name}, skipping it", node.name());
+                    continue;
+                }
+                copy_file(&src, &node_dest.join(&*file_name)).await?;
+                files.push(file_name.into_owned());
+            }
+
+            manifest.nodes.push(ManifestNode {
+                name: node.name().to_owned(),
+                validator: node.validator(),
+                public_key: node.public_key().to_string(),
+                rpc_port: node.rpc_port(),
+                rest_port: node.rest_port(),
+                speculative_execution_port: node.speculative_execution_port(),
+                bind_port: node.bind_port(),
+                event_stream_port: node.event_stream_port(),
+                pinned_ports: node.pinned_port_names(),
+                files,
+            });
+        }
+
+        let manifest_path = dest.join("manifest.json");
+        fs::write(
+            &manifest_path,
+            serde_json::to_string_pretty(&manifest).expect("JSON serialization failed"),
+        )
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("writing the manifest {manifest_path:?}"),
+            io_err,
+        })?;
+
+        log::info!("Logs collected in {dest:?}");
+
+        Ok(())
+    }
+}
+
+impl RunningNetwork {
+    /// Copies the storage directory of the node with the given `name` into
+    /// `dest`, for offline analysis.
+    ///
+    /// The node is not stopped: the copy of the storage of a running node may
+    /// be inconsistent, so a warning is logged in that case.
+    pub async fn snapshot_storage(&self, name: &str, dest: impl AsRef<Path>) -> Result<()> {
+        let node = self.node_by_name(name)?;
+
+        if node.running().await {
+            log::warn!("Node {name} is running: its storage snapshot may be inconsistent");
+        }
+
+        util::copy_dir(&node.storage_path(), dest.as_ref()).await
+    }
+}
+
+async fn copy_file(src: &Path, dest: &Path) -> Result<()> {
+    fs::copy(src, dest)
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("copying the file {src:?} to {dest:?}"),
+            io_err,
+        })?;
+
+    Ok(())
+}
+
+/// Appends the `output` of the node to the `dest` file, and raises an alert for
+/// each line containing one of the `patterns`.
+///
+/// The output is read until the end even if it cannot be written, so that the
+/// node never blocks on a full pipe.
+pub(crate) async fn watch_output(
+    output: impl AsyncRead + Unpin,
+    dest: PathBuf,
+    node: RunningNode,
+    patterns: Arc<[String]>,
+) {
+    let mut file = match OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&dest)
+        .await
+    {
+        Ok(file) => Some(file),
+        Err(io_err) => {
+            log::warn!(
+                "Cannot open {dest:?}, the output of {} is lost: {io_err:?}",
+                node.name()
+            );
+            None
+        }
+    };
+    let mut output = BufReader::new(output);
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        match output.read_until(b'\n', &mut buf).await {
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(io_err) => {
+                log::warn!("Cannot read the output of {}: {io_err:?}", node.name());
+                break;
+            }
+        }
+
+        if let Some(writer) = file.as_mut() {
+            if let Err(io_err) = writer.write_all(&buf).await {
+                log::warn!(
+                    "Cannot write to {dest:?}, the output of {} is lost: {io_err:?}",
+                    node.name()
+                );
+                file = None;
+            }
+        }
+
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end();
+        if patterns
+            .iter()
+            .any(|pattern| line.contains(pattern.as_str()))
+        {
+            log::warn!("Alert raised by {}: {line}", node.name());
+
+            let alert = LogAlert {
+                node: node.name().to_owned(),
+                line: line.to_owned(),
+                timestamp: SystemTime::now(),
+            };
+            node.alerts.lock().await.push(alert.clone());
+            // There may be no subscriber:
+            let _ = node.events.send(NetworkEvent::LogAlert(alert));
+        }
+    }
+}
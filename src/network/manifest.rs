@@ -0,0 +1,194 @@
+//! Describes a running network in a file, so that another process can reach
+//! its nodes, for example to check their status from the command line.
+
+use crate::{
+    error::{Error, Result},
+    network::{monitor, RunningNetwork},
+};
+use futures::future::join_all;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+
+/// The file written at the root of the run directory by
+/// [`RunningNetwork::write_manifest`].
+const MANIFEST_FILE: &str = "network.json";
+
+/// Where the nodes of a network are reached. See
+/// [`RunningNetwork::write_manifest`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkManifest {
+    /// The name of the chain.
+    pub chain_name: String,
+    /// The directory the network runs in.
+    pub run_directory: PathBuf,
+    /// The nodes, in the order they were added.
+    pub nodes: Vec<ManifestNode>,
+}
+
+/// A node of a [`NetworkManifest`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestNode {
+    /// The name of the node.
+    pub name: String,
+    /// Weither the node is a validator.
+    pub validator: bool,
+    /// The ID of the node process when the manifest was written, if it was
+    /// running.
+    pub pid: Option<u32>,
+    /// The address of the RPC server.
+    pub rpc_address: SocketAddr,
+    /// The address of the REST server.
+    pub rest_address: SocketAddr,
+    /// The port the node binds to for the other nodes.
+    #[serde(default)]
+    pub bind_port: u16,
+    /// The names of the ports pinned with [`Node::rpc_port`],
+    /// [`Node::rest_port`] or [`Node::bind_port`]: `rpc`, `rest` or `bind`. The
+    /// other ones were allocated automatically.
+    ///
+    /// [`Node::rpc_port`]: crate::network::Node::rpc_port
+    /// [`Node::rest_port`]: crate::network::Node::rest_port
+    /// [`Node::bind_port`]: crate::network::Node::bind_port
+    #[serde(default)]
+    pub pinned_ports: Vec<String>,
+}
+
+/// The status of a node, as answered at its REST address. See
+/// [`NetworkManifest::status`].
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeReport {
+    /// The name of the node.
+    pub name: String,
+    /// Weither the node answered with its last block.
+    pub reachable: bool,
+    /// The era of the last block added by the node.
+    pub era: Option<u64>,
+    /// The height of the last block added by the node.
+    pub height: Option<u64>,
+}
+
+impl RunningNetwork {
+    /// Writes the [`NetworkManifest`] of the network as `network.json` at the
+    /// root of its directory, and returns the file path. It is read with
+    /// [`NetworkManifest::read`].
+    pub async fn write_manifest(&self) -> Result<PathBuf> {
+        let manifest = NetworkManifest {
+            chain_name: self.chain_name().to_owned(),
+            run_directory: self.temp_directory().to_owned(),
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| ManifestNode {
+                    name: node.name().to_owned(),
+                    validator: node.validator(),
+                    pid: node.pid(),
+                    rpc_address: node.rpc_address(),
+                    rest_address: node.rest_address(),
+                    bind_port: node.bind_port(),
+                    pinned_ports: node
+                        .pinned_port_names()
+                        .into_iter()
+                        .map(ToOwned::to_owned)
+                        .collect(),
+                })
+                .collect(),
+        };
+        let path = self.temp_directory().join(MANIFEST_FILE);
+
+        fs::write(
+            &path,
+            serde_json::to_string_pretty(&manifest).expect("JSON serialization failed"),
+        )
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("writing the network manifest {path:?}"),
+            io_err,
+        })?;
+
+        Ok(path)
+    }
+}
+
+impl NetworkManifest {
+    /// Reads the manifest written by [`RunningNetwork::write_manifest`].
+    pub async fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("reading the network manifest {path:?}"),
+                io_err,
+            })?;
+
+        serde_json::from_str(&content).map_err(|json_err| Error::InvalidManifest {
+            path: path.to_owned(),
+            json_err,
+        })
+    }
+
+    /// Asks each node for its last block. The nodes which do not answer are
+    /// reported as unreachable.
+    pub async fn status(&self) -> Vec<NodeReport> {
+        let client = Client::new();
+
+        join_all(self.nodes.iter().map(|node| {
+            let client = &client;
+            async move {
+                let info =
+                    monitor::fetch_block_info_at(client, node.rest_address, &node.name).await;
+
+                NodeReport {
+                    name: node.name.clone(),
+                    reachable: info.is_some(),
+                    era: info.as_ref().map(|info| info.era_id),
+                    height: info.map(|info| info.height),
+                }
+            }
+        }))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::Node, testing};
+
+    #[tokio::test]
+    async fn the_pinned_ports_are_in_the_manifest() {
+        let (_dir, artifacts) = testing::artifacts();
+        // Not bound in a dry run:
+        let bind_port = 65_000;
+        let network = testing::network(&artifacts, 1)
+            .with(Node::validator(artifacts.clone()).bind_port(bind_port))
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+
+        let path = network.write_manifest().await.unwrap();
+        let manifest = NetworkManifest::read(&path).await.unwrap();
+
+        let auto = &manifest.nodes[0];
+        assert!(auto.pinned_ports.is_empty());
+        assert_eq!(
+            auto.bind_port,
+            network.node_by_name("Node_A").unwrap().bind_port()
+        );
+        let pinned = &manifest.nodes[1];
+        assert_eq!(pinned.pinned_ports, ["bind"]);
+        assert_eq!(pinned.bind_port, bind_port);
+        assert_eq!(
+            pinned.rpc_address,
+            network.node_by_name("Node_B").unwrap().rpc_address()
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! Polls the status of the running nodes in the background, and keeps a
+//! bounded history of their progress, which tells if they are stalled. Also
+//! watches the free disk space.
+
+use crate::{
+    error::{Error, Result},
+    network::{NetworkEvent, RunningNetwork, RunningNode},
+    util,
+};
+use futures::future::join_all;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write as _},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::{fs, select, sync::broadcast, time::MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+/// How the nodes are polled.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MonitorSettings {
+    /// Time between two polls.
+    pub interval: Duration,
+    /// The maximum amount of entries kept per node. Zero disables the polling.
+    pub history_length: usize,
+    /// The free space, in bytes, below which an event is raised. Zero
+    /// disables the check.
+    pub disk_space_threshold: u64,
+    /// How long the height of a node can stay the same before it is stalled.
+    pub stall_threshold: Duration,
+    /// How many blocks a node can be behind before it is syncing.
+    pub syncing_lag: u64,
+    /// How long the nodes have to become ready.
+    pub readiness_timeout: Duration,
+    /// Time between two readiness checks.
+    pub readiness_interval: Duration,
+}
+
+/// The state of a node, derived from its recorded progress. See
+/// [`RunningNetwork::health`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeHealth {
+    /// The node is running, and adds blocks like the other nodes.
+    Healthy,
+    /// The node is running, but its height has not increased for longer than
+    /// the [stall threshold](super::NetworkBuilder::stall_threshold).
+    Stalled {
+        /// When the node reached its current height.
+        since: SystemTime,
+    },
+    /// The node is running, but it is behind the highest node by more than
+    /// the [syncing lag](super::NetworkBuilder::syncing_lag), or it has not
+    /// added any block yet.
+    Syncing {
+        /// The amount of blocks the node is behind.
+        behind: u64,
+    },
+    /// The node is not running.
+    Down,
+}
+
+/// The progress of a node at a given time. See [`RunningNode::history`].
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryEntry {
+    /// When the node was polled.
+    pub timestamp: SystemTime,
+    /// The era of the last block added by the node.
+    pub era_id: u64,
+    /// The height of the last block added by the node.
+    pub height: u64,
+}
+
+#[derive(Serialize)]
+struct ExportedEntry {
+    timestamp: String,
+    era_id: u64,
+    height: u64,
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    last_added_block_info: Option<LastAddedBlockInfo>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct LastAddedBlockInfo {
+    pub era_id: u64,
+    pub height: u64,
+}
+
+impl Default for MonitorSettings {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            history_length: 600,
+            disk_space_threshold: 512 << 20,
+            stall_threshold: Duration::from_secs(60),
+            syncing_lag: 10,
+            readiness_timeout: Duration::from_secs(60),
+            readiness_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl NodeHealth {
+    /// Returns the name of the state, such as `stalled`.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Healthy => "healthy",
+            Self::Stalled { .. } => "stalled",
+            Self::Syncing { .. } => "syncing",
+            Self::Down => "down",
+        }
+    }
+}
+
+impl fmt::Display for NodeHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stalled { since } => {
+                let stalled_for = since.elapsed().unwrap_or_default();
+                let stalled_for = Duration::from_secs(stalled_for.as_secs());
+
+                write!(f, "stalled for {}", humantime::format_duration(stalled_for))
+            }
+            Self::Syncing { behind } => write!(f, "syncing, {behind} blocks behind"),
+            other => f.write_str(other.label()),
+        }
+    }
+}
+
+impl RunningNode {
+    /// Returns the recorded progress of the node, oldest first. The stopped
+    /// nodes are not polled, so there is no entry for the time they were not
+    /// running.
+    pub async fn history(&self) -> Vec<HistoryEntry> {
+        self.history.lock().await.iter().copied().collect()
+    }
+}
+
+impl RunningNetwork {
+    /// Returns the state of each node, in the order they were added, derived
+    /// from their recorded progress. The nodes are only known to be stalled
+    /// or syncing if their progress is recorded, see
+    /// [`NetworkBuilder::history_length`](super::NetworkBuilder::history_length).
+    pub async fn health(&self) -> Vec<(&RunningNode, NodeHealth)> {
+        let progress = join_all(self.nodes.iter().map(progress)).await;
+        let max_height = max_height(&progress);
+
+        self.nodes
+            .iter()
+            .zip(progress)
+            .map(|(node, progress)| {
+                let health = health(progress, max_height, &self.monitor_settings);
+                (node, health)
+            })
+            .collect()
+    }
+
+    /// Writes the recorded progress of all the nodes into the file at `path`:
+    /// as JSON (an object with a list of entries per node name) if the file
+    /// extension is `json`, as CSV otherwise.
+    pub async fn export_history(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let mut histories = BTreeMap::new();
+
+        for node in &self.nodes {
+            histories.insert(node.name(), node.history().await);
+        }
+
+        let content = if path.extension().is_some_and(|ext| ext == "json") {
+            let histories: BTreeMap<_, Vec<_>> = histories
+                .into_iter()
+                .map(|(name, history)| (name, history.iter().map(ExportedEntry::from).collect()))
+                .collect();
+
+            serde_json::to_string_pretty(&histories).expect("JSON serialization failed")
+        } else {
+            let mut csv = String::from("node,timestamp,era_id,height\n");
+
+            for (name, history) in histories {
+                for entry in history.iter().map(ExportedEntry::from) {
+                    let _ = writeln!(
+                        csv,
+                        "{name},{},{},{}",
+                        entry.timestamp, entry.era_id, entry.height
+                    );
+                }
+            }
+
+            csv
+        };
+
+        fs::write(path, content)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("writing the history {path:?}"),
+                io_err,
+            })
+    }
+}
+
+impl From<&HistoryEntry> for ExportedEntry {
+    fn from(entry: &HistoryEntry) -> Self {
+        Self {
+            timestamp: humantime::format_rfc3339_millis(entry.timestamp).to_string(),
+            era_id: entry.era_id,
+            height: entry.height,
+        }
+    }
+}
+
+/// Polls the nodes and the free space in `run_dir` until the `cancellation`
+/// token is cancelled.
+pub(crate) async fn monitor(
+    nodes: Vec<RunningNode>,
+    run_dir: PathBuf,
+    events: broadcast::Sender<NetworkEvent>,
+    settings: MonitorSettings,
+    cancellation: CancellationToken,
+) {
+    let client = Client::builder()
+        .timeout(settings.interval.max(Duration::from_secs(1)))
+        .build()
+        .expect("the HTTP client to be valid");
+    let mut interval = tokio::time::interval(settings.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut low_disk_space = false;
+    let mut stalled = vec![false; nodes.len()];
+
+    loop {
+        select! {
+            _ = cancellation.cancelled() => break,
+            _ = interval.tick() => (),
+        }
+
+        if settings.disk_space_threshold > 0 {
+            low_disk_space = check_disk_space(
+                &run_dir,
+                settings.disk_space_threshold,
+                low_disk_space,
+                &events,
+            );
+        }
+        if settings.history_length > 0 {
+            join_all(
+                nodes
+                    .iter()
+                    .map(|node| sample(&client, node, settings.history_length)),
+            )
+            .await;
+            check_stalls(&nodes, &settings, &mut stalled, &events).await;
+        }
+    }
+
+    log::debug!("Node monitoring stopped");
+}
+
+/// The progress of a node: `None` if it is not running, otherwise its last
+/// height, and when it was first reached, if any block is recorded.
+type Progress = Option<Option<(u64, SystemTime)>>;
+
+async fn progress(node: &RunningNode) -> Progress {
+    if !node.running().await {
+        return None;
+    }
+    let history = node.history.lock().await;
+    let Some(last) = history.back() else {
+        return Some(None);
+    };
+    let reached_at = history
+        .iter()
+        .rev()
+        .take_while(|entry| entry.height == last.height)
+        .last()
+        .map_or(last.timestamp, |entry| entry.timestamp);
+
+    Some(Some((last.height, reached_at)))
+}
+
+fn max_height(progress: &[Progress]) -> u64 {
+    progress
+        .iter()
+        .flatten()
+        .flatten()
+        .map(|(height, _)| *height)
+        .max()
+        .unwrap_or_default()
+}
+
+fn health(progress: Progress, max_height: u64, settings: &MonitorSettings) -> NodeHealth {
+    match progress {
+        None => NodeHealth::Down,
+        Some(None) => NodeHealth::Syncing { behind: max_height },
+        Some(Some((_, since)))
+            if since.elapsed().unwrap_or_default() >= settings.stall_threshold =>
+        {
+            NodeHealth::Stalled { since }
+        }
+        Some(Some((height, _))) if max_height - height > settings.syncing_lag => {
+            NodeHealth::Syncing {
+                behind: max_height - height,
+            }
+        }
+        Some(Some(_)) => NodeHealth::Healthy,
+    }
+}
+
+/// Raises an event when a node becomes stalled, once until it is not
+/// anymore.
+async fn check_stalls(
+    nodes: &[RunningNode],
+    settings: &MonitorSettings,
+    stalled: &mut [bool],
+    events: &broadcast::Sender<NetworkEvent>,
+) {
+    let progress = join_all(nodes.iter().map(progress)).await;
+    let max_height = max_height(&progress);
+
+    for ((node, progress), was_stalled) in nodes.iter().zip(progress).zip(stalled) {
+        let health = health(progress, max_height, settings);
+        let is_stalled = matches!(health, NodeHealth::Stalled { .. });
+
+        if let (NodeHealth::Stalled { since }, false) = (health, *was_stalled) {
+            log::warn!("The node {} is {health}", node.name());
+            let _ = events.send(NetworkEvent::NodeStalled {
+                name: node.name().to_owned(),
+                since,
+            });
+        }
+        *was_stalled = is_stalled;
+    }
+}
+
+/// Raises an event when the free space drops below the threshold, once until
+/// it gets back above. Returns weither the space is below the threshold.
+fn check_disk_space(
+    run_dir: &Path,
+    threshold: u64,
+    was_low: bool,
+    events: &broadcast::Sender<NetworkEvent>,
+) -> bool {
+    let Ok(Some(available)) = util::available_space(run_dir) else {
+        return was_low;
+    };
+    let low = available < threshold;
+
+    if low && !was_low {
+        log::warn!("Only {available} bytes left for the network in {run_dir:?}");
+        let _ = events.send(NetworkEvent::LowDiskSpace {
+            available,
+            threshold,
+        });
+    }
+
+    low
+}
+
+async fn sample(client: &Client, node: &RunningNode, history_length: usize) {
+    if !node.running().await {
+        return;
+    }
+    let Some(info) = fetch_block_info(client, node).await else {
+        return;
+    };
+
+    let mut history = node.history.lock().await;
+    if history.len() >= history_length {
+        history.pop_front();
+    }
+    history.push_back(HistoryEntry {
+        timestamp: SystemTime::now(),
+        era_id: info.era_id,
+        height: info.height,
+    });
+}
+
+/// Returns the last block added by the node, if the node answered and has one.
+pub(super) async fn fetch_block_info(
+    client: &Client,
+    node: &RunningNode,
+) -> Option<LastAddedBlockInfo> {
+    fetch_block_info_at(client, node.rest_address(), node.name()).await
+}
+
+/// Same as [`fetch_block_info`], for the node of the given `name` reachable
+/// at the REST `address`.
+pub(super) async fn fetch_block_info_at(
+    client: &Client,
+    address: SocketAddr,
+    name: &str,
+) -> Option<LastAddedBlockInfo> {
+    let response = client
+        .get(format!("http://{address}/status"))
+        .send()
+        .await
+        .ok()?;
+
+    match response.json::<Payload>().await {
+        Ok(payload) => payload.last_added_block_info,
+        Err(e) => {
+            log::debug!("Could not deserialize the status of {name}: {e:?}");
+            None
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! How the ports of the nodes are allocated.
+
+use crate::error::{Error, Result};
+
+/// The ports of the nodes which are not pinned: the port of a category for the
+/// node number `i` is `base + i * stride`. See
+/// [`NetworkBuilder::port_scheme`](super::NetworkBuilder::port_scheme).
+///
+/// The range of each category must not overlap the other ones, which is checked
+/// when the network is prepared. The default bases leave room for about 1000
+/// nodes: larger networks need bases further apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortScheme {
+    /// The base of the ports the nodes bind to for the other nodes.
+    pub bind: u16,
+    /// The base of the speculative execution ports.
+    pub speculative_execution: u16,
+    /// The base of the RPC ports.
+    pub rpc: u16,
+    /// The base of the REST ports.
+    pub rest: u16,
+    /// The base of the event stream ports.
+    pub event_stream: u16,
+    /// The gap between the ports of two consecutive nodes. It cannot be zero.
+    pub stride: u16,
+}
+
+/// A category of ports, with its name.
+pub(crate) type Category = (&'static str, fn(&PortScheme) -> u16);
+
+pub(crate) const BIND: Category = ("bind", |scheme| scheme.bind);
+pub(crate) const SPECULATIVE_EXECUTION: Category = ("speculative execution", |scheme| {
+    scheme.speculative_execution
+});
+pub(crate) const RPC: Category = ("rpc", |scheme| scheme.rpc);
+pub(crate) const REST: Category = ("rest", |scheme| scheme.rest);
+pub(crate) const EVENT_STREAM: Category = ("event stream", |scheme| scheme.event_stream);
+
+const CATEGORIES: [Category; 5] = [BIND, SPECULATIVE_EXECUTION, RPC, REST, EVENT_STREAM];
+
+impl Default for PortScheme {
+    fn default() -> Self {
+        Self {
+            bind: 34000,
+            speculative_execution: 6666,
+            rpc: 7777,
+            rest: 8888,
+            event_stream: 9999,
+            stride: 1,
+        }
+    }
+}
+
+impl PortScheme {
+    /// Returns the port of the `category` for the node at `index`, or `None`
+    /// if it is past the last port.
+    pub(crate) fn port(&self, (_, base): Category, index: usize) -> Option<u16> {
+        let offset = u16::try_from(index).ok()?.checked_mul(self.stride)?;
+
+        base(self).checked_add(offset)
+    }
+
+    /// Fails if the stride is zero, if the ports of `amount_nodes` nodes do
+    /// not fit, or if two categories overlap.
+    pub(crate) fn validate(&self, amount_nodes: usize) -> Result<()> {
+        // The nodes added later would all look for a port forever:
+        if self.stride == 0 {
+            return Err(Error::ZeroPortStride);
+        }
+        if amount_nodes == 0 {
+            return Ok(());
+        }
+        let mut ranges = Vec::with_capacity(CATEGORIES.len());
+
+        for category @ (name, base) in CATEGORIES {
+            let last = self
+                .port(category, amount_nodes - 1)
+                .ok_or(Error::PortRangeOverflow {
+                    category: name,
+                    amount_nodes,
+                })?;
+            ranges.push((name, base(self), last));
+        }
+        ranges.sort_by_key(|&(_, first, _)| first);
+
+        for pair in ranges.windows(2) {
+            let [(first, _, last), (second, start, _)] = pair else {
+                unreachable!("windows of 2")
+            };
+            if start <= last {
+                return Err(Error::PortRangesOverlap {
+                    first,
+                    second,
+                    amount_nodes,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_stride_is_rejected() {
+        let scheme = PortScheme {
+            stride: 0,
+            ..PortScheme::default()
+        };
+
+        assert!(matches!(scheme.validate(0), Err(Error::ZeroPortStride)));
+        assert!(matches!(scheme.validate(3), Err(Error::ZeroPortStride)));
+        assert!(PortScheme::default().validate(3).is_ok());
+    }
+}
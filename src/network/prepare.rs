@@ -2,151 +2,588 @@
 //! consists of configuration and filesystem operations.
 
 use crate::{
+    artifacts::Artifacts,
     error::{Error, Result},
-    network::{NetworkBuilder, RunningNetwork, RunningNode},
-    util::{crypto::generate_pair, toml_map, update_toml, LettersGen, Spinner},
+    network::{
+        config_diff,
+        config_patch::{NetworkSection, NodeConfigPatch, ServerSection, StorageSection},
+        describe::ConfigValidators,
+        monitor, ports, Backend, GenesisAccounts, NetworkBuilder, NetworkEvent, NetworkState,
+        PhaseWait, PortScheme, PreparedNetwork, RunningNetwork, RunningNode, StakeDistribution,
+    },
+    util::{
+        self,
+        crypto::{generate_pair, generate_pair_like},
+        merge_tables, read_toml, toml_map, update_toml, LettersGen, RunDirectory, Spinner,
+    },
 };
+use rand::{rngs::StdRng, Rng as _, SeedableRng as _};
 use std::{
-    path::Path,
-    str::FromStr as _,
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet},
+    hash::{Hash as _, Hasher as _},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, SystemTime},
 };
-use tempfile::TempDir;
-use tokio::fs;
-use tokio_util::task::TaskTracker;
+use tokio::{
+    fs,
+    sync::{broadcast, watch},
+};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+/// How many events are kept for the slow subscribers.
+const EVENTS_CAPACITY: usize = 1024;
+/// The balance of every account, in motes, when they are not randomized.
+const DEFAULT_BALANCE: u128 = 1_000_000_000_000_000_000_000_000_000;
+/// The stake of every validator, in motes, when they are not randomized.
+const DEFAULT_BONDED_AMOUNT: u128 = 500_000_000_000_000;
+/// The free space required for each node, in bytes, when not specified.
+const DEFAULT_FREE_SPACE_PER_NODE: u64 = 1 << 30;
 
-pub async fn prepare_network(network: NetworkBuilder) -> Result<RunningNetwork> {
-    let temp_directory = create_temp_dir()?;
+pub async fn prepare_network(mut network: NetworkBuilder) -> Result<PreparedNetwork> {
+    // Fail fast, before creating anything:
+    if network.chainspec().is_none() {
+        return Err(Error::NoChainspec);
+    }
+    network.check_toml_sources().await?;
+    check_free_space(&network)?;
+    network.port_scheme.validate(network.amount_nodes())?;
+    if let Some(distribution) = &network.stake_distribution {
+        distribution.validate(network.amount_validators())?;
+    }
+    let temp_directory = create_temp_dir(&network.run_dir_prefix, network.keep_files)?;
     let base_data_dir = temp_directory.path();
     let chainspec_path = base_data_dir.join("chainspec.toml");
     let accounts_path = base_data_dir.join("accounts.toml");
-    let spinner = Spinner::create("Preparing the node files");
+    let spinner = Spinner::create("Preparing the node files", network.output_mode);
     let task_tracker = {
         let tt = TaskTracker::default();
         tt.close();
         tt
     };
 
-    log::debug!("Running dir created at: {:?}", base_data_dir);
-    println!("Running dir created at: {:?}", base_data_dir);
+    log::info!("Network files are in {}", base_data_dir.display());
 
-    write_chainspec(
-        network.chainspec_path(),
-        &chainspec_path,
+    // Gather the overridden artifact files, so that each node sees a single folder:
+    if let Some(artifacts) = network.default_artifacts.take() {
+        network.default_artifacts = Some(materialize_artifacts(artifacts, base_data_dir).await?);
+    }
+    for node in &mut network.nodes {
+        if let Some(artifacts) = node.artifacts.take() {
+            node.artifacts = Some(materialize_artifacts(artifacts, base_data_dir).await?);
+        }
+    }
+
+    let chain_name = network.effective_chain_name();
+    let chainspec_overrides = {
+        let mut network_section = toml::Table::new();
+        network_section.insert("name".to_owned(), chain_name.as_str().into());
+        let mut chain_name_patch = toml::Table::new();
+        chain_name_patch.insert("network".to_owned(), network_section.into());
+
+        merge_tables(network.effective_chainspec_overrides(), chain_name_patch)
+    };
+    let chainspec = network.chainspec();
+    let amount_nodes = network.amount_nodes();
+    let activation_delay = network
+        .activation_delay
+        .max(network.startup_allowance * amount_nodes as u32);
+    log::debug!(
+        "The genesis happens in {}",
+        humantime::format_duration(activation_delay)
+    );
+    let chainspec_updates = merge_tables(
         toml_map! {
-            "core", "validator_slots" => network.amount_nodes() as i64,
-            "protocol", "activation_point" => millis_from_now(1000),
+            "core", "validator_slots" => amount_nodes as i64,
+            "protocol", "activation_point" => time_from_now(activation_delay),
             "protocol", "version" => "1.0.0",
         },
+        chainspec_overrides.clone(),
+    );
+    // The activation point may be overridden, with an era for example:
+    let activation_point = chainspec_updates
+        .get("protocol")
+        .and_then(|protocol| protocol.get("activation_point"))
+        .and_then(toml::Value::as_str)
+        .and_then(|timestamp| humantime::parse_rfc3339_weak(timestamp).ok());
+    let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+    let log_alert_patterns = network
+        .capture_logs
+        .then(|| Arc::from(network.log_alert_patterns));
+    let rng = match network.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut node_factory = NodeFactory {
+        base_data_dir: base_data_dir.to_owned(),
+        default_artifacts: network.default_artifacts,
+        task_tracker: task_tracker.clone(),
+        events: events.clone(),
+        log_alert_patterns,
+        dry_run: network.dry_run,
+        backend: network.backend,
+        absolute_storage_path: network.absolute_storage_path,
+        rng,
+        conf_names: LettersGen::new(),
+        groups: BTreeSet::new(),
+        next_index: 0,
+        used_ports: BTreeMap::new(),
+        port_scheme: network.port_scheme,
+        chain_name: Arc::from(chain_name.as_str()),
+        bind_host: network.bind_host,
+        chainspec_updates: chainspec_updates.clone(),
+        shared_genesis: network.shared_genesis,
+        shutdown_timeout: network.shutdown_timeout,
+        rpc_client: reqwest::Client::builder()
+            .timeout(network.rpc_timeout)
+            .build()
+            .expect("the HTTP client to be valid"),
+    };
+    let mut nodes = node_factory.create(network.nodes)?;
+
+    let chainspec = chainspec.ok_or(Error::NoChainspec)?;
+    write_chainspec(
+        chainspec.read().await?,
+        &chainspec_path,
+        chainspec_updates.clone(),
     )
     .await?;
 
-    let nodes = node_data(network.nodes, base_data_dir, &task_tracker);
+    let known_addresses = known_addresses(&nodes);
 
-    let known_addresses: Vec<_> = (port::bind(0)..port::bind(nodes.len()))
-        .map(|i| toml::Value::from(format!("127.0.0.1:{i}")))
-        .collect();
+    // Create the `accounts.toml` file to the root, before it is linked:
+    let (accounts, genesis_stakes) = accounts(
+        &nodes,
+        &mut node_factory.rng,
+        network.balance_range,
+        network.stake_distribution.as_ref(),
+    );
+    let genesis_accounts = GenesisAccounts::generated(
+        accounts.clone(),
+        nodes
+            .iter()
+            .map(|node| (node.name.clone(), node.public_key().to_string())),
+    );
+    check_genesis(&nodes, &genesis_accounts, &chainspec_updates)?;
+    fs::write(
+        &accounts_path,
+        toml::to_string_pretty(&accounts).expect("TOML serialization failed"),
+    )
+    .await
+    .map_err(|io_err| Error::FileOperation {
+        description: format!("writing the chainspec accounts {accounts_path:?}"),
+        io_err,
+    })?;
 
-    // Create an empty accounts file to be able to link to (the hardlink call fails otherwise):
-    fs::write(&accounts_path, "")
+    for (index, node) in nodes.iter_mut().enumerate() {
+        write_node_files(
+            node,
+            index,
+            &known_addresses,
+            network.absolute_storage_path,
+            base_data_dir,
+            &chainspec_updates,
+        )
         .await
-        .map_err(|io_err| Error::FileOperation {
-            description: format!("creating the accounts file {accounts_path:?}"),
-            io_err,
-        })?;
+        .map_err(|e| e.context(format!("preparing the node {}", node.name)))?;
+    }
+
+    spinner.success();
+
+    Ok(PreparedNetwork {
+        nodes,
+        temp_directory,
+        chainspec_overrides,
+        monitor_settings: network.monitor_settings,
+        output_mode: network.output_mode,
+        task_tracker,
+        events,
+        node_factory,
+        chain_name,
+        shutdown_timeout: network.shutdown_timeout,
+        web_port: network.web_port,
+        web_auth: network.web_auth,
+        era_hooks: Default::default(),
+        genesis_stakes,
+        genesis_accounts,
+        activation_point,
+        strict_genesis: network.strict_genesis,
+    })
+}
+
+impl RunningNode {
+    /// Replaces the keys of the stopped node by new ones, of the same
+    /// algorithm. The previous secret key is kept as `secret_key.pem.old`.
+    ///
+    /// The `accounts.toml` file is part of the genesis, so it is not changed:
+    /// with its new key, the node is not a genesis validator anymore. This is
+    /// meant to test the synchronization, or the identity of the nodes.
+    pub async fn rotate_keys(&self) -> Result<()> {
+        if self.running().await {
+            return Err(Error::NodeRunning(self.name.clone()));
+        }
+        let secret_key_path = self.secret_key_path();
+        let old_path = self.data_dir.join("secret_key.pem.old");
 
-    for (index, node) in nodes.iter().enumerate() {
-        // Create the directory:
-        fs::create_dir_all(&node.data_dir)
+        fs::rename(&secret_key_path, &old_path)
             .await
             .map_err(|io_err| Error::FileOperation {
-                description: format!("cannot create the folder {:?}", node.data_dir),
+                description: format!("moving the secret key {secret_key_path:?} to {old_path:?}"),
                 io_err,
             })?;
 
+        let (public_key, secret_key) =
+            generate_pair_like(&self.public_key(), &mut StdRng::from_entropy());
+        public_key.write_pem(self.public_key_path()).await?;
+        public_key.write_hex(self.public_key_hex_path()).await?;
+        secret_key.write_pem(&secret_key_path).await?;
+        log::info!("Node {} has the new public key {public_key}", self.name);
+        *self.keys.write().expect("poisoned lock") = (public_key, secret_key);
+
+        Ok(())
+    }
+
+    /// Patches the chainspec of this node only, such as to test a validator
+    /// with a divergent chainspec. The change is seen at the next start of
+    /// the node.
+    ///
+    /// This fails if the chainspec is a hard link to the one of the other
+    /// nodes, which is the case unless the node has its own chainspec or the
+    /// network is built with
+    /// [`shared_genesis(false)`](NetworkBuilder::shared_genesis).
+    pub async fn patch_chainspec(&self, updates: toml::Table) -> Result<()> {
+        if self.links_chainspec() {
+            return Err(Error::SharedChainspec(self.name.clone()));
+        }
+        let chainspec_path = self.chainspec_path();
+
+        write_chainspec(
+            read_toml(&chainspec_path, "the chainspec").await?,
+            &chainspec_path,
+            updates,
+        )
+        .await
+    }
+
+    /// Returns `true` if the chainspec of the node is linked to the shared
+    /// one.
+    fn links_chainspec(&self) -> bool {
+        self.shared_genesis && self.chainspec.is_none()
+    }
+}
+
+impl RunningNetwork {
+    /// Adds the `node` to the network, and returns it ready to be started. Its
+    /// files are prepared in the run directory, and it is given its own ports,
+    /// and the address of all the other nodes.
+    ///
+    /// The node is not part of the genesis: it has no account, and must join
+    /// the network and catch up with it once started. If `node` was multiplied,
+    /// a single node is added anyway.
+    ///
+    /// The clones of the network made before this call, such as the one of the
+    /// web app, do not know about the new node, and it is not monitored. This
+    /// is why it takes `&mut self`, unlike the other methods of the network:
+    /// each clone has its own list of nodes, and sharing it would change every
+    /// access to the nodes.
+    ///
+    /// Fails with [`Error::DuplicateNodeName`] if the name of the `node` is
+    /// taken, without using up an index or a port.
+    pub async fn add_node(&mut self, mut node: super::Node) -> Result<&RunningNode> {
+        if let Some(name) = &node.name {
+            if self
+                .nodes
+                .iter()
+                .any(|running| &running.name == name || &running.group == name)
+            {
+                return Err(Error::DuplicateNodeName(name.clone()));
+            }
+        }
+        node.amount = 1;
+        if let Some(artifacts) = node.artifacts.take() {
+            node.artifacts = Some(materialize_artifacts(artifacts, self.temp_directory()).await?);
+        }
+        // The index of the node, written in its config header:
+        let (mut running_node, index, absolute_storage_path, chainspec_updates) = {
+            let mut node_factory = self.node_factory.lock().expect("poisoned lock");
+            let index = node_factory.next_index;
+            let running_node = node_factory
+                .create(vec![node])?
+                .pop()
+                .expect("a node to be created");
+
+            (
+                running_node,
+                index,
+                node_factory.absolute_storage_path,
+                node_factory.chainspec_updates.clone(),
+            )
+        };
+
+        let known_addresses: Vec<_> = known_addresses(&self.nodes)
+            .into_iter()
+            .chain(known_addresses(std::slice::from_ref(&running_node)))
+            .collect();
+        write_node_files(
+            &mut running_node,
+            index,
+            &known_addresses,
+            absolute_storage_path,
+            self.temp_directory(),
+            &chainspec_updates,
+        )
+        .await
+        .map_err(|e| e.context(format!("adding the node {}", running_node.name)))?;
+        log::info!("Node {} added to the network", running_node.name);
+        self.nodes.push(running_node);
+
+        Ok(self.nodes.last().expect("the node to be added"))
+    }
+}
+
+impl PreparedNetwork {
+    /// Returns the number of nodes in the network.
+    pub fn nodes_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns the directory where all the data is located in.
+    pub fn temp_directory(&self) -> &Path {
+        self.temp_directory.path()
+    }
+
+    /// Returns the values applied on top of the chainspec template: the
+    /// [`Preset`](super::Preset) ones merged with the user overrides.
+    pub fn chainspec_overrides(&self) -> &toml::Table {
+        &self.chainspec_overrides
+    }
+
+    /// Reads the chainspec shared by the nodes: the template with the
+    /// [overrides](PreparedNetwork::chainspec_overrides) and the values set by
+    /// the network applied on top of it.
+    pub async fn chainspec(&self) -> Result<toml::Table> {
+        let chainspec_path = self.temp_directory().join("chainspec.toml");
+
+        match read_toml(&chainspec_path, "the chainspec").await? {
+            toml::Value::Table(chainspec) => Ok(chainspec),
+            _ => unreachable!("a TOML document is a table"),
+        }
+    }
+
+    /// Returns the name of the chain. See [`NetworkBuilder::chain_name`].
+    pub fn chain_name(&self) -> &str {
+        &self.chain_name
+    }
+
+    /// Returns the accounts written in `accounts.toml`. See
+    /// [`RunningNetwork::genesis_accounts`].
+    pub fn genesis_accounts(&self) -> &GenesisAccounts {
+        &self.genesis_accounts
+    }
+
+    /// Returns the names of the nodes, in the order they were added.
+    pub fn node_names(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().map(RunningNode::name)
+    }
+
+    /// Returns the directory of the node with the given `name`, with its
+    /// config, keys, chainspec, etc.
+    pub fn node_data_dir(&self, name: &str) -> Result<&Path> {
+        Ok(self.node_by_name(name)?.data_dir())
+    }
+
+    /// Patches the config of the node with the given `name`, with either a raw
+    /// TOML table or a [`NodeConfigPatch`].
+    pub async fn apply_config_overrides(
+        &self,
+        name: &str,
+        updates: impl Into<toml::Table>,
+    ) -> Result<()> {
+        let index = self
+            .nodes
+            .iter()
+            .position(|node| node.name == name)
+            .ok_or_else(|| Error::NodeNameNotFound(name.to_owned()))?;
+        let node = &self.nodes[index];
+        let config_path = node.config_path();
+
         write_config(
-            &node.default_config_path,
-            node.data_dir.join("config.toml"),
-            toml_map! {
-                "network", "bind_address" => format!("0.0.0.0:{}", port::bind(index)),
-                "network", "known_addresses" => known_addresses.clone(),
-                "rpc_server", "address" => format!("0.0.0.0:{}", node.rpc_port),
-                "speculative_exec_server", "address" => format!("0.0.0.0:{}", node.speculative_execution_port),
-                "rest_server", "address" => format!("0.0.0.0:{}", node.rest_port),
-                "event_stream_server", "address" => format!("0.0.0.0:{}", port::event_stream(index)),
-                "storage", "path" => "./node-storage",
-            },
+            read_toml(&config_path, "the config").await?,
+            &config_path,
+            &config_header(node, index),
+            updates.into(),
+            &node.config_validators,
         )
-        .await?;
+        .await
+    }
 
-        // Create the `pem` files:
-        node.public_key
-            .write_pem(node.data_dir.join("public_key.pem"))
-            .await?;
-        node.secret_key
-            .write_pem(node.data_dir.join("secret_key.pem"))
-            .await?;
+    /// Patches the chainspec shared by all the nodes, and the ones of the
+    /// nodes with their own chainspec. See [`Node::chainspec`](super::Node::chainspec)
+    /// and [`NetworkBuilder::shared_genesis`].
+    pub async fn apply_chainspec_overrides(&self, updates: toml::Table) -> Result<()> {
+        let own_chainspecs = self
+            .nodes
+            .iter()
+            .filter(|node| !node.links_chainspec())
+            .map(RunningNode::chainspec_path);
 
-        // Link the chainspec (including the accounts):
+        // The files are written in place, so that the hard links are preserved:
+        for chainspec_path in
+            std::iter::once(self.temp_directory().join("chainspec.toml")).chain(own_chainspecs)
         {
-            let dest = node.data_dir.join("chainspec.toml");
-            fs::hard_link(&chainspec_path, &dest)
-                .await
-                .map_err(|io_err| Error::FileOperation {
-                    description: format!(
-                        "hard-linking the chainspec {chainspec_path:?} to {dest:?}"
-                    ),
-                    io_err,
-                })?;
-            let dest = node.data_dir.join("accounts.toml");
-            fs::hard_link(&accounts_path, &dest)
-                .await
-                .map_err(|io_err| Error::FileOperation {
-                    description: format!("hard-linking the accounts {accounts_path:?} to {dest:?}"),
+            write_chainspec(
+                read_toml(&chainspec_path, "the chainspec").await?,
+                &chainspec_path,
+                updates.clone(),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the file `file_name` in the directory of the node with the
+    /// given `name` by a copy of `src`.
+    ///
+    /// The previous file is unlinked first, so replacing a file shared by
+    /// several nodes (such as the chainspec) only affects this node.
+    pub async fn replace_file(
+        &self,
+        name: &str,
+        file_name: impl AsRef<Path>,
+        src: impl AsRef<Path>,
+    ) -> Result<()> {
+        let (file_name, src) = (file_name.as_ref(), src.as_ref());
+        let dest = self.node_data_dir(name)?.join(file_name);
+
+        match fs::remove_file(&dest).await {
+            Err(io_err) if io_err.kind() != std::io::ErrorKind::NotFound => {
+                return Err(Error::FileOperation {
+                    description: format!("removing the file {dest:?}"),
                     io_err,
-                })?;
+                })
+            }
+            _ => (),
         }
+        fs::copy(src, &dest)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("copying the file {src:?} to {dest:?}"),
+                io_err,
+            })?;
+
+        Ok(())
     }
 
-    // Create the `accounts.toml` file to the root:
-    fs::write(
-        &accounts_path,
-        toml::to_string_pretty(&accounts(&nodes)).expect("TOML serialization failed"),
-    )
-    .await
-    .map_err(|io_err| Error::FileOperation {
-        description: format!("writing the chainspec accounts {accounts_path:?}"),
-        io_err,
-    })?;
+    /// Starts all the nodes, and returns the running network.
+    pub async fn start(self) -> Result<RunningNetwork> {
+        let network = self.into_running();
 
-    spinner.success();
+        network.start_all().await?;
 
-    Ok(RunningNetwork {
-        nodes,
-        temp_directory,
-        shutdown_state: Default::default(),
-        exit_notification: Arc::new(Default::default()),
-        task_tracker,
-    })
+        Ok(network)
+    }
+
+    /// Starts the nodes phase by phase, and returns the running network. See
+    /// [`RunningNetwork::start_phased`].
+    pub async fn start_phased(self, wait_between: PhaseWait) -> Result<RunningNetwork> {
+        let network = self.into_running();
+
+        network.start_phased(wait_between).await?;
+
+        Ok(network)
+    }
+
+    /// Same as [`PreparedNetwork::start`], kept so that the
+    /// `prepare().await?.start_all()` chain keeps working.
+    pub async fn start_all(self) -> Result<RunningNetwork> {
+        self.start().await
+    }
+
+    fn into_running(self) -> RunningNetwork {
+        let Self {
+            nodes,
+            temp_directory,
+            chainspec_overrides: _,
+            monitor_settings,
+            output_mode,
+            task_tracker,
+            events,
+            mut node_factory,
+            chain_name,
+            shutdown_timeout,
+            web_port,
+            web_auth,
+            era_hooks,
+            genesis_stakes,
+            genesis_accounts,
+            activation_point,
+            strict_genesis,
+        } = self;
+        let selection_rng =
+            StdRng::from_rng(&mut node_factory.rng).expect("StdRng to be seedable from StdRng");
+        let background_tasks = CancellationToken::new();
+
+        if monitor_settings.history_length > 0 || monitor_settings.disk_space_threshold > 0 {
+            tokio::spawn(monitor::monitor(
+                nodes.clone(),
+                temp_directory.path().to_owned(),
+                events.clone(),
+                monitor_settings,
+                background_tasks.clone(),
+            ));
+        }
+
+        let network = RunningNetwork {
+            nodes,
+            temp_directory,
+            shutdown_state: Default::default(),
+            exit_notification: Arc::new(Default::default()),
+            output_mode,
+            task_tracker,
+            events,
+            _background_tasks_guard: Arc::new(background_tasks.clone().drop_guard()),
+            background_tasks,
+            node_factory: Arc::new(std::sync::Mutex::new(node_factory)),
+            chain_name,
+            shutdown_timeout,
+            web_port,
+            web_auth,
+            era_hooks,
+            genesis_stakes,
+            genesis_accounts,
+            selection_rng: Arc::new(std::sync::Mutex::new(selection_rng)),
+            health_cache: Default::default(),
+            monitor_settings,
+            isolated: Default::default(),
+            shutdown_hooks: Default::default(),
+            state: Arc::new(watch::channel(NetworkState::Prepared).0),
+            activation_point,
+            strict_genesis,
+        };
+        network.spawn_era_hooks(monitor_settings.interval);
+
+        network
+    }
+
+    /// Returns the node with the given `name`.
+    pub fn node_by_name(&self, name: &str) -> Result<&RunningNode> {
+        self.nodes
+            .iter()
+            .find(|node| node.name == name)
+            .ok_or_else(|| Error::NodeNameNotFound(name.to_owned()))
+    }
 }
 
 async fn write_chainspec(
-    src: impl AsRef<Path>,
+    chainspec: toml::Value,
     dest: impl AsRef<Path>,
     updates: toml::Table,
 ) -> Result<()> {
-    let (src, dest) = (src.as_ref(), dest.as_ref());
+    let dest = dest.as_ref();
 
-    log::debug!("Copying the chainspec from {src:?} to {dest:?}");
-
-    let chainspec = fs::read_to_string(src)
-        .await
-        .map_err(|io_err| Error::FileOperation {
-            description: format!("reading the chainspec {src:?}"),
-            io_err,
-        })?;
-    let chainspec = toml::Value::from_str(&chainspec)?;
+    log::debug!("Writing the chainspec to {dest:?}");
 
     fs::write(
         dest,
@@ -162,26 +599,29 @@ async fn write_chainspec(
     Ok(())
 }
 
-async fn write_config(
-    src: impl AsRef<Path>,
+/// Writes the patched config, preceded by the `header` comment, once the
+/// `validators` accept it.
+pub(super) async fn write_config(
+    config: toml::Value,
     dest: impl AsRef<Path>,
+    header: &str,
     updates: toml::Table,
+    validators: &ConfigValidators,
 ) -> Result<()> {
-    let (src, dest) = (src.as_ref(), dest.as_ref());
-
-    log::debug!("Copying the config from {src:?} to {dest:?} after patching");
-
-    let config = fs::read_to_string(src)
-        .await
-        .map_err(|io_err| Error::FileOperation {
-            description: format!("reading the config {src:?}"),
-            io_err,
+    let dest = dest.as_ref();
+    let config = update_toml(config, updates);
+    validators
+        .check(&config)
+        .map_err(|reason| Error::InvalidNodeConfig {
+            path: dest.to_owned(),
+            reason,
         })?;
-    let config = toml::Value::from_str(&config)?;
+
+    log::debug!("Writing the config to {dest:?} after patching");
 
     fs::write(
         dest,
-        toml::to_string_pretty(&update_toml(config, updates)).expect("TOML serialization failed"),
+        header.to_owned() + &toml::to_string_pretty(&config).expect("TOML serialization failed"),
     )
     .await
     .map_err(|io_err| Error::FileOperation {
@@ -192,35 +632,221 @@ async fn write_config(
     Ok(())
 }
 
-/// A timestamp of the moment in `n` seconds from now.
-fn millis_from_now(n: u64) -> String {
-    let value = SystemTime::now() + Duration::from_millis(n);
+/// A comment identifying the node, written at the top of its config. It is
+/// commented TOML, so that it can be read back by removing the `# ` prefixes,
+/// while the node ignores it.
+pub(super) fn config_header(node: &RunningNode, index: usize) -> String {
+    format!(
+        "# [cnut]\n\
+         # name = {:?}\n\
+         # group = {:?}\n\
+         # index = {index}\n\
+         # public_key = \"{}\"\n\
+         # generated_at = \"{}\"\n\n",
+        node.name,
+        node.group,
+        node.public_key(),
+        humantime::format_rfc3339_seconds(SystemTime::now()),
+    )
+}
+
+/// Creates the directory of the node, with its config, keys, and links to the
+/// chainspec files of the `base_data_dir`.
+async fn write_node_files(
+    node: &mut RunningNode,
+    index: usize,
+    known_addresses: &[SocketAddr],
+    absolute_storage_path: bool,
+    base_data_dir: &Path,
+    chainspec_updates: &toml::Table,
+) -> Result<()> {
+    // Create the directory:
+    fs::create_dir_all(&node.data_dir)
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("cannot create the folder {:?}", node.data_dir),
+            io_err,
+        })?;
+
+    let storage_path = if absolute_storage_path {
+        node.data_dir.join("node-storage")
+    } else {
+        PathBuf::from("./node-storage")
+    };
+    let any_interface = |port| Some(SocketAddr::new(node.bind_host, port));
+    let mut patch = NodeConfigPatch {
+        network: Some(NetworkSection {
+            bind_address: any_interface(node.bind_port),
+            known_addresses: Some(known_addresses.to_vec()),
+        }),
+        rpc_server: Some(ServerSection {
+            address: any_interface(node.rpc_port),
+        }),
+        speculative_exec_server: Some(ServerSection {
+            address: any_interface(node.speculative_execution_port),
+        }),
+        rest_server: Some(ServerSection {
+            address: any_interface(node.rest_port),
+        }),
+        event_stream_server: Some(ServerSection {
+            address: any_interface(node.event_stream_port),
+        }),
+        storage: Some(StorageSection {
+            path: Some(storage_path),
+        }),
+    };
+    let config = node.default_config.read().await?;
+    let skipped = patch.retain_known_sections(&config);
+    if !skipped.is_empty() {
+        log::info!(
+            "The config of {} has no {} section, not patching it",
+            node.name,
+            skipped.join(" nor "),
+        );
+    }
+    node.config_patches = patch.sections();
+    node.per_node_keys = config_diff::flatten(&toml::Table::from(patch.clone()).into())
+        .into_keys()
+        .collect();
+    write_config(
+        config,
+        node.data_dir.join("config.toml"),
+        &config_header(node, index),
+        merge_tables(
+            merge_tables(patch.into(), node.config_overrides.clone()),
+            config_from_env(node)?,
+        ),
+        &node.config_validators,
+    )
+    .await?;
+
+    // Create the key files:
+    node.public_key().write_pem(node.public_key_path()).await?;
+    node.public_key()
+        .write_hex(node.public_key_hex_path())
+        .await?;
+    node.secret_key().write_pem(node.secret_key_path()).await?;
+
+    // Link or copy the chainspec (including the accounts), unless the node has its own:
+    match &node.chainspec {
+        Some(chainspec) => {
+            write_chainspec(
+                chainspec.read().await?,
+                node.chainspec_path(),
+                chainspec_updates.clone(),
+            )
+            .await?
+        }
+        None => {
+            share_file(
+                &base_data_dir.join("chainspec.toml"),
+                &node.chainspec_path(),
+                node.shared_genesis,
+            )
+            .await?
+        }
+    }
+    share_file(
+        &base_data_dir.join("accounts.toml"),
+        &node.data_dir.join("accounts.toml"),
+        node.shared_genesis,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The config values read from the environment, see
+/// [`Node::config_from_env`](super::Node::config_from_env).
+fn config_from_env(node: &RunningNode) -> Result<toml::Table> {
+    let mut table = toml::Table::new().into();
+
+    for (key, var) in &node.config_from_env {
+        let Ok(content) = std::env::var(var) else {
+            log::debug!("{var} is not set, {key} keeps its value for {}", node.name);
+            continue;
+        };
+        let value = parse_env_value(&content);
+        let path: Vec<_> = key.split('.').collect();
+
+        log::debug!("Setting {key} to {value} from {var} for {}", node.name);
+        util::create_update_table(&mut table, &path, value)?;
+    }
+
+    match table {
+        toml::Value::Table(table) => Ok(table),
+        _ => unreachable!("table is a table"),
+    }
+}
+
+/// Parses the content of an environment variable as a TOML value, or as a
+/// string if it isn't one.
+fn parse_env_value(content: &str) -> toml::Value {
+    format!("value = {content}")
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| content.into())
+}
+
+/// The addresses the `nodes` reach each other with.
+fn known_addresses(nodes: &[RunningNode]) -> Vec<SocketAddr> {
+    nodes
+        .iter()
+        .map(|node| SocketAddr::new(node.host(), node.bind_port))
+        .collect()
+}
+
+/// The timestamp of the moment `delay` from now.
+fn time_from_now(delay: Duration) -> String {
+    let value = SystemTime::now() + delay;
 
     humantime::format_rfc3339_millis(value).to_string()
 }
 
-/// Returns a TOML data structure with the accounts.
-fn accounts(nodes: &[RunningNode]) -> toml::Value {
+/// Returns a TOML data structure with the accounts, and the stake of each
+/// validator. The amounts are drawn from the `balance_range` if any, and the
+/// stakes spread following the `stake_distribution` if any.
+fn accounts(
+    nodes: &[RunningNode],
+    rng: &mut StdRng,
+    balance_range: Option<(u128, u128)>,
+    stake_distribution: Option<&StakeDistribution>,
+) -> (toml::Value, Vec<(String, u128)>) {
     use toml::{map::Map, Value};
 
+    let mut amount = |default: u128| -> u128 {
+        balance_range.map_or(default, |(min, max)| rng.gen_range(min..=max))
+    };
+    let mut distributed_stakes = stake_distribution
+        .map(|distribution| {
+            let validators = nodes.iter().filter(|node| node.validator).count();
+
+            distribution.stakes(amount(DEFAULT_BONDED_AMOUNT), validators)
+        })
+        .unwrap_or_default()
+        .into_iter();
+    let mut genesis_stakes = Vec::new();
     let accounts = nodes
         .iter()
         .map(|node| {
             let mut map = Map::new();
-            map.insert("public_key".to_owned(), node.public_key.to_string().into());
-            //TODO make it random by default (if no value is specified)
             map.insert(
-                "balance".to_owned(),
-                "1000000000000000000000000000".to_owned().into(),
+                "public_key".to_owned(),
+                node.public_key().to_string().into(),
             );
+            let balance = node.balance.unwrap_or_else(|| amount(DEFAULT_BALANCE));
+            map.insert("balance".to_owned(), balance.to_string().into());
             if node.validator {
+                let distributed = distributed_stakes.next();
+                let stake = node
+                    .bonded_amount
+                    .or(distributed)
+                    .unwrap_or_else(|| amount(DEFAULT_BONDED_AMOUNT));
+                genesis_stakes.push((node.name.clone(), stake));
                 map.insert("validator".to_owned(), {
                     let mut map = Map::new();
-                    map.insert(
-                        "bonded_amount".to_owned(),
-                        //TODO make it random by default (if no value is specified)
-                        "500000000000000".to_owned().into(),
-                    );
+                    map.insert("bonded_amount".to_owned(), stake.to_string().into());
                     map.into()
                 });
             }
@@ -234,109 +860,922 @@ fn accounts(nodes: &[RunningNode]) -> toml::Value {
         map
     };
 
-    Value::Table(accounts)
+    (Value::Table(accounts), genesis_stakes)
 }
 
-fn create_temp_dir() -> Result<Arc<TempDir>> {
-    let temp_dir = Arc::new(tempfile::tempdir().map_err(|io_err| Error::FileOperation {
-        description: format!("creating the temporary directory"),
-        io_err,
-    })?);
+/// Checks the genesis before the node does, to fail with the name of the
+/// faulty node: each validator must have a stake, not above the balance set
+/// for it, the validators must fit in the validator slots of the chainspec,
+/// and their total stake must fit in 128 bits, like the amounts handled by
+/// cnut.
+fn check_genesis(
+    nodes: &[RunningNode],
+    genesis_accounts: &GenesisAccounts,
+    chainspec_updates: &toml::Table,
+) -> Result<()> {
+    let validators: Vec<_> = nodes
+        .iter()
+        .zip(&genesis_accounts.accounts)
+        .filter(|(node, _)| node.validator)
+        .collect();
+
+    for (node, account) in &validators {
+        let bonded_amount = account.bonded_amount();
+        if bonded_amount == 0 {
+            return Err(Error::ZeroBondedAmount(node.name.clone()));
+        }
+        // The drawn amounts are not related, only the ones set by the user are checked:
+        let set_by_user = node.balance.is_some() || node.bonded_amount.is_some();
+        if set_by_user && bonded_amount > account.balance {
+            return Err(Error::BondedAmountExceedsBalance {
+                name: node.name.clone(),
+                bonded_amount,
+                balance: account.balance,
+            });
+        }
+    }
+
+    let slots = chainspec_updates
+        .get("core")
+        .and_then(|core| core.get("validator_slots"))
+        .and_then(toml::Value::as_integer);
+    if let Some(slots) = slots {
+        if validators.len() as i64 > slots {
+            return Err(Error::TooManyValidators {
+                validators: validators.len(),
+                slots,
+            });
+        }
+    }
+
+    validators
+        .iter()
+        .try_fold(0u128, |total, (_, account)| {
+            total.checked_add(account.bonded_amount())
+        })
+        .ok_or(Error::TotalStakeOverflow)?;
 
-    Ok(temp_dir)
+    Ok(())
 }
 
-mod port {
-    const BASE_BIND_ADDRESS: u16 = 34000;
-    const BASE_SPEC_ADDRESS: u16 = 6666;
-    const BASE_RPC_ADDRESS: u16 = 7777;
-    const BASE_REST_ADDRESS: u16 = 8888;
-    const BASE_EVENT_STREAM_ADDRESS: u16 = 9999;
+/// Gathers the files of the artifacts with overrides in the `.artifacts`
+/// directory of the run directory, and returns the merged artifacts. The
+/// artifacts without overrides are returned unchanged.
+pub(super) async fn materialize_artifacts(
+    artifacts: Artifacts,
+    run_dir: &Path,
+) -> Result<Artifacts> {
+    if artifacts.overrides().is_empty() {
+        return Ok(artifacts);
+    }
+    // The same artifacts are gathered once:
+    let id = {
+        let mut hasher = DefaultHasher::new();
+        artifacts.hash(&mut hasher);
+        hasher.finish()
+    };
+    let dest = run_dir.join(".artifacts").join(format!("{id:016x}"));
+    if dest.is_dir() {
+        return Ok(Artifacts::from_path(dest));
+    }
 
-    pub fn bind(index: usize) -> u16 {
-        BASE_BIND_ADDRESS + index as u16
+    fs::create_dir_all(&dest)
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("creating the artifacts directory {dest:?}"),
+            io_err,
+        })?;
+    let source = artifacts.path();
+    let mut entries = fs::read_dir(source)
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("reading the artifacts directory {source:?}"),
+            io_err,
+        })?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("reading the directory entry in {source:?}"),
+            io_err,
+        })?
+    {
+        let overridden = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| artifacts.overrides().contains_key(name));
+
+        if !overridden && entry.path().is_file() {
+            link_or_copy(&entry.path(), &dest.join(entry.file_name())).await?;
+        }
     }
+    for (file_name, path) in artifacts.overrides() {
+        link_or_copy(path, &dest.join(file_name)).await?;
+    }
+    log::debug!("Artifacts {source:?} with overrides gathered in {dest:?}");
+
+    Ok(Artifacts::from_path(dest))
+}
+
+/// Fails if the name given by the user cannot be used as a directory name in
+/// the run directory.
+fn validate_node_name(name: &str) -> Result<()> {
+    let reason = if name.is_empty() {
+        "it is empty"
+    } else if name.contains(['/', '\\']) {
+        "it contains a path separator"
+    } else if name.starts_with('.') {
+        "it starts with a dot"
+    } else if name.chars().any(char::is_control) {
+        "it contains a control character"
+    } else {
+        return Ok(());
+    };
+
+    Err(Error::InvalidNodeName {
+        name: name.to_owned(),
+        reason,
+    })
+}
 
-    pub fn spec(index: usize) -> u16 {
-        BASE_SPEC_ADDRESS + index as u16
+/// Hard-links `src` to `dest`, or copies it if they are on different
+/// filesystems.
+///
+/// A copy does not follow the later changes of the source, such as the ones of
+/// [`PreparedNetwork::apply_chainspec_overrides`].
+async fn link_or_copy(src: &Path, dest: &Path) -> Result<()> {
+    match fs::hard_link(src, dest).await {
+        Ok(()) => {
+            log::debug!("Hard-linked {src:?} to {dest:?}");
+            Ok(())
+        }
+        Err(io_err) if io_err.kind() == std::io::ErrorKind::CrossesDevices => {
+            log::info!("Copying {src:?} to {dest:?}, since they are on different filesystems");
+            copy_file(src, dest).await
+        }
+        Err(io_err) => Err(Error::FileOperation {
+            description: format!("hard-linking the file {src:?} to {dest:?}"),
+            io_err,
+        }),
     }
+}
 
-    pub fn rpc(index: usize) -> u16 {
-        BASE_RPC_ADDRESS + index as u16
+/// Links `src` to `dest` if the file is `shared`, see [`link_or_copy`], or
+/// copies it otherwise.
+async fn share_file(src: &Path, dest: &Path, shared: bool) -> Result<()> {
+    if shared {
+        link_or_copy(src, dest).await
+    } else {
+        copy_file(src, dest).await
     }
+}
+
+async fn copy_file(src: &Path, dest: &Path) -> Result<()> {
+    fs::copy(src, dest)
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("copying the file {src:?} to {dest:?}"),
+            io_err,
+        })?;
 
-    pub fn rest(index: usize) -> u16 {
-        BASE_REST_ADDRESS + index as u16
+    Ok(())
+}
+
+/// Fails if the filesystem the run directory will be created in does not have
+/// the space required by the network.
+fn check_free_space(network: &NetworkBuilder) -> Result<()> {
+    let path = std::env::temp_dir();
+    let required = network
+        .min_free_space
+        .unwrap_or(DEFAULT_FREE_SPACE_PER_NODE * network.amount_nodes() as u64);
+    let available = util::available_space(&path).map_err(|io_err| Error::FileOperation {
+        description: format!("reading the free space of {path:?}"),
+        io_err,
+    })?;
+
+    match available {
+        Some(available) if available < required => Err(Error::InsufficientDiskSpace {
+            available,
+            required,
+            path,
+        }),
+        _ => Ok(()),
     }
+}
 
-    pub fn event_stream(index: usize) -> u16 {
-        BASE_EVENT_STREAM_ADDRESS + index as u16
+/// Creates the run directory, named after the prefix and the current time, for
+/// example `cnut-run-20240613-153000-XXXX`.
+fn create_temp_dir(prefix: &str, keep: bool) -> Result<Arc<RunDirectory>> {
+    let date: String = humantime::format_rfc3339_seconds(SystemTime::now())
+        .to_string()
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect();
+    let (day, time) = date.split_at(8);
+    let temp_dir = tempfile::Builder::new()
+        .prefix(&format!("{prefix}{day}-{time}-"))
+        .tempdir()
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("creating the temporary directory"),
+            io_err,
+        })?;
+    let temp_dir = RunDirectory::temporary(temp_dir);
+    if keep {
+        temp_dir.keep();
     }
+
+    Ok(Arc::new(temp_dir))
 }
 
-/// Convert the `Node`s into `RunningNode`s.
-fn node_data(
-    nodes: Vec<super::Node>,
-    base_data_dir: &Path,
-    task_tracker: &TaskTracker,
-) -> Vec<RunningNode> {
-    let mut result = Vec::new();
-    let mut index = 0..;
-    let mut conf_names = LettersGen::new();
-    let rng = &mut rand::thread_rng();
-
-    for super::Node {
-        artifacts,
-        amount,
-        config,
-        name,
-        validator,
-    } in nodes
-    {
-        let name = name.unwrap_or_else(|| format!("Node_{}", conf_names.next()));
-
-        let node_paths_and_names = match amount {
-            0 => vec![],
-            1 => vec![(base_data_dir.join(&name), name)],
-            n => (0..n)
-                .map(|i| {
-                    (
-                        base_data_dir.join(&name).join(format!("{i}")),
-                        format!("{name}/{i}"),
-                    )
+/// What the nodes share, or get from the network settings. It is kept by the
+/// network, so that nodes can be added once it is prepared.
+#[derive(Debug, Clone)]
+pub(super) struct NodeFactory {
+    base_data_dir: PathBuf,
+    default_artifacts: Option<Artifacts>,
+    task_tracker: TaskTracker,
+    events: broadcast::Sender<NetworkEvent>,
+    log_alert_patterns: Option<Arc<[String]>>,
+    dry_run: bool,
+    backend: Backend,
+    absolute_storage_path: bool,
+    rng: StdRng,
+    /// Names the nodes added without a name.
+    conf_names: LettersGen,
+    /// The names of the groups created so far, which are their directory
+    /// names.
+    groups: BTreeSet<String>,
+    /// The index of the next node created, which its ports are derived from.
+    next_index: usize,
+    /// The ports given to the nodes so far, with the name of their node.
+    used_ports: BTreeMap<u16, String>,
+    port_scheme: PortScheme,
+    chain_name: Arc<str>,
+    bind_host: IpAddr,
+    /// The values written on top of the chainspecs: the generated ones, then
+    /// the network overrides.
+    chainspec_updates: toml::Table,
+    /// See [`NetworkBuilder::shared_genesis`].
+    shared_genesis: bool,
+    /// See [`NetworkBuilder::shutdown_timeout`].
+    shutdown_timeout: Duration,
+    /// The JSON-RPC client shared by the nodes.
+    rpc_client: reqwest::Client,
+}
+
+impl NodeFactory {
+    /// Converts the `Node`s into `RunningNode`s. The pinned ports are
+    /// reserved first, so that the automatic ones skip them. Fails if a name
+    /// cannot be a directory name, or if two groups have the same name, even
+    /// across calls, since their directories would collide. The generated
+    /// names skip the ones given by the user.
+    ///
+    /// On failure, the factory is left unchanged: no index, port or generated
+    /// name is used up.
+    fn create(&mut self, nodes: Vec<super::Node>) -> Result<Vec<RunningNode>> {
+        let mut factory = self.clone();
+        let result = factory.create_all(nodes)?;
+        *self = factory;
+
+        Ok(result)
+    }
+
+    fn create_all(&mut self, nodes: Vec<super::Node>) -> Result<Vec<RunningNode>> {
+        let mut pending = Vec::new();
+        let user_groups: BTreeSet<&str> = nodes
+            .iter()
+            .filter_map(|node| node.name.as_deref())
+            .collect();
+
+        for node in &nodes {
+            let group = match &node.name {
+                Some(name) => {
+                    validate_node_name(name)?;
+                    name.clone()
+                }
+                None => loop {
+                    let name = format!("Node_{}", self.conf_names.next());
+                    if !user_groups.contains(name.as_str()) && !self.groups.contains(&name) {
+                        break name;
+                    }
+                },
+            };
+            if !self.groups.insert(group.clone()) {
+                return Err(Error::DuplicateNodeName(group));
+            }
+            let artifacts = node
+                .artifacts
+                .clone()
+                .or_else(|| self.default_artifacts.clone())
+                .ok_or_else(|| Error::NoArtifactsForNode {
+                    name: group.clone(),
+                })?;
+
+            let node_paths_and_names = match node.amount {
+                0 => vec![],
+                1 => vec![(self.base_data_dir.join(&group), group.clone())],
+                n => (0..n)
+                    .map(|i| {
+                        (
+                            self.base_data_dir.join(&group).join(format!("{i}")),
+                            format!("{group}/{i}"),
+                        )
+                    })
+                    .collect(),
+            };
+
+            for (_, name) in &node_paths_and_names {
+                let super::describe::PinnedPorts { rpc, rest, bind } = node.pinned_ports;
+
+                for port in [rpc, rest, bind].into_iter().flatten() {
+                    self.reserve_pinned_port(port, name)?;
+                }
+            }
+            pending.push((node, group, artifacts, node_paths_and_names));
+        }
+
+        let mut result = Vec::new();
+
+        for (node, group, artifacts, node_paths_and_names) in pending {
+            let default_config = node
+                .config
+                .clone()
+                .unwrap_or_else(|| super::NodeConfig::Artifacts(artifacts.clone()));
+
+            for (data_dir, name) in node_paths_and_names.into_iter() {
+                let (public_key, secret_key) = generate_pair(&mut self.rng);
+                let index = self.next_index;
+                self.next_index += 1;
+                let pinned_ports = node.pinned_ports;
+                let rpc_port = match pinned_ports.rpc {
+                    Some(port) => port,
+                    None => self.auto_port(ports::RPC, index, &name)?,
+                };
+                let rest_port = match pinned_ports.rest {
+                    Some(port) => port,
+                    None => self.auto_port(ports::REST, index, &name)?,
+                };
+                let bind_port = match pinned_ports.bind {
+                    Some(port) => port,
+                    None => self.auto_port(ports::BIND, index, &name)?,
+                };
+                let speculative_execution_port =
+                    self.auto_port(ports::SPECULATIVE_EXECUTION, index, &name)?;
+                let event_stream_port = self.auto_port(ports::EVENT_STREAM, index, &name)?;
+
+                result.push(RunningNode {
+                    data_dir,
+                    artifact_dir: Arc::new(std::sync::RwLock::new(artifacts.path().to_owned())),
+                    default_config: default_config.clone(),
+                    name,
+                    group: group.clone(),
+                    keys: Arc::new(std::sync::RwLock::new((public_key, secret_key))),
+                    validator: node.validator,
+                    rpc_port,
+                    rest_port,
+                    speculative_execution_port,
+                    bind_port,
+                    event_stream_port,
+                    bind_host: self.bind_host,
+                    config_patches: Vec::new(),
+                    per_node_keys: Vec::new(),
+                    pinned_ports,
+                    env: node.env.clone(),
+                    config_from_env: node.config_from_env.clone(),
+                    config_overrides: node.config_overrides.clone(),
+                    config_validators: node.config_validators.clone(),
+                    chainspec: node.chainspec.clone(),
+                    shared_genesis: self.shared_genesis,
+                    rpc_client: self.rpc_client.clone(),
+                    shutdown_timeout: self.shutdown_timeout,
+                    balance: node.balance,
+                    bonded_amount: node.bonded_amount,
+                    launch_mode: node.launch_mode.clone(),
+                    extra_args: node.extra_args.clone(),
+                    launcher: node.launcher.clone(),
+                    start_phase: node.start_phase,
+                    chain_name: self.chain_name.clone(),
+                    log_alert_patterns: self.log_alert_patterns.clone(),
+                    dry_run: self.dry_run,
+                    backend: self.backend.clone(),
+                    process_id: Default::default(),
+                    task_tracker: self.task_tracker.clone(),
+                    status: Default::default(),
+                    alerts: Default::default(),
+                    history: Default::default(),
+                    events: self.events.clone(),
+                    kill_notifier: Default::default(),
+                    operation_lock: Default::default(),
+                    frozen: Default::default(),
+                    crashes: Default::default(),
+                    exit_status: Arc::new(watch::channel(Some(Ok(Default::default()))).0),
                 })
-                .collect(),
-        };
+            }
+        }
+        Ok(result)
+    }
+
+    /// Fails if the pinned `port` is already given to a node, or if it cannot
+    /// be bound.
+    fn reserve_pinned_port(&mut self, port: u16, name: &str) -> Result<()> {
+        if let Some(first) = self.used_ports.get(&port) {
+            return Err(Error::DuplicatePortAssignment {
+                port,
+                first: first.clone(),
+                second: name.to_owned(),
+            });
+        }
+        if !self.dry_run {
+            std::net::TcpListener::bind((self.bind_host, port)).map_err(|io_err| {
+                Error::PortUnavailable {
+                    port,
+                    node: name.to_owned(),
+                    io_err,
+                }
+            })?;
+        }
+        self.used_ports.insert(port, name.to_owned());
+
+        Ok(())
+    }
+
+    /// Returns the first port of the `category` from the automatic scheme,
+    /// starting at the node `index`, which is not given to another node yet.
+    fn auto_port(&mut self, category: ports::Category, index: usize, name: &str) -> Result<u16> {
+        let port = (index..)
+            .map_while(|index| self.port_scheme.port(category, index))
+            .find(|port| !self.used_ports.contains_key(port))
+            .ok_or(Error::PortRangeOverflow {
+                category: category.0,
+                amount_nodes: index + 1,
+            })?;
+        self.used_ports.insert(port, name.to_owned());
+
+        Ok(port)
+    }
+}
 
-        let default_config_path = config
-            .clone()
-            .unwrap_or_else(|| super::NodeConfig::Artifacts(artifacts.clone()))
-            .path();
-
-        for (data_dir, name) in node_paths_and_names.into_iter() {
-            let (public_key, secret_key) = generate_pair(rng);
-            let index = index.next().unwrap();
-            let rpc_port = port::rpc(index);
-            let rest_port = port::rest(index);
-            let speculative_execution_port = port::spec(index);
-
-            result.push(RunningNode {
-                data_dir,
-                artifact_dir: artifacts.0.clone(),
-                default_config_path: default_config_path.clone(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::Node, testing};
+
+    #[tokio::test]
+    async fn a_name_with_a_separator_is_rejected() {
+        let (_dir, artifacts) = testing::artifacts();
+        let result = testing::builder()
+            .with(Node::validator(artifacts.clone()).name("Alice/0"))
+            .prepare()
+            .await;
+
+        assert!(matches!(result, Err(Error::InvalidNodeName { name, .. }) if name == "Alice/0"));
+    }
+
+    #[tokio::test]
+    async fn a_node_cannot_have_the_name_of_a_group() {
+        let (_dir, artifacts) = testing::artifacts();
+        let result = testing::builder()
+            .with(Node::validator(artifacts.clone()).name("Alice"))
+            .with(3 * Node::validator(artifacts.clone()).name("Alice"))
+            .prepare()
+            .await;
+
+        assert!(matches!(result, Err(Error::DuplicateNodeName(name)) if name == "Alice"));
+    }
+
+    #[tokio::test]
+    async fn an_added_node_cannot_reuse_a_group_name() {
+        let (_dir, artifacts) = testing::artifacts();
+        let mut network = testing::builder()
+            .with(3 * Node::validator(artifacts.clone()).name("Alice"))
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+
+        let result = network
+            .add_node(Node::keep_up(artifacts.clone()).name("Alice"))
+            .await;
+        assert!(matches!(result, Err(Error::DuplicateNodeName(name)) if name == "Alice"));
+        assert!(!network.temp_directory().join("Alice/config.toml").exists());
+        assert_eq!(network.nodes_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn a_name_collision_uses_nothing_up() {
+        let (_dir, artifacts) = testing::artifacts();
+        let mut network = testing::builder()
+            .with(2 * Node::validator(artifacts.clone()).name("Alice"))
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let last_port = network.node_by_name("Alice/1").unwrap().rpc_port();
+
+        for name in ["Alice", "Alice/1"] {
+            let result = network
+                .add_node(Node::keep_up(artifacts.clone()).name(name))
+                .await;
+            assert!(matches!(result, Err(Error::DuplicateNodeName(taken)) if taken == name));
+        }
+
+        let added = network
+            .add_node(Node::keep_up(artifacts.clone()))
+            .await
+            .unwrap();
+        assert_eq!(added.name(), "Node_A");
+        assert_eq!(added.rpc_port(), last_port + 1);
+        let (header, _) = read_config_header(&added.config_path()).await;
+        assert_eq!(header["index"].as_integer(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn a_failed_creation_uses_nothing_up() {
+        let (_dir, artifacts) = testing::artifacts();
+        let mut network = testing::builder()
+            .with(3 * Node::validator(artifacts.clone()).name("Alice"))
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let last_port = network.node_by_name("Alice/2").unwrap().rpc_port();
+
+        let result = network
+            .add_node(Node::keep_up(artifacts.clone()).rpc_port(last_port))
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::DuplicatePortAssignment { port, .. }) if port == last_port
+        ));
+
+        let added = network
+            .add_node(Node::keep_up(artifacts.clone()))
+            .await
+            .unwrap();
+        assert_eq!(added.name(), "Node_A");
+        assert_eq!(added.rpc_port(), last_port + 1);
+    }
+
+    #[tokio::test]
+    async fn a_port_pinned_twice_names_both_nodes() {
+        let (_dir, artifacts) = testing::artifacts();
+        let result = testing::builder()
+            .with(
+                Node::validator(artifacts.clone())
+                    .name("Alice")
+                    .rpc_port(65_000),
+            )
+            .with(
+                Node::validator(artifacts.clone())
+                    .name("Bob")
+                    .rpc_port(65_000),
+            )
+            .dry_run(true)
+            .prepare()
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::DuplicatePortAssignment { port: 65_000, first, second })
+                if first == "Alice" && second == "Bob"
+        ));
+    }
+
+    #[tokio::test]
+    async fn the_automatic_ports_skip_the_pinned_ones() {
+        let (_dir, artifacts) = testing::artifacts();
+        let scheme = testing::port_scheme();
+        let first_rpc = scheme.port(ports::RPC, 0).unwrap();
+        let first_bind = scheme.port(ports::BIND, 0).unwrap();
+        let network = testing::builder()
+            .port_scheme(scheme)
+            .with(2 * Node::validator(artifacts.clone()).name("Auto"))
+            .with(
+                Node::validator(artifacts.clone())
+                    .name("Pinned")
+                    .rpc_port(first_rpc)
+                    .bind_port(first_bind + 1),
+            )
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+
+        let rpc_ports: Vec<_> = ["Auto/0", "Auto/1", "Pinned"]
+            .map(|name| network.node_by_name(name).unwrap().rpc_port())
+            .into();
+        assert_eq!(rpc_ports, [first_rpc + 1, first_rpc + 2, first_rpc]);
+        let bind_ports: Vec<_> = ["Auto/0", "Auto/1", "Pinned"]
+            .map(|name| network.node_by_name(name).unwrap().bind_port())
+            .into();
+        assert_eq!(bind_ports, [first_bind, first_bind + 2, first_bind + 1]);
+    }
+
+    #[tokio::test]
+    async fn a_validator_without_stake_is_rejected() {
+        let (_dir, artifacts) = testing::artifacts();
+        let result = testing::network(&artifacts, 2)
+            .with(
+                Node::validator(artifacts.clone())
+                    .name("Alice")
+                    .bonded_amount(0),
+            )
+            .dry_run(true)
+            .prepare()
+            .await;
+
+        assert!(matches!(result, Err(Error::ZeroBondedAmount(name)) if name == "Alice"));
+    }
+
+    #[tokio::test]
+    async fn a_stake_above_the_balance_is_rejected() {
+        let (_dir, artifacts) = testing::artifacts();
+        let result = testing::builder()
+            .with(
+                Node::validator(artifacts.clone())
+                    .name("Alice")
+                    .balance(10)
+                    .bonded_amount(20),
+            )
+            .dry_run(true)
+            .prepare()
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::BondedAmountExceedsBalance {
                 name,
-                public_key,
-                secret_key,
-                validator,
-                rpc_port,
-                rest_port,
-                speculative_execution_port,
-                process_id: Default::default(),
-                task_tracker: task_tracker.clone(),
-                status: Default::default(),
-                kill_notifier: Default::default(),
+                bonded_amount: 20,
+                balance: 10,
+            }) if name == "Alice"
+        ));
+    }
+
+    #[tokio::test]
+    async fn more_validators_than_slots_are_rejected() {
+        let (_dir, artifacts) = testing::artifacts();
+        let result = testing::network(&artifacts, 3)
+            .with(Node::keep_up(artifacts.clone()))
+            .chainspec_overrides(toml_map! {
+                "core", "validator_slots" => 2,
+            })
+            .dry_run(true)
+            .prepare()
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::TooManyValidators {
+                validators: 3,
+                slots: 2,
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn an_overflowing_total_stake_is_rejected() {
+        let (_dir, artifacts) = testing::artifacts();
+        let whale = Node::validator(artifacts.clone())
+            .balance(u128::MAX)
+            .bonded_amount(u128::MAX / 2 + 1);
+        let result = testing::builder()
+            .with(2 * whale)
+            .dry_run(true)
+            .prepare()
+            .await;
+
+        assert!(matches!(result, Err(Error::TotalStakeOverflow)));
+    }
+
+    /// Returns the `core.validator_slots` in the chainspec of the node `name`.
+    async fn validator_slots(network: &PreparedNetwork, name: &str) -> Option<i64> {
+        let chainspec_path = network.node_by_name(name).unwrap().chainspec_path();
+        let chainspec = read_toml(&chainspec_path, "the chainspec").await.unwrap();
+
+        chainspec["core"]["validator_slots"].as_integer()
+    }
+
+    #[tokio::test]
+    async fn a_chainspec_not_shared_is_patched_alone() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 3)
+            .shared_genesis(false)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+
+        let node = network.node_by_name("Node_A/1").unwrap();
+        node.patch_chainspec(toml_map! {
+            "core", "validator_slots" => 7,
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(validator_slots(&network, "Node_A/1").await, Some(7));
+        assert_eq!(validator_slots(&network, "Node_A/0").await, Some(3));
+        assert_eq!(validator_slots(&network, "Node_A/2").await, Some(3));
+        assert_eq!(
+            network.chainspec().await.unwrap()["core"]["validator_slots"].as_integer(),
+            Some(3)
+        );
+    }
+
+    #[tokio::test]
+    async fn a_shared_chainspec_is_not_patched() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 3)
+            .shared_genesis(true)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+
+        let result = network
+            .node_by_name("Node_A/1")
+            .unwrap()
+            .patch_chainspec(toml_map! {
+                "core", "validator_slots" => 7,
             })
+            .await;
+
+        assert!(matches!(result, Err(Error::SharedChainspec(name)) if name == "Node_A/1"));
+        for name in ["Node_A/0", "Node_A/1", "Node_A/2"] {
+            assert_eq!(validator_slots(&network, name).await, Some(3));
+        }
+    }
+
+    /// Returns the `# [cnut]` header of the config at `path`, read back as
+    /// TOML, and the config itself.
+    async fn read_config_header(path: &Path) -> (toml::Table, toml::Table) {
+        let config = fs::read_to_string(path).await.unwrap();
+        let header: String = config
+            .lines()
+            .take_while(|line| !line.is_empty())
+            .map(|line| {
+                line.strip_prefix("# ")
+                    .expect("a commented line")
+                    .to_owned()
+                    + "\n"
+            })
+            .collect();
+        let header: toml::Table = header.parse().unwrap();
+
+        (
+            header["cnut"].as_table().unwrap().clone(),
+            config.parse().unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn the_config_header_is_read_back() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 2)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+        let node = network.node_by_name("Node_A/1").unwrap();
+
+        let (header, config) = read_config_header(&node.config_path()).await;
+        assert_eq!(header["name"].as_str(), Some("Node_A/1"));
+        assert_eq!(header["group"].as_str(), Some("Node_A"));
+        assert_eq!(header["index"].as_integer(), Some(1));
+        assert_eq!(
+            header["public_key"].as_str(),
+            Some(node.public_key().to_string().as_str())
+        );
+        humantime::parse_rfc3339(header["generated_at"].as_str().unwrap()).unwrap();
+        assert!(!config.contains_key("cnut"));
+    }
+
+    #[tokio::test]
+    async fn the_storage_path_is_relative_unless_asked_otherwise() {
+        let (_dir, artifacts) = testing::artifacts();
+        for absolute in [false, true] {
+            let network = testing::network(&artifacts, 1)
+                .absolute_storage_path(absolute)
+                .dry_run(true)
+                .prepare()
+                .await
+                .unwrap();
+            let node = network.node_by_name("Node_A").unwrap();
+
+            let (_, config) = read_config_header(&node.config_path()).await;
+            let path = Path::new(config["storage"]["path"].as_str().unwrap());
+            if absolute {
+                assert_eq!(path, node.data_dir().join("node-storage"));
+            } else {
+                assert_eq!(path, Path::new("./node-storage"));
+            }
+        }
+    }
+
+    /// Returns the name of the run directory, without the `prefix` and the
+    /// random suffix: the creation date.
+    fn run_dir_date(network: &PreparedNetwork, prefix: &str) -> String {
+        let name = network
+            .temp_directory()
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap();
+        let date = name
+            .strip_prefix(prefix)
+            .unwrap_or_else(|| panic!("{name} without {prefix}"));
+
+        date.rsplit_once('-').unwrap().0.to_owned()
+    }
+
+    #[tokio::test]
+    async fn the_run_directory_starts_with_the_prefix() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 1)
+            .run_dir_prefix("upgrade-test-")
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+        let default = testing::network(&artifacts, 1)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+
+        for date in [
+            run_dir_date(&network, "upgrade-test-"),
+            run_dir_date(&default, "cnut-run-"),
+        ] {
+            let (day, time) = date.split_once('-').unwrap();
+            assert_eq!((day.len(), time.len()), (8, 6), "{date}");
+            assert!(
+                date.chars().all(|c| c.is_ascii_digit() || c == '-'),
+                "{date}"
+            );
         }
     }
-    result
+
+    #[tokio::test]
+    async fn the_node_artifacts_take_precedence_over_the_default_ones() {
+        let (_default_dir, default) = testing::artifacts();
+        let (_own_dir, own) = testing::artifacts();
+        let network = testing::builder()
+            .default_artifacts(default.clone())
+            .with(Node::default_validator())
+            .with(Node::validator(own.clone()))
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            network.node_by_name("Node_A").unwrap().artifact_dir(),
+            default.path()
+        );
+        assert_eq!(
+            network.node_by_name("Node_B").unwrap().artifact_dir(),
+            own.path()
+        );
+    }
+
+    #[tokio::test]
+    async fn a_node_without_artifacts_is_rejected() {
+        let (_dir, artifacts) = testing::artifacts();
+        let result = testing::builder()
+            .with(Node::validator(artifacts.clone()))
+            .with(Node::default_validator())
+            .dry_run(true)
+            .prepare()
+            .await;
+
+        assert!(matches!(result, Err(Error::NoArtifactsForNode { name }) if name == "Node_B"));
+    }
+
+    #[tokio::test]
+    async fn a_network_without_chainspec_is_rejected_before_any_file_is_written() {
+        let prefix = "no-chainspec-test-";
+        let result = NetworkBuilder::new()
+            .run_dir_prefix(prefix)
+            .keep_files(true)
+            .prepare()
+            .await;
+
+        assert!(matches!(result, Err(Error::NoChainspec)));
+        let run_dirs = std::fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with(prefix)
+            });
+        assert_eq!(run_dirs.count(), 0);
+    }
 }
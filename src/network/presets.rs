@@ -0,0 +1,123 @@
+//! Named sets of chainspec values for the usual kinds of networks.
+
+use crate::util::toml_map;
+use serde::Deserialize;
+
+/// A named set of chainspec values, applied on top of the chainspec template
+/// of the network. The values given with
+/// [`NetworkBuilder::chainspec_overrides`](super::NetworkBuilder::chainspec_overrides)
+/// take precedence over them.
+///
+/// It is used with [`Chainspec::preset`](super::Chainspec::preset), or added
+/// directly to the network to apply it to the default chainspec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Preset {
+    /// Eras of a few seconds and a small era height, to see the network
+    /// progress quickly.
+    FastLocal,
+    /// Timings close to the production network ones.
+    Realistic,
+    /// A single validator slot, for a network with only one validator.
+    SingleValidator,
+}
+
+impl Preset {
+    /// Returns the chainspec values for this preset.
+    pub fn overrides(self) -> toml::Table {
+        match self {
+            Self::FastLocal => toml_map! {
+                "core", "era_duration" => "10 seconds",
+                "core", "minimum_era_height" => 3,
+                "core", "minimum_block_time" => "1024 ms",
+            },
+            Self::Realistic => toml_map! {
+                "core", "era_duration" => "120 minutes",
+                "core", "minimum_era_height" => 20,
+                "core", "minimum_block_time" => "16384 ms",
+                "core", "auction_delay" => 1,
+                "core", "unbonding_delay" => 7,
+            },
+            Self::SingleValidator => toml_map! {
+                "core", "validator_slots" => 1,
+                "core", "minimum_era_height" => 3,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        network::{Chainspec, NetworkBuilder, Node},
+        testing,
+    };
+
+    /// Returns the chainspec of the prepared `network`.
+    async fn prepared_chainspec(network: NetworkBuilder) -> toml::Table {
+        network
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .chainspec()
+            .await
+            .unwrap()
+    }
+
+    /// Panics if a value of the `preset` is not in the `chainspec`.
+    fn assert_applied(preset: Preset, chainspec: &toml::Table) {
+        for (section, values) in preset.overrides() {
+            for (key, value) in values.as_table().unwrap() {
+                assert_eq!(chainspec[&section][key], *value, "{section}.{key}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fast_local_is_applied() {
+        let (_dir, artifacts) = testing::artifacts();
+        let chainspec =
+            prepared_chainspec(testing::network(&artifacts, 3).with(Preset::FastLocal)).await;
+
+        assert_applied(Preset::FastLocal, &chainspec);
+        // The template values are kept:
+        assert_eq!(chainspec["protocol"]["version"].as_str(), Some("1.0.0"));
+    }
+
+    #[tokio::test]
+    async fn realistic_is_applied_under_the_user_overrides() {
+        let (_dir, artifacts) = testing::artifacts();
+        let chainspec = prepared_chainspec(
+            testing::network(&artifacts, 3)
+                .with(Chainspec::from(artifacts.clone()).preset(Preset::Realistic))
+                .chainspec_overrides(crate::util::toml_map! {
+                    "core", "unbonding_delay" => 2,
+                }),
+        )
+        .await;
+
+        assert_eq!(chainspec["core"]["unbonding_delay"].as_integer(), Some(2));
+        assert_eq!(chainspec["core"]["auction_delay"].as_integer(), Some(1));
+        assert_eq!(
+            chainspec["core"]["era_duration"].as_str(),
+            Some("120 minutes")
+        );
+    }
+
+    #[tokio::test]
+    async fn single_validator_is_applied() {
+        let (_dir, artifacts) = testing::artifacts();
+        let chainspec = prepared_chainspec(
+            testing::network(&artifacts, 1)
+                .with(Node::keep_up(artifacts.clone()))
+                .with(Preset::SingleValidator),
+        )
+        .await;
+
+        // Instead of a slot for each node:
+        assert_applied(Preset::SingleValidator, &chainspec);
+    }
+}
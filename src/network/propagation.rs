@@ -0,0 +1,269 @@
+//! Measures how long a deploy takes to reach every node. See
+//! [`RunningNetwork::trace_deploy`].
+
+use crate::{
+    error::Result,
+    network::{RunningNetwork, RunningNode},
+    util::deploy::Deploy,
+};
+use futures::future::join_all;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+
+/// How often each node is asked for the deploy.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How often the execution of the deploy is checked once it is seen.
+const EXECUTION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// The amount sent by [`RunningNetwork::measure_propagation`], the minimum
+/// transfer accepted by the node.
+const PROPAGATION_TRANSFER_AMOUNT: u128 = 2_500_000_000;
+/// The payment of the transfer sent by [`RunningNetwork::measure_propagation`].
+const PROPAGATION_TRANSFER_PAYMENT: u128 = 100_000_000;
+
+/// When each node first knew about a deploy. See
+/// [`RunningNetwork::trace_deploy`].
+#[derive(Debug, Clone)]
+pub struct DeployTrace {
+    /// The hash of the traced deploy, hex-encoded.
+    pub deploy_hash: String,
+    /// When the trace started. The delays are counted from there.
+    pub started_at: SystemTime,
+    /// The name of each node which returned the deploy, with the time it
+    /// first did, the earliest first.
+    pub observations: Vec<(String, SystemTime)>,
+    /// The names of the running nodes which did not return the deploy before
+    /// the timeout.
+    pub missing: Vec<String>,
+    /// The timestamp of the block the deploy was executed in, if it was
+    /// executed before the timeout.
+    pub included_at: Option<SystemTime>,
+}
+
+impl DeployTrace {
+    /// Returns the time each node took to return the deploy since the trace
+    /// started, the shortest first.
+    pub fn delays(&self) -> Vec<Duration> {
+        self.observations
+            .iter()
+            .map(|(_, seen_at)| seen_at.duration_since(self.started_at).unwrap_or_default())
+            .collect()
+    }
+
+    /// Returns the shortest delay, see [`DeployTrace::delays`].
+    pub fn min_delay(&self) -> Option<Duration> {
+        self.delays().first().copied()
+    }
+
+    /// Returns the longest delay, see [`DeployTrace::delays`].
+    pub fn max_delay(&self) -> Option<Duration> {
+        self.delays().last().copied()
+    }
+
+    /// Returns the median delay, see [`DeployTrace::delays`].
+    pub fn median_delay(&self) -> Option<Duration> {
+        let delays = self.delays();
+
+        delays.get(delays.len() / 2).copied()
+    }
+
+    /// Returns the time between the first and the last node returning the
+    /// deploy.
+    pub fn spread(&self) -> Option<Duration> {
+        Some(self.max_delay()? - self.min_delay()?)
+    }
+}
+
+impl RunningNetwork {
+    /// Asks every running node for the deploy with the given `hash`, until
+    /// they all return it or the `timeout` expires, and records when each one
+    /// first did. Once a node has the deploy, it is polled for its execution,
+    /// to get the timestamp of the block including it. The nodes not
+    /// returning the deploy in time are listed in [`DeployTrace::missing`].
+    pub async fn trace_deploy(&self, hash: &str, timeout: Duration) -> DeployTrace {
+        self.trace_since(hash, SystemTime::now(), timeout).await
+    }
+
+    /// Sends a throwaway native transfer between two nodes, and traces it
+    /// with [`RunningNetwork::trace_deploy`]. The delays are counted from the
+    /// submission of the transfer.
+    pub async fn measure_propagation(&self, timeout: Duration) -> Result<DeployTrace> {
+        let sender = self.any_healthy_node().await?;
+        let target = self
+            .nodes
+            .iter()
+            .find(|node| node.name != sender.name)
+            .unwrap_or(sender);
+        let deploy = Deploy::transfer(
+            (&sender.public_key(), &sender.secret_key()),
+            &self.chain_name,
+            &target.public_key(),
+            PROPAGATION_TRANSFER_AMOUNT,
+            PROPAGATION_TRANSFER_PAYMENT,
+            rand::random(),
+        );
+
+        let started_at = SystemTime::now();
+        sender.rpc().put_deploy(&deploy).await?;
+        log::info!(
+            "Deploy {} sent to {} to measure the propagation",
+            deploy.hash(),
+            sender.name
+        );
+
+        Ok(self.trace_since(&deploy.hash(), started_at, timeout).await)
+    }
+
+    async fn trace_since(
+        &self,
+        hash: &str,
+        started_at: SystemTime,
+        timeout: Duration,
+    ) -> DeployTrace {
+        let deadline = Instant::now() + timeout;
+        let mut running = Vec::new();
+        for node in &self.nodes {
+            if node.running().await {
+                running.push(node);
+            }
+        }
+
+        let seen = join_all(running.iter().map(|node| first_seen(node, hash, deadline))).await;
+        let (mut observations, mut missing) = (Vec::new(), Vec::new());
+        for (node, seen_at) in running.iter().zip(seen) {
+            match seen_at {
+                Some(seen_at) => observations.push((node.name.clone(), seen_at)),
+                None => missing.push(node.name.clone()),
+            }
+        }
+        observations.sort_by_key(|&(_, seen_at)| seen_at);
+
+        let first_node = observations
+            .first()
+            .and_then(|(name, _)| running.iter().find(|node| &node.name == name));
+        let included_at = match first_node {
+            Some(node) => inclusion_time(node, hash, deadline).await,
+            None => None,
+        };
+
+        DeployTrace {
+            deploy_hash: hash.to_owned(),
+            started_at,
+            observations,
+            missing,
+            included_at,
+        }
+    }
+}
+
+/// Polls the `node` for the deploy until it returns it, and returns when it
+/// did, or `None` if it did not before the `deadline`.
+async fn first_seen(node: &RunningNode, hash: &str, deadline: Instant) -> Option<SystemTime> {
+    let rpc = node.rpc();
+
+    loop {
+        if rpc.get_deploy(hash).await.is_ok() {
+            return Some(SystemTime::now());
+        }
+        if Instant::now() + POLL_INTERVAL > deadline {
+            return None;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Polls the `node` until it has executed the deploy, and returns the
+/// timestamp of the block including it, or `None` if it is not executed
+/// before the `deadline`.
+async fn inclusion_time(node: &RunningNode, hash: &str, deadline: Instant) -> Option<SystemTime> {
+    let rpc = node.rpc();
+
+    loop {
+        let block_hash = rpc.get_deploy(hash).await.ok().and_then(|deploy| {
+            deploy
+                .get("execution_results")?
+                .as_array()?
+                .first()?
+                .get("block_hash")?
+                .as_str()
+                .map(ToOwned::to_owned)
+        });
+        if let Some(block_hash) = block_hash {
+            return rpc.block_timestamp(&block_hash).await.ok().flatten();
+        }
+        if Instant::now() + EXECUTION_POLL_INTERVAL > deadline {
+            return None;
+        }
+        tokio::time::sleep(EXECUTION_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::Node, testing};
+
+    /// Returns the timestamp of the block of the fake nodes.
+    fn block_timestamp() -> SystemTime {
+        humantime::parse_rfc3339(testing::BLOCK_TIMESTAMP).unwrap()
+    }
+
+    #[tokio::test]
+    async fn each_node_is_traced_when_it_returns_the_deploy() {
+        let (_dir, artifacts) = testing::artifacts();
+        let node = || Node::validator(artifacts.clone());
+        let network = testing::builder()
+            .with(node().env("CNUT_FAKE_NODE_DEPLOY_AFTER", "0"))
+            .with(node().env("CNUT_FAKE_NODE_DEPLOY_AFTER", "2000"))
+            .with(node())
+            .with(node().env("CNUT_FAKE_NODE_DEPLOY_AFTER", "0"))
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        network.wait_until_ready().await.unwrap();
+        network
+            .node_by_name("Node_D")
+            .unwrap()
+            .clone()
+            .stop()
+            .await
+            .unwrap();
+
+        let trace = network.trace_deploy("0a0a0a", Duration::from_secs(4)).await;
+        network.shutdown();
+        network.wait().await.unwrap();
+
+        let names: Vec<_> = trace.observations.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["Node_A", "Node_B"]);
+        assert_eq!(trace.missing, ["Node_C"]);
+        assert_eq!(trace.included_at, Some(block_timestamp()));
+        assert!(trace.min_delay().unwrap() < Duration::from_secs(1));
+        assert!(trace.spread().unwrap() >= Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn a_deploy_never_returned_is_missing_everywhere() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 2)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        network.wait_until_ready().await.unwrap();
+
+        let trace = network
+            .trace_deploy("0a0a0a", Duration::from_millis(500))
+            .await;
+        network.shutdown();
+        network.wait().await.unwrap();
+
+        assert!(trace.observations.is_empty());
+        assert_eq!(trace.missing, ["Node_A/0", "Node_A/1"]);
+        assert_eq!(trace.included_at, None);
+        assert_eq!(trace.spread(), None);
+    }
+}
@@ -0,0 +1,260 @@
+//! Logs a summary of the network health at a regular interval, and appends it
+//! to a file, to tell when a long run degraded.
+
+use crate::{
+    error::{Error, Result},
+    network::{fork, BlockAgreement, NodeHealth, RunningNetwork, RunningNode},
+};
+use futures::future::join_all;
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::{fs, io::AsyncWriteExt as _, select, time::MissedTickBehavior};
+
+/// The shortest interval between two reports.
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A line of the report file.
+#[derive(Serialize)]
+struct HealthReport {
+    timestamp: String,
+    nodes: Vec<NodeReport>,
+    /// The block compared, with the agreement of the nodes on it.
+    fork_check: Option<(u64, &'static str)>,
+}
+
+#[derive(Serialize)]
+struct NodeReport {
+    name: String,
+    state: &'static str,
+    era_id: Option<u64>,
+    height: Option<u64>,
+    storage_bytes: u64,
+    crashes: u32,
+}
+
+impl RunningNetwork {
+    /// Reports the health of the network every `interval`, until it is shut
+    /// down: the state and the progress of each node, the fork check at the
+    /// highest common block, the size of the node storages and their crash
+    /// count. A summary is logged, and the report is appended to the file at
+    /// `path` as a JSON object per line, which can be read with `jq`.
+    ///
+    /// The interval must be one second or longer. The states are only known
+    /// if the progress of the nodes is recorded, see
+    /// [`NetworkBuilder::history_length`](super::NetworkBuilder::history_length).
+    pub fn enable_health_reports(
+        &self,
+        interval: Duration,
+        path: impl Into<PathBuf>,
+    ) -> Result<()> {
+        if interval < MIN_INTERVAL {
+            return Err(Error::HealthReportIntervalTooShort(interval));
+        }
+        let network = self.detached();
+        let cancellation = self.background_tasks.clone();
+        let path = path.into();
+
+        tokio::spawn(async move {
+            let mut file = match fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await
+            {
+                Ok(file) => file,
+                Err(e) => {
+                    log::error!("Could not open the health report file {path:?}: {e}");
+                    return;
+                }
+            };
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                select! {
+                    _ = cancellation.cancelled() => break,
+                    _ = interval.tick() => (),
+                }
+
+                let report = health_report(&network).await;
+                log::info!("Health: {}", summary(&report));
+
+                let mut line = serde_json::to_string(&report).expect("JSON serialization failed");
+                line.push('\n');
+                let written = async {
+                    file.write_all(line.as_bytes()).await?;
+                    file.flush().await
+                };
+                if let Err(e) = written.await {
+                    log::warn!("Could not write the health report to {path:?}: {e}");
+                }
+            }
+
+            if let Err(e) = file.sync_all().await {
+                log::warn!("Could not flush the health report file {path:?}: {e}");
+            }
+            log::debug!("Health reports stopped");
+        });
+
+        Ok(())
+    }
+}
+
+async fn health_report(network: &RunningNetwork) -> HealthReport {
+    let health = network.health().await;
+    let nodes = join_all(
+        health
+            .into_iter()
+            .map(|(node, health)| node_report(node, health)),
+    )
+    .await;
+    let fork_check = match fork::common_height(&network.nodes).await {
+        Some(height) => {
+            let agreement = match network.check_fork(height).await.agreement {
+                BlockAgreement::Consistent(_) => "consistent",
+                BlockAgreement::Forked(_) => "forked",
+                BlockAgreement::NoBlock => "no-block",
+            };
+            Some((height, agreement))
+        }
+        None => None,
+    };
+
+    HealthReport {
+        timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        nodes,
+        fork_check,
+    }
+}
+
+async fn node_report(node: &RunningNode, health: NodeHealth) -> NodeReport {
+    let last = node.history.lock().await.back().copied();
+
+    NodeReport {
+        name: node.name().to_owned(),
+        state: health.label(),
+        era_id: last.map(|entry| entry.era_id),
+        height: last.map(|entry| entry.height),
+        storage_bytes: dir_size(&node.data_dir().join("node-storage")).await,
+        crashes: node.crash_count(),
+    }
+}
+
+/// Returns a line like `3/4 healthy, heights 10-12, consistent at 10, 5 MiB
+/// stored, 1 crash`.
+fn summary(report: &HealthReport) -> String {
+    let healthy = report
+        .nodes
+        .iter()
+        .filter(|node| node.state == "healthy")
+        .count();
+    let heights = report.nodes.iter().filter_map(|node| node.height);
+    let heights = match (heights.clone().min(), heights.max()) {
+        (Some(min), Some(max)) => format!("heights {min}-{max}"),
+        _ => "no height".to_owned(),
+    };
+    let fork_check = match report.fork_check {
+        Some((height, agreement)) => format!("{agreement} at {height}"),
+        None => "no common block".to_owned(),
+    };
+    let storage: u64 = report.nodes.iter().map(|node| node.storage_bytes).sum();
+    let crashes: u32 = report.nodes.iter().map(|node| node.crashes).sum();
+
+    format!(
+        "{healthy}/{} healthy, {heights}, {fork_check}, {} MiB stored, {crashes} crash{}",
+        report.nodes.len(),
+        storage >> 20,
+        if crashes == 1 { "" } else { "es" },
+    )
+}
+
+/// Returns the size of the files in the directory, zero if it cannot be read.
+async fn dir_size(dir: &Path) -> u64 {
+    let mut size = 0;
+    let mut to_visit = vec![dir.to_owned()];
+
+    while let Some(dir) = to_visit.pop() {
+        let Ok(mut entries) = fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            match entry.metadata().await {
+                Ok(metadata) if metadata.is_dir() => to_visit.push(entry.path()),
+                Ok(metadata) => size += metadata.len(),
+                Err(_) => (),
+            }
+        }
+    }
+
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[tokio::test]
+    async fn the_reports_are_appended_as_json_lines() {
+        let (dir, artifacts) = testing::artifacts();
+        let path = dir.path().join("health.jsonl");
+        let network = testing::network(&artifacts, 2)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        network.wait_until_ready().await.unwrap();
+
+        network.enable_health_reports(MIN_INTERVAL, &path).unwrap();
+        let lines = tokio::time::timeout(MIN_INTERVAL * 10, async {
+            loop {
+                let content = fs::read_to_string(&path).await.unwrap_or_default();
+                if content.ends_with('\n') && content.lines().count() >= 2 {
+                    break content;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("two reports to be written");
+        network.shutdown();
+        network.wait().await.unwrap();
+
+        for line in lines.lines() {
+            let report: serde_json::Value = serde_json::from_str(line).unwrap();
+            let names: Vec<_> = report["nodes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|node| node["name"].as_str().unwrap())
+                .collect();
+            assert_eq!(names, ["Node_A/0", "Node_A/1"]);
+            assert!(report["timestamp"].is_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn an_interval_too_short_is_rejected() {
+        let (dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 1)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+
+        let result = network
+            .enable_health_reports(Duration::from_millis(500), dir.path().join("health.jsonl"));
+        assert!(matches!(
+            result,
+            Err(Error::HealthReportIntervalTooShort(_))
+        ));
+    }
+}
@@ -10,44 +10,343 @@
 //! - The node keys.
 
 use crate::{
+    artifacts::Artifacts,
     error::{Error, Result},
-    network::{NodeStatus, RunningNetwork, RunningNode},
+    network::{
+        logs, monitor, prepare, LogAlert, NetworkEvent, NetworkState, NodeStatus,
+        ProcessExitStatus, RunningNetwork, RunningNode, Signal,
+    },
+    util::{self, spawn_process},
     web_app,
 };
+use futures::future::join_all;
 use std::{
+    collections::BTreeSet,
+    net::{Ipv4Addr, SocketAddr},
+    path::Path,
     process::{ExitStatus, Stdio},
     sync::Arc,
+    time::{Duration, SystemTime},
 };
-use tokio::{process::Command, select, signal, sync::mpsc};
+use tokio::{
+    process::{Child, Command},
+    select, signal,
+    sync::{broadcast, OwnedMutexGuard},
+};
+
+/// How many consecutive polls the nodes must agree on their height to be
+/// synced. See [`RunningNetwork::wait_for_all_synced`].
+const SYNCED_POLLS: u32 = 3;
+
+/// How long to wait before starting the next phase of nodes. See
+/// [`RunningNetwork::start_phased`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhaseWait {
+    /// Waits for a fixed time.
+    Delay(Duration),
+    /// Waits until the nodes started so far reached the given era.
+    ReachedEra(u64),
+    /// Waits until the nodes started so far have added a block.
+    AllReady,
+}
+
+/// How the nodes are restarted by [`RunningNetwork::restart_all`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartMode {
+    /// All the nodes are stopped, then all started again.
+    Simultaneous,
+    /// The nodes are restarted one after the other, waiting for the `delay`
+    /// between each one.
+    Rolling {
+        /// The time waited after a node is restarted.
+        delay: Duration,
+    },
+}
 
 impl RunningNetwork {
     /// Starts all the nodes.
     pub async fn start_all(&self) -> Result<&Self> {
+        let genesis_start = self.state() == NetworkState::Prepared;
+        let mut late = Vec::new();
+
+        self.set_state(NetworkState::Starting);
         for node in &self.nodes {
             node.clone().start().await?;
+            if genesis_start && self.missed_genesis(node) {
+                late.push(node.name.clone());
+            }
         }
+        self.set_state(NetworkState::Running);
+        self.check_missed_genesis(late)?;
 
         Ok(self)
     }
 
-    /// Shuts the network down.
+    /// Starts the nodes by ascending [phase](crate::network::Node::start_phase),
+    /// the nodes of a phase together. Before starting the next phase, waits
+    /// according to `wait_between`. The condition is checked on the nodes still
+    /// running, every second, and fails with [`Error::PhaseNotReached`] when
+    /// it is not met after the
+    /// [readiness timeout](crate::network::NetworkBuilder::readiness_timeout).
+    pub async fn start_phased(&self, wait_between: PhaseWait) -> Result<&Self> {
+        let phases: BTreeSet<_> = self.nodes.iter().map(RunningNode::start_phase).collect();
+        let client = reqwest::Client::new();
+        let genesis_start = self.state() == NetworkState::Prepared;
+        let mut late = Vec::new();
+
+        self.set_state(NetworkState::Starting);
+        for (i, phase) in phases.into_iter().enumerate() {
+            if i > 0 {
+                log::info!("Waiting ({wait_between:?}) before starting the phase {phase}");
+                self.wait_for_phase(&client, wait_between, phase).await?;
+            }
+            log::info!("Starting the nodes of the phase {phase}");
+            for node in self.nodes.iter().filter(|node| node.start_phase == phase) {
+                node.clone().start().await?;
+                if genesis_start && self.missed_genesis(node) {
+                    late.push(node.name.clone());
+                }
+            }
+        }
+        self.set_state(NetworkState::Running);
+        self.check_missed_genesis(late)?;
+
+        Ok(self)
+    }
+
+    /// Returns `true` if the `node` is a validator started after the genesis.
+    fn missed_genesis(&self, node: &RunningNode) -> bool {
+        node.validator
+            && !node.dry_run
+            && self
+                .activation_point
+                .is_some_and(|genesis| SystemTime::now() > genesis)
+    }
+
+    /// Warns about the validators started after the genesis, or fails with
+    /// [`Error::MissedGenesis`] if the network must have all its validators
+    /// at genesis.
+    fn check_missed_genesis(&self, nodes: Vec<String>) -> Result<()> {
+        if nodes.is_empty() {
+            return Ok(());
+        }
+        if self.strict_genesis {
+            return Err(Error::MissedGenesis { nodes });
+        }
+        log::warn!(
+            "The validators {} were started after the genesis, consider a longer startup allowance",
+            nodes.join(", ")
+        );
+
+        Ok(())
+    }
+
+    /// Waits until each running node answers with a block, which means it
+    /// has joined the network. The nodes are checked at the
+    /// [readiness interval](crate::network::NetworkBuilder::readiness_interval).
+    /// Fails with [`Error::NodesNotReady`] and the nodes still not ready after
+    /// the [readiness timeout](crate::network::NetworkBuilder::readiness_timeout).
+    pub async fn wait_until_ready(&self) -> Result<&Self> {
+        let client = reqwest::Client::new();
+        let timeout = self.monitor_settings.readiness_timeout;
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        loop {
+            let mut not_ready = Vec::new();
+            for node in &self.nodes {
+                if node.running().await && monitor::fetch_block_info(&client, node).await.is_none()
+                {
+                    not_ready.push(node.name.clone());
+                }
+            }
+            if not_ready.is_empty() {
+                return Ok(self);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::NodesNotReady {
+                    timeout,
+                    nodes: not_ready,
+                });
+            }
+            tokio::time::sleep(self.monitor_settings.readiness_interval).await;
+        }
+    }
+
+    /// Waits until the reachable nodes agree on their height, within one
+    /// block, for a few seconds in a row. It is checked every second, and the
+    /// nodes which are stopped or do not answer are ignored. Fails with
+    /// [`Error::NodesNotSynced`] and the height of each node after `timeout`.
+    pub async fn wait_for_all_synced(&self, timeout: Duration) -> Result<()> {
+        let client = reqwest::Client::new();
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut synced_polls = 0;
+
+        loop {
+            let heights = join_all(self.nodes.iter().map(|node| {
+                let client = &client;
+                async move {
+                    let height = if node.running().await {
+                        monitor::fetch_block_info(client, node)
+                            .await
+                            .map(|info| info.height)
+                    } else {
+                        None
+                    };
+                    (node.name.clone(), height)
+                }
+            }))
+            .await;
+            let reached = heights.iter().filter_map(|(_, height)| *height);
+
+            synced_polls = match (reached.clone().min(), reached.max()) {
+                (Some(min), Some(max)) if max - min <= 1 => synced_polls + 1,
+                _ => 0,
+            };
+            if synced_polls >= SYNCED_POLLS {
+                log::info!("The nodes are synced: {heights:?}");
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::NodesNotSynced { timeout, heights });
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Waits for the condition on the nodes started before the `next_phase`,
+    /// at most for the readiness timeout.
+    async fn wait_for_phase(
+        &self,
+        client: &reqwest::Client,
+        wait: PhaseWait,
+        next_phase: u8,
+    ) -> Result<()> {
+        let era = match wait {
+            PhaseWait::Delay(delay) => {
+                tokio::time::sleep(delay).await;
+                return Ok(());
+            }
+            PhaseWait::ReachedEra(era) => era,
+            PhaseWait::AllReady => 0,
+        };
+        let timeout = self.monitor_settings.readiness_timeout;
+        let deadline = tokio::time::Instant::now() + timeout;
+        let started = || {
+            self.nodes
+                .iter()
+                .filter(|node| node.start_phase < next_phase)
+        };
+
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            let mut waited_for = Vec::new();
+            for node in started() {
+                if !node.running().await {
+                    continue;
+                }
+                match monitor::fetch_block_info(client, node).await {
+                    Some(info) if info.era_id >= era => (),
+                    _ => waited_for.push(node.name.clone()),
+                }
+            }
+            if waited_for.is_empty() {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::PhaseNotReached {
+                    next_phase,
+                    timeout,
+                    nodes: waited_for,
+                });
+            }
+        }
+    }
+
+    /// Shuts the network down: the nodes are stopped together, and the ones
+    /// still running after the shutdown timeout are killed. See
+    /// [`NetworkBuilder::shutdown_timeout`](crate::network::NetworkBuilder::shutdown_timeout).
     pub async fn stop_all(&self) -> Result<&Self> {
+        stop_nodes(&self.nodes, self.shutdown_timeout).await?;
+        self.set_state(NetworkState::Stopped);
+
+        Ok(self)
+    }
+
+    /// Stops and starts again the running nodes, according to the `mode`. The
+    /// storage of the nodes is kept, so that they resync instead of starting
+    /// from the genesis. The stopped nodes are left stopped.
+    pub async fn restart_all(&self, mode: RestartMode) -> Result<&Self> {
+        let mut running = Vec::new();
         for node in &self.nodes {
-            node.clone().stop().await?;
+            if node.running().await {
+                running.push(node.clone());
+            }
         }
 
+        self.set_state(NetworkState::Starting);
+        match mode {
+            RestartMode::Simultaneous => {
+                log::info!("Restarting {} nodes simultaneously", running.len());
+                stop_nodes(&running, self.shutdown_timeout).await?;
+                for node in &mut running {
+                    node.start().await?;
+                }
+            }
+            RestartMode::Rolling { delay } => {
+                log::info!("Restarting {} nodes one by one", running.len());
+                for (i, node) in running.iter_mut().enumerate() {
+                    if i > 0 {
+                        tokio::time::sleep(delay).await;
+                    }
+                    node.restart().await?;
+                }
+            }
+        }
+        self.set_state(NetworkState::Running);
+
         Ok(self)
     }
 
+    /// Same as [`RunningNetwork::restart_all`], the nodes being restarted onto
+    /// the new `artifacts`, to test an upgrade of the node binary. All the
+    /// nodes use the new artifacts, including the stopped ones when they are
+    /// started again. The chainspec and config of the nodes are kept.
+    pub async fn restart_all_onto(&self, mode: RestartMode, artifacts: Artifacts) -> Result<&Self> {
+        let artifacts = prepare::materialize_artifacts(artifacts, self.temp_directory()).await?;
+        for node in &self.nodes {
+            *node.artifact_dir.write().expect("poisoned lock") = artifacts.path().to_owned();
+        }
+
+        self.restart_all(mode).await
+    }
+
     /// Wait for the network.
     ///
     /// Note that this will prevent any node to be started. Any attempt to do so
     /// will deadlock the call.
     pub async fn wait(&self) -> Result<()> {
+        self.wait_at_most(None).await
+    }
+
+    /// Waits for the network like [`RunningNetwork::wait`], but shuts it down
+    /// once the `duration` has elapsed.
+    pub async fn run_for(&self, duration: Duration) -> Result<()> {
+        self.wait_at_most(Some(duration)).await
+    }
+
+    async fn wait_at_most(&self, duration: Option<Duration>) -> Result<()> {
+        let elapsed = async {
+            match duration {
+                Some(duration) => tokio::time::sleep(duration).await,
+                None => std::future::pending().await,
+            }
+        };
+
         select! {
             _ = signal::ctrl_c() => {log::debug!("Got CTRL+C signal, shutting down")},
             _ = self.exit_notification.notified() => {log::debug!("Got a shutting down order")},
             _ = self.task_tracker.wait() => {log::debug!("No node is running anymore")},
+            _ = elapsed => {log::debug!("The run duration has elapsed, shutting down")},
         };
 
         clean_kill_all(self).await;
@@ -55,17 +354,34 @@ impl RunningNetwork {
         Ok(())
     }
 
-    /// Serves the web app for debugging, then returns immediately (non-blocking).
+    /// Serves the web app for debugging on `127.0.0.1`, at the port set with
+    /// [`NetworkBuilder::web_port`](super::NetworkBuilder::web_port), then
+    /// returns immediately (non-blocking).
     pub async fn serve_web_app(&self) -> Result<()> {
-        web_app::serve(self.clone()).await
+        self.serve_web_app_on(SocketAddr::from((Ipv4Addr::LOCALHOST, self.web_port)))
+            .await
+    }
+
+    /// Serves the web app for debugging at the given `address`, such as
+    /// `0.0.0.0:6532` to reach it from other machines, then returns
+    /// immediately (non-blocking). See
+    /// [`NetworkBuilder::web_credentials`](super::NetworkBuilder::web_credentials)
+    /// to protect it.
+    pub async fn serve_web_app_on(&self, address: SocketAddr) -> Result<()> {
+        web_app::serve(self.clone(), address).await
     }
 
     /// Serves the web app for debugging, then wait for the network to stop.
     pub async fn serve_web_app_and_wait(&self) -> Result<()> {
-        web_app::serve(self.clone()).await?;
+        self.serve_web_app().await?;
         self.wait().await
     }
 
+    /// Subscribes to the events happening in the network. See [`NetworkEvent`].
+    pub fn subscribe(&self) -> broadcast::Receiver<NetworkEvent> {
+        self.events.subscribe()
+    }
+
     /// Returns the node with the given `name`.
     pub fn node_by_name(&self, name: &str) -> Result<&RunningNode> {
         self.nodes
@@ -83,61 +399,160 @@ impl RunningNetwork {
 }
 
 impl RunningNode {
-    /// Starts the node.
+    /// Starts the node. It waits for the start or stop operation in progress
+    /// on this node, if any.
     pub async fn start(&mut self) -> Result<()> {
-        let node_path = self.artifact_dir.join("casper-node");
-        let config_path = self.data_dir.join("config.toml");
-        let mut child = Command::new(&node_path)
-            .arg("validator")
-            .arg(&config_path)
-            .current_dir(&self.data_dir)
+        let _operation = self.operation_lock.clone().lock_owned().await;
+
+        self.start_locked()
+            .await
+            .map_err(|e| e.context(format!("starting the node {}", self.name)))
+    }
+
+    /// Stops the node: its process is asked to terminate with `SIGTERM`, and
+    /// is killed if it still runs after the
+    /// [shutdown timeout](crate::network::NetworkBuilder::shutdown_timeout).
+    /// It waits for the start or stop operation in progress on this node, if
+    /// any.
+    pub async fn stop(&mut self) -> Result<()> {
+        let _operation = self.operation_lock.clone().lock_owned().await;
+
+        self.stop_locked()
+            .await
+            .map_err(|e| e.context(format!("stopping the node {}", self.name)))
+    }
+
+    /// Stops the node if it is running, waits for its process to exit, then
+    /// starts it again with the same storage.
+    pub async fn restart(&mut self) -> Result<()> {
+        let _operation = self.operation_lock.clone().lock_owned().await;
+
+        async {
+            if self.running().await {
+                self.stop_locked().await?;
+                let _ = self.wait_for_exit().await;
+            }
+            self.start_locked().await
+        }
+        .await
+        .map_err(|e| e.context(format!("restarting the node {}", self.name)))
+    }
+
+    /// Returns the guard allowing to start or stop the node, or `None` if an
+    /// operation is already in progress.
+    pub(crate) fn try_lock_operation(&self) -> Option<OwnedMutexGuard<()>> {
+        self.operation_lock.clone().try_lock_owned().ok()
+    }
+
+    /// Starts the node. The caller must hold the operation lock.
+    pub(crate) async fn start_locked(&mut self) -> Result<()> {
+        let node_path = self.artifact_dir().join("casper-node");
+        let mut command = self.command();
+        if self.dry_run {
+            let full_command = full_command(&command);
+            log::info!("Dry run: node {} would run: {full_command}", self.name);
+            *self.status.lock().await = NodeStatus::WouldRun(full_command);
+
+            return Ok(());
+        }
+        if !is_executable(&node_path).await {
+            return Err(Error::NodeBinaryMissing(node_path));
+        }
+        if self.log_alert_patterns.is_some() {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        } else {
             // Remove the output:
-            .stdout(Stdio::null())
-            .spawn()
-            .map_err(|io_err| crate::error::Error::FailedToSpawnProcess {
-                full_command: format!(
-                    "{} validator {}",
-                    node_path.to_string_lossy(),
-                    config_path.to_string_lossy(),
-                ),
-                io_err,
-            })?;
+            command.stdout(Stdio::null());
+        }
+        let mut child =
+            command
+                .spawn()
+                .map_err(|io_err| crate::error::Error::FailedToSpawnProcess {
+                    full_command: full_command(&command),
+                    io_err,
+                })?;
 
         log::info!("Node {} spawned successfully", self.name);
 
+        if let Some(patterns) = &self.log_alert_patterns {
+            let stdout = child.stdout.take().expect("stdout to be piped");
+            let stderr = child.stderr.take().expect("stderr to be piped");
+
+            self.task_tracker.spawn(logs::watch_output(
+                stdout,
+                self.stdout_path(),
+                self.clone(),
+                patterns.clone(),
+            ));
+            self.task_tracker.spawn(logs::watch_output(
+                stderr,
+                self.stderr_path(),
+                self.clone(),
+                patterns.clone(),
+            ));
+        }
+
+        self.exit_status.send_replace(None);
+        let pid = child.id().unwrap_or_default();
+        self.process_id
+            .store(pid, std::sync::atomic::Ordering::Relaxed);
+        if let Err(e) = tokio::fs::write(self.pid_path(), pid.to_string()).await {
+            log::warn!("Could not write the pid file of {}: {e}", self.name);
+        }
+        // Set before watching the process, so that an early crash is not overwritten:
+        *self.status.lock().await = NodeStatus::Running;
+
         let name = self.name.clone();
         let kill_notifier = self.kill_notifier.clone();
-        let pid = child.id().unwrap_or_default();
+        let node_status = self.status.clone();
+        let process_id = self.process_id.clone();
+        let crashes = self.crashes.clone();
+        let pid_path = self.pid_path();
+        let exit_status = self.exit_status.clone();
+        let container_id = self.container_id();
+        let data_dir = self.data_dir.clone();
+        let shutdown_timeout = self.shutdown_timeout;
         self.task_tracker.spawn(async move {
             let (result, crash) = tokio::select! {
                 exit_result = child.wait() => (exit_result, true), // Early exit (error in the node for example)
-                _ = kill_notifier.notified() => (child.kill().await.map(|()| ExitStatus::default()), false),
+                _ = kill_notifier.notified() => match &container_id {
+                    // Killing the Docker CLI would leave the container running:
+                    Some(container_id) => {
+                        let timeout = shutdown_timeout.as_secs_f64().ceil().to_string();
+                        let stop = ["docker", "stop", "--time", &timeout, container_id];
+                        if let Err(e) = spawn_process(&data_dir, stop).await {
+                            log::warn!("Could not stop the container {container_id}: {e}");
+                        }
+                        (child.wait().await, false)
+                    }
+                    None => (terminate(&mut child, &name, shutdown_timeout).await, false),
+                },
             };
             log::info!("Child process {name:?} has stopped: {result:?}. Crashed: {crash}");
 
             if let Err(io_err) = result.as_ref() {
                 log::warn!("Child process {name:?} has errored: {io_err:?}");
             }
-            let status = if crash {
-                NodeStatus::Crashed(result)
-            } else {
-                NodeStatus::Stopped(result)
-            };
-            (name, status)
+            // A stopped node has its status set by `stop`:
+            exit_status.send_replace(Some(copy_exit_status(&result)));
+            if crash {
+                crashes.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                process_id.store(0, std::sync::atomic::Ordering::Relaxed);
+                let _ = tokio::fs::remove_file(&pid_path).await;
+                *node_status.lock().await = NodeStatus::Crashed(result);
+            }
         });
 
-        self.process_id
-            .store(pid, std::sync::atomic::Ordering::Relaxed);
-        *self.status.lock().await = NodeStatus::Running;
-
         Ok(())
     }
 
-    /// Stops the node.
-    pub async fn stop(&mut self) -> Result<()> {
+    /// Stops the node. The caller must hold the operation lock.
+    pub(crate) async fn stop_locked(&mut self) -> Result<()> {
+        self.thaw().await;
         self.kill_process()?;
         self.process_id
             .store(0, std::sync::atomic::Ordering::Relaxed);
+        let _ = tokio::fs::remove_file(self.pid_path()).await;
         *self.status.lock().await = NodeStatus::Stopped(Ok(ExitStatus::default()));
 
         Ok(())
@@ -150,6 +565,26 @@ impl RunningNode {
         Ok(())
     }
 
+    /// Waits for the node process to terminate, and returns its exit status.
+    /// Returns immediately if the node is not running.
+    ///
+    /// When the node was stopped, the status is the one of the killed process.
+    pub async fn wait_for_exit(&self) -> ProcessExitStatus {
+        let mut receiver = self.exit_status.subscribe();
+        let exit_status = receiver
+            .wait_for(Option::is_some)
+            .await
+            .expect("the sender to be held by the node");
+
+        copy_exit_status(exit_status.as_ref().expect("the process to have exited"))
+    }
+
+    /// Returns the alerts raised so far by the output of this node. See
+    /// [`NetworkBuilder::log_alert_patterns`](crate::network::NetworkBuilder::log_alert_patterns).
+    pub async fn alerts(&self) -> Vec<LogAlert> {
+        self.alerts.lock().await.clone()
+    }
+
     /// Returns the current status for the node.
     pub async fn status<'a>(&'a self) -> tokio::sync::MutexGuard<'a, NodeStatus> {
         self.status.lock().await
@@ -162,28 +597,388 @@ impl Drop for RunningNetwork {
         // kill all the hard way:
         if self.shutdown_state.must_shut_down() {
             hard_kill_all(self);
+            self.spawn_shutdown_hooks();
+            self.set_state(NetworkState::Stopped);
+        }
+    }
+}
+
+/// Returns the command, prefixed by its environment variables.
+fn full_command(command: &Command) -> String {
+    let command = command.as_std();
+    let env = command.get_envs().filter_map(|(key, value)| {
+        Some(format!(
+            "{}={}",
+            key.to_string_lossy(),
+            value?.to_string_lossy()
+        ))
+    });
+    let program = std::iter::once(command.get_program().to_string_lossy().into_owned());
+    let args = command
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned());
+
+    env.chain(program).chain(args).collect::<Vec<_>>().join(" ")
+}
+
+/// Asks the process to terminate with `SIGTERM`, and kills it if it is still
+/// running after the `timeout`. It is killed right away if the signal cannot
+/// be sent, such as on the platforms other than Unix.
+async fn terminate(child: &mut Child, name: &str, timeout: Duration) -> ProcessExitStatus {
+    if let Some(pid) = child.id() {
+        if util::send_signal(pid, Signal::Term).is_ok() {
+            match tokio::time::timeout(timeout, child.wait()).await {
+                Ok(result) => return result,
+                Err(_) => log::warn!(
+                    "Node {name} did not stop within {}, killing it",
+                    humantime::format_duration(timeout)
+                ),
+            }
         }
     }
+    child.kill().await?;
+
+    child.wait().await
+}
+
+/// Copies the exit status, since the IO error cannot be cloned.
+fn copy_exit_status(exit_status: &ProcessExitStatus) -> ProcessExitStatus {
+    match exit_status {
+        Ok(status) => Ok(*status),
+        Err(io_err) => Err(std::io::Error::new(io_err.kind(), io_err.to_string())),
+    }
 }
 
-/// Set the network as shutting down and ask all the processes to stop.
+/// Returns `true` if the file exists and, on Unix, can be executed.
+async fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = tokio::fs::metadata(path).await else {
+        return false;
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+/// Set the network as shutting down and ask all the processes to stop. Does
+/// nothing if the network is already shutting down.
 async fn clean_kill_all(network: &RunningNetwork) {
+    if !network.shutdown_state.begin_shutdown() {
+        log::debug!("Network is already shutting down");
+        return;
+    }
     log::info!("Network will now shut down");
+    network.set_state(NetworkState::ShuttingDown);
 
-    //TODO verify that the network isn't already shutting down
+    network.background_tasks.cancel();
+    if let Err(e) = stop_nodes(&network.nodes, network.shutdown_timeout).await {
+        log::warn!("Error while shutting the network down: {e}");
+    }
+    network.run_shutdown_hooks().await;
+    network.shutdown_state.finish_shutdown();
+    network.set_state(NetworkState::Stopped);
+}
 
-    for mut node in network.nodes.iter().map(Clone::clone) {
-        let _ = node.stop().await;
+/// Stops the nodes concurrently, then kills the processes still running after
+/// the `timeout`.
+async fn stop_nodes(nodes: &[RunningNode], timeout: Duration) -> Result<()> {
+    let pids: Vec<_> = nodes.iter().map(RunningNode::pid).collect();
+    let results = join_all(nodes.iter().map(|node| {
+        let mut node = node.clone();
+        async move { node.stop().await }
+    }))
+    .await;
+    let exits = join_all(nodes.iter().map(RunningNode::wait_for_exit));
+
+    if tokio::time::timeout(timeout, exits).await.is_err() {
+        for (node, pid) in nodes.iter().zip(pids) {
+            if node.exit_status.borrow().is_none() {
+                log::warn!(
+                    "Node {} did not stop within {}, killing it",
+                    node.name,
+                    humantime::format_duration(timeout)
+                );
+                hard_kill(node, pid);
+            }
+        }
     }
+
+    results.into_iter().collect()
 }
 
 /// Set the network as shutting down and force kill all the processes.
 fn hard_kill_all(network: &RunningNetwork) {
     log::info!("Network will now shut down");
 
-    for mut node in network.nodes.iter().map(Clone::clone) {
-        if node.process_id.load(std::sync::atomic::Ordering::Relaxed) != 0 {
-            //
+    for node in &network.nodes {
+        hard_kill(node, node.pid());
+    }
+}
+
+/// Kills the node process right away.
+fn hard_kill(node: &RunningNode, pid: Option<u32>) {
+    // The containers are not children of this process, so they survive it:
+    if let Some(container_id) = node.container_id() {
+        let _ = std::process::Command::new("docker")
+            .args(["kill", &container_id])
+            .output();
+    } else if let Some(pid) = pid {
+        util::kill_process(pid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::Node, testing};
+
+    /// Returns `true` if a process has the `pid`.
+    #[cfg(unix)]
+    fn process_exists(pid: u32) -> bool {
+        let pid = rustix::process::Pid::from_raw(pid as i32).expect("a valid PID");
+
+        rustix::process::test_kill_process(pid).is_ok()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn the_nodes_ignoring_sigterm_are_killed_after_the_timeout() {
+        let timeout = Duration::from_millis(500);
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 1)
+            .with(Node::validator(artifacts.clone()).env("CNUT_FAKE_NODE_IGNORE_SIGTERM", "1"))
+            .shutdown_timeout(timeout)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        network.wait_until_ready().await.unwrap();
+        let stubborn = network.node_by_name("Node_B").unwrap().clone();
+        let pid = stubborn.pid().unwrap();
+
+        let started = tokio::time::Instant::now();
+        network.shutdown();
+        tokio::time::timeout(timeout * 4, network.wait())
+            .await
+            .expect("the shutdown to be bounded by the timeout")
+            .unwrap();
+        assert!(started.elapsed() >= timeout);
+        assert_eq!(network.state(), NetworkState::Stopped);
+
+        let _ = stubborn.wait_for_exit().await;
+        assert!(!process_exists(pid));
+    }
+
+    /// Returns a network whose genesis is in 3 seconds, with a validator
+    /// ready after `ready_after` milliseconds, if any, and a validator started
+    /// once it is ready.
+    fn phased_network(
+        artifacts: &Artifacts,
+        ready_after: Option<&str>,
+        strict_genesis: bool,
+    ) -> crate::network::NetworkBuilder {
+        let mut first = Node::validator(artifacts.clone());
+        if let Some(ready_after) = ready_after {
+            first = first.env("CNUT_FAKE_NODE_READY_AFTER", ready_after);
+        }
+
+        testing::builder()
+            .with(first)
+            .with(Node::validator(artifacts.clone()).start_phase(1))
+            .activation_delay(Duration::from_secs(3))
+            .strict_genesis(strict_genesis)
+    }
+
+    #[tokio::test]
+    async fn the_validators_started_before_the_genesis_are_fine() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = phased_network(&artifacts, None, true)
+            .prepare()
+            .await
+            .unwrap()
+            .start_phased(PhaseWait::AllReady)
+            .await
+            .unwrap();
+
+        assert_eq!(network.state(), NetworkState::Running);
+        network.shutdown();
+        network.wait().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_validator_started_after_the_genesis_fails_a_strict_network() {
+        let (_dir, artifacts) = testing::artifacts();
+        let result = phased_network(&artifacts, Some("5000"), true)
+            .prepare()
+            .await
+            .unwrap()
+            .start_phased(PhaseWait::AllReady)
+            .await;
+
+        match result {
+            Err(Error::MissedGenesis { nodes }) => assert_eq!(nodes, ["Node_B"]),
+            other => panic!("expected a missed genesis, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_validator_started_after_the_genesis_is_only_a_warning_by_default() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = phased_network(&artifacts, Some("5000"), false)
+            .prepare()
+            .await
+            .unwrap()
+            .start_phased(PhaseWait::AllReady)
+            .await
+            .unwrap();
+
+        assert_eq!(network.state(), NetworkState::Running);
+        network.shutdown();
+        network.wait().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_phase_is_started_once_the_previous_one_is_ready() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = phased_network(&artifacts, Some("3000"), false)
+            .prepare()
+            .await
+            .unwrap();
+        let first = network.node_by_name("Node_A").unwrap().clone();
+        let second = network.node_by_name("Node_B").unwrap().clone();
+        let started = tokio::spawn(network.start_phased(PhaseWait::AllReady));
+
+        let client = reqwest::Client::new();
+        let first_ready = loop {
+            if second.pid().is_some() {
+                break monitor::fetch_block_info(&client, &first).await.is_some();
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        };
+        let network = started.await.unwrap().unwrap();
+        network.shutdown();
+        network.wait().await.unwrap();
+
+        assert!(
+            first_ready,
+            "the phase 1 was started before the phase 0 was ready"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_phase_never_ready_times_out() {
+        let (_dir, artifacts) = testing::artifacts();
+        let result = phased_network(&artifacts, Some("60000"), false)
+            .readiness_timeout(Duration::from_secs(2))
+            .prepare()
+            .await
+            .unwrap()
+            .start_phased(PhaseWait::AllReady)
+            .await;
+
+        match result {
+            Err(Error::PhaseNotReached {
+                next_phase, nodes, ..
+            }) => {
+                assert_eq!(next_phase, 1);
+                assert_eq!(nodes, ["Node_A"]);
+            }
+            other => panic!("expected the phase to time out, got {other:?}"),
+        }
+    }
+
+    /// Returns a started network of 3 nodes whose last one was stopped, with
+    /// the PIDs of the nodes still running.
+    async fn partly_stopped_network(artifacts: &Artifacts) -> (RunningNetwork, Vec<u32>) {
+        let network = testing::network(artifacts, 3)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        network.wait_until_ready().await.unwrap();
+        let mut stopped = network.node_by_name("Node_A/2").unwrap().clone();
+        stopped.stop().await.unwrap();
+        let _ = stopped.wait_for_exit().await;
+        let pids = network.nodes[..2]
+            .iter()
+            .map(|node| node.pid().unwrap())
+            .collect();
+
+        (network, pids)
+    }
+
+    /// Checks that the 2 nodes which were running have a new process, and
+    /// that the last one is still stopped.
+    async fn assert_only_the_running_nodes_restarted(network: &RunningNetwork, pids: &[u32]) {
+        for (node, pid) in network.nodes[..2].iter().zip(pids) {
+            assert!(node.running().await, "{} is not running", node.name);
+            assert_ne!(node.pid(), Some(*pid), "{} was not restarted", node.name);
+        }
+        let stopped = network.node_by_name("Node_A/2").unwrap();
+        assert!(!stopped.running().await);
+        assert_eq!(stopped.pid(), None);
+        assert_eq!(network.state(), NetworkState::Running);
+    }
+
+    #[tokio::test]
+    async fn a_simultaneous_restart_only_restarts_the_running_nodes() {
+        let (_dir, artifacts) = testing::artifacts();
+        let (network, pids) = partly_stopped_network(&artifacts).await;
+
+        network
+            .restart_all(RestartMode::Simultaneous)
+            .await
+            .unwrap();
+
+        assert_only_the_running_nodes_restarted(&network, &pids).await;
+        network.shutdown();
+        network.wait().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_rolling_restart_only_restarts_the_running_nodes() {
+        let (_dir, artifacts) = testing::artifacts();
+        let (network, pids) = partly_stopped_network(&artifacts).await;
+
+        let mode = RestartMode::Rolling {
+            delay: Duration::from_millis(100),
+        };
+        network.restart_all(mode).await.unwrap();
+
+        assert_only_the_running_nodes_restarted(&network, &pids).await;
+        network.shutdown();
+        network.wait().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_restart_onto_new_artifacts_switches_every_node() {
+        let (old_dir, artifacts) = testing::artifacts();
+        let (network, pids) = partly_stopped_network(&artifacts).await;
+        // Restarting from the old artifacts would then fail:
+        drop(old_dir);
+
+        let (new_dir, new_artifacts) = testing::artifacts();
+        network
+            .restart_all_onto(RestartMode::Simultaneous, new_artifacts)
+            .await
+            .unwrap();
+
+        assert_only_the_running_nodes_restarted(&network, &pids).await;
+        for node in &network.nodes {
+            assert_eq!(node.artifact_dir(), new_dir.path());
         }
+        network.shutdown();
+        network.wait().await.unwrap();
     }
 }
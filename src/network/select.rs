@@ -0,0 +1,332 @@
+//! Picks nodes of the network, for example the one a chaos action targets.
+
+use crate::{
+    error::{Error, Result},
+    network::{RunningNetwork, RunningNode},
+};
+use futures::future::join_all;
+use rand::seq::SliceRandom as _;
+use reqwest::Client;
+use std::time::{Duration, Instant};
+
+/// How long the result of a health probe is trusted.
+const HEALTH_CACHE_DURATION: Duration = Duration::from_secs(2);
+/// How long a node has to answer a health probe.
+const HEALTH_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The conditions the selected nodes must meet. Every condition left unset
+/// matches all the nodes. See [`RunningNetwork::nodes_matching`].
+#[derive(Debug, Clone, Default)]
+pub struct NodeFilter {
+    running: Option<bool>,
+    validator: Option<bool>,
+    excluded_names: Vec<String>,
+}
+
+/// The nodes which answered the last health probe. See
+/// [`RunningNetwork::any_healthy_node`].
+#[derive(Debug, Default)]
+pub(crate) struct HealthCache {
+    /// When the nodes were probed.
+    probed_at: Option<Instant>,
+    /// The names of the nodes which answered. The cache is shared by the
+    /// clones of the network, whose nodes may differ, so it holds no index.
+    healthy: Vec<String>,
+    /// Rotates among the healthy nodes.
+    next: usize,
+}
+
+impl HealthCache {
+    /// Returns the next healthy node, if the probe is recent enough.
+    fn next(&mut self) -> Option<String> {
+        let fresh = self
+            .probed_at
+            .is_some_and(|probed_at| probed_at.elapsed() < HEALTH_CACHE_DURATION);
+        if !fresh || self.healthy.is_empty() {
+            return None;
+        }
+        let name = self.healthy[self.next % self.healthy.len()].clone();
+        self.next = self.next.wrapping_add(1);
+
+        Some(name)
+    }
+
+    /// Forgets the probe, so that the next call probes the nodes again.
+    fn invalidate(&mut self) {
+        self.probed_at = None;
+    }
+}
+
+impl NodeFilter {
+    /// Creates a filter matching all the nodes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only matches the running nodes, or the stopped ones.
+    pub fn running(self, running: bool) -> Self {
+        Self {
+            running: Some(running),
+            ..self
+        }
+    }
+
+    /// Only matches the validators, or the non-validators.
+    pub fn validator(self, validator: bool) -> Self {
+        Self {
+            validator: Some(validator),
+            ..self
+        }
+    }
+
+    /// Does not match the nodes with these names.
+    pub fn exclude_names(mut self, names: &[&str]) -> Self {
+        self.excluded_names
+            .extend(names.iter().map(|&name| name.to_owned()));
+        self
+    }
+
+    async fn matches(&self, node: &RunningNode) -> bool {
+        if self
+            .validator
+            .is_some_and(|validator| node.validator() != validator)
+            || self.excluded_names.iter().any(|name| name == node.name())
+        {
+            return false;
+        }
+
+        match self.running {
+            Some(running) => node.running().await == running,
+            None => true,
+        }
+    }
+}
+
+impl RunningNetwork {
+    /// Returns the nodes matching the `filter`, in the order they were added.
+    pub async fn nodes_matching(&self, filter: &NodeFilter) -> Vec<&RunningNode> {
+        let mut result = Vec::new();
+
+        for node in &self.nodes {
+            if filter.matches(node).await {
+                result.push(node);
+            }
+        }
+
+        result
+    }
+
+    /// Returns a running node answering its REST status, for the operations
+    /// needing any node of the network. The nodes are probed at most every
+    /// few seconds, and the calls rotate among the ones which answered, to
+    /// spread the load. A node stopped since the probe is not returned.
+    pub async fn any_healthy_node(&self) -> Result<&RunningNode> {
+        loop {
+            let cached = self.health_cache.lock().expect("poisoned lock").next();
+            let Some(name) = cached else {
+                break;
+            };
+            // The node may be missing from this clone of the network:
+            if let Some(node) = self.nodes.iter().find(|node| node.name == name) {
+                if node.running().await {
+                    return Ok(node);
+                }
+            }
+            // Another node may have stopped as well:
+            self.health_cache
+                .lock()
+                .expect("poisoned lock")
+                .invalidate();
+        }
+
+        let client = Client::builder()
+            .timeout(HEALTH_PROBE_TIMEOUT)
+            .build()
+            .expect("the HTTP client to be built");
+        let healthy = join_all(self.nodes.iter().map(|node| probe(&client, node)))
+            .await
+            .into_iter()
+            .zip(&self.nodes)
+            .filter(|&(healthy, _)| healthy)
+            .map(|(_, node)| node.name.clone())
+            .collect();
+        let mut cache = self.health_cache.lock().expect("poisoned lock");
+
+        *cache = HealthCache {
+            probed_at: Some(Instant::now()),
+            healthy,
+            next: cache.next,
+        };
+        let name = cache.next().ok_or(Error::NoHealthyNode)?;
+
+        Ok(self
+            .nodes
+            .iter()
+            .find(|node| node.name == name)
+            .expect("the probed node to be in the network"))
+    }
+
+    /// Picks a node matching the `filter` at random. The pick is reproducible
+    /// if the network is seeded, see [`NetworkBuilder::seed`](super::NetworkBuilder::seed).
+    pub async fn random_node(&self, filter: &NodeFilter) -> Result<&RunningNode> {
+        let [node] = self
+            .random_nodes(1, filter)
+            .await?
+            .try_into()
+            .expect("one node to be picked");
+
+        Ok(node)
+    }
+
+    /// Picks `amount` different nodes matching the `filter` at random. Fails if
+    /// there are not enough of them. See [`RunningNetwork::random_node`].
+    pub async fn random_nodes(
+        &self,
+        amount: usize,
+        filter: &NodeFilter,
+    ) -> Result<Vec<&RunningNode>> {
+        let matching = self.nodes_matching(filter).await;
+
+        if matching.len() < amount {
+            return Err(Error::NotEnoughMatchingNodes {
+                requested: amount,
+                matching: matching.len(),
+            });
+        }
+        let mut rng = self.selection_rng.lock().expect("poisoned lock");
+
+        Ok(matching
+            .choose_multiple(&mut *rng, amount)
+            .copied()
+            .collect())
+    }
+}
+
+/// Returns `true` if the node runs and answers its REST status.
+async fn probe(client: &Client, node: &RunningNode) -> bool {
+    if !node.running().await {
+        return false;
+    }
+
+    client
+        .get(format!("http://{}/status", node.rest_address()))
+        .send()
+        .await
+        .is_ok_and(|response| response.status().is_success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{artifacts::Artifacts, network::Node, testing};
+    use std::collections::BTreeSet;
+
+    /// Returns a network of 4 validators and 2 other nodes, which are not
+    /// started, seeded with `seed`.
+    async fn seeded_network(artifacts: &Artifacts, seed: u64) -> RunningNetwork {
+        testing::network(artifacts, 4)
+            .with(2 * Node::keep_up(artifacts.clone()))
+            .seed(seed)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap()
+    }
+
+    /// Returns the names of the nodes of 5 successive picks of 2 validators.
+    async fn picks(network: &RunningNetwork) -> Vec<Vec<String>> {
+        let mut picks = Vec::new();
+        for _ in 0..5 {
+            let picked = network
+                .random_nodes(2, &NodeFilter::new().validator(true))
+                .await
+                .unwrap();
+            assert!(picked.iter().all(|node| node.validator()));
+            picks.push(picked.iter().map(|node| node.name().to_owned()).collect());
+        }
+
+        picks
+    }
+
+    #[tokio::test]
+    async fn an_empty_selection_is_an_error() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = seeded_network(&artifacts, 1).await;
+
+        let non_validators = NodeFilter::new().validator(false);
+        assert_eq!(network.nodes_matching(&non_validators).await.len(), 2);
+        assert!(matches!(
+            network.random_nodes(3, &non_validators).await,
+            Err(Error::NotEnoughMatchingNodes {
+                requested: 3,
+                matching: 2
+            })
+        ));
+
+        let none = non_validators.exclude_names(&["Node_B/0", "Node_B/1"]);
+        assert!(network.nodes_matching(&none).await.is_empty());
+        assert!(matches!(
+            network.random_node(&none).await,
+            Err(Error::NotEnoughMatchingNodes {
+                requested: 1,
+                matching: 0
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn the_picks_are_reproducible_with_a_seed() {
+        let (_dir, artifacts) = testing::artifacts();
+
+        let first = picks(&seeded_network(&artifacts, 7).await).await;
+        assert_eq!(picks(&seeded_network(&artifacts, 7).await).await, first);
+        // Not a constant pick:
+        assert!(first.iter().collect::<BTreeSet<_>>().len() > 1);
+
+        let network = seeded_network(&artifacts, 7).await;
+        let node = network
+            .random_node(&NodeFilter::new())
+            .await
+            .unwrap()
+            .name();
+        let again = seeded_network(&artifacts, 7).await;
+        assert_eq!(
+            again.random_node(&NodeFilter::new()).await.unwrap().name(),
+            node
+        );
+    }
+
+    #[tokio::test]
+    async fn any_healthy_node_fails_over_to_the_running_nodes() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 3)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        network.wait_until_ready().await.unwrap();
+        // Probed once with all the nodes healthy, then the first one stops:
+        network.any_healthy_node().await.unwrap();
+        network.nodes[0].clone().stop().await.unwrap();
+
+        let mut picked = BTreeSet::new();
+        for _ in 0..4 {
+            picked.insert(network.any_healthy_node().await.unwrap().name().to_owned());
+        }
+        assert_eq!(
+            picked,
+            BTreeSet::from(["Node_A/1".into(), "Node_A/2".into()])
+        );
+
+        network.stop_all().await.unwrap();
+        assert!(matches!(
+            network.any_healthy_node().await,
+            Err(Error::NoHealthyNode)
+        ));
+    }
+}
@@ -0,0 +1,282 @@
+//! Runs the callbacks registered to clean up when the network shuts down.
+
+use crate::{
+    error::{Error, Result},
+    network::RunningNetwork,
+};
+use futures::{future::BoxFuture, FutureExt as _};
+use std::{fmt, future::Future, panic::AssertUnwindSafe, time::Duration};
+
+/// How long a shutdown callback may run before it is abandoned.
+const SHUTDOWN_HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+type OnShutdown = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+/// The callbacks registered with [`RunningNetwork::on_shutdown`], shared by the
+/// network clones.
+#[derive(Default)]
+pub(crate) struct ShutdownHooks(Vec<OnShutdown>);
+
+impl fmt::Debug for ShutdownHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ShutdownHooks").field(&self.0.len()).finish()
+    }
+}
+
+impl RunningNetwork {
+    /// Runs the `callback` when the network shuts down, once the nodes are
+    /// stopped, whatever the reason of the shutdown: CTRL+C, the shutdown
+    /// button of the web app, [`RunningNetwork::shutdown`], or the end of
+    /// [`RunningNetwork::run_for`]. The wait functions return once all the
+    /// callbacks are over.
+    ///
+    /// The callbacks are run one after the other, in the order they were
+    /// registered, and each one is abandoned after 10 seconds. If the network
+    /// is dropped without being shut down, the callbacks are spawned on the
+    /// current runtime, if any, without being waited for.
+    ///
+    /// This fails if the network is already shutting down.
+    pub fn on_shutdown<F, Fut>(&self, callback: F) -> Result<()>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut hooks = self.shutdown_hooks.lock().expect("poisoned lock");
+        if self.shutdown_state.has_begun_shutdown() {
+            return Err(Error::ShuttingDown);
+        }
+        hooks.0.push(Box::new(move || callback().boxed()));
+
+        Ok(())
+    }
+
+    /// Takes the shutdown callbacks, so that they are run only once.
+    fn take_shutdown_hooks(&self) -> Vec<OnShutdown> {
+        std::mem::take(&mut self.shutdown_hooks.lock().expect("poisoned lock").0)
+    }
+
+    /// Runs the shutdown callbacks in order, each one with a timeout.
+    pub(super) async fn run_shutdown_hooks(&self) {
+        for (index, callback) in self.take_shutdown_hooks().into_iter().enumerate() {
+            let future = AssertUnwindSafe(callback()).catch_unwind();
+            match tokio::time::timeout(SHUTDOWN_HOOK_TIMEOUT, future).await {
+                Ok(Ok(())) => (),
+                Ok(Err(_panic)) => log::error!("The shutdown callback #{index} panicked"),
+                Err(_elapsed) => log::warn!(
+                    "The shutdown callback #{index} did not finish within {}, abandoning it",
+                    humantime::format_duration(SHUTDOWN_HOOK_TIMEOUT)
+                ),
+            }
+        }
+    }
+
+    /// Spawns the shutdown callbacks without waiting for them, when the
+    /// network is killed on drop.
+    pub(super) fn spawn_shutdown_hooks(&self) {
+        let hooks = self.take_shutdown_hooks();
+        if hooks.is_empty() {
+            return;
+        }
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                log::warn!(
+                    "The network was dropped without being shut down, \
+                    its shutdown callbacks are run without being waited for"
+                );
+                runtime.spawn(async move {
+                    for callback in hooks {
+                        let future = AssertUnwindSafe(callback()).catch_unwind();
+                        let _ = tokio::time::timeout(SHUTDOWN_HOOK_TIMEOUT, future).await;
+                    }
+                });
+            }
+            Err(_) => log::warn!(
+                "The network was dropped outside of a runtime, \
+                its {} shutdown callbacks are not run",
+                hooks.len()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::sync::{Arc, Mutex};
+
+    /// Set in the test binary run by [`a_ctrl_c_runs_the_hooks_in_order`].
+    #[cfg(unix)]
+    const CTRL_C_VAR: &str = "CNUT_TEST_CTRL_C";
+
+    /// Starts a network of one fake node.
+    async fn network() -> (tempfile::TempDir, RunningNetwork) {
+        let (dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 1)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        network.wait_until_ready().await.unwrap();
+
+        (dir, network)
+    }
+
+    /// Registers three hooks which record their run in `events`, the second
+    /// one trying to register a hook while the network is shutting down.
+    fn register_hooks(network: &RunningNetwork, events: &Arc<Mutex<Vec<String>>>) {
+        let record = |events: &Arc<Mutex<Vec<String>>>, event: String| {
+            events.lock().unwrap().push(event);
+        };
+
+        let first = events.clone();
+        network
+            .on_shutdown(move || async move { record(&first, "first".to_owned()) })
+            .unwrap();
+        let second = events.clone();
+        let clone = network.clone();
+        network
+            .on_shutdown(move || async move {
+                let late = clone.on_shutdown(|| async {});
+                record(&second, format!("second, late hook: {late:?}"));
+            })
+            .unwrap();
+        let third = events.clone();
+        network
+            .on_shutdown(move || async move { record(&third, "third".to_owned()) })
+            .unwrap();
+    }
+
+    /// The events recorded by the hooks of [`register_hooks`].
+    fn expected_events() -> Vec<String> {
+        vec![
+            "first".to_owned(),
+            "second, late hook: Err(ShuttingDown)".to_owned(),
+            "third".to_owned(),
+        ]
+    }
+
+    #[tokio::test]
+    async fn the_web_shutdown_runs_the_hooks_in_order() {
+        let (_dir, network) = network().await;
+        let events = Arc::default();
+        register_hooks(&network, &events);
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        network.serve_web_app_on(address).await.unwrap();
+
+        let wait = tokio::spawn({
+            let network = network.clone();
+            async move { network.wait().await }
+        });
+        let response = reqwest::Client::new()
+            .post(format!("http://{address}/shutdown"))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        wait.await.unwrap().unwrap();
+
+        assert_eq!(*events.lock().unwrap(), expected_events());
+        assert!(matches!(
+            network.on_shutdown(|| async {}),
+            Err(Error::ShuttingDown)
+        ));
+    }
+
+    /// Run in its own process by [`a_ctrl_c_runs_the_hooks_in_order`], since
+    /// the signal reaches the whole process: prints the events of the hooks
+    /// once the network is stopped.
+    #[cfg(unix)]
+    #[test]
+    #[ignore = "run by a_ctrl_c_runs_the_hooks_in_order"]
+    fn ctrl_c_process() {
+        if std::env::var_os(CTRL_C_VAR).is_none() {
+            return;
+        }
+
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            // Keeps the signal from killing the process before it is waited
+            // for:
+            let _interrupt =
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt()).unwrap();
+            let (_dir, network) = network().await;
+            let events = Arc::default();
+            register_hooks(&network, &events);
+            println!("ready");
+
+            network.wait().await.unwrap();
+            let late = network.on_shutdown(|| async {});
+            for event in events.lock().unwrap().iter() {
+                println!("event: {event}");
+            }
+            println!("event: after the shutdown: {late:?}");
+        });
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn a_ctrl_c_runs_the_hooks_in_order() {
+        use tokio::io::{AsyncBufReadExt as _, BufReader};
+
+        let mut child = tokio::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "network::shutdown_hooks::tests::ctrl_c_process",
+                "--ignored",
+                "--nocapture",
+                "--quiet",
+            ])
+            .env(CTRL_C_VAR, "1")
+            .stdout(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let pid = rustix::process::Pid::from_raw(child.id().unwrap() as i32).unwrap();
+        let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+        while lines
+            .next_line()
+            .await
+            .unwrap()
+            .expect("the process to get ready")
+            != "ready"
+        {}
+
+        // The signal is sent until the process waits for it:
+        let events = tokio::time::timeout(Duration::from_secs(30), async {
+            let mut events = Vec::new();
+            loop {
+                rustix::process::kill_process(pid, rustix::process::Signal::Int).unwrap();
+                let next = tokio::time::timeout(Duration::from_millis(200), lines.next_line());
+                match next.await {
+                    Ok(Ok(Some(line))) => {
+                        if let Some(event) = line.strip_prefix("event: ") {
+                            events.push(event.to_owned());
+                        }
+                        break;
+                    }
+                    Ok(result) => panic!("the process stopped early: {result:?}"),
+                    Err(_elapsed) => (),
+                }
+            }
+            while let Some(line) = lines.next_line().await.unwrap() {
+                if let Some(event) = line.strip_prefix("event: ") {
+                    events.push(event.to_owned());
+                }
+            }
+            events
+        })
+        .await
+        .expect("the process to stop");
+        assert!(child.wait().await.unwrap().success());
+
+        let mut expected = expected_events();
+        expected.push("after the shutdown: Err(ShuttingDown)".to_owned());
+        assert_eq!(events, expected);
+    }
+}
@@ -0,0 +1,139 @@
+//! Sends signals to the node processes, for example to pause a node and
+//! simulate an unresponsive peer.
+
+use crate::{
+    error::{Error, Result},
+    network::RunningNode,
+    util::{self, spawn_process, ProcessOutputExt as _},
+};
+use std::{fmt, sync::atomic::Ordering, time::Duration};
+
+/// A signal sent to a node process. See [`RunningNode::send_signal`].
+///
+/// The signals are only supported on Unix: on the other platforms, sending one
+/// to a node running as a process fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// Pauses the process, which cannot ignore it (`SIGSTOP`).
+    Stop,
+    /// Resumes a paused process (`SIGCONT`).
+    Cont,
+    /// Asks the process to terminate (`SIGTERM`).
+    Term,
+    /// Kills the process right away (`SIGKILL`).
+    Kill,
+    /// Interrupts the process, like CTRL+C (`SIGINT`).
+    Int,
+    /// Hangs up the process (`SIGHUP`).
+    Hup,
+    /// A signal for the application (`SIGUSR1`).
+    Usr1,
+    /// A signal for the application (`SIGUSR2`).
+    Usr2,
+}
+
+impl Signal {
+    /// The name of the signal, without the `SIG` prefix.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Stop => "STOP",
+            Self::Cont => "CONT",
+            Self::Term => "TERM",
+            Self::Kill => "KILL",
+            Self::Int => "INT",
+            Self::Hup => "HUP",
+            Self::Usr1 => "USR1",
+            Self::Usr2 => "USR2",
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SIG{}", self.name())
+    }
+}
+
+impl RunningNode {
+    /// Sends the `signal` to the node process, or to its container if it runs
+    /// in Docker. Fails if the node is not running.
+    ///
+    /// The node status is not changed: a node paused with [`Signal::Stop`] is
+    /// still running until it is resumed with [`Signal::Cont`], and a node
+    /// terminated by a signal is reported as crashed.
+    pub async fn send_signal(&self, signal: Signal) -> Result<()> {
+        let not_sent = |reason: String| Error::SignalNotSent {
+            name: self.name.clone(),
+            signal,
+            reason,
+        };
+        let pid = match self.pid() {
+            Some(pid) if self.running().await => pid,
+            _ => return Err(not_sent("the node is not running".to_owned())),
+        };
+
+        if let Some(container_id) = self.container_id() {
+            let output = spawn_process(
+                ".",
+                ["docker", "kill", "--signal", signal.name(), &container_id],
+            )
+            .await?;
+            if !output.status.success() {
+                return Err(not_sent(output.stderr_string().trim().to_owned()));
+            }
+        } else {
+            util::send_signal(pid, signal).map_err(|e| not_sent(e.to_string()))?;
+        }
+        log::info!("Sent {signal} to the node {}", self.name);
+
+        Ok(())
+    }
+
+    /// Pauses the node for `duration` with [`Signal::Stop`], then resumes it
+    /// with [`Signal::Cont`], to simulate an unresponsive node. Its storage
+    /// and connections are kept. Fails if the node is not running or already
+    /// frozen, and on the platforms other than Unix for the nodes running as
+    /// processes.
+    ///
+    /// The call returns early if the node is stopped meanwhile, for example
+    /// when the network shuts down: the node is resumed first, so that it can
+    /// stop.
+    pub async fn freeze(&self, duration: Duration) -> Result<()> {
+        if self.frozen.swap(true, Ordering::SeqCst) {
+            return Err(Error::SignalNotSent {
+                name: self.name.clone(),
+                signal: Signal::Stop,
+                reason: "the node is already frozen".to_owned(),
+            });
+        }
+        if let Err(e) = self.send_signal(Signal::Stop).await {
+            self.frozen.store(false, Ordering::SeqCst);
+            return Err(e);
+        }
+        log::info!(
+            "Node {} frozen for {}",
+            self.name,
+            humantime::format_duration(duration)
+        );
+
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => (),
+            _ = self.wait_for_exit() => (),
+        }
+
+        if self.frozen.swap(false, Ordering::SeqCst) {
+            self.send_signal(Signal::Cont).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Resumes the node if it is frozen, before it is stopped.
+    pub(crate) async fn thaw(&self) {
+        if self.frozen.swap(false, Ordering::SeqCst) {
+            if let Err(e) = self.send_signal(Signal::Cont).await {
+                log::warn!("Could not resume the frozen node {}: {e}", self.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Spreads the stakes of the validators, to get interesting vote patterns.
+
+use crate::error::{Error, Result};
+
+/// How the stakes are spread across the validators, in the order they are
+/// added. See [`NetworkBuilder::stake_distribution`](super::NetworkBuilder::stake_distribution).
+///
+/// The amounts are relative to a base stake: the default one, or the one
+/// drawn with [`NetworkBuilder::randomize_balances`](super::NetworkBuilder::randomize_balances).
+#[derive(Debug, Clone, PartialEq)]
+pub enum StakeDistribution {
+    /// Every validator gets the base stake.
+    Equal,
+    /// The stakes decrease linearly: out of `n` validators, the `i`-th one
+    /// (starting from 0) gets `base * (n - i) / n`.
+    Linear,
+    /// The stakes follow a Zipf law: the `i`-th validator (starting from 0)
+    /// gets `base / (i + 1)^s`.
+    Zipf {
+        /// The exponent, the higher the more the first validators weigh.
+        s: f64,
+    },
+    /// The stakes themselves, one per validator.
+    Custom(Vec<u128>),
+}
+
+impl StakeDistribution {
+    /// Fails if the distribution cannot be applied to this amount of
+    /// validators.
+    pub(crate) fn validate(&self, validators: usize) -> Result<()> {
+        match self {
+            Self::Custom(stakes) if stakes.len() != validators => {
+                Err(Error::StakeDistributionMismatch {
+                    stakes: stakes.len(),
+                    validators,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the stakes of the `validators`, every one being at least 1.
+    pub(crate) fn stakes(&self, base: u128, validators: usize) -> Vec<u128> {
+        let n = validators as u128;
+
+        (0..validators)
+            .map(|i| match self {
+                Self::Equal => base,
+                Self::Linear => base / n * (n - i as u128),
+                Self::Zipf { s } => (base as f64 / ((i + 1) as f64).powf(*s)) as u128,
+                Self::Custom(stakes) => stakes[i],
+            })
+            .map(|stake| stake.max(1))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        artifacts::Artifacts,
+        network::{NetworkBuilder, Node},
+        testing,
+    };
+
+    /// Returns a network of 4 validators and a non-validator between them,
+    /// with the stakes spread by the `distribution` from a seeded base.
+    fn network(artifacts: &Artifacts, distribution: StakeDistribution) -> NetworkBuilder {
+        testing::network(artifacts, 2)
+            .with(Node::keep_up(artifacts.clone()))
+            .with(2 * Node::validator(artifacts.clone()))
+            .seed(5)
+            .randomize_balances(1_000_000_000, 2_000_000_000)
+            .stake_distribution(distribution)
+            .dry_run(true)
+    }
+
+    /// Returns the bonded amounts written in `accounts.toml` by the prepared
+    /// `network`, in the order of the validators.
+    async fn bonded_amounts(network: NetworkBuilder) -> Vec<u128> {
+        let network = network.prepare().await.unwrap();
+        let accounts = std::fs::read_to_string(network.temp_directory().join("accounts.toml"))
+            .unwrap()
+            .parse::<toml::Table>()
+            .unwrap();
+
+        accounts["accounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|account| account.get("validator"))
+            .map(|validator| {
+                validator["bonded_amount"]
+                    .as_str()
+                    .unwrap()
+                    .parse()
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    /// Panics if the stakes written for the `distribution` are not the ones
+    /// it spreads from the first one, or are not reproducible.
+    async fn assert_spread(distribution: StakeDistribution) {
+        let (_dir, artifacts) = testing::artifacts();
+
+        let stakes = bonded_amounts(network(&artifacts, distribution.clone())).await;
+
+        assert_eq!(stakes.len(), 4);
+        assert_eq!(stakes, distribution.stakes(stakes[0], 4));
+        assert_eq!(
+            bonded_amounts(network(&artifacts, distribution)).await,
+            stakes
+        );
+    }
+
+    #[tokio::test]
+    async fn equal_stakes() {
+        assert_spread(StakeDistribution::Equal).await;
+    }
+
+    #[tokio::test]
+    async fn linear_stakes() {
+        assert_spread(StakeDistribution::Linear).await;
+    }
+
+    #[tokio::test]
+    async fn zipf_stakes() {
+        assert_spread(StakeDistribution::Zipf { s: 1.5 }).await;
+    }
+
+    #[tokio::test]
+    async fn custom_stakes() {
+        let (_dir, artifacts) = testing::artifacts();
+        let custom = StakeDistribution::Custom(vec![10, 20, 30, 40]);
+
+        assert_eq!(
+            bonded_amounts(network(&artifacts, custom)).await,
+            [10, 20, 30, 40]
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_stakes_are_one_per_validator() {
+        let (_dir, artifacts) = testing::artifacts();
+        let custom = StakeDistribution::Custom(vec![10, 20, 30]);
+
+        let result = network(&artifacts, custom).prepare().await;
+
+        assert!(matches!(
+            result,
+            Err(Error::StakeDistributionMismatch {
+                stakes: 3,
+                validators: 4,
+            })
+        ));
+    }
+}
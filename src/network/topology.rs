@@ -0,0 +1,294 @@
+//! Renders the network as a diagram: the nodes, the addresses they are told
+//! about in their config, and the peers they are connected to.
+
+use crate::{
+    error::Result,
+    network::{RunningNetwork, RunningNode},
+    util::read_toml,
+};
+use futures::future::join_all;
+use std::{collections::BTreeSet, fmt::Write as _, net::SocketAddr};
+
+/// The language of the diagram. See [`RunningNetwork::export_topology`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopologyFormat {
+    /// The Graphviz language, rendered with `dot -Tsvg`.
+    Dot,
+    /// The Mermaid flowchart language.
+    Mermaid,
+}
+
+/// A node of the diagram.
+struct Vertex {
+    name: String,
+    validator: bool,
+    running: bool,
+}
+
+/// The whole diagram, the edges holding the indices of the vertices.
+struct Topology {
+    vertices: Vec<Vertex>,
+    /// From the node config to the node it knows about.
+    known: BTreeSet<(usize, usize)>,
+    /// The connections reported by the running nodes, the lower index first.
+    peers: BTreeSet<(usize, usize)>,
+}
+
+impl RunningNetwork {
+    /// Renders the network as a diagram in the given `format`. The nodes are
+    /// labelled with their name, role and status, the stopped ones being
+    /// greyed out.
+    ///
+    /// The dashed edges go from a node to the known addresses written in its
+    /// config, and the plain ones link the nodes connected to each other, as
+    /// reported by the running nodes.
+    pub async fn export_topology(&self, format: TopologyFormat) -> Result<String> {
+        let topology = self.topology().await?;
+
+        Ok(match format {
+            TopologyFormat::Dot => topology.to_dot(),
+            TopologyFormat::Mermaid => topology.to_mermaid(),
+        })
+    }
+
+    async fn topology(&self) -> Result<Topology> {
+        let mut vertices = Vec::new();
+        let mut known = BTreeSet::new();
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            vertices.push(Vertex {
+                name: node.name().to_owned(),
+                validator: node.validator(),
+                running: node.running().await,
+            });
+            for address in known_addresses(node).await? {
+                if let Some(other) = self.node_index(address) {
+                    if other != index {
+                        known.insert((index, other));
+                    }
+                }
+            }
+        }
+
+        let peers = join_all(self.nodes.iter().map(peer_addresses))
+            .await
+            .into_iter()
+            .enumerate()
+            .flat_map(|(index, addresses)| {
+                addresses
+                    .into_iter()
+                    .filter_map(|address| self.node_index(address))
+                    .filter(move |&other| other != index)
+                    .map(move |other| (index.min(other), index.max(other)))
+            })
+            .collect();
+
+        Ok(Topology {
+            vertices,
+            known,
+            peers,
+        })
+    }
+
+    /// Returns the index of the node binding to the port of `address`.
+    fn node_index(&self, address: SocketAddr) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.bind_port() == address.port())
+    }
+}
+
+/// Reads the known addresses from the config written for the node.
+pub(super) async fn known_addresses(node: &RunningNode) -> Result<Vec<SocketAddr>> {
+    let config = read_toml(&node.config_path(), "the config").await?;
+    let addresses = config
+        .get("network")
+        .and_then(|network| network.get("known_addresses"))
+        .and_then(toml::Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    Ok(addresses
+        .iter()
+        .filter_map(toml::Value::as_str)
+        .filter_map(|address| address.parse().ok())
+        .collect())
+}
+
+/// Asks the node for the addresses of its peers. Nothing is returned if the
+/// node is not running or not reachable.
+async fn peer_addresses(node: &RunningNode) -> Vec<SocketAddr> {
+    if !node.running().await {
+        return Vec::new();
+    }
+    let status = match node.rpc().get_status().await {
+        Ok(status) => status,
+        Err(e) => {
+            log::debug!("Could not get the status of {}: {e}", node.name());
+            return Vec::new();
+        }
+    };
+
+    status["peers"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|peer| peer["address"].as_str()?.parse().ok())
+        .collect()
+}
+
+impl Vertex {
+    fn role(&self) -> &'static str {
+        if self.validator {
+            "validator"
+        } else {
+            "keep-up"
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        if self.running {
+            "running"
+        } else {
+            "stopped"
+        }
+    }
+}
+
+impl Topology {
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph network {\n    node [shape=box];\n");
+
+        for (index, vertex) in self.vertices.iter().enumerate() {
+            let style = if vertex.running {
+                ""
+            } else {
+                ", style=filled, fillcolor=lightgrey, fontcolor=grey40"
+            };
+            let _ = writeln!(
+                dot,
+                "    n{index} [label=\"{}\\n{}\\n{}\"{style}];",
+                vertex.name.escape_default(),
+                vertex.role(),
+                vertex.status(),
+            );
+        }
+        for (from, to) in &self.known {
+            let _ = writeln!(dot, "    n{from} -> n{to} [style=dashed];");
+        }
+        for (from, to) in &self.peers {
+            let _ = writeln!(dot, "    n{from} -> n{to} [dir=none, penwidth=2];");
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("graph LR\n    classDef stopped fill:#ddd,color:#888;\n");
+
+        for (index, vertex) in self.vertices.iter().enumerate() {
+            let _ = writeln!(
+                mermaid,
+                "    n{index}[\"{}<br/>{}<br/>{}\"]",
+                vertex.name.replace('"', "#quot;"),
+                vertex.role(),
+                vertex.status(),
+            );
+            if !vertex.running {
+                let _ = writeln!(mermaid, "    class n{index} stopped");
+            }
+        }
+        for (from, to) in &self.known {
+            let _ = writeln!(mermaid, "    n{from} -.-> n{to}");
+        }
+        for (from, to) in &self.peers {
+            let _ = writeln!(mermaid, "    n{from} === n{to}");
+        }
+
+        mermaid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network::Node, testing};
+
+    /// Returns a validator and a stopped keep-up node, the validator knowing
+    /// about the keep-up node, and both connected to a third running node.
+    fn topology() -> Topology {
+        let vertex = |name: &str, validator, running| Vertex {
+            name: name.to_owned(),
+            validator,
+            running,
+        };
+
+        Topology {
+            vertices: vec![
+                vertex("Alice", true, true),
+                vertex("Bob", false, false),
+                vertex("Carol", true, true),
+            ],
+            known: BTreeSet::from([(0, 1)]),
+            peers: BTreeSet::from([(0, 2), (1, 2)]),
+        }
+    }
+
+    #[test]
+    fn the_dot_diagram_has_the_nodes_and_edges() {
+        assert_eq!(
+            topology().to_dot(),
+            "digraph network {\n    \
+                node [shape=box];\n    \
+                n0 [label=\"Alice\\nvalidator\\nrunning\"];\n    \
+                n1 [label=\"Bob\\nkeep-up\\nstopped\", \
+                    style=filled, fillcolor=lightgrey, fontcolor=grey40];\n    \
+                n2 [label=\"Carol\\nvalidator\\nrunning\"];\n    \
+                n0 -> n1 [style=dashed];\n    \
+                n0 -> n2 [dir=none, penwidth=2];\n    \
+                n1 -> n2 [dir=none, penwidth=2];\n\
+            }\n"
+        );
+    }
+
+    #[test]
+    fn the_mermaid_diagram_has_the_nodes_and_edges() {
+        assert_eq!(
+            topology().to_mermaid(),
+            "graph LR\n    \
+                classDef stopped fill:#ddd,color:#888;\n    \
+                n0[\"Alice<br/>validator<br/>running\"]\n    \
+                n1[\"Bob<br/>keep-up<br/>stopped\"]\n    \
+                class n1 stopped\n    \
+                n2[\"Carol<br/>validator<br/>running\"]\n    \
+                n0 -.-> n1\n    \
+                n0 === n2\n    \
+                n1 === n2\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn each_node_knows_the_others_from_its_config() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 3)
+            .with(Node::keep_up(artifacts.clone()))
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+
+        let topology = network.topology().await.unwrap();
+        let every_other: BTreeSet<_> = (0..4)
+            .flat_map(|from| (0..4).map(move |to| (from, to)))
+            .filter(|(from, to)| from != to)
+            .collect();
+        assert_eq!(topology.known, every_other);
+        assert!(!topology.vertices[3].validator);
+        assert!(topology.peers.is_empty());
+    }
+}
@@ -0,0 +1,261 @@
+//! A minimal client for the JSON-RPC API of the nodes, and the commands
+//! running the official client against them.
+
+use crate::{
+    artifacts::Artifacts,
+    error::{Error, Result},
+    network::RunningNode,
+    util::deploy::Deploy,
+};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::SystemTime;
+use tokio::process::Command;
+
+/// The client subcommands taking a `--chain-name` argument.
+const CHAIN_NAME_SUBCOMMANDS: [&str; 6] = [
+    "make-deploy",
+    "put-deploy",
+    "make-transfer",
+    "transfer",
+    "make-transaction",
+    "put-transaction",
+];
+
+/// A JSON-RPC client for one node. See [`RunningNode::rpc`].
+#[derive(Debug, Clone)]
+pub struct NodeRpc {
+    client: Client,
+    url: String,
+}
+
+/// A CL value stored in the global state, as returned by the node. See
+/// [`ClValue::from_stored_value`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ClValue {
+    /// The CL type, such as `"U512"` or `{ "Option": "U64" }`.
+    pub cl_type: Value,
+    /// The serialized value, hex-encoded.
+    pub bytes: String,
+    /// The value parsed by the node, if it could.
+    #[serde(default)]
+    pub parsed: Value,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    result: Option<Value>,
+    error: Option<ResponseError>,
+}
+
+#[derive(Deserialize)]
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+impl NodeRpc {
+    /// Calls the JSON-RPC `method` with the given `params`, and returns its
+    /// result.
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request_error = |source| Error::RpcRequest {
+            method: method.to_owned(),
+            source,
+        };
+        let response: Response = self
+            .client
+            .post(&self.url)
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await
+            .map_err(request_error)?
+            .json()
+            .await
+            .map_err(request_error)?;
+
+        match response {
+            Response {
+                error: Some(ResponseError { code, message }),
+                ..
+            } => Err(Error::RpcResponse {
+                method: method.to_owned(),
+                code,
+                message,
+            }),
+            Response { result, .. } => Ok(result.unwrap_or_default()),
+        }
+    }
+
+    /// Returns the status of the node.
+    pub async fn get_status(&self) -> Result<Value> {
+        self.call("info_get_status", json!([])).await
+    }
+
+    /// Returns the auction state at the last block: the bids, and the
+    /// validators of the current and next eras.
+    pub async fn get_auction_info(&self) -> Result<Value> {
+        self.call("state_get_auction_info", json!([])).await
+    }
+
+    /// Returns the deploy with the given hash, with its execution results.
+    pub async fn get_deploy(&self, hash: &str) -> Result<Value> {
+        self.call("info_get_deploy", json!({ "deploy_hash": hash }))
+            .await
+    }
+
+    /// Returns the hash of the block at the given `height`, or `None` if the
+    /// node has no such block.
+    pub async fn block_hash(&self, height: u64) -> Result<Option<String>> {
+        let result = self
+            .call(
+                "chain_get_block",
+                json!({ "block_identifier": { "Height": height } }),
+            )
+            .await?;
+
+        Ok(block(&result)
+            .and_then(|block| block["hash"].as_str())
+            .map(ToOwned::to_owned))
+    }
+
+    /// Returns the timestamp of the block with the given `hash`, or `None` if
+    /// the node has no such block.
+    pub async fn block_timestamp(&self, hash: &str) -> Result<Option<SystemTime>> {
+        let result = self
+            .call(
+                "chain_get_block",
+                json!({ "block_identifier": { "Hash": hash } }),
+            )
+            .await?;
+
+        Ok(block(&result)
+            .and_then(|block| block["header"]["timestamp"].as_str())
+            .and_then(|timestamp| humantime::parse_rfc3339_weak(timestamp).ok()))
+    }
+
+    /// Returns the state root hash of the last block added by the node, or
+    /// `None` if it has no block yet.
+    pub async fn state_root_hash(&self) -> Result<Option<String>> {
+        let result = self.call("chain_get_state_root_hash", json!([])).await?;
+
+        Ok(result["state_root_hash"].as_str().map(ToOwned::to_owned))
+    }
+
+    /// Returns the value stored under the `key` in the global state at the
+    /// given state root hash, following the named keys of the `path`. The
+    /// `key` is formatted like `hash-…`, `uref-…` or `account-hash-…`, and the
+    /// value is returned as the node sends it, such as
+    /// `{ "CLValue": { … } }`. See [`ClValue::from_stored_value`].
+    pub async fn query_global_state(
+        &self,
+        state_root_hash: &str,
+        key: &str,
+        path: &[&str],
+    ) -> Result<Value> {
+        let mut result = self
+            .call(
+                "query_global_state",
+                json!({
+                    "state_identifier": { "StateRootHash": state_root_hash },
+                    "key": key,
+                    "path": path,
+                }),
+            )
+            .await
+            .map_err(|e| e.context(format!("querying the key {key} at the path {path:?}")))?;
+
+        Ok(result["stored_value"].take())
+    }
+
+    /// Returns the height of the last block added by the node, or `None` if
+    /// it has no block yet.
+    pub async fn latest_block_height(&self) -> Result<Option<u64>> {
+        let result = self.call("chain_get_block", json!([])).await?;
+
+        Ok(block(&result).and_then(|block| block["header"]["height"].as_u64()))
+    }
+
+    /// Sends the deploy to the node.
+    pub(crate) async fn put_deploy(&self, deploy: &Deploy) -> Result<()> {
+        self.call("account_put_deploy", json!({ "deploy": deploy.to_json() }))
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Returns the block from the result of `chain_get_block`, whatever the node
+/// version.
+fn block(result: &Value) -> Option<&Value> {
+    let block = result
+        .get("block")
+        .or_else(|| result.get("block_with_signatures")?.get("block"))?;
+
+    match block.get("Version2").or_else(|| block.get("Version1")) {
+        Some(versioned) => Some(versioned),
+        None if block.is_null() => None,
+        None => Some(block),
+    }
+}
+
+impl ClValue {
+    /// Returns the CL value of a value returned by
+    /// [`NodeRpc::query_global_state`], or `None` if it is another kind of
+    /// stored value, such as an account or a contract.
+    pub fn from_stored_value(stored_value: &Value) -> Option<Self> {
+        serde_json::from_value(stored_value.get("CLValue")?.clone()).ok()
+    }
+}
+
+impl RunningNode {
+    /// Returns the value stored under the `key` in the global state, following
+    /// the named keys of the `path`, at the last block added by this node.
+    /// See [`NodeRpc::query_global_state`].
+    pub async fn query_state(&self, key: &str, path: &[&str]) -> Result<Value> {
+        let rpc = self.rpc();
+        let state_root_hash = rpc
+            .state_root_hash()
+            .await?
+            .ok_or_else(|| Error::NoStateRootHash(self.name().to_owned()))?;
+
+        rpc.query_global_state(&state_root_hash, key, path).await
+    }
+
+    /// Returns a JSON-RPC client for this node. The requests fail if the node
+    /// does not answer within the
+    /// [RPC timeout](crate::network::NetworkBuilder::rpc_timeout).
+    pub fn rpc(&self) -> NodeRpc {
+        NodeRpc {
+            client: self.rpc_client.clone(),
+            url: format!("http://{}/rpc", self.rpc_address()),
+        }
+    }
+
+    /// Returns a command running the `casper-client` `subcommand` against this
+    /// node: its address is set, and the chain name as well for the
+    /// subcommands creating deploys or transactions. More arguments can be
+    /// added before spawning it.
+    ///
+    /// Fails if the client is not in the artifacts, see
+    /// [`ArtifactsBuilder::with_client`](crate::artifacts::ArtifactsBuilder::with_client).
+    pub fn client_command(&self, subcommand: &str) -> Result<Command> {
+        let client_path = Artifacts::from_path(self.artifact_dir()).client_path()?;
+        let mut command = Command::new(client_path);
+
+        command
+            .arg(subcommand)
+            .arg("--node-address")
+            .arg(format!("http://{}", self.rpc_address()));
+        if CHAIN_NAME_SUBCOMMANDS.contains(&subcommand) {
+            command.arg("--chain-name").arg(&*self.chain_name);
+        }
+
+        Ok(command)
+    }
+}
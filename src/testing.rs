@@ -0,0 +1,273 @@
+//! The fixtures of the unit tests: fake artifacts, whose `casper-node` runs
+//! the test binary itself as a fake node. The fake node answers the REST
+//! status and a few RPC methods on the ports of its config, and its behavior
+//! is set with environment variables, see [`Node::env`]:
+//!
+//! - `CNUT_FAKE_NODE_READY_AFTER`: the milliseconds before it reports a block;
+//! - `CNUT_FAKE_NODE_DEPLOY_AFTER`: the milliseconds before it returns the
+//!   deploys asked for, executed in a block;
+//! - `CNUT_FAKE_NODE_HANG`: set to never answer the RPC requests;
+//! - `CNUT_FAKE_NODE_IGNORE_SIGTERM`: set to keep running on `SIGTERM`, so
+//!   that it must be killed.
+
+use crate::{
+    artifacts::Artifacts,
+    network::{NetworkBuilder, Node, PortScheme},
+    OutputMode,
+};
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::{
+    future::IntoFuture as _,
+    net::{Ipv4Addr, SocketAddr},
+    path::Path,
+    sync::atomic::{AtomicU16, Ordering},
+    time::Duration,
+};
+use tempfile::TempDir;
+use tokio::time::Instant;
+
+/// Set by the fake `casper-node` to the config path.
+const CONFIG_VAR: &str = "CNUT_FAKE_NODE_CONFIG";
+/// The fake node exits after this time, in case the test process did not
+/// kill it.
+const MAX_LIFETIME: Duration = Duration::from_secs(300);
+/// The ports of each network, enough for 20 nodes in each category.
+const PORTS_PER_NETWORK: u16 = 100;
+/// The hash of the block every deploy is executed in.
+pub(crate) const BLOCK_HASH: &str = "0101010101010101";
+/// The timestamp of the block every deploy is executed in.
+pub(crate) const BLOCK_TIMESTAMP: &str = "2024-01-01T00:00:00Z";
+
+const CHAINSPEC: &str = r#"[protocol]
+version = "1.0.0"
+activation_point = "2024-01-01T00:00:00Z"
+
+[network]
+name = "casper-example"
+
+[core]
+validator_slots = 100
+"#;
+
+const CONFIG: &str = r#"[network]
+bind_address = "0.0.0.0:34553"
+known_addresses = []
+
+[rpc_server]
+address = "0.0.0.0:7777"
+
+[rest_server]
+address = "0.0.0.0:8888"
+
+[event_stream_server]
+address = "0.0.0.0:9999"
+
+[storage]
+path = "./node-storage"
+"#;
+
+/// Writes the fake artifacts. They are removed when the returned directory
+/// is dropped, so it must outlive the network.
+pub(crate) fn artifacts() -> (TempDir, Artifacts) {
+    let dir = TempDir::new().expect("a temporary directory");
+    let exe = std::env::current_exe().expect("the path of the test binary");
+    let node_path = dir.path().join("casper-node");
+
+    std::fs::write(dir.path().join("chainspec.toml"), CHAINSPEC).expect("the chainspec written");
+    std::fs::write(dir.path().join("config.toml"), CONFIG).expect("the config written");
+    std::fs::write(
+        &node_path,
+        format!(
+            "#!/bin/sh\n{CONFIG_VAR}=\"$2\" exec '{}' --exact testing::fake_node --ignored --nocapture --quiet\n",
+            exe.display()
+        ),
+    )
+    .expect("the node written");
+    make_executable(&node_path);
+    let artifacts = Artifacts::from_path(dir.path());
+
+    (dir, artifacts)
+}
+
+/// Returns a network of `amount` fake validators, on ports no other test
+/// uses, without any output.
+pub(crate) fn network(artifacts: &Artifacts, amount: usize) -> NetworkBuilder {
+    builder().with(amount * Node::validator(artifacts.clone()))
+}
+
+/// Returns a network without nodes, set up like the one of [`network`].
+pub(crate) fn builder() -> NetworkBuilder {
+    NetworkBuilder::new()
+        .port_scheme(port_scheme())
+        .bind_host(Ipv4Addr::LOCALHOST.into())
+        .output_mode(OutputMode::Silent)
+        .min_free_space(0)
+        .low_disk_space_threshold(0)
+        .activation_delay(Duration::ZERO)
+        .startup_allowance(Duration::ZERO)
+        .shutdown_timeout(Duration::from_secs(5))
+}
+
+/// Returns ports which no other network of the test process uses.
+pub(crate) fn port_scheme() -> PortScheme {
+    static NEXT_BASE: AtomicU16 = AtomicU16::new(20_000);
+    let base = NEXT_BASE.fetch_add(PORTS_PER_NETWORK, Ordering::Relaxed);
+    let step = PORTS_PER_NETWORK / 5;
+
+    PortScheme {
+        bind: base,
+        speculative_execution: base + step,
+        rpc: base + 2 * step,
+        rest: base + 3 * step,
+        event_stream: base + 4 * step,
+        stride: 1,
+    }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+        .expect("the node to be made executable");
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) {}
+
+/// What the fake node was told to do.
+#[derive(Debug)]
+struct FakeNode {
+    started: Instant,
+    ready_after: Option<Duration>,
+    deploy_after: Option<Duration>,
+    hang: bool,
+    ignore_sigterm: bool,
+}
+
+impl FakeNode {
+    fn from_env() -> Self {
+        let millis = |var| {
+            std::env::var(var)
+                .ok()
+                .map(|millis| Duration::from_millis(millis.parse().expect("milliseconds")))
+        };
+
+        Self {
+            started: Instant::now(),
+            ready_after: millis("CNUT_FAKE_NODE_READY_AFTER"),
+            deploy_after: millis("CNUT_FAKE_NODE_DEPLOY_AFTER"),
+            hang: std::env::var_os("CNUT_FAKE_NODE_HANG").is_some(),
+            ignore_sigterm: std::env::var_os("CNUT_FAKE_NODE_IGNORE_SIGTERM").is_some(),
+        }
+    }
+
+    /// Returns the height of the last block, one per second since the node
+    /// is ready.
+    fn height(&self) -> Option<u64> {
+        let elapsed = self.started.elapsed();
+        let since_ready = elapsed.checked_sub(self.ready_after.unwrap_or_default())?;
+
+        Some(since_ready.as_secs())
+    }
+}
+
+/// The fake node, run by the `casper-node` of [`artifacts`]. Does nothing when
+/// the test binary is not run by it.
+#[test]
+#[ignore = "run by the fake casper-node"]
+fn fake_node() {
+    let Ok(config_path) = std::env::var(CONFIG_VAR) else {
+        return;
+    };
+
+    tokio::runtime::Runtime::new()
+        .expect("a runtime")
+        .block_on(serve(Path::new(&config_path)));
+}
+
+async fn serve(config_path: &Path) {
+    let config: toml::Table = std::fs::read_to_string(config_path)
+        .expect("the config to be readable")
+        .parse()
+        .expect("the config to be valid");
+    let address = |section: &str| -> SocketAddr {
+        config[section]["address"]
+            .as_str()
+            .and_then(|address| address.parse().ok())
+            .expect("the server address")
+    };
+    let node = std::sync::Arc::new(FakeNode::from_env());
+    // Handling the signal replaces its default action, which terminates:
+    #[cfg(unix)]
+    let _sigterm = node.ignore_sigterm.then(|| {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("the SIGTERM handler to be installed")
+    });
+    let rest = Router::new()
+        .route("/status", get(status))
+        .with_state(node.clone());
+    let rpc = Router::new().route("/rpc", post(rpc)).with_state(node);
+    let rest_listener = tokio::net::TcpListener::bind(address("rest_server"))
+        .await
+        .expect("the REST port to be free");
+    let rpc_listener = tokio::net::TcpListener::bind(address("rpc_server"))
+        .await
+        .expect("the RPC port to be free");
+
+    tokio::select! {
+        _ = axum::serve(rest_listener, rest).into_future() => (),
+        _ = axum::serve(rpc_listener, rpc).into_future() => (),
+        _ = tokio::time::sleep(MAX_LIFETIME) => (),
+    }
+}
+
+async fn status(State(node): State<std::sync::Arc<FakeNode>>) -> Json<Value> {
+    let block = node
+        .height()
+        .map(|height| json!({ "era_id": height, "height": height }));
+
+    Json(json!({ "last_added_block_info": block }))
+}
+
+async fn rpc(
+    State(node): State<std::sync::Arc<FakeNode>>,
+    Json(request): Json<Value>,
+) -> Json<Value> {
+    if node.hang {
+        std::future::pending::<()>().await;
+    }
+    let deploy_seen = node
+        .deploy_after
+        .is_some_and(|after| node.started.elapsed() >= after);
+    let result = match request["method"].as_str().unwrap_or_default() {
+        "info_get_deploy" if deploy_seen => Ok(json!({
+            "deploy": { "hash": request["params"]["deploy_hash"] },
+            "execution_results": [{ "block_hash": BLOCK_HASH }],
+        })),
+        "info_get_deploy" => Err("No such deploy"),
+        "chain_get_block" => Ok(json!({
+            "block": {
+                "hash": BLOCK_HASH,
+                "header": { "timestamp": BLOCK_TIMESTAMP, "height": node.height() },
+            },
+        })),
+        "account_put_deploy" => Ok(json!({ "deploy_hash": request["params"]["deploy"]["hash"] })),
+        "info_get_status" => Ok(json!({})),
+        _ => Err("Method not found"),
+    };
+
+    Json(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+        Err(message) => json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "error": { "code": -32000, "message": message },
+        }),
+    })
+}
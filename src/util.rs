@@ -1,22 +1,34 @@
 mod spinner;
-pub use spinner::Spinner;
+pub use spinner::{OutputMode, Spinner};
 mod dir;
-pub use dir::cache;
+pub use dir::{cache, RunDirectory};
+mod lock;
+pub use lock::FileLock;
 pub mod crypto;
+pub mod deploy;
 mod process;
 pub use process::NodeProcess;
 
-use crate::error::{ProcessError, Result};
+use crate::error::{Error, ProcessError, Result};
 use std::{
     ffi::OsStr,
     path::Path,
     process::Output,
     sync::{atomic::AtomicU8, Arc},
+    time::Duration,
 };
 use tokio::process::Command;
 
+/// Helpers on the output of a finished process.
 pub trait ProcessOutputExt {
+    /// Returns the `error` built from the output if the process failed.
     fn status_ok_or(self, error: fn(Output) -> ProcessError) -> Result<()>;
+
+    /// Returns the captured standard output, decoded as lossy UTF-8.
+    fn stdout_string(&self) -> String;
+
+    /// Returns the captured standard error, decoded as lossy UTF-8.
+    fn stderr_string(&self) -> String;
 }
 
 impl ProcessOutputExt for Output {
@@ -27,40 +39,100 @@ impl ProcessOutputExt for Output {
             Err(error(self).into())
         }
     }
+
+    fn stdout_string(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).into_owned()
+    }
+
+    fn stderr_string(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).into_owned()
+    }
 }
 
-pub(crate) fn create_update_table(toml: &mut toml::Value, args: &[&str], value: toml::Value) {
-    match args {
-        [] => *toml = value,
-        [arg, rest @ ..] => {
-            if let toml::Value::Table(table) = toml {
-                let toml = match table.entry(arg.to_owned()) {
-                    toml::map::Entry::Vacant(vacant) => vacant.insert(toml::Table::new().into()),
-                    toml::map::Entry::Occupied(occupied) => occupied.into_mut(),
-                };
+/// Sets the `value` at the path `args` in `toml`, creating the missing
+/// tables. In an array, the key is the index of an existing element, so that
+/// `["accounts", "0", "balance"]` reaches the balance of the first account.
+///
+/// Fails if a key goes through a value which is neither a table nor an array,
+/// or is not the index of an array element.
+pub(crate) fn create_update_table(
+    toml: &mut toml::Value,
+    args: &[&str],
+    value: toml::Value,
+) -> Result<()> {
+    let invalid = |key: &str, reason: String| Error::InvalidTomlPath {
+        path: args.join("."),
+        key: key.to_owned(),
+        reason,
+    };
+    let mut toml = toml;
 
-                create_update_table(toml, rest, value)
-            } else {
-                panic!("{arg:?} is not the key for a table")
+    for arg in args {
+        toml = match toml {
+            toml::Value::Table(table) => match table.entry(arg.to_owned()) {
+                toml::map::Entry::Vacant(vacant) => vacant.insert(toml::Table::new().into()),
+                toml::map::Entry::Occupied(occupied) => occupied.into_mut(),
+            },
+            toml::Value::Array(array) => {
+                let len = array.len();
+                let index = arg
+                    .parse::<usize>()
+                    .map_err(|_| invalid(arg, "it is not the index of an array".to_owned()))?;
+
+                array.get_mut(index).ok_or_else(|| {
+                    invalid(
+                        arg,
+                        format!("it is out of bounds of an array of length {len}"),
+                    )
+                })?
+            }
+            other => {
+                return Err(invalid(
+                    arg,
+                    format!("it is the key of a {}, not of a table", other.type_str()),
+                ))
             }
+        };
+    }
+    *toml = value;
+
+    Ok(())
+}
+
+/// Panics if the key `path` is the same as one already set by the
+/// [`toml_map!`] being built, or if one of them contains the other, since the
+/// later value would overwrite the earlier one.
+#[cfg(debug_assertions)]
+pub(crate) fn assert_new_key_path(paths: &mut Vec<Vec<String>>, path: &[&str]) {
+    for previous in paths.iter() {
+        let common = previous.len().min(path.len());
+
+        if previous[..common] == path[..common] {
+            panic!(
+                "toml_map! sets {:?} after {:?}, the later would overwrite the earlier",
+                path.join("."),
+                previous.join("."),
+            );
         }
     }
+    paths.push(path.iter().map(|&key| key.to_owned()).collect());
 }
 
-/// Creates a TOML map.
+/// Creates a TOML map. There is no reason to set twice the same key, so in
+/// debug builds, this panics if a key path is repeated, or contains another
+/// one.
 macro_rules! toml_map {
     () => { toml::Table::new() };
-    ( $( $first_keys:expr ),+ => $first_value:expr $( , $( $keys:expr ),+ => $value:expr )* $(,)? ) => {{
+    ( $( $( $keys:expr ),+ => $value:expr ),+ $(,)? ) => {{
         let mut map = toml::Table::new().into();
-        // There is no reason to add twice the same key. In such a case, this
-        // is treated as a bug.
-        crate::util::create_update_table(&mut map, [$(
-            $first_keys,
-        )+].as_ref(), toml::Value::from($first_value));
+        #[cfg(debug_assertions)]
+        let mut paths = Vec::new();
         $(
-            crate::util::create_update_table(&mut map, [$(
-                $keys,
-            )+].as_ref(), toml::Value::from($value));
+            let path = [$( $keys, )+];
+            #[cfg(debug_assertions)]
+            crate::util::assert_new_key_path(&mut paths, &path);
+            crate::util::create_update_table(&mut map, path.as_ref(), toml::Value::from($value))
+                .expect("toml_map! only creates tables");
         )+
 
         match map {
@@ -71,6 +143,56 @@ macro_rules! toml_map {
 }
 pub(crate) use toml_map;
 
+/// How many times a command touching the network is run before giving up.
+const NETWORK_ATTEMPTS: u32 = 3;
+/// The delay before the first retry, doubled at each attempt.
+const NETWORK_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Same as [`spawn_process`], but the command is run again, after a growing
+/// delay, if it failed because of the network.
+pub async fn spawn_network_process<S: AsRef<OsStr>>(
+    path: impl AsRef<Path>,
+    params: impl AsRef<[S]>,
+) -> Result<Output> {
+    let (path, params) = (path.as_ref(), params.as_ref());
+    let mut delay = NETWORK_RETRY_DELAY;
+
+    for attempt in 1.. {
+        let output = spawn_process(path, params).await?;
+
+        if output.status.success() || attempt == NETWORK_ATTEMPTS || !is_network_failure(&output) {
+            return Ok(output);
+        }
+        log::warn!(
+            "Network failure running {:?} (attempt {attempt}/{NETWORK_ATTEMPTS}), retrying in {}",
+            params[0].as_ref(),
+            humantime::format_duration(delay),
+        );
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+
+    unreachable!("the last attempt returns")
+}
+
+/// Returns `true` if the error output of the failed command looks like a
+/// network error rather than a genuine failure.
+fn is_network_failure(output: &Output) -> bool {
+    const PATTERNS: &[&str] = &[
+        "network",
+        "timed out",
+        "timeout",
+        "connection",
+        "could not resolve",
+        "failed to download",
+        "spurious",
+        "temporary failure",
+    ];
+    let stderr = output.stderr_string().to_lowercase();
+
+    PATTERNS.iter().any(|pattern| stderr.contains(pattern))
+}
+
 pub async fn spawn_process<S: AsRef<OsStr>>(
     path: impl AsRef<Path>,
     params: impl AsRef<[S]>,
@@ -95,6 +217,7 @@ pub async fn spawn_process<S: AsRef<OsStr>>(
         })
 }
 
+#[derive(Debug, Clone)]
 pub struct LettersGen(Vec<u8>);
 
 impl LettersGen {
@@ -155,9 +278,152 @@ pub fn update_toml(mut content: toml::Value, updates: toml::Table) -> toml::Valu
     content
 }
 
+/// Returns the space available to the user on the filesystem hosting `path`,
+/// in bytes, or `None` if it cannot be known on this platform.
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> std::io::Result<Option<u64>> {
+    let stat = rustix::fs::statvfs(path)?;
+
+    Ok(Some(stat.f_bavail.saturating_mul(stat.f_frsize)))
+}
+
+/// Returns the space available to the user on the filesystem hosting `path`,
+/// in bytes, or `None` if it cannot be known on this platform.
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> std::io::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// Kills the process with the given ID right away.
+#[cfg(unix)]
+pub fn kill_process(pid: u32) {
+    use rustix::process::{kill_process, Pid, Signal};
+
+    let Some(pid) = i32::try_from(pid).ok().and_then(Pid::from_raw) else {
+        return;
+    };
+    if let Err(e) = kill_process(pid, Signal::Kill) {
+        log::warn!("Could not kill the process {pid:?}: {e}");
+    }
+}
+
+/// Kills the process with the given ID right away. Not supported on this
+/// platform.
+#[cfg(not(unix))]
+pub fn kill_process(pid: u32) {
+    log::warn!("Cannot kill the process {pid} on this platform");
+}
+
+/// Sends the `signal` to the process with the given ID.
+#[cfg(unix)]
+pub fn send_signal(pid: u32, signal: crate::network::Signal) -> std::io::Result<()> {
+    use crate::network::Signal as S;
+    use rustix::process::{kill_process, Pid, Signal};
+
+    let pid = i32::try_from(pid)
+        .ok()
+        .and_then(Pid::from_raw)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid PID"))?;
+    let signal = match signal {
+        S::Stop => Signal::Stop,
+        S::Cont => Signal::Cont,
+        S::Term => Signal::Term,
+        S::Kill => Signal::Kill,
+        S::Int => Signal::Int,
+        S::Hup => Signal::Hup,
+        S::Usr1 => Signal::Usr1,
+        S::Usr2 => Signal::Usr2,
+    };
+
+    Ok(kill_process(pid, signal)?)
+}
+
+/// Sends the `signal` to the process with the given ID. Not supported on this
+/// platform.
+#[cfg(not(unix))]
+pub fn send_signal(_pid: u32, _signal: crate::network::Signal) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the signals are only supported on Unix",
+    ))
+}
+
+/// Copies the directory `src` and its content into `dest`, which is created
+/// if needed. The symbolic links are followed.
+pub async fn copy_dir(src: &Path, dest: &Path) -> Result<()> {
+    use tokio::fs;
+
+    let mut to_copy = vec![(src.to_owned(), dest.to_owned())];
+
+    while let Some((src, dest)) = to_copy.pop() {
+        fs::create_dir_all(&dest)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("creating the directory {dest:?}"),
+                io_err,
+            })?;
+        let mut entries = fs::read_dir(&src)
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("reading the directory {src:?}"),
+                io_err,
+            })?;
+
+        while let Some(entry) =
+            entries
+                .next_entry()
+                .await
+                .map_err(|io_err| Error::FileOperation {
+                    description: format!("reading the directory entry in {src:?}"),
+                    io_err,
+                })?
+        {
+            let (entry_src, entry_dest) = (entry.path(), dest.join(entry.file_name()));
+
+            if fs::metadata(&entry_src).await.is_ok_and(|m| m.is_dir()) {
+                to_copy.push((entry_src, entry_dest));
+            } else {
+                fs::copy(&entry_src, &entry_dest)
+                    .await
+                    .map_err(|io_err| Error::FileOperation {
+                        description: format!("copying the file {entry_src:?} to {entry_dest:?}"),
+                        io_err,
+                    })?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads and parses the TOML file at `path`. The `description` names the file
+/// in the error, for example `the chainspec`.
+pub async fn read_toml(path: &Path, description: &str) -> Result<toml::Value> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|io_err| Error::FileOperation {
+            description: format!("reading {description} {path:?}"),
+            io_err,
+        })?;
+
+    content.parse().map_err(|source| Error::TomlParse {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Merges the `updates` into the `base` table, see [`update_toml`].
+pub fn merge_tables(base: toml::Table, updates: toml::Table) -> toml::Table {
+    match update_toml(base.into(), updates) {
+        toml::Value::Table(table) => table,
+        _ => unreachable!("merging into a table gives a table"),
+    }
+}
+
 /// 0: network is running.
 /// 1: network must shut down.
 /// 2: network has already shut down.
+/// 3: network is shutting down.
 #[derive(Debug, Default, Clone)]
 pub struct ShutdownState(Arc<AtomicU8>);
 
@@ -179,9 +445,150 @@ impl ShutdownState {
         }
     }
 
+    /// Marks the network as shutting down, and returns `false` if it is
+    /// already shutting down or shut down.
+    pub fn begin_shutdown(&self) -> bool {
+        let order = std::sync::atomic::Ordering::Relaxed;
+        [0, 1]
+            .into_iter()
+            .any(|state| self.0.compare_exchange(state, 3, order, order).is_ok())
+    }
+
+    /// Returns `true` if the network is shutting down or shut down.
+    pub fn has_begun_shutdown(&self) -> bool {
+        self.0.load(std::sync::atomic::Ordering::Relaxed) != 0
+    }
+
+    /// Marks the network as shut down, once the shutdown is over.
+    pub fn finish_shutdown(&self) {
+        self.0.store(2, std::sync::atomic::Ordering::Relaxed);
+    }
+
     pub fn set_shut_down(&self) {
         // If it's on 0:running, store that it must shut down:
         let order = std::sync::atomic::Ordering::Relaxed;
         let _ = self.0.compare_exchange(0, 1, order, order);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    #[test]
+    fn distinct_key_paths_are_accepted() {
+        let map = toml_map! {
+            "core", "validator_slots" => 5,
+            "core", "era_duration" => "10 seconds",
+            "protocol", "version" => "1.0.0",
+        };
+
+        assert_eq!(map["core"]["validator_slots"].as_integer(), Some(5));
+        assert_eq!(map["protocol"]["version"].as_str(), Some("1.0.0"));
+    }
+
+    #[test]
+    #[should_panic(expected = "sets \"core.validator_slots\" after \"core.validator_slots\"")]
+    #[cfg(debug_assertions)]
+    fn a_repeated_key_path_panics() {
+        let _ = toml_map! {
+            "core", "validator_slots" => 5,
+            "core", "validator_slots" => 6,
+        };
+    }
+
+    #[test]
+    #[should_panic(expected = "sets \"core.era_duration\" after \"core\"")]
+    #[cfg(debug_assertions)]
+    fn a_key_path_under_a_previous_one_panics() {
+        let _ = toml_map! {
+            "core" => toml::Table::new(),
+            "core", "era_duration" => "10 seconds",
+        };
+    }
+
+    #[test]
+    #[should_panic(expected = "sets \"core\" after \"core.era_duration\"")]
+    #[cfg(debug_assertions)]
+    fn a_prefix_of_a_previous_key_path_panics() {
+        let _ = toml_map! {
+            "core", "era_duration" => "10 seconds",
+            "core" => toml::Table::new(),
+        };
+    }
+
+    /// Returns accounts with the balances `1` and `2`.
+    fn accounts() -> toml::Value {
+        r#"
+            [[accounts]]
+            public_key = "01aa"
+            balance = "1"
+
+            [[accounts]]
+            public_key = "01bb"
+            balance = "2"
+        "#
+        .parse::<toml::Table>()
+        .unwrap()
+        .into()
+    }
+
+    #[test]
+    fn an_array_element_is_reached_by_its_index() {
+        let mut toml = accounts();
+
+        create_update_table(&mut toml, &["accounts", "1", "balance"], "3".into()).unwrap();
+        create_update_table(&mut toml, &["accounts", "0", "stake", "amount"], 4.into()).unwrap();
+
+        assert_eq!(toml["accounts"][0]["balance"].as_str(), Some("1"));
+        assert_eq!(toml["accounts"][0]["stake"]["amount"].as_integer(), Some(4));
+        assert_eq!(toml["accounts"][1]["balance"].as_str(), Some("3"));
+        assert_eq!(toml["accounts"][1]["public_key"].as_str(), Some("01bb"));
+    }
+
+    #[test]
+    fn an_index_out_of_bounds_is_an_error() {
+        let mut toml = accounts();
+
+        let result = create_update_table(&mut toml, &["accounts", "2", "balance"], "3".into());
+
+        assert!(matches!(
+            result,
+            Err(Error::InvalidTomlPath { path, key, reason })
+                if path == "accounts.2.balance"
+                    && key == "2"
+                    && reason == "it is out of bounds of an array of length 2"
+        ));
+        assert_eq!(toml, accounts());
+    }
+
+    #[test]
+    fn a_key_in_an_array_is_an_error() {
+        let mut toml = accounts();
+
+        let result = create_update_table(&mut toml, &["accounts", "first", "balance"], "3".into());
+
+        assert!(matches!(
+            result,
+            Err(Error::InvalidTomlPath { key, .. }) if key == "first"
+        ));
+    }
+
+    #[test]
+    fn a_key_in_a_value_is_an_error() {
+        let mut toml = accounts();
+
+        let result = create_update_table(
+            &mut toml,
+            &["accounts", "0", "balance", "amount"],
+            "3".into(),
+        );
+
+        assert!(matches!(
+            result,
+            Err(Error::InvalidTomlPath { key, reason, .. })
+                if key == "amount" && reason == "it is the key of a string, not of a table"
+        ));
+    }
+}
@@ -3,6 +3,7 @@ use derp::{Der, Tag};
 use ed25519_dalek::pkcs8::spki::der::pem;
 use hex_fmt::HexFmt;
 use rand::Rng;
+use serde::{Serialize, Serializer};
 use std::{fmt, path::Path, sync::Arc};
 use tokio::fs;
 
@@ -10,6 +11,8 @@ const ED25519_OBJECT_IDENTIFIER: [u8; 3] = [43, 101, 112];
 const SECP256K1_OBJECT_IDENTIFIER: [u8; 5] = [43, 129, 4, 0, 10];
 const EC_PUBLIC_KEY_OBJECT_IDENTIFIER: [u8; 7] = [42, 134, 72, 206, 61, 2, 1];
 
+/// The public key of a node, displayed in hexadecimal as in the
+/// `public_key_hex` file: the algorithm tag followed by the key bytes.
 #[derive(Debug, Clone)]
 pub enum PublicKey {
     /// Ed25519 public key.
@@ -18,6 +21,16 @@ pub enum PublicKey {
     Secp256k1(k256::ecdsa::VerifyingKey),
 }
 
+/// A signature made with the secret key of a node. See
+/// [`RunningNode::sign`](crate::network::RunningNode::sign).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signature {
+    /// Ed25519 signature.
+    Ed25519(ed25519_dalek::Signature),
+    /// secp256k1 signature.
+    Secp256k1(k256::ecdsa::Signature),
+}
+
 #[derive(Debug, Clone)]
 pub enum SecretKey {
     /// Ed25519 public key.
@@ -29,7 +42,16 @@ pub enum SecretKey {
 pub fn generate_pair(rng: &mut impl Rng) -> (PublicKey, SecretKey) {
     let bytes = rng.gen();
 
-    if rng.gen() {
+    generate(rng.gen(), bytes)
+}
+
+/// Generates a key pair of the same algorithm as the `public_key`.
+pub fn generate_pair_like(public_key: &PublicKey, rng: &mut impl Rng) -> (PublicKey, SecretKey) {
+    generate(matches!(public_key, PublicKey::Ed25519(_)), rng.gen())
+}
+
+fn generate(ed25519: bool, bytes: [u8; 32]) -> (PublicKey, SecretKey) {
+    if ed25519 {
         let secret_key = ed25519_dalek::SigningKey::from_bytes(&bytes);
         let public_key = secret_key.verifying_key();
 
@@ -50,6 +72,33 @@ pub fn generate_pair(rng: &mut impl Rng) -> (PublicKey, SecretKey) {
 }
 
 impl PublicKey {
+    /// Returns the key as the node serializes it: the algorithm tag followed by
+    /// the key bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            PublicKey::Ed25519(key) => [&[1], key.as_bytes().as_slice()].concat(),
+            PublicKey::Secp256k1(key) => [&[2], &*key.to_sec1_bytes()].concat(),
+        }
+    }
+
+    /// Returns `true` if the `signature` of the `message` was made with the
+    /// secret key matching this one. A signature of the other algorithm is
+    /// never valid.
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> bool {
+        use ed25519_dalek::Verifier as _;
+
+        match (self, signature) {
+            (Self::Ed25519(key), Signature::Ed25519(signature)) => {
+                key.verify(message, signature).is_ok()
+            }
+            (Self::Secp256k1(key), Signature::Secp256k1(signature)) => {
+                key.verify(message, signature).is_ok()
+            }
+            _ => false,
+        }
+    }
+
+    /// Writes the key in the PEM format, as in the `public_key.pem` file.
     pub async fn write_pem(&self, path: impl AsRef<Path>) -> Result<()> {
         let pem_string = self.pem()?;
         let path = path.as_ref();
@@ -64,6 +113,21 @@ impl PublicKey {
         Ok(())
     }
 
+    /// Writes the key in hexadecimal, as in the `public_key_hex` file read by
+    /// the Casper tools.
+    pub async fn write_hex(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        fs::write(&path, self.to_string())
+            .await
+            .map_err(|io_err| Error::FileOperation {
+                description: format!("cannot write the public key file {path:?}"),
+                io_err,
+            })?;
+
+        Ok(())
+    }
+
     fn pem(&self) -> Result<String> {
         let label = "PUBLIC KEY";
 
@@ -101,7 +165,28 @@ impl PublicKey {
     }
 }
 
+impl Signature {
+    /// Returns the signature as the node serializes it: the algorithm tag
+    /// followed by the signature bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Ed25519(signature) => [&[1], signature.to_bytes().as_slice()].concat(),
+            Self::Secp256k1(signature) => [&[2], signature.to_bytes().as_slice()].concat(),
+        }
+    }
+}
+
 impl SecretKey {
+    /// Signs the `message`.
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        use ed25519_dalek::Signer as _;
+
+        match self {
+            Self::Ed25519(secret_key) => Signature::Ed25519(secret_key.sign(message)),
+            Self::Secp256k1(secret_key) => Signature::Secp256k1(secret_key.sign(message)),
+        }
+    }
+
     pub async fn write_pem(&self, path: impl AsRef<Path>) -> Result<()> {
         let pem_string = self.pem()?;
         let path = path.as_ref();
@@ -171,3 +256,56 @@ impl fmt::Display for PublicKey {
         }
     }
 }
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", HexFmt(self.to_bytes()))
+    }
+}
+
+impl Serialize for Signature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8] = b"deploy hash";
+
+    /// Panics if a signature made with a key pair of the algorithm is not
+    /// verified by its public key only, or if it does not start with `tag`.
+    fn assert_round_trip(ed25519: bool, tag: u8) {
+        let (public_key, secret_key) = generate(ed25519, [7; 32]);
+        let (other_public_key, _) = generate(ed25519, [8; 32]);
+
+        let signature = secret_key.sign(MESSAGE);
+
+        assert!(public_key.verify(MESSAGE, &signature));
+        assert!(!public_key.verify(b"another message", &signature));
+        assert!(!other_public_key.verify(MESSAGE, &signature));
+        assert_eq!(signature.to_bytes()[0], tag);
+        assert_eq!(public_key.to_bytes()[0], tag);
+    }
+
+    #[test]
+    fn ed25519_signatures_are_verified() {
+        assert_round_trip(true, 1);
+    }
+
+    #[test]
+    fn secp256k1_signatures_are_verified() {
+        assert_round_trip(false, 2);
+    }
+
+    #[test]
+    fn a_signature_of_the_other_algorithm_is_invalid() {
+        let (ed25519_public_key, ed25519_secret_key) = generate(true, [7; 32]);
+        let (secp256k1_public_key, secp256k1_secret_key) = generate(false, [7; 32]);
+
+        assert!(!ed25519_public_key.verify(MESSAGE, &secp256k1_secret_key.sign(MESSAGE)));
+        assert!(!secp256k1_public_key.verify(MESSAGE, &ed25519_secret_key.sign(MESSAGE)));
+    }
+}
@@ -0,0 +1,274 @@
+//! Builds and signs deploys. The hashes are computed over the same binary
+//! serialization as the node one, so that the node accepts them.
+
+use crate::util::crypto::{PublicKey, SecretKey};
+use blake2::{digest::consts::U32, Blake2b, Digest as _};
+use hex_fmt::HexFmt;
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long a deploy is valid.
+const TTL: Duration = Duration::from_secs(30 * 60);
+const GAS_PRICE: u64 = 1;
+
+/// A signed deploy, ready to be sent to a node.
+#[derive(Debug, Clone)]
+pub struct Deploy {
+    hash: [u8; 32],
+    json: Value,
+}
+
+/// The few CL types needed by the deploys built here.
+#[derive(Debug, Clone, Copy)]
+enum ClType {
+    U512,
+    PublicKey,
+    OptionU64,
+}
+
+/// A named runtime argument.
+#[derive(Debug, Clone)]
+struct Arg {
+    name: &'static str,
+    cl_type: ClType,
+    bytes: Vec<u8>,
+    parsed: Value,
+}
+
+/// What the node calls an executable deploy item.
+#[derive(Debug, Clone)]
+enum Item {
+    ModuleBytes {
+        module_bytes: Vec<u8>,
+        args: Vec<Arg>,
+    },
+    Transfer {
+        args: Vec<Arg>,
+    },
+}
+
+impl Deploy {
+    /// Creates a native transfer of `amount` motes to the `target` account.
+    pub fn transfer(
+        (public_key, secret_key): (&PublicKey, &SecretKey),
+        chain_name: &str,
+        target: &PublicKey,
+        amount: u128,
+        payment: u128,
+        id: u64,
+    ) -> Self {
+        let session = Item::Transfer {
+            args: vec![
+                Arg::u512("amount", amount),
+                Arg::public_key("target", target),
+                Arg::option_u64("id", Some(id)),
+            ],
+        };
+
+        Self::new((public_key, secret_key), chain_name, payment, session)
+    }
+
+    /// Creates a deploy running the given WASM module without argument.
+    pub fn module_bytes(
+        (public_key, secret_key): (&PublicKey, &SecretKey),
+        chain_name: &str,
+        module_bytes: Vec<u8>,
+        payment: u128,
+    ) -> Self {
+        let session = Item::ModuleBytes {
+            module_bytes,
+            args: Vec::new(),
+        };
+
+        Self::new((public_key, secret_key), chain_name, payment, session)
+    }
+
+    /// Returns the deploy hash, hex-encoded.
+    pub fn hash(&self) -> String {
+        HexFmt(self.hash).to_string()
+    }
+
+    /// Returns the JSON representation expected by the `account_put_deploy`
+    /// RPC method.
+    pub fn to_json(&self) -> &Value {
+        &self.json
+    }
+
+    fn new(
+        (public_key, secret_key): (&PublicKey, &SecretKey),
+        chain_name: &str,
+        payment: u128,
+        session: Item,
+    ) -> Self {
+        let payment = Item::ModuleBytes {
+            module_bytes: Vec::new(),
+            args: vec![Arg::u512("amount", payment)],
+        };
+        let body_hash = blake2b([payment.to_bytes(), session.to_bytes()].concat());
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("the clock to be after the epoch")
+            .as_millis() as u64;
+
+        let header = {
+            let mut buf = public_key.to_bytes();
+            buf.extend(timestamp.to_le_bytes());
+            buf.extend((TTL.as_millis() as u64).to_le_bytes());
+            buf.extend(GAS_PRICE.to_le_bytes());
+            buf.extend(body_hash);
+            // No dependency:
+            buf.extend(0u32.to_le_bytes());
+            buf.extend(string_bytes(chain_name));
+            buf
+        };
+        let hash = blake2b(header);
+        let signature = secret_key.sign(&hash);
+
+        let json = json!({
+            "hash": HexFmt(hash).to_string(),
+            "header": {
+                "account": public_key.to_string(),
+                "timestamp": humantime::format_rfc3339_millis(
+                    UNIX_EPOCH + Duration::from_millis(timestamp)
+                ).to_string(),
+                "ttl": humantime::format_duration(TTL).to_string(),
+                "gas_price": GAS_PRICE,
+                "body_hash": HexFmt(body_hash).to_string(),
+                "dependencies": [],
+                "chain_name": chain_name,
+            },
+            "payment": payment.to_json(),
+            "session": session.to_json(),
+            "approvals": [{
+                "signer": public_key.to_string(),
+                "signature": signature.to_string(),
+            }],
+        });
+
+        Self { hash, json }
+    }
+}
+
+impl Item {
+    fn to_bytes(&self) -> Vec<u8> {
+        let (tag, module_bytes, args) = match self {
+            Self::ModuleBytes { module_bytes, args } => (0, Some(module_bytes), args),
+            Self::Transfer { args } => (5, None, args),
+        };
+        let mut buf = vec![tag];
+
+        if let Some(module_bytes) = module_bytes {
+            buf.extend(bytes(module_bytes));
+        }
+        buf.extend((args.len() as u32).to_le_bytes());
+        for arg in args {
+            buf.extend(string_bytes(arg.name));
+            buf.extend(bytes(&arg.bytes));
+            buf.extend(arg.cl_type.to_bytes());
+        }
+
+        buf
+    }
+
+    fn to_json(&self) -> Value {
+        let args_json = |args: &[Arg]| -> Value {
+            args.iter()
+                .map(|arg| {
+                    json!([arg.name, {
+                        "cl_type": arg.cl_type.to_json(),
+                        "bytes": HexFmt(&arg.bytes).to_string(),
+                        "parsed": arg.parsed,
+                    }])
+                })
+                .collect()
+        };
+
+        match self {
+            Self::ModuleBytes { module_bytes, args } => json!({
+                "ModuleBytes": {
+                    "module_bytes": HexFmt(module_bytes).to_string(),
+                    "args": args_json(args),
+                }
+            }),
+            Self::Transfer { args } => json!({
+                "Transfer": {
+                    "args": args_json(args),
+                }
+            }),
+        }
+    }
+}
+
+impl Arg {
+    fn u512(name: &'static str, value: u128) -> Self {
+        let le_bytes = value.to_le_bytes();
+        let len = le_bytes
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |i| i + 1);
+        let mut bytes = vec![len as u8];
+        bytes.extend(&le_bytes[..len]);
+
+        Self {
+            name,
+            cl_type: ClType::U512,
+            bytes,
+            parsed: value.to_string().into(),
+        }
+    }
+
+    fn public_key(name: &'static str, value: &PublicKey) -> Self {
+        Self {
+            name,
+            cl_type: ClType::PublicKey,
+            bytes: value.to_bytes(),
+            parsed: value.to_string().into(),
+        }
+    }
+
+    fn option_u64(name: &'static str, value: Option<u64>) -> Self {
+        let bytes = match value {
+            None => vec![0],
+            Some(value) => [&[1], value.to_le_bytes().as_slice()].concat(),
+        };
+
+        Self {
+            name,
+            cl_type: ClType::OptionU64,
+            bytes,
+            parsed: value.into(),
+        }
+    }
+}
+
+impl ClType {
+    fn to_bytes(self) -> Vec<u8> {
+        match self {
+            Self::U512 => vec![8],
+            Self::PublicKey => vec![22],
+            // The option tag followed by the U64 one:
+            Self::OptionU64 => vec![13, 5],
+        }
+    }
+
+    fn to_json(self) -> Value {
+        match self {
+            Self::U512 => json!("U512"),
+            Self::PublicKey => json!("PublicKey"),
+            Self::OptionU64 => json!({ "Option": "U64" }),
+        }
+    }
+}
+
+fn blake2b(data: impl AsRef<[u8]>) -> [u8; 32] {
+    Blake2b::<U32>::digest(data).into()
+}
+
+/// Serializes a byte slice, prefixed by its length.
+fn bytes(bytes: &[u8]) -> Vec<u8> {
+    [&(bytes.len() as u32).to_le_bytes(), bytes].concat()
+}
+
+fn string_bytes(s: &str) -> Vec<u8> {
+    bytes(s.as_bytes())
+}
@@ -2,7 +2,42 @@ use crate::{
     error::{Error, Result},
     PROJECT_NAME,
 };
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tempfile::TempDir;
+
+/// The directory a network runs in. It is removed when dropped, unless it is
+/// kept.
+#[derive(Debug)]
+pub struct RunDirectory {
+    path: PathBuf,
+    /// Removes the directory when dropped, `None` once kept.
+    temp_dir: Mutex<Option<TempDir>>,
+}
+
+impl RunDirectory {
+    /// Wraps the temporary directory, removed when dropped.
+    pub fn temporary(temp_dir: TempDir) -> Self {
+        Self {
+            path: temp_dir.path().to_owned(),
+            temp_dir: Mutex::new(Some(temp_dir)),
+        }
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Leaves the directory on the disk when dropped.
+    pub fn keep(&self) {
+        if let Some(temp_dir) = self.temp_dir.lock().expect("poisoned lock").take() {
+            let _ = temp_dir.into_path();
+        }
+    }
+}
 
 /// Returns the directory where the binaries cache is.
 pub fn cache() -> Result<PathBuf> {
@@ -0,0 +1,105 @@
+//! An advisory lock on a file, so that several processes do not write the same
+//! files together, such as two test binaries building the same artifacts.
+
+use crate::error::{Error, Result};
+use std::{
+    fs::{self, File},
+    io::Write as _,
+    path::PathBuf,
+};
+
+/// Held until dropped. The operating system releases the lock of a process
+/// which exits, so a crashed process never leaves a stale lock.
+#[derive(Debug)]
+pub struct FileLock {
+    /// The lock lives as long as the file is open.
+    _file: File,
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Locks the file at `path`, creating it if needed, and waits for the
+    /// other process holding it, if any. The ID of this process is written in
+    /// the file, to tell which one holds it.
+    pub async fn acquire(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file_error = |description: String| {
+            move |io_err| Error::FileOperation {
+                description,
+                io_err,
+            }
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(file_error(format!("creating the directory {parent:?}")))?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(file_error(format!("opening the lock file {path:?}")))?;
+
+        let mut file = match try_lock(&file) {
+            Ok(true) => file,
+            Ok(false) => {
+                log::info!("Waiting for another process to release {path:?}");
+                tokio::task::spawn_blocking(move || lock(&file).map(|()| file))
+                    .await
+                    .expect("the lock task not to panic")
+                    .map_err(file_error(format!("locking the file {path:?}")))?
+            }
+            Err(io_err) => return Err(file_error(format!("locking the file {path:?}"))(io_err)),
+        };
+        let written = file
+            .set_len(0)
+            .and_then(|()| write!(file, "{}", std::process::id()));
+        if let Err(e) = written {
+            log::debug!("Could not write the process ID in the lock file {path:?}: {e}");
+        }
+        log::debug!("Lock {path:?} acquired");
+
+        Ok(Self { _file: file, path })
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        log::debug!("Lock {:?} released", self.path);
+    }
+}
+
+/// Locks the file without waiting, and returns `false` if another process
+/// holds it.
+#[cfg(unix)]
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    use rustix::{fs::FlockOperation, io::Errno};
+
+    match rustix::fs::flock(file, FlockOperation::NonBlockingLockExclusive) {
+        Ok(()) => Ok(true),
+        Err(Errno::WOULDBLOCK) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Waits for the file to be locked.
+#[cfg(unix)]
+fn lock(file: &File) -> std::io::Result<()> {
+    Ok(rustix::fs::flock(
+        file,
+        rustix::fs::FlockOperation::LockExclusive,
+    )?)
+}
+
+/// The files cannot be locked on this platform, so the lock is always given.
+#[cfg(not(unix))]
+fn try_lock(_file: &File) -> std::io::Result<bool> {
+    Ok(true)
+}
+
+/// The files cannot be locked on this platform.
+#[cfg(not(unix))]
+fn lock(_file: &File) -> std::io::Result<()> {
+    Ok(())
+}
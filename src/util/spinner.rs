@@ -1,34 +1,202 @@
-use std::time::Duration;
+use std::{cell::Cell, time::Duration};
 
 use indicatif::ProgressBar;
 
+/// How cnut reports its progress to the user. The errors are returned in any
+/// case.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Animated spinners and messages printed on the terminal. The progress
+    /// is logged as well, for the logs captured without a terminal.
+    #[default]
+    Interactive,
+    /// Log lines only, for example when the terminal is used by another
+    /// program.
+    Plain,
+    /// Nothing is printed by cnut itself.
+    Silent,
+}
+
 pub struct Spinner {
     message: String,
-    bar: ProgressBar,
+    mode: OutputMode,
+    bar: Option<ProgressBar>,
+    finished: Cell<bool>,
+}
+
+impl OutputMode {
+    /// Shows a message to the user.
+    pub(crate) fn print(self, message: impl std::fmt::Display) {
+        match self {
+            Self::Interactive => println!("{message}"),
+            Self::Plain => log::info!("{message}"),
+            Self::Silent => (),
+        }
+    }
 }
 
 impl Spinner {
-    /// Creates a new spinner and displays it.
-    pub fn create(message: impl Into<String>) -> Self {
+    /// Creates a new spinner and displays it. The message is logged too,
+    /// unless the mode is silent.
+    pub fn create(message: impl Into<String>, mode: OutputMode) -> Self {
         let message = message.into();
-        let bar = ProgressBar::new_spinner().with_message(format!("{message}…"));
-
-        bar.enable_steady_tick(Duration::from_millis(300));
+        let bar = match mode {
+            OutputMode::Interactive => {
+                log::info!("{message}…");
+                let bar = ProgressBar::new_spinner().with_message(format!("{message}…"));
+                bar.enable_steady_tick(Duration::from_millis(300));
+                Some(bar)
+            }
+            OutputMode::Plain => {
+                log::info!("{message}…");
+                None
+            }
+            OutputMode::Silent => None,
+        };
 
-        Self { message, bar }
+        Self {
+            message,
+            mode,
+            bar,
+            finished: Cell::new(false),
+        }
     }
 
     /// Finishes the spinner with an “OK” message.
     pub fn success(&self) {
-        self.bar.finish_with_message(format!("{} OK", self.message));
+        self.finished.set(true);
+        match &self.bar {
+            Some(bar) => {
+                log::info!("{} OK", self.message);
+                bar.finish_with_message(format!("{} OK", self.message));
+            }
+            None => self.mode.print(format_args!("{} OK", self.message)),
+        }
     }
 }
 
 impl Drop for Spinner {
     fn drop(&mut self) {
-        if self.bar.is_finished() == false {
-            self.bar
-                .finish_with_message(format!("{} ERROR", self.message))
+        if self.finished.get() == false {
+            match &self.bar {
+                Some(bar) => {
+                    log::error!("{} ERROR", self.message);
+                    bar.finish_with_message(format!("{} ERROR", self.message));
+                }
+                None if self.mode == OutputMode::Plain => log::error!("{} ERROR", self.message),
+                None => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The output mode of the test binary run by [`output`].
+    const OUTPUT_MODE_VAR: &str = "CNUT_TEST_OUTPUT_MODE";
+
+    /// Prints the log records, so that they are read with the messages.
+    struct StdoutLogger;
+
+    impl log::Log for StdoutLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            println!("{} {}", record.level(), record.args());
         }
+
+        fn flush(&self) {}
+    }
+
+    /// Run in its own process by [`output`], since the messages are printed:
+    /// shows a message, a successful spinner and a failed one, or prepares a
+    /// network without a logger for `silent-prepare`.
+    #[test]
+    #[ignore = "run by the output mode tests"]
+    fn output_process() {
+        let mode = match std::env::var(OUTPUT_MODE_VAR).as_deref() {
+            Ok("plain") => OutputMode::Plain,
+            Ok("silent") => OutputMode::Silent,
+            Ok("silent-prepare") => return prepare_process(),
+            _ => return,
+        };
+        log::set_logger(&StdoutLogger).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        println!("begin");
+        mode.print("Message");
+        Spinner::create("Success", mode).success();
+        drop(Spinner::create("Failure", mode));
+        println!("end");
+    }
+
+    /// Prepares a silent network of fake nodes.
+    fn prepare_process() {
+        let (_dir, artifacts) = crate::testing::artifacts();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        println!("begin");
+        let network = runtime.block_on(
+            crate::testing::network(&artifacts, 3)
+                .output_mode(OutputMode::Silent)
+                .prepare(),
+        );
+        println!("end");
+        network.unwrap();
+    }
+
+    /// Returns the lines written by [`output_process`] with the `mode` on the
+    /// standard output, and its standard error.
+    fn output(mode: &str) -> (Vec<String>, String) {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "util::spinner::tests::output_process",
+                "--ignored",
+                "--nocapture",
+                "--quiet",
+            ])
+            .env(OUTPUT_MODE_VAR, mode)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let lines = stdout
+            .lines()
+            .skip_while(|&line| line != "begin")
+            .skip(1)
+            .take_while(|&line| line != "end")
+            .map(ToOwned::to_owned)
+            .collect();
+
+        (lines, String::from_utf8(output.stderr).unwrap())
+    }
+
+    #[test]
+    fn the_plain_mode_logs_the_progress() {
+        assert_eq!(
+            output("plain").0,
+            [
+                "INFO Message",
+                "INFO Success…",
+                "INFO Success OK",
+                "INFO Failure…",
+                "ERROR Failure ERROR"
+            ]
+        );
+    }
+
+    #[test]
+    fn the_silent_mode_writes_nothing() {
+        assert_eq!(output("silent"), (Vec::new(), String::new()));
+    }
+
+    #[test]
+    fn a_silent_network_is_prepared_without_output() {
+        assert_eq!(output("silent-prepare"), (Vec::new(), String::new()));
     }
 }
@@ -2,12 +2,18 @@
 /// an user interface to monitor the network.
 
 mod endpoints {
+    mod diff;
+    pub use diff::diff;
+    mod isolate;
+    pub use isolate::{isolate, rejoin};
     mod node_status;
-    pub use node_status::node_status;
+    pub use node_status::{node_status, node_status_json};
     mod static_file;
     pub use static_file::static_file;
     mod stop_start;
-    pub use stop_start::stop_start;
+    pub use stop_start::{start, start_form, stop, stop_form, stop_start};
+    mod topology;
+    pub use topology::topology;
 }
 
 use crate::{
@@ -15,52 +21,175 @@ use crate::{
     network::RunningNetwork,
 };
 use axum::{
-    extract::State as AxumState,
-    response::{Html, IntoResponse},
+    extract::{Request, State as AxumState},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
     routing::{get, post},
     Router,
 };
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use futures::FutureExt;
-use std::time::Duration;
+use std::{fmt, net::SocketAddr, sync::Arc, time::Duration};
 use tokio::spawn;
 
+/// The environment variables the credentials are read from when they are not
+/// set on the builder.
+const USERNAME_VAR: &str = "CNUT_WEB_USERNAME";
+const PASSWORD_VAR: &str = "CNUT_WEB_PASSWORD";
+
 #[derive(Debug, Clone)]
 struct AppState {
     network: RunningNetwork,
 }
 
-pub async fn serve(network: RunningNetwork) -> Result<()> {
+/// Who may use the web app. See
+/// [`NetworkBuilder::web_credentials`](crate::network::NetworkBuilder::web_credentials).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WebAuth {
+    /// The credentials required, if any.
+    pub(crate) credentials: Option<WebCredentials>,
+    /// Weither the read-only pages are open despite the credentials.
+    pub(crate) public_status: bool,
+}
+
+/// The username and password of the basic authentication.
+#[derive(Clone, PartialEq)]
+pub(crate) struct WebCredentials {
+    username: String,
+    password: String,
+}
+
+impl WebCredentials {
+    pub(crate) fn new(username: &str, password: &str) -> Self {
+        Self {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    /// Reads the credentials from the environment, if both are set.
+    fn from_env() -> Option<Self> {
+        let username = std::env::var(USERNAME_VAR).ok()?;
+        let password = std::env::var(PASSWORD_VAR).ok()?;
+
+        Some(Self { username, password })
+    }
+
+    /// Returns `true` if the `Authorization` header holds these credentials.
+    fn authorize(&self, authorization: &str) -> bool {
+        let Some(encoded) = authorization.strip_prefix("Basic ") else {
+            return false;
+        };
+        let Ok(decoded) = BASE64.decode(encoded.trim()) else {
+            return false;
+        };
+
+        decoded == format!("{}:{}", self.username, self.password).as_bytes()
+    }
+}
+
+impl fmt::Debug for WebCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebCredentials")
+            .field("username", &self.username)
+            .field("password", &"…")
+            .finish()
+    }
+}
+
+pub async fn serve(network: RunningNetwork, address: SocketAddr) -> Result<()> {
+    let output_mode = network.output_mode;
+    let app = router(network);
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .map_err(|io_err| Error::StartingServerWeb { address, io_err })?;
+
+    let handle = spawn(async move {
+        axum::serve(listener, app).await.map_err(|io_err| {
+            log::error!("Monitoring web server crashed: {io_err:?}");
+            Error::StartingServerWeb { address, io_err }
+        })
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    if let Some(Ok(result)) = handle.now_or_never() {
+        result?;
+    }
+
+    output_mode.print(format!("Web app at http://{address}"));
+    Ok(())
+}
+
+/// Returns the routes of the web app, the ones changing the network behind the
+/// credentials, if any.
+fn router(network: RunningNetwork) -> Router {
     use endpoints::*;
 
+    let mut auth = network.web_auth.clone();
+    if auth.credentials.is_none() {
+        auth.credentials = WebCredentials::from_env();
+    }
     let state = AppState { network };
 
-    let app = Router::new()
+    let mut read_only = Router::new()
         .route("/", get(index))
         .route("/index.css", get(css))
+        .route("/hx.js", get(script))
+        .route("/mermaid.js", get(mermaid_script))
         .route("/favicon.ico", get(favicon))
+        .route("/network-info", get(network_info))
+        .route("/node-status", get(node_status))
+        .route("/api/node-status", get(node_status_json))
+        .route("/topology", get(topology));
+    let mut protected = Router::new()
         .nest(
             "/file",
             Router::new().route("/*path", get(endpoints::static_file)),
         )
-        .route("/node-status", get(node_status))
+        .route("/diff", get(diff))
         .route("/shutdown", post(shutdown))
         .route("/stop-start", post(stop_start))
-        .with_state(state);
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:6532").await.unwrap();
+        .route("/node/:name/stop", post(stop))
+        .route("/node/:name/start", post(start))
+        .route("/node/stop", post(stop_form))
+        .route("/node/start", post(start_form))
+        .route("/node/isolate", post(isolate))
+        .route("/node/rejoin", post(rejoin));
 
-    let handle = spawn(async move {
-        axum::serve(listener, app).await.map_err(|e| {
-            log::error!("Monitoring web server crashed: {e:?}");
-            Error::StartingServerWeb(e)
-        })
-    });
-    tokio::time::sleep(Duration::from_millis(100)).await;
-    if let Some(Ok(result)) = handle.now_or_never() {
-        result?;
+    if let Some(credentials) = auth.credentials {
+        let layer = middleware::from_fn_with_state(Arc::new(credentials), require_credentials);
+        if !auth.public_status {
+            read_only = read_only.route_layer(layer.clone());
+        }
+        protected = protected.route_layer(layer);
     }
 
-    println!("Web app at http://127.0.0.1:6532");
-    Ok(())
+    read_only.merge(protected).with_state(state)
+}
+
+/// Rejects the requests without the right credentials, asking the browser for
+/// them.
+async fn require_credentials(
+    AxumState(credentials): AxumState<Arc<WebCredentials>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| credentials.authorize(value));
+
+    if authorized {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, r#"Basic realm="cnut""#)],
+            "Unauthorized",
+        )
+            .into_response()
+    }
 }
 
 async fn shutdown(AxumState(state): AxumState<AppState>) -> &'static str {
@@ -70,6 +199,19 @@ async fn shutdown(AxumState(state): AxumState<AppState>) -> &'static str {
     "Network is shutting down"
 }
 
+async fn network_info(AxumState(state): AxumState<AppState>) -> Html<String> {
+    let run_directory = state.network.temp_directory().display().to_string();
+    let chain_name = state.network.chain_name();
+
+    Html(
+        maud::html! {
+            p { "Chain " code { (chain_name) } }
+            p { "Network files in " code { (run_directory) } }
+        }
+        .into(),
+    )
+}
+
 async fn index() -> Html<&'static str> {
     include_str!("../public/index.html").into()
 }
@@ -81,6 +223,394 @@ async fn css() -> impl IntoResponse {
     )
 }
 
+async fn script() -> impl IntoResponse {
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/javascript")],
+        include_bytes!("../public/hx.js"),
+    )
+}
+
+async fn mermaid_script() -> impl IntoResponse {
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/javascript")],
+        include_bytes!("../public/mermaid.js"),
+    )
+}
+
 async fn favicon() -> &'static [u8] {
     include_bytes!("../public/favicon.ico")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use reqwest::StatusCode;
+    use std::{collections::BTreeMap, future::IntoFuture as _};
+
+    /// Serves the web app of the `network` on a free port, and returns its
+    /// URL.
+    async fn serve(network: &RunningNetwork) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        spawn(axum::serve(listener, router(network.clone())).into_future());
+
+        format!("http://{address}")
+    }
+
+    /// Returns the `hx-post` path of the button with the `label` acting on
+    /// the node `name` in the status table, and its `hx-vals` as the form
+    /// hx.js sends.
+    fn status_button(html: &str, label: &str, name: &str) -> (String, Vec<(String, String)>) {
+        let attribute = |tag: &str, attribute: &str| -> Option<String> {
+            let start = tag.find(&format!(" {attribute}=\""))? + attribute.len() + 3;
+            let end = start + tag[start..].find('"')?;
+
+            Some(
+                tag[start..end]
+                    .replace("&quot;", "\"")
+                    .replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&amp;", "&"),
+            )
+        };
+
+        html.split("<button")
+            .skip(1)
+            .filter_map(|button| button.split_once("</button>").map(|(button, _)| button))
+            .filter(|button| button.contains(&format!(">{label}<")))
+            .find_map(|button| {
+                let path = attribute(button, "hx-post")?;
+                let vals: BTreeMap<String, String> =
+                    serde_json::from_str(&attribute(button, "hx-vals")?).ok()?;
+
+                (vals.get("name").map(String::as_str) == Some(name))
+                    .then(|| (path, vals.into_iter().collect()))
+            })
+            .unwrap_or_else(|| panic!("no {label} button for {name} in {html}"))
+    }
+
+    /// Returns a network of 2 nodes which are not started, whose web app asks
+    /// for credentials, and opens the read-only pages if `public_status`.
+    async fn protected_network(
+        artifacts: &crate::artifacts::Artifacts,
+        public_status: bool,
+    ) -> RunningNetwork {
+        testing::network(artifacts, 2)
+            .web_credentials("alice", "secret")
+            .web_public_status(public_status)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap()
+    }
+
+    /// Returns the status of the answer to a `GET` of `path`, with the
+    /// `credentials` if any, and its `WWW-Authenticate` header.
+    async fn get_status(
+        url: &str,
+        path: &str,
+        credentials: Option<(&str, &str)>,
+    ) -> (StatusCode, Option<String>) {
+        let mut request = reqwest::Client::new().get(format!("{url}{path}"));
+        if let Some((username, password)) = credentials {
+            request = request.basic_auth(username, Some(password));
+        }
+        let response = request.send().await.unwrap();
+        let challenge = response
+            .headers()
+            .get(reqwest::header::WWW_AUTHENTICATE)
+            .map(|value| value.to_str().unwrap().to_owned());
+
+        (response.status(), challenge)
+    }
+
+    #[tokio::test]
+    async fn the_protected_routes_ask_for_the_credentials() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = protected_network(&artifacts, true).await;
+        let url = serve(&network).await;
+        let diff = "/diff?left=Node_A/0&right=Node_A/1";
+
+        assert_eq!(
+            get_status(&url, diff, None).await,
+            (
+                StatusCode::UNAUTHORIZED,
+                Some(r#"Basic realm="cnut""#.to_owned())
+            )
+        );
+        assert_eq!(
+            get_status(&url, diff, Some(("alice", "wrong"))).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get_status(&url, diff, Some(("alice", "secret"))).await,
+            (StatusCode::OK, None)
+        );
+        assert_eq!(
+            get_status(&url, "/file/Node_A/0/config.toml", None).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn the_read_only_routes_follow_public_status() {
+        let (_dir, artifacts) = testing::artifacts();
+        let read_only = ["/", "/node-status", "/network-info", "/topology"];
+
+        let network = protected_network(&artifacts, true).await;
+        let url = serve(&network).await;
+        for path in read_only {
+            assert_eq!(
+                get_status(&url, path, None).await.0,
+                StatusCode::OK,
+                "{path}"
+            );
+        }
+
+        let network = protected_network(&artifacts, false).await;
+        let url = serve(&network).await;
+        for path in read_only {
+            assert_eq!(
+                get_status(&url, path, None).await.0,
+                StatusCode::UNAUTHORIZED,
+                "{path}"
+            );
+            assert_eq!(
+                get_status(&url, path, Some(("alice", "secret"))).await.0,
+                StatusCode::OK,
+                "{path}"
+            );
+        }
+    }
+
+    /// Returns the values of the `src` and `href` attributes of the `html`.
+    fn asset_paths(html: &str) -> Vec<String> {
+        [" src=\"", " href=\""]
+            .into_iter()
+            .flat_map(|attribute| html.split(attribute).skip(1))
+            .filter_map(|rest| rest.split_once('"').map(|(path, _)| path.to_owned()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn every_referenced_asset_is_served() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 2)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let url = serve(&network).await;
+        let client = reqwest::Client::new();
+        let get = |path: String| {
+            let path = path.trim_start_matches('/').to_owned();
+            let request = client.get(format!("{url}/{path}")).send();
+            async move {
+                let response = request.await.unwrap();
+                (response.status(), response.bytes().await.unwrap())
+            }
+        };
+
+        let (status, topology) = get("/topology".to_owned()).await;
+        assert_eq!(status, StatusCode::OK);
+        let topology = String::from_utf8(topology.to_vec()).unwrap();
+        let mut paths = asset_paths(include_str!("../public/index.html"));
+        paths.extend(asset_paths(&topology));
+        paths.push("/favicon.ico".to_owned());
+        for expected in ["hx.js", "index.css", "mermaid.js"] {
+            assert!(paths.iter().any(|path| path == expected), "{expected}");
+        }
+
+        for path in paths {
+            assert!(!path.contains("://"), "{path} is not served locally");
+            let (status, body) = get(path.clone()).await;
+            assert_eq!(status, StatusCode::OK, "{path}");
+            assert!(!body.is_empty(), "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn status_buttons_send_the_node_name() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 2)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let url = serve(&network).await;
+        let client = reqwest::Client::new();
+        let node = network.node_by_name("Node_A/1").unwrap();
+        let status = || async {
+            let response = client.get(format!("{url}/node-status")).send().await;
+            response.unwrap().text().await.unwrap()
+        };
+
+        let (path, form) = status_button(&status().await, "Stop", "Node_A/1");
+        let response = client
+            .post(format!("{url}{path}"))
+            .form(&form)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!node.running().await);
+        let _ = node.wait_for_exit().await;
+
+        let (path, form) = status_button(&status().await, "Start", "Node_A/1");
+        let response = client
+            .post(format!("{url}{path}"))
+            .form(&form)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(node.running().await);
+
+        network.stop_all().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn grouped_nodes_are_stopped_and_started() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 2)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let url = serve(&network).await;
+        let client = reqwest::Client::new();
+        let node = network.node_by_name("Node_A/1").unwrap();
+
+        let response = client
+            .post(format!("{url}/node/stop"))
+            .form(&[("name", "Node_A/1")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "Node Node_A/1 stopped");
+        assert!(!node.running().await);
+
+        let response = client
+            .post(format!("{url}/node/start"))
+            .form(&[("name", "Node_A/1")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(node.running().await);
+
+        let response = client
+            .post(format!("{url}/node/stop"))
+            .form(&[("name", "Node_B")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        network.stop_all().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn nodes_are_stopped_and_started_by_path() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 2)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let url = serve(&network).await;
+        let client = reqwest::Client::new();
+        let node = network.node_by_name("Node_A/1").unwrap();
+
+        let response = client
+            .post(format!("{url}/node/Node_A%2F1/stop"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "Node Node_A/1 stopped");
+        assert!(!node.running().await);
+
+        let response = client
+            .post(format!("{url}/node/Node_A%2F1/stop"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.text().await.unwrap(),
+            "Node Node_A/1 is already stopped"
+        );
+
+        let response = client
+            .post(format!("{url}/node/Node_A%2F1/start"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(node.running().await);
+
+        let response = client
+            .post(format!("{url}/node/Node_B/start"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        network.stop_all().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn grouped_nodes_are_isolated_and_rejoin() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 2)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let url = serve(&network).await;
+        let client = reqwest::Client::new();
+        let status = || async {
+            let response = client.get(format!("{url}/node-status")).send().await;
+            response.unwrap().text().await.unwrap()
+        };
+
+        let (path, form) = status_button(&status().await, "Isolate", "Node_A/0");
+        let response = client
+            .post(format!("{url}{path}"))
+            .form(&form)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(network.is_isolated("Node_A/0"));
+
+        let (path, form) = status_button(&status().await, "Rejoin", "Node_A/0");
+        let response = client
+            .post(format!("{url}{path}"))
+            .form(&form)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!network.is_isolated("Node_A/0"));
+
+        network.stop_all().await.unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+use crate::web_app::AppState;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Html,
+};
+use maud::{html, Markup, DOCTYPE};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    left: String,
+    right: String,
+    /// Weither the keys set by cnut for each node are shown as differing.
+    #[serde(default)]
+    all: bool,
+}
+
+/// Renders the configs of two nodes side by side, the differing keys being
+/// highlighted. See [`RunningNetwork::compare_configs`](crate::network::RunningNetwork::compare_configs).
+pub async fn diff(
+    State(state): State<AppState>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    log::trace!("diff endpoint");
+
+    let diff = state
+        .network
+        .compare_configs(&query.left, &query.right)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Cannot compare the configs: {e}"),
+            )
+        })?;
+    let value = |value: &Option<toml::Value>| -> Markup {
+        match value {
+            Some(value) => html! { code { (value) } },
+            None => html! { em { "absent" } },
+        }
+    };
+    let differences = if query.all {
+        diff.all_differences().count()
+    } else {
+        diff.differences().count()
+    };
+
+    Ok(Html(
+        html! {
+            (DOCTYPE)
+            html lang="en" {
+                head {
+                    meta charset="UTF-8";
+                    link rel="stylesheet" href="index.css";
+                    title { "Configs of " (diff.left) " and " (diff.right) }
+                }
+                body {
+                    p { (differences) " differing keys" }
+                    table .diff {
+                        tr { th { "Key" } th { (diff.left) } th { (diff.right) } }
+                        @for entry in &diff.entries {
+                            @let class = match (entry.differs(), entry.per_node && !query.all) {
+                                (false, _) => "",
+                                (true, true) => "per-node",
+                                (true, false) => "differs",
+                            };
+                            tr class=(class) {
+                                td { code { (entry.key) } }
+                                td { (value(&entry.left)) }
+                                td { (value(&entry.right)) }
+                            }
+                        }
+                    }
+                    p { a href="/" { "Back" } }
+                }
+            }
+        }
+        .into(),
+    ))
+}
@@ -0,0 +1,38 @@
+use super::stop_start::Named;
+use crate::web_app::AppState;
+use axum::{extract::State, http::StatusCode, Form};
+
+/// Isolates the node, see [`RunningNetwork::isolate_node`](crate::network::RunningNetwork::isolate_node).
+pub async fn isolate(
+    State(state): State<AppState>,
+    Form(Named { name }): Form<Named>,
+) -> (StatusCode, String) {
+    log::trace!("isolate endpoint");
+    log::debug!("Node {name} is asked to be ISOLATED");
+
+    match state.network.isolate_node(&name).await {
+        Ok(()) => (StatusCode::OK, format!("Node {name} isolated")),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Cannot isolate the node {name}: {e}"),
+        ),
+    }
+}
+
+/// Makes the isolated node rejoin the network, see
+/// [`RunningNetwork::rejoin_node`](crate::network::RunningNetwork::rejoin_node).
+pub async fn rejoin(
+    State(state): State<AppState>,
+    Form(Named { name }): Form<Named>,
+) -> (StatusCode, String) {
+    log::trace!("rejoin endpoint");
+    log::debug!("Node {name} is asked to REJOIN");
+
+    match state.network.rejoin_node(&name).await {
+        Ok(()) => (StatusCode::OK, format!("Node {name} rejoined the network")),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Cannot make the node {name} rejoin: {e}"),
+        ),
+    }
+}
@@ -1,32 +1,85 @@
-use crate::{network::RunningNode, web_app::AppState};
-use axum::extract::State;
+use crate::{network::RunningNetwork, web_app::AppState};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
 use maud::html;
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use tokio::task::JoinSet;
 
+#[derive(Serialize)]
 struct Status {
     name: String,
+    /// See [`RunningNode::dir_name`](crate::network::RunningNode::dir_name).
+    #[serde(skip)]
+    dir_name: String,
+    public_key: String,
     validator: bool,
     running: bool,
     info: Option<LastAddedBlockInfo>,
+    /// The [`NodeHealth`](crate::network::NodeHealth) label, like `stalled`.
+    state: &'static str,
+    /// The health with its details, like `syncing, 12 blocks behind`.
+    state_details: String,
+    /// Weither the node is cut from the network, see
+    /// [`RunningNetwork::isolate_node`].
+    isolated: bool,
 }
 
-pub async fn node_status(State(state): State<AppState>) -> String {
-    match gather_info(&state.network.nodes).await {
+/// Renders the status of the nodes as an HTML table, or as JSON if the client
+/// accepts `application/json`.
+pub async fn node_status(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let wants_json = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("application/json"));
+
+    if wants_json {
+        node_status_json(State(state)).await.into_response()
+    } else {
+        Html(node_status_html(&state).await).into_response()
+    }
+}
+
+/// Returns the status of the nodes as JSON.
+pub async fn node_status_json(State(state): State<AppState>) -> Response {
+    match gather_info(&state.network).await {
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Error while reading the data" })),
+        )
+            .into_response(),
+        Ok(status) => Json(status).into_response(),
+    }
+}
+
+async fn node_status_html(state: &AppState) -> String {
+    match gather_info(&state.network).await {
         Err(_) => html! {
             "Error while reading the data"
         },
         Ok(status) => html! {
             table {
                 tr {
-                    th{"Name"} th{"Era ID"} th{"Height"} th{"Validator"} th{"Config File"} th{"Stop/Start"}
+                    th{"Name"} th{"Public Key"} th{"State"} th{"Era ID"} th{"Height"} th{"Validator"} th{"Config File"} th{"Stop/Start"} th{"Partition"}
                 }
                 @for status in &status {
-                    @let path = format!("/file/{}/config.toml", status.name);
-                    @let stop_start = format!("/stop-start?name={}", status.name);
+                    @let path = format!("/file/{}/config.toml", status.dir_name);
+                    @let named = json!({ "name": status.name }).to_string();
                     tr {
                         td{(status.name)}
+                        td{code{(status.public_key)}}
+                        td .(status.state) title=(status.state_details) {
+                            (status.state)
+                            @if status.isolated {
+                                " " span .badge {"isolated"}
+                            }
+                        }
                         @if status.running == false {
                             td colspan="2"{"Node not running"}
                         } @else if let Some(info) = status.info.as_ref() {
@@ -39,9 +92,22 @@ pub async fn node_status(State(state): State<AppState>) -> String {
                         td{ @if status.validator { "Yes" } @else { "No" } }
                         td{a .file href=(path) {"config.toml"}}
                         td{@if status.running {
-                            button class="red" hx-post=(stop_start) {"Stop"}
+                            button class="red" hx-post="/node/stop" hx-vals=(named) hx-disabled-elt="this" {
+                                "Stop" span .htmx-indicator {"…"}
+                            }
+                        } @else {
+                            button class="green" hx-post="/node/start" hx-vals=(named) hx-disabled-elt="this" {
+                                "Start" span .htmx-indicator {"…"}
+                            }
+                        }}
+                        td{@if status.isolated {
+                            button hx-post="/node/rejoin" hx-vals=(named) hx-disabled-elt="this" {
+                                "Rejoin" span .htmx-indicator {"…"}
+                            }
                         } @else {
-                            button class="green" hx-post=(stop_start) {"Start"}
+                            button hx-post="/node/isolate" hx-vals=(named) hx-disabled-elt="this" {
+                                "Isolate" span .htmx-indicator {"…"}
+                            }
                         }}
                     }
                 }
@@ -51,15 +117,17 @@ pub async fn node_status(State(state): State<AppState>) -> String {
     .into()
 }
 
-async fn gather_info(nodes: &[RunningNode]) -> Result<Vec<Status>, ()> {
+async fn gather_info(network: &RunningNetwork) -> Result<Vec<Status>, ()> {
     let mut requests = JoinSet::new();
     let client = Client::new();
 
-    for node in nodes {
+    for node in &network.nodes {
         let name = node.name().to_owned();
+        let dir_name = node.dir_name().to_owned();
+        let public_key = node.public_key().to_string();
         let validator = node.validator();
         let request = client
-            .get(format!("http://127.0.0.1:{}/status", node.rest_port()))
+            .get(format!("http://{}/status", node.rest_address()))
             .send();
         requests.spawn(async move {
             match request.await {
@@ -68,9 +136,14 @@ async fn gather_info(nodes: &[RunningNode]) -> Result<Vec<Status>, ()> {
                         last_added_block_info,
                     }) => Ok(Status {
                         name,
+                        dir_name,
+                        public_key,
                         validator,
                         running: true,
                         info: last_added_block_info,
+                        state: "",
+                        state_details: String::new(),
+                        isolated: false,
                     }),
                     Err(e) => {
                         log::debug!("Could not deserialize the node status: {e:?}");
@@ -79,9 +152,14 @@ async fn gather_info(nodes: &[RunningNode]) -> Result<Vec<Status>, ()> {
                 },
                 Err(_) => Ok(Status {
                     name,
+                    dir_name,
+                    public_key,
                     validator,
                     running: false,
                     info: None,
+                    state: "",
+                    state_details: String::new(),
+                    isolated: false,
                 }),
             }
         });
@@ -98,6 +176,13 @@ async fn gather_info(nodes: &[RunningNode]) -> Result<Vec<Status>, ()> {
         };
     }
 
+    for (node, health) in network.health().await {
+        if let Some(status) = result.iter_mut().find(|status| status.name == node.name()) {
+            status.state = health.label();
+            status.state_details = health.to_string();
+            status.isolated = network.is_isolated(node.name());
+        }
+    }
     result.sort_by(|a, b| a.name.cmp(&b.name));
 
     Ok(result)
@@ -108,8 +193,55 @@ struct Payload {
     last_added_block_info: Option<LastAddedBlockInfo>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 struct LastAddedBlockInfo {
     era_id: usize,
     height: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::net::{IpAddr, UdpSocket};
+
+    /// Returns an address of this machine which is not a loopback one, if it
+    /// has a route to the outside. No packet is sent.
+    fn non_loopback_address() -> Option<IpAddr> {
+        let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+        socket.connect("192.0.2.1:9").ok()?;
+        let address = socket.local_addr().ok()?.ip();
+
+        (!address.is_loopback() && !address.is_unspecified()).then_some(address)
+    }
+
+    #[tokio::test]
+    async fn the_nodes_are_probed_at_their_host() {
+        let Some(host) = non_loopback_address() else {
+            eprintln!("No address other than the loopback one, skipped");
+            return;
+        };
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 2)
+            .bind_host(host)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        network.wait_until_ready().await.unwrap();
+
+        let status = gather_info(&network).await.unwrap();
+
+        assert_eq!(status.len(), 2);
+        assert!(status
+            .iter()
+            .all(|status| status.running && status.info.is_some()));
+        // Nothing to reach at the loopback address:
+        let rest_port = network.nodes[0].rest_port();
+        assert!(std::net::TcpStream::connect(("127.0.0.1", rest_port)).is_err());
+
+        network.stop_all().await.unwrap();
+    }
+}
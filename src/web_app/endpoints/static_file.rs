@@ -31,6 +31,8 @@ fn beautify_toml(input: String) -> String {
     for line in input.lines() {
         if line.starts_with('[') && line.ends_with(']') {
             buf.push_str(&format!("<span class=\"strong\">{line}</span>"));
+        } else if line.starts_with('#') {
+            buf.push_str(&format!("<span class=\"comment\">{line}</span>"));
         } else {
             buf.push_str(line);
         }
@@ -58,6 +60,10 @@ code {{
         font-weight: bold;
         color: green;
     }}
+    .comment {{
+        font-style: italic;
+        color: grey;
+    }}
 }}
     </style>
 </head>
@@ -1,32 +1,139 @@
-use crate::web_app::AppState;
-use axum::extract::{Query, State};
+use crate::{network::RunningNode, web_app::AppState};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Form,
+};
 use serde::Deserialize;
 
+/// The node an endpoint acts on, for the buttons of the web app: the names of
+/// the multiplied nodes contain a `/`, which must be percent-encoded in a path.
 #[derive(Deserialize)]
 pub struct Named {
-    name: String,
+    pub name: String,
 }
 
 pub async fn stop_start(
     State(mut state): State<AppState>,
     Query(Named { name }): Query<Named>,
-) -> Result<(), &'static str> {
+) -> Result<(), (StatusCode, &'static str)> {
     log::trace!("stop_start endpoint");
     let node = state
         .network
         .nodes
         .iter_mut()
         .find(|node| node.name() == name)
-        .ok_or("Unknown node name")
+        .ok_or((StatusCode::NOT_FOUND, "Unknown node name"))
         .inspect_err(|_| log::warn!("Unknown node name: {name}"))?;
+    let _operation = node
+        .try_lock_operation()
+        .ok_or((StatusCode::CONFLICT, "Operation in progress"))?;
 
     if node.running().await {
         log::debug!("Node {name} is asked to STOP");
-        node.stop().await.map_err(|_| "Cannot stop the node")?;
+        node.stop_locked()
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Cannot stop the node"))?;
     } else {
         log::debug!("Node {name} is asked to START");
-        node.start().await.map_err(|_| "Cannot start the node")?;
+        node.start_locked()
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Cannot start the node"))?;
     }
 
     Ok(())
 }
+
+/// Stops the node if it is running. The name is percent-encoded in the path,
+/// such as `/node/Alice%2F0/stop`.
+pub async fn stop(State(state): State<AppState>, Path(name): Path<String>) -> (StatusCode, String) {
+    stop_node(&state, name).await
+}
+
+/// Same as [`stop`], the name being a form field.
+pub async fn stop_form(
+    State(state): State<AppState>,
+    Form(Named { name }): Form<Named>,
+) -> (StatusCode, String) {
+    stop_node(&state, name).await
+}
+
+/// Starts the node if it is not running. The name is percent-encoded in the
+/// path, such as `/node/Alice%2F0/start`.
+pub async fn start(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> (StatusCode, String) {
+    start_node(&state, name).await
+}
+
+/// Same as [`start`], the name being a form field.
+pub async fn start_form(
+    State(state): State<AppState>,
+    Form(Named { name }): Form<Named>,
+) -> (StatusCode, String) {
+    start_node(&state, name).await
+}
+
+async fn stop_node(state: &AppState, name: String) -> (StatusCode, String) {
+    log::trace!("stop endpoint");
+    let Some(mut node) = find_node(state, &name) else {
+        return (StatusCode::NOT_FOUND, format!("Unknown node name: {name}"));
+    };
+    let Some(_operation) = node.try_lock_operation() else {
+        return in_progress(&name);
+    };
+
+    if !node.running().await {
+        return (StatusCode::OK, format!("Node {name} is already stopped"));
+    }
+    log::debug!("Node {name} is asked to STOP");
+    match node.stop_locked().await {
+        Ok(()) => (StatusCode::OK, format!("Node {name} stopped")),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Cannot stop the node {name}: {e}"),
+        ),
+    }
+}
+
+async fn start_node(state: &AppState, name: String) -> (StatusCode, String) {
+    log::trace!("start endpoint");
+    let Some(mut node) = find_node(state, &name) else {
+        return (StatusCode::NOT_FOUND, format!("Unknown node name: {name}"));
+    };
+    let Some(_operation) = node.try_lock_operation() else {
+        return in_progress(&name);
+    };
+
+    if node.running().await {
+        return (StatusCode::OK, format!("Node {name} is already running"));
+    }
+    log::debug!("Node {name} is asked to START");
+    match node.start_locked().await {
+        Ok(()) => (StatusCode::OK, format!("Node {name} started")),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Cannot start the node {name}: {e}"),
+        ),
+    }
+}
+
+fn find_node(state: &AppState, name: &str) -> Option<RunningNode> {
+    let node = state.network.nodes.iter().find(|node| node.name() == name);
+    if node.is_none() {
+        log::warn!("Unknown node name: {name}");
+    }
+
+    node.cloned()
+}
+
+/// The answer when the node is already being started or stopped.
+fn in_progress(name: &str) -> (StatusCode, String) {
+    log::debug!("Node {name} is already being started or stopped");
+
+    (
+        StatusCode::CONFLICT,
+        format!("An operation is in progress on the node {name}"),
+    )
+}
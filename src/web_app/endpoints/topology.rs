@@ -0,0 +1,47 @@
+use crate::{network::TopologyFormat, web_app::AppState};
+use axum::{extract::State, http::StatusCode, response::Html};
+use maud::{html, PreEscaped, DOCTYPE};
+
+/// Renders a page with the Mermaid diagram of the network, drawn by the served
+/// mermaid.js, and its Graphviz equivalent.
+pub async fn topology(
+    State(state): State<AppState>,
+) -> Result<Html<String>, (StatusCode, &'static str)> {
+    let export = |format| state.network.export_topology(format);
+    let (mermaid, dot) = match (
+        export(TopologyFormat::Mermaid).await,
+        export(TopologyFormat::Dot).await,
+    ) {
+        (Ok(mermaid), Ok(dot)) => (mermaid, dot),
+        (Err(e), _) | (_, Err(e)) => {
+            log::warn!("Could not export the topology: {e}");
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Could not read the topology",
+            ));
+        }
+    };
+
+    Ok(Html(
+        html! {
+            (DOCTYPE)
+            html lang="en" {
+                head {
+                    meta charset="UTF-8";
+                    link rel="stylesheet" href="index.css";
+                    script src="mermaid.js" {}
+                    title { "Network topology" }
+                }
+                body {
+                    h2 { "Mermaid" }
+                    pre .mermaid { (mermaid) }
+                    h2 { "Graphviz" }
+                    pre { (dot) }
+                    p { a href="/" { "Back" } }
+                    script { (PreEscaped("if (window.mermaid) { mermaid.run(); }")) }
+                }
+            }
+        }
+        .into(),
+    ))
+}
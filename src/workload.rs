@@ -0,0 +1,469 @@
+//! Generates a sustained load on a running network, for soak testing. See
+//! [`RunningNetwork::run_workload`].
+
+use crate::{
+    error::{Error, Result},
+    network::{RunningNetwork, RunningNode},
+    util::deploy::Deploy,
+};
+use futures::FutureExt as _;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{fs, select, sync::Notify, task::JoinHandle, task::JoinSet};
+
+/// The payment of a native transfer, in motes.
+const TRANSFER_PAYMENT: u128 = 100_000_000;
+/// The slowest pace the generator backs off to when the nodes reject deploys.
+const MAX_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the pending deploys are queried for their execution result.
+const EXECUTION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What each deploy of the workload does.
+#[derive(Debug, Clone)]
+pub enum DeployTemplate {
+    /// A native transfer of `amount` motes from the sender to the account of
+    /// another node. The node rejects transfers below 2.5 CSPR.
+    Transfer {
+        /// The transferred amount, in motes.
+        amount: u128,
+    },
+    /// A WASM module from the artifacts, run without argument, for example
+    /// `do_nothing.wasm`.
+    Wasm {
+        /// The file name in the artifacts directory.
+        file_name: String,
+        /// The payment amount, in motes.
+        payment: u128,
+    },
+}
+
+/// Describes a workload. See [`RunningNetwork::run_workload`].
+#[derive(Debug, Clone)]
+pub struct WorkloadConfig {
+    template: DeployTemplate,
+    rate: f64,
+    duration: Duration,
+    senders: Vec<String>,
+    execution_timeout: Duration,
+    rpc_node: Option<String>,
+}
+
+/// A workload being generated. Dropping it does not stop the workload.
+#[derive(Debug)]
+pub struct WorkloadGenerator {
+    stop: Arc<Notify>,
+    handle: JoinHandle<Result<WorkloadReport>>,
+}
+
+/// What happened during a workload.
+#[derive(Debug, Clone, Default)]
+pub struct WorkloadReport {
+    /// The amount of deploys sent to the nodes.
+    pub submitted: usize,
+    /// The amount of deploys accepted by the nodes.
+    pub accepted: usize,
+    /// The amount of deploys rejected by the nodes.
+    pub rejected: usize,
+    /// The amount of deploys which could not be sent, for example because the
+    /// node was unreachable.
+    pub errors: usize,
+    /// The amount of accepted deploys executed successfully.
+    pub executed: usize,
+    /// The amount of accepted deploys whose execution failed.
+    pub execution_failures: usize,
+    /// The amount of accepted deploys not executed before the execution
+    /// timeout.
+    pub not_executed: usize,
+    /// The median time for a node to answer a deploy submission.
+    pub latency_p50: Option<Duration>,
+    /// The 90th percentile of the submission latency.
+    pub latency_p90: Option<Duration>,
+    /// The 99th percentile of the submission latency.
+    pub latency_p99: Option<Duration>,
+    /// The longest submission latency.
+    pub latency_max: Option<Duration>,
+}
+
+/// The result of one submission.
+struct Submission {
+    rpc_node: RunningNode,
+    deploy_hash: String,
+    latency: Duration,
+    result: Result<()>,
+}
+
+impl WorkloadConfig {
+    /// Creates a workload sending one deploy per second for a minute, signed
+    /// in turn by every node key.
+    pub fn new(template: DeployTemplate) -> Self {
+        Self {
+            template,
+            rate: 1.0,
+            duration: Duration::from_secs(60),
+            senders: Vec::new(),
+            execution_timeout: Duration::from_secs(60),
+            rpc_node: None,
+        }
+    }
+
+    /// Sets the target rate, in deploys per second. It must be positive, which
+    /// is checked when the workload starts.
+    pub fn rate(self, rate: f64) -> Self {
+        Self { rate, ..self }
+    }
+
+    /// Sets how long the deploys are sent for.
+    pub fn duration(self, duration: Duration) -> Self {
+        Self { duration, ..self }
+    }
+
+    /// Sets the nodes whose keys sign the deploys, in turn. All the nodes by
+    /// default.
+    pub fn senders(self, senders: Vec<String>) -> Self {
+        Self { senders, ..self }
+    }
+
+    /// Sends all the deploys to the node with this name. By default, they are
+    /// spread across the healthy nodes, see
+    /// [`RunningNetwork::any_healthy_node`].
+    pub fn rpc_node(self, name: impl Into<String>) -> Self {
+        Self {
+            rpc_node: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Sets how long to wait for the accepted deploys to be executed once the
+    /// generation is over. A zero duration skips the execution check.
+    pub fn execution_timeout(self, execution_timeout: Duration) -> Self {
+        Self {
+            execution_timeout,
+            ..self
+        }
+    }
+}
+
+impl WorkloadGenerator {
+    /// Stops sending deploys before the end of the workload duration.
+    pub fn stop(&self) {
+        self.stop.notify_one();
+    }
+
+    /// Waits for the end of the workload and returns its report.
+    pub async fn report(self) -> Result<WorkloadReport> {
+        self.handle
+            .await
+            .expect("the workload generator not to panic")
+    }
+}
+
+impl RunningNetwork {
+    /// Starts sending deploys built from the workload template, spread across
+    /// the healthy nodes unless [`WorkloadConfig::rpc_node`] is set.
+    ///
+    /// When the nodes reject deploys, the generator slows down, then gets back
+    /// to the target rate progressively.
+    ///
+    /// Fails with [`Error::InvalidWorkloadRate`] if the rate is not positive,
+    /// or with [`Error::NotEnoughTransferTargets`] if the network has a single
+    /// node to send transfers between.
+    pub fn run_workload(&self, config: WorkloadConfig) -> Result<WorkloadGenerator> {
+        let rate = config.rate;
+        if !(rate.is_finite() && rate > 0.0 && Duration::try_from_secs_f64(rate.recip()).is_ok()) {
+            return Err(Error::InvalidWorkloadRate(config.rate));
+        }
+        if matches!(config.template, DeployTemplate::Transfer { .. }) && self.nodes.len() < 2 {
+            return Err(Error::NotEnoughTransferTargets);
+        }
+        let stop = Arc::new(Notify::new());
+        let handle = tokio::spawn(generate(self.clone(), config, stop.clone()));
+
+        Ok(WorkloadGenerator { stop, handle })
+    }
+}
+
+async fn generate(
+    network: RunningNetwork,
+    config: WorkloadConfig,
+    stop: Arc<Notify>,
+) -> Result<WorkloadReport> {
+    let WorkloadConfig {
+        template,
+        rate,
+        duration,
+        senders,
+        execution_timeout,
+        rpc_node,
+    } = config;
+    let rpc_node = rpc_node
+        .map(|name| network.node_by_name(&name).cloned())
+        .transpose()?;
+    let senders = if senders.is_empty() {
+        network.nodes.clone()
+    } else {
+        senders
+            .iter()
+            .map(|name| network.node_by_name(name).cloned())
+            .collect::<Result<_>>()?
+    };
+    let Some(first_sender) = senders.first() else {
+        return Ok(WorkloadReport::default());
+    };
+    let chain_name = network.chain_name().to_owned();
+    let module_bytes = match &template {
+        DeployTemplate::Transfer { .. } => Vec::new(),
+        DeployTemplate::Wasm { file_name, .. } => {
+            let path = first_sender.artifact_dir().join(file_name);
+
+            fs::read(&path)
+                .await
+                .map_err(|io_err| Error::FileOperation {
+                    description: format!("reading the WASM module {path:?}"),
+                    io_err,
+                })?
+        }
+    };
+
+    let base_interval = Duration::from_secs_f64(1.0 / rate);
+    let max_interval = MAX_INTERVAL.max(base_interval);
+    let mut interval = base_interval;
+    let deadline = tokio::time::Instant::now() + duration;
+    let mut submissions = JoinSet::new();
+    let mut results = Vec::new();
+
+    log::info!("Starting a workload of {rate} deploys/s for {duration:?}");
+
+    for count in 0.. {
+        // Slow down on rejections, and get back to the target rate on success:
+        while let Some(Some(submission)) = submissions.join_next().now_or_never() {
+            let submission: Submission = submission.expect("the submission not to panic");
+            interval = match submission.result {
+                Ok(()) => interval.mul_f64(0.9).max(base_interval),
+                Err(_) => (interval * 2).min(max_interval),
+            };
+            results.push(submission);
+        }
+
+        select! {
+            _ = stop.notified() => break,
+            _ = tokio::time::sleep_until(deadline) => break,
+            _ = tokio::time::sleep(interval) => (),
+        }
+
+        let rpc_node = match &rpc_node {
+            Some(rpc_node) => rpc_node.clone(),
+            None => match network.any_healthy_node().await {
+                Ok(rpc_node) => rpc_node.clone(),
+                Err(e) => {
+                    log::warn!("Cannot send the workload deploy: {e}");
+                    continue;
+                }
+            },
+        };
+        let sender = &senders[count % senders.len()];
+        let (public_key, secret_key) = (sender.public_key(), sender.secret_key());
+        let keys = (&public_key, &secret_key);
+        let deploy = match &template {
+            DeployTemplate::Transfer { amount } => {
+                let target = transfer_target(&network.nodes, sender, count);
+
+                Deploy::transfer(
+                    keys,
+                    &chain_name,
+                    &target.public_key(),
+                    *amount,
+                    TRANSFER_PAYMENT,
+                    count as u64,
+                )
+            }
+            DeployTemplate::Wasm { payment, .. } => {
+                Deploy::module_bytes(keys, &chain_name, module_bytes.clone(), *payment)
+            }
+        };
+
+        submissions.spawn(async move {
+            let start = Instant::now();
+            let result = rpc_node.rpc().put_deploy(&deploy).await;
+
+            Submission {
+                rpc_node,
+                deploy_hash: deploy.hash(),
+                latency: start.elapsed(),
+                result,
+            }
+        });
+    }
+
+    while let Some(submission) = submissions.join_next().await {
+        results.push(submission.expect("the submission not to panic"));
+    }
+
+    let mut report = WorkloadReport {
+        submitted: results.len(),
+        ..Default::default()
+    };
+    for submission in &results {
+        match &submission.result {
+            Ok(()) => report.accepted += 1,
+            Err(Error::RpcResponse { message, .. }) => {
+                log::debug!("Deploy {} rejected: {message}", submission.deploy_hash);
+                report.rejected += 1;
+            }
+            Err(e) => {
+                log::debug!("Deploy {} not sent: {e}", submission.deploy_hash);
+                report.errors += 1;
+            }
+        }
+    }
+
+    let mut latencies: Vec<_> = results.iter().map(|s| s.latency).collect();
+    latencies.sort();
+    report.latency_p50 = percentile(&latencies, 0.5);
+    report.latency_p90 = percentile(&latencies, 0.9);
+    report.latency_p99 = percentile(&latencies, 0.99);
+    report.latency_max = latencies.last().copied();
+
+    let accepted = results.into_iter().filter(|s| s.result.is_ok()).collect();
+    check_execution(accepted, execution_timeout, &mut report).await;
+
+    log::info!("Workload over: {report:?}");
+
+    Ok(report)
+}
+
+/// Polls the accepted deploys until they are executed or the timeout expires.
+async fn check_execution(
+    mut pending: Vec<Submission>,
+    timeout: Duration,
+    report: &mut WorkloadReport,
+) {
+    let deadline = Instant::now() + timeout;
+
+    while !pending.is_empty() && Instant::now() < deadline {
+        let mut still_pending = Vec::new();
+
+        for submission in pending {
+            let result = submission
+                .rpc_node
+                .rpc()
+                .get_deploy(&submission.deploy_hash)
+                .await;
+            let execution_result = result.ok().and_then(|deploy| {
+                deploy
+                    .get("execution_results")?
+                    .as_array()?
+                    .first()?
+                    .get("result")
+                    .cloned()
+            });
+
+            match execution_result {
+                None => still_pending.push(submission),
+                Some(result) if result.get("Failure").is_some() => report.execution_failures += 1,
+                Some(_) => report.executed += 1,
+            }
+        }
+
+        pending = still_pending;
+        if !pending.is_empty() {
+            tokio::time::sleep(EXECUTION_POLL_INTERVAL).await;
+        }
+    }
+
+    report.not_executed = pending.len();
+}
+
+/// Returns the node receiving the `count`th transfer of the `sender`, never
+/// the sender itself. There must be at least 2 nodes.
+fn transfer_target<'a>(
+    nodes: &'a [RunningNode],
+    sender: &RunningNode,
+    count: usize,
+) -> &'a RunningNode {
+    let targets: Vec<_> = nodes
+        .iter()
+        .filter(|node| node.name() != sender.name())
+        .collect();
+
+    targets[count % targets.len()]
+}
+
+/// Returns the `q` quantile of the `sorted` durations, or `None` if there is
+/// none.
+fn percentile(sorted: &[Duration], q: f64) -> Option<Duration> {
+    let last = sorted.len().checked_sub(1)?;
+
+    Some(sorted[(last as f64 * q).round() as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[tokio::test]
+    async fn invalid_rates_are_rejected() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 2)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let transfer = WorkloadConfig::new(DeployTemplate::Transfer { amount: 1 });
+
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY, 1e-300] {
+            assert!(matches!(
+                network.run_workload(transfer.clone().rate(rate)),
+                Err(Error::InvalidWorkloadRate(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn transfers_need_another_node() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 1)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+        let transfer = WorkloadConfig::new(DeployTemplate::Transfer { amount: 1 });
+
+        assert!(matches!(
+            network.run_workload(transfer),
+            Err(Error::NotEnoughTransferTargets)
+        ));
+    }
+
+    #[tokio::test]
+    async fn transfers_never_target_the_sender() {
+        let (_dir, artifacts) = testing::artifacts();
+        let network = testing::network(&artifacts, 3)
+            .dry_run(true)
+            .prepare()
+            .await
+            .unwrap()
+            .start()
+            .await
+            .unwrap();
+
+        for sender in &network.nodes {
+            for count in 0..6 {
+                let target = transfer_target(&network.nodes, sender, count);
+                assert_ne!(target.name(), sender.name());
+            }
+        }
+        // With 2 nodes, they send to each other:
+        let two = &network.nodes[..2];
+        assert_eq!(transfer_target(two, &two[0], 0).name(), two[1].name());
+        assert_eq!(transfer_target(two, &two[1], 1).name(), two[0].name());
+    }
+}